@@ -0,0 +1,168 @@
+// Recording exchanges with Ollama to a file and answering from it (`--record`, `--replay`)
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// One request and the response it got
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<serde_json::Value>,
+    pub status: u16,
+    /// The response body; streamed responses are newline-delimited JSON
+    pub response: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug)]
+pub enum Cassette {
+    /// Write every exchange to the file, rewritten as each response completes
+    Record { path: PathBuf, interactions: Mutex<Vec<Interaction>> },
+    /// Answer requests from a recorded file, each interaction at most once
+    Replay { path: PathBuf, unused: Mutex<Vec<Interaction>> },
+}
+
+impl Cassette {
+    /// The cassette asked for on the command line, if any
+    pub fn open(record: Option<&Path>, replay: Option<&Path>) -> Result<Option<Self>> {
+        match (record, replay) {
+            (Some(path), _) => Ok(Some(Self::Record {
+                path: path.to_path_buf(),
+                interactions: Mutex::new(Vec::new()),
+            })),
+            (None, Some(path)) => Self::load(path).map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let file: CassetteFile =
+            serde_json::from_str(&text).with_context(|| format!("{} is not a recorded session", path.display()))?;
+        Ok(Self::Replay {
+            path: path.to_path_buf(),
+            unused: Mutex::new(file.interactions),
+        })
+    }
+
+    pub const fn is_replaying(&self) -> bool {
+        matches!(self, Self::Replay { .. })
+    }
+
+    /// The recorded response to a request: the first unused one with the same
+    /// request, else the first unused one for the same endpoint
+    pub fn answer(&self, method: &str, path: &str, request: Option<&serde_json::Value>) -> Result<Interaction> {
+        let Self::Replay { path: file, unused } = self else {
+            anyhow::bail!("Not replaying a recorded session");
+        };
+        let mut unused = unused.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let same_endpoint = |i: &Interaction| i.method == method && i.path == path;
+        let position = unused
+            .iter()
+            .position(|i| same_endpoint(i) && i.request.as_ref() == request)
+            .or_else(|| unused.iter().position(same_endpoint))
+            .with_context(|| format!("No recorded response left for {method} {path} in {}", file.display()))?;
+        Ok(unused.remove(position))
+    }
+
+    /// Add a finished exchange to the file being recorded
+    pub fn store(&self, interaction: Interaction) -> Result<()> {
+        let Self::Record { path, interactions } = self else {
+            return Ok(());
+        };
+        let mut interactions = interactions.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        interactions.push(interaction);
+        let file = CassetteFile { interactions: interactions.clone() };
+        // Written under the lock, so a slower writer can't put back an older list
+        let written = crate::storage::write_atomic(path, &serde_json::to_string_pretty(&file)?);
+        drop(interactions);
+        written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{ChatMessage, ChatRequest, OllamaClient};
+    use futures::StreamExt;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn chat_request(text: &str) -> ChatRequest {
+        ChatRequest {
+            model: "qwen3:4b".to_string(),
+            messages: vec![ChatMessage::new("user", text)],
+            stream: true,
+            options: None,
+        }
+    }
+
+    async fn chat_text(client: &OllamaClient, text: &str) -> String {
+        let mut stream = client.chat_stream(chat_request(text)).await.unwrap();
+        let mut content = String::new();
+        while let Some(chunk) = stream.next().await {
+            content.push_str(&chunk.unwrap().message.content);
+        }
+        content
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(concat!(
+                "{\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"done\":false}\n",
+                "{\"message\":{\"role\":\"assistant\",\"content\":\"lo\"},\"done\":true}\n",
+            )))
+            .mount(&server)
+            .await;
+        let temp = tempfile::TempDir::new().unwrap();
+        let file = temp.path().join("session.json");
+
+        let recorder = Cassette::open(Some(&file), None).unwrap().unwrap();
+        let client = OllamaClient::new(server.uri(), 10).unwrap().with_cassette(Some(recorder));
+        assert_eq!(chat_text(&client, "hi").await, "Hello");
+        drop(server);
+
+        // No server needed to play it back, whatever the URL
+        let player = Cassette::open(None, Some(&file)).unwrap().unwrap();
+        assert!(player.is_replaying());
+        let client = OllamaClient::new("http://127.0.0.1:9".to_string(), 10)
+            .unwrap()
+            .with_cassette(Some(player));
+        assert_eq!(chat_text(&client, "hi").await, "Hello");
+        let used_up = client.chat_stream(chat_request("hi")).await.err().unwrap();
+        assert!(format!("{used_up:#}").contains("No recorded response left for POST /api/chat"));
+    }
+
+    #[test]
+    fn test_answer_prefers_the_same_request() {
+        let interaction = |request: &str, response: &str| Interaction {
+            method: "POST".to_string(),
+            path: "/api/chat".to_string(),
+            request: Some(serde_json::json!({ "prompt": request })),
+            status: 200,
+            response: response.to_string(),
+        };
+        let cassette = Cassette::Replay {
+            path: PathBuf::from("session.json"),
+            unused: Mutex::new(vec![interaction("a", "first"), interaction("b", "second")]),
+        };
+        let b = serde_json::json!({ "prompt": "b" });
+        assert_eq!(cassette.answer("POST", "/api/chat", Some(&b)).unwrap().response, "second");
+        // A request that was never recorded gets the next one for its endpoint
+        let c = serde_json::json!({ "prompt": "c" });
+        assert_eq!(cassette.answer("POST", "/api/chat", Some(&c)).unwrap().response, "first");
+        assert!(cassette.answer("GET", "/api/tags", None).is_err());
+    }
+}
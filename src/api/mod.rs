@@ -1,16 +1,27 @@
 // Ollama API client
 
+pub mod cassette;
+
 use anyhow::{Context, Result};
 use futures::stream::{Stream, StreamExt};
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use reqwest::{Client, Method, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::time::Duration;
 
+use crate::models::{AppConfig, GenerationOptions};
+use crate::traffic::{Direction, Traffic};
+use cassette::{Cassette, Interaction};
+
 #[derive(Debug, Clone)]
 pub struct OllamaClient {
     base_url: String,
     client: Client,
+    /// Streamed requests and responses, for the debug overlay
+    traffic: Traffic,
+    /// Session being recorded (`--record`) or replayed (`--replay`)
+    cassette: Option<Arc<Cassette>>,
 }
 
 #[derive(Debug, Serialize)]
@@ -20,6 +31,8 @@ pub struct GenerateRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub system: Option<String>,
     pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<GenerationOptions>,
 }
 
 #[allow(dead_code)]
@@ -46,6 +59,111 @@ impl GenerateResponse {
     }
 }
 
+/// One turn of a conversation sent to /api/chat
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+    /// Base64 images for vision models
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.into(),
+            images: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<GenerationOptions>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ChatResponseMessage {
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub thinking: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatResponse {
+    #[serde(default)]
+    pub message: ChatResponseMessage,
+    pub done: bool,
+    /// Why generation stopped: "stop", or "length" when `num_predict` was reached
+    #[serde(default)]
+    pub done_reason: Option<String>,
+    /// Stats of the finished response (durations in nanoseconds); only on the last chunk
+    #[serde(default)]
+    pub prompt_eval_count: Option<usize>,
+    #[serde(default)]
+    pub eval_count: Option<usize>,
+    #[serde(default)]
+    pub total_duration: Option<u64>,
+    #[serde(default)]
+    pub load_duration: Option<u64>,
+    #[serde(default)]
+    pub prompt_eval_duration: Option<u64>,
+    #[serde(default)]
+    pub eval_duration: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbedRequest<'a> {
+    pub model: &'a str,
+    pub input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbedResponse {
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PullRequest<'a> {
+    pub model: &'a str,
+    pub stream: bool,
+}
+
+/// One status update while a model downloads
+#[derive(Debug, Default, Deserialize)]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub completed: Option<u64>,
+    /// Set instead of a status when the pull fails
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl PullProgress {
+    /// Download progress of the current layer, when it reports sizes
+    pub fn percent(&self) -> Option<u64> {
+        let total = self.total.filter(|&total| total > 0)?;
+        Some(self.completed.unwrap_or(0).min(total) * 100 / total)
+    }
+}
+
+/// Whether an installed model name (from `/api/tags`) is `wanted`, treating a
+/// missing tag as `:latest`
+pub fn model_matches(installed: &str, wanted: &str) -> bool {
+    installed == wanted || (!wanted.contains(':') && installed.strip_suffix(":latest") == Some(wanted))
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct ModelInfo {
@@ -77,6 +195,17 @@ pub struct ShowResponse {
     pub capabilities: Vec<String>,
 }
 
+impl ShowResponse {
+    /// The model's trained context window, from `<architecture>.context_length`
+    pub fn context_length(&self) -> Option<usize> {
+        self.model_info
+            .iter()
+            .find(|(key, _)| key.ends_with(".context_length"))
+            .and_then(|(_, value)| value.as_u64())
+            .and_then(|length| usize::try_from(length).ok())
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ModelDetails {
     #[serde(default)]
@@ -101,7 +230,70 @@ impl OllamaClient {
             .build()
             .context("Failed to create HTTP client")?;
 
-        Ok(Self { base_url, client })
+        Ok(Self { base_url, client, traffic: Traffic::default(), cassette: None })
+    }
+
+    /// Record streamed requests and response lines into `traffic`
+    pub fn with_traffic(mut self, traffic: Traffic) -> Self {
+        self.traffic = traffic;
+        self
+    }
+
+    /// Record every exchange into `cassette`, or answer from it instead of the server
+    pub fn with_cassette(mut self, cassette: Option<Cassette>) -> Self {
+        self.cassette = cassette.map(Arc::new);
+        self
+    }
+
+    /// Keep a request body for the debug overlay
+    fn record_request(&self, url: &str, body: &impl Serialize) {
+        self.traffic.record(Direction::Sent, || {
+            format!("POST {url} {}", serde_json::to_string(body).unwrap_or_default())
+        });
+    }
+
+    /// Send a request to `path` on the server, or take its answer from the cassette
+    /// being replayed. While recording, the exchange is stored once the response has
+    /// been read (or dropped).
+    async fn send(&self, method: Method, path: &str, body: Option<&(impl Serialize + Sync)>) -> Result<Reply> {
+        let body = body.map(serde_json::to_value).transpose()?;
+        if let Some(cassette) = self.cassette.as_ref().filter(|cassette| cassette.is_replaying()) {
+            let interaction = cassette.answer(method.as_str(), path, body.as_ref())?;
+            let status = StatusCode::from_u16(interaction.status)?;
+            let lines: Vec<Result<Vec<u8>>> =
+                interaction.response.split_inclusive('\n').map(|line| Ok(line.as_bytes().to_vec())).collect();
+            return Ok(Reply { status, body: Box::pin(futures::stream::iter(lines)) });
+        }
+
+        let mut request = self.client.request(method.clone(), format!("{}{path}", self.base_url));
+        if let Some(body) = &body {
+            request = request.json(body);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        let mut chunks = response.bytes_stream().map(|chunk| chunk.map(|bytes| bytes.to_vec()).map_err(Into::into));
+        let Some(cassette) = self.cassette.clone() else {
+            return Ok(Reply { status, body: Box::pin(chunks) });
+        };
+        let mut tape = Tape {
+            cassette,
+            interaction: Interaction {
+                method: method.to_string(),
+                path: path.to_string(),
+                request: body,
+                status: status.as_u16(),
+                response: String::new(),
+            },
+            body: Vec::new(),
+        };
+        let recorded = futures::stream::poll_fn(move |cx| {
+            let chunk = chunks.poll_next_unpin(cx);
+            if let std::task::Poll::Ready(Some(Ok(bytes))) = &chunk {
+                tape.body.extend_from_slice(bytes);
+            }
+            chunk
+        });
+        Ok(Reply { status, body: Box::pin(recorded) })
     }
 
     pub fn with_default_url() -> Result<Self> {
@@ -110,19 +302,14 @@ impl OllamaClient {
 
     #[allow(dead_code)]
     pub async fn generate(&self, request: GenerateRequest) -> Result<GenerateResponse> {
-        let url = format!("{}/api/generate", self.base_url);
-
         let response = self
-            .client
-            .post(&url)
-            .json(&request)
-            .send()
+            .send(Method::POST, "/api/generate", Some(&request))
             .await
             .context("Failed to send generate request")?;
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
+        if !response.status.is_success() {
+            let status = response.status;
+            let text = response.text().await;
             anyhow::bail!("API request failed with status {status}: {text}");
         }
 
@@ -140,105 +327,91 @@ impl OllamaClient {
         request: GenerateRequest,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<GenerateResponse>> + Send>>> {
         let url = format!("{}/api/generate", self.base_url);
+        tracing::debug!(%url, model = %request.model, "generate request");
+        self.record_request(&url, &request);
 
         let response = self
-            .client
-            .post(&url)
-            .json(&request)
-            .send()
+            .send(Method::POST, "/api/generate", Some(&request))
             .await
             .context("Failed to send generate request")?;
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
+        if !response.status.is_success() {
+            let status = response.status;
+            let text = response.text().await;
+            tracing::warn!(%status, body = %text, "generate request refused");
             anyhow::bail!("API request failed with status {status}: {text}");
         }
 
-        // Use a stateful stream that buffers incomplete lines
-        let stream = futures::stream::unfold(
-            (response.bytes_stream(), Vec::new()),
-            |(mut byte_stream, mut buffer)| async move {
-                loop {
-                    // Try to find a newline in the buffer
-                    if let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                        // Extract the line including the newline
-                        let mut line = buffer.split_off(pos + 1);
-                        // Swap buffer and line so buffer has the rest and line has the line
-                        std::mem::swap(&mut buffer, &mut line);
-                        // Now 'line' has the bytes up to newline, 'buffer' has the rest
-
-                        let text = String::from_utf8_lossy(&line);
-                        let trimmed = text.trim();
-                        if !trimmed.is_empty() {
-                            let result = serde_json::from_str::<GenerateResponse>(trimmed)
-                                .with_context(|| "Failed to parse streaming response");
-                            return Some((result, (byte_stream, buffer)));
-                        }
-                        // If empty line, loop again to get next line or more bytes
-                        continue;
-                    }
-
-                    // Try to parse the entire buffer as a complete JSON object
-                    // This handles cases where the last chunk doesn't end with a newline
-                    // e.g. {"done":true}
-                    if !buffer.is_empty() {
-                         let text = String::from_utf8_lossy(&buffer);
-                         let trimmed = text.trim();
-                         if !trimmed.is_empty() {
-                             if let Ok(result) = serde_json::from_str::<GenerateResponse>(trimmed) {
-                                 // Success! We parsed the whole buffer
-                                 buffer.clear();
-                                 return Some((Ok(result), (byte_stream, buffer)));
-                             }
-                         }
-                    }
+        Ok(ndjson_stream(response.body, self.traffic.clone()))
+    }
 
-                    // No newline found and not a complete object, need more bytes
-                    match byte_stream.next().await {
-                        Some(Ok(bytes)) => {
-                            buffer.extend_from_slice(&bytes);
-                            // Loop back to check for newline
-                        }
-                        Some(Err(e)) => {
-                            return Some((Err(anyhow::anyhow!("Stream error: {e}")), (byte_stream, buffer)));
-                        }
-                        None => {
-                            // End of stream
-                            if !buffer.is_empty() {
-                                // Process remaining buffer
-                                let text = String::from_utf8_lossy(&buffer);
-                                let trimmed = text.trim();
-                                if !trimmed.is_empty() {
-                                    let result = serde_json::from_str::<GenerateResponse>(trimmed)
-                                        .with_context(|| "Failed to parse final streaming response");
-                                    // Clear buffer to end loop next time
-                                    buffer.clear();
-                                    return Some((result, (byte_stream, buffer)));
-                                }
-                            }
-                            return None;
-                        }
-                    }
-                }
-            },
+    /// Stream a chat completion for a whole conversation
+    pub async fn chat_stream(
+        &self,
+        request: ChatRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatResponse>> + Send>>> {
+        let url = format!("{}/api/chat", self.base_url);
+        tracing::debug!(
+            %url,
+            model = %request.model,
+            messages = request.messages.len(),
+            options = ?request.options,
+            "chat request"
         );
+        self.record_request(&url, &request);
 
-        Ok(Box::pin(stream))
+        let response = self
+            .send(Method::POST, "/api/chat", Some(&request))
+            .await
+            .context("Failed to send chat request")?;
+
+        if !response.status.is_success() {
+            let status = response.status;
+            let text = response.text().await;
+            tracing::warn!(%status, body = %text, "chat request refused");
+            anyhow::bail!("API request failed with status {status}: {text}");
+        }
+
+        Ok(ndjson_stream(response.body, self.traffic.clone()))
     }
 
-    pub async fn list_models(&self) -> Result<Vec<ModelInfo>> {
-        let url = format!("{}/api/tags", self.base_url);
+    /// Embed each input with an embedding model, in input order
+    pub async fn embed(&self, model: &str, input: &[String]) -> Result<Vec<Vec<f32>>> {
+        let response = self
+            .send(Method::POST, "/api/embed", Some(&EmbedRequest { model, input }))
+            .await
+            .context("Failed to send embed request")?;
+
+        if !response.status.is_success() {
+            let status = response.status;
+            let text = response.text().await;
+            tracing::warn!(%status, body = %text, "embedding with {model} refused");
+            anyhow::bail!("Embedding with {model} failed with status {status}: {text}");
+        }
 
+        let result = response
+            .json::<EmbedResponse>()
+            .await
+            .context("Failed to parse embed response")?;
+
+        if result.embeddings.len() != input.len() {
+            anyhow::bail!(
+                "Expected {} embeddings from {model}, got {}",
+                input.len(),
+                result.embeddings.len()
+            );
+        }
+        Ok(result.embeddings)
+    }
+
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>> {
         let response = self
-            .client
-            .get(&url)
-            .send()
+            .send(Method::GET, "/api/tags", None::<&()>)
             .await
             .context("Failed to send tags request")?;
 
-        if !response.status().is_success() {
-            let status = response.status();
+        if !response.status.is_success() {
+            let status = response.status;
             anyhow::bail!("Failed to list models: {status}");
         }
 
@@ -250,24 +423,66 @@ impl OllamaClient {
         Ok(result.models)
     }
 
+    /// The first of the default models in `config` that is installed (see
+    /// `AppConfig::default_model_from`). The server is only asked when
+    /// `default_model` lists more than one.
+    pub async fn pick_default_model(&self, config: &AppConfig) -> String {
+        let installed = if config.default_model.candidates().len() > 1 {
+            self.list_models()
+                .await
+                .ok()
+                .map(|models| models.into_iter().map(|model| model.name).collect::<Vec<_>>())
+        } else {
+            None
+        };
+        config.default_model_from(installed.as_deref())
+    }
+
+    pub async fn has_model(&self, model: &str) -> Result<bool> {
+        Ok(self
+            .list_models()
+            .await?
+            .iter()
+            .any(|info| model_matches(&info.name, model)))
+    }
+
+    /// Download a model, streaming status updates
+    pub async fn pull_stream(
+        &self,
+        model: &str,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<PullProgress>> + Send>>> {
+        let url = format!("{}/api/pull", self.base_url);
+        let request = PullRequest { model, stream: true };
+        self.record_request(&url, &request);
+
+        let response = self
+            .send(Method::POST, "/api/pull", Some(&request))
+            .await
+            .context("Failed to send pull request")?;
+
+        if !response.status.is_success() {
+            let status = response.status;
+            let text = response.text().await;
+            tracing::warn!(%status, body = %text, "pull of {model} refused");
+            anyhow::bail!("Pulling {model} failed with status {status}: {text}");
+        }
+
+        Ok(ndjson_stream(response.body, self.traffic.clone()))
+    }
+
     #[allow(dead_code)]
     pub async fn show_model(&self, model_name: &str) -> Result<ShowResponse> {
-        let url = format!("{}/api/show", self.base_url);
-
         let request = serde_json::json!({
             "name": model_name
         });
 
         let response = self
-            .client
-            .post(&url)
-            .json(&request)
-            .send()
+            .send(Method::POST, "/api/show", Some(&request))
             .await
             .context("Failed to send show request")?;
 
-        if !response.status().is_success() {
-            let status = response.status();
+        if !response.status.is_success() {
+            let status = response.status;
             anyhow::bail!("Failed to show model: {status}");
         }
 
@@ -280,17 +495,141 @@ impl OllamaClient {
     }
 
     pub async fn health_check(&self) -> Result<bool> {
-        let url = format!("{}/api/tags", self.base_url);
-
         Ok(self
-            .client
-            .get(&url)
-            .send()
+            .send(Method::GET, "/api/tags", None::<&()>)
             .await
-            .is_ok_and(|response| response.status().is_success()))
+            .is_ok_and(|response| response.status.is_success()))
+    }
+}
+
+type ByteStream = Pin<Box<dyn Stream<Item = Result<Vec<u8>>> + Send>>;
+
+/// A response from the server, or from the cassette being replayed
+struct Reply {
+    status: StatusCode,
+    body: ByteStream,
+}
+
+impl Reply {
+    async fn bytes(mut self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        while let Some(chunk) = self.body.next().await {
+            bytes.extend_from_slice(&chunk?);
+        }
+        Ok(bytes)
+    }
+
+    /// The body of an error response, as far as it can be read
+    async fn text(self) -> String {
+        self.bytes().await.map(|bytes| String::from_utf8_lossy(&bytes).to_string()).unwrap_or_default()
+    }
+
+    async fn json<T: DeserializeOwned>(self) -> Result<T> {
+        Ok(serde_json::from_slice(&self.bytes().await?)?)
     }
 }
 
+/// A response being recorded; it goes into the cassette when the body is dropped,
+/// whether it was read to the end or the response was aborted
+struct Tape {
+    cassette: Arc<Cassette>,
+    interaction: Interaction,
+    body: Vec<u8>,
+}
+
+impl Drop for Tape {
+    fn drop(&mut self) {
+        let mut interaction = self.interaction.clone();
+        interaction.response = String::from_utf8_lossy(&self.body).to_string();
+        if let Err(e) = self.cassette.store(interaction) {
+            tracing::warn!(error = format!("{e:#}"), "failed to record response");
+        }
+    }
+}
+
+/// Parse a streaming response of newline-delimited JSON objects
+fn ndjson_stream<T: DeserializeOwned + Send + 'static>(
+    body: ByteStream,
+    traffic: Traffic,
+) -> Pin<Box<dyn Stream<Item = Result<T>> + Send>> {
+    // Use a stateful stream that buffers incomplete lines
+    let stream = futures::stream::unfold(
+        (body, Vec::new(), traffic),
+        |(mut byte_stream, mut buffer, traffic)| async move {
+            loop {
+                // Try to find a newline in the buffer
+                if let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                    // Extract the line including the newline
+                    let mut line = buffer.split_off(pos + 1);
+                    // Swap buffer and line so buffer has the rest and line has the line
+                    std::mem::swap(&mut buffer, &mut line);
+                    // Now 'line' has the bytes up to newline, 'buffer' has the rest
+
+                    let text = String::from_utf8_lossy(&line);
+                    let trimmed = text.trim();
+                    if !trimmed.is_empty() {
+                        traffic.record(Direction::Received, || trimmed.to_string());
+                        let result = serde_json::from_str::<T>(trimmed)
+                            .inspect_err(|e| tracing::warn!(line = trimmed, error = %e, "unparseable stream line"))
+                            .with_context(|| "Failed to parse streaming response");
+                        return Some((result, (byte_stream, buffer, traffic)));
+                    }
+                    // If empty line, loop again to get next line or more bytes
+                    continue;
+                }
+
+                // Try to parse the entire buffer as a complete JSON object
+                // This handles cases where the last chunk doesn't end with a newline
+                // e.g. {"done":true}
+                if !buffer.is_empty() {
+                     let text = String::from_utf8_lossy(&buffer);
+                     let trimmed = text.trim();
+                     if !trimmed.is_empty() {
+                         if let Ok(result) = serde_json::from_str::<T>(trimmed) {
+                             traffic.record(Direction::Received, || trimmed.to_string());
+                             // Success! We parsed the whole buffer
+                             buffer.clear();
+                             return Some((Ok(result), (byte_stream, buffer, traffic)));
+                         }
+                     }
+                }
+
+                // No newline found and not a complete object, need more bytes
+                match byte_stream.next().await {
+                    Some(Ok(bytes)) => {
+                        buffer.extend_from_slice(&bytes);
+                        // Loop back to check for newline
+                    }
+                    Some(Err(e)) => {
+                        tracing::warn!(error = %e, "response stream broke off");
+                        return Some((Err(anyhow::anyhow!("Stream error: {e}")), (byte_stream, buffer, traffic)));
+                    }
+                    None => {
+                        // End of stream
+                        if !buffer.is_empty() {
+                            // Process remaining buffer
+                            let text = String::from_utf8_lossy(&buffer);
+                            let trimmed = text.trim();
+                            if !trimmed.is_empty() {
+                                traffic.record(Direction::Received, || trimmed.to_string());
+                                let result = serde_json::from_str::<T>(trimmed)
+                                    .inspect_err(|e| tracing::warn!(line = trimmed, error = %e, "unparseable last stream line"))
+                                    .with_context(|| "Failed to parse final streaming response");
+                                // Clear buffer to end loop next time
+                                buffer.clear();
+                                return Some((result, (byte_stream, buffer, traffic)));
+                            }
+                        }
+                        return None;
+                    }
+                }
+            }
+        },
+    );
+
+    Box::pin(stream)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -335,11 +674,88 @@ mod tests {
             prompt: "Hello".to_string(),
             system: None,
             stream: false,
+            options: None,
         };
 
         let json = serde_json::to_string(&request);
         assert!(json.is_ok());
-        assert!(json.unwrap().contains("test"));
+        let json = json.unwrap();
+        assert!(json.contains("test"));
+        assert!(!json.contains("options"));
+    }
+
+    #[test]
+    fn test_generate_request_with_options() {
+        let request = GenerateRequest {
+            model: "test".to_string(),
+            prompt: "Hello".to_string(),
+            system: Some("Be brief".to_string()),
+            stream: true,
+            options: Some(GenerationOptions {
+                temperature: Some(0.2),
+                ..Default::default()
+            }),
+        };
+
+        let json: serde_json::Value = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["system"], "Be brief");
+        assert!((json["options"]["temperature"].as_f64().unwrap() - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_embed_request_and_response() {
+        let input = vec!["fn main() {}".to_string()];
+        let json = serde_json::to_value(EmbedRequest { model: "nomic-embed-text", input: &input }).unwrap();
+        assert_eq!(json["input"][0], "fn main() {}");
+
+        let response: EmbedResponse =
+            serde_json::from_str(r#"{"model":"nomic-embed-text","embeddings":[[0.5,-1.0]]}"#).unwrap();
+        assert_eq!(response.embeddings, vec![vec![0.5, -1.0]]);
+    }
+
+    #[test]
+    fn test_chat_request_and_response() {
+        let request = ChatRequest {
+            model: "test".to_string(),
+            messages: vec![ChatMessage::new("system", "Be brief"), ChatMessage::new("user", "Hi")],
+            stream: true,
+            options: None,
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["messages"][1]["role"], "user");
+        assert!(json.get("options").is_none());
+
+        let chunk: ChatResponse =
+            serde_json::from_str(r#"{"message":{"role":"assistant","content":"Hel"},"done":false}"#).unwrap();
+        assert_eq!(chunk.message.content, "Hel");
+        let last: ChatResponse = serde_json::from_str(r#"{"done":true,"done_reason":"length"}"#).unwrap();
+        assert_eq!(last.done_reason.as_deref(), Some("length"));
+    }
+
+    #[test]
+    fn test_pull_progress_and_model_matching() {
+        let progress: PullProgress =
+            serde_json::from_str(r#"{"status":"pulling abc","total":200,"completed":50}"#).unwrap();
+        assert_eq!(progress.percent(), Some(25));
+        let failed: PullProgress = serde_json::from_str(r#"{"error":"file does not exist"}"#).unwrap();
+        assert_eq!(failed.error.as_deref(), Some("file does not exist"));
+        assert_eq!(failed.percent(), None);
+
+        assert!(model_matches("llama3:latest", "llama3"));
+        assert!(model_matches("qwen3:4b", "qwen3:4b"));
+        assert!(!model_matches("qwen3:8b", "qwen3:4b"));
+        assert!(!model_matches("llama3:8b", "llama3"));
+    }
+
+    #[test]
+    fn test_show_response_context_length() {
+        let info: ShowResponse = serde_json::from_str(
+            r#"{"model_info":{"general.architecture":"qwen3","qwen3.context_length":40960}}"#,
+        )
+        .unwrap();
+        assert_eq!(info.context_length(), Some(40960));
+        let bare: ShowResponse = serde_json::from_str("{}").unwrap();
+        assert_eq!(bare.context_length(), None);
     }
 
     #[tokio::test]
@@ -367,6 +783,7 @@ mod tests {
             prompt: "Say 'test successful' and nothing else".to_string(),
             system: None,
             stream: false,
+            options: None,
         };
 
         let response = client.generate(request).await;
@@ -0,0 +1,273 @@
+// One-shot headless mode: `yumchat ask "prompt"` streams the answer to stdout
+
+use anyhow::{bail, Context, Result};
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use std::io::{IsTerminal, Read, Write};
+
+use crate::api::cassette::Cassette;
+use crate::api::{ChatMessage, ChatRequest, ChatResponse, OllamaClient};
+use crate::cli::{Args, OutputFormat};
+
+/// One line of `--output json`
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum JsonEvent<'a> {
+    /// Text as it streams in: response content, or the model's thinking
+    Delta {
+        #[serde(skip_serializing_if = "str::is_empty")]
+        content: &'a str,
+        #[serde(skip_serializing_if = "str::is_empty")]
+        thinking: &'a str,
+    },
+    /// The whole response, once finished
+    Done(&'a Answer),
+    Error { message: String },
+}
+
+/// The finished response with Ollama's stats; durations in milliseconds
+#[derive(Debug, Default, Serialize)]
+struct Answer {
+    model: String,
+    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<String>,
+    done_reason: Option<String>,
+    prompt_tokens: Option<usize>,
+    completion_tokens: Option<usize>,
+    total_duration_ms: Option<f64>,
+    load_duration_ms: Option<f64>,
+    prompt_eval_duration_ms: Option<f64>,
+    eval_duration_ms: Option<f64>,
+    tokens_per_second: Option<f64>,
+}
+
+impl Answer {
+    #[allow(clippy::cast_precision_loss)]
+    fn finish(&mut self, last: &ChatResponse) {
+        let millis = |nanos: Option<u64>| nanos.map(|n| n as f64 / 1_000_000.0);
+        self.done_reason.clone_from(&last.done_reason);
+        self.prompt_tokens = last.prompt_eval_count;
+        self.completion_tokens = last.eval_count;
+        self.total_duration_ms = millis(last.total_duration);
+        self.load_duration_ms = millis(last.load_duration);
+        self.prompt_eval_duration_ms = millis(last.prompt_eval_duration);
+        self.eval_duration_ms = millis(last.eval_duration);
+        self.tokens_per_second = match (last.eval_count, last.eval_duration) {
+            (Some(tokens), Some(nanos)) if nanos > 0 => Some(tokens as f64 * 1e9 / nanos as f64),
+            _ => None,
+        };
+    }
+}
+
+/// `yumchat ask`: the config's server and model unless overridden on the command line
+pub async fn run(args: &Args, words: &[String], output: OutputFormat) -> Result<()> {
+    let result = async {
+        // Bad settings fall back to defaults with a warning, as in the TUI
+        let (config, problems) = crate::config::load_config_checked();
+        for problem in problems {
+            eprintln!("yumchat: config.toml {problem} (using the default)");
+        }
+        let prompt = read_prompt(words)?;
+        let url = args.url.clone().unwrap_or_else(|| config.ollama_url.clone());
+        let client = OllamaClient::new(url, config.request_timeout)?
+            .with_cassette(Cassette::open(args.record.as_deref(), args.replay.as_deref())?);
+        let model = match &args.model {
+            Some(model) => config.resolve_model(model),
+            None => client.pick_default_model(&config).await,
+        };
+        let system = args.system.as_deref().unwrap_or(&config.default_system_prompt);
+        ask(&client, &model, system, &prompt, output).await
+    }
+    .await;
+    // JSON consumers read errors from stdout like everything else
+    if let (Err(e), OutputFormat::Json) = (&result, output) {
+        print_json(&mut std::io::stdout(), &JsonEvent::Error { message: format!("{e:#}") })?;
+    }
+    result
+}
+
+/// The prompt: the words given on the command line, followed by whatever was piped
+/// in (so `git diff | yumchat ask "review this"` works). `-` reads only stdin.
+fn read_prompt(words: &[String]) -> Result<String> {
+    let stdin = std::io::stdin();
+    let piped = if stdin.is_terminal() {
+        None
+    } else {
+        let mut text = String::new();
+        stdin.lock().read_to_string(&mut text).context("Failed to read stdin")?;
+        Some(text)
+    };
+    build_prompt(words, piped.as_deref())
+}
+
+fn build_prompt(words: &[String], piped: Option<&str>) -> Result<String> {
+    let words = words.join(" ");
+    let words = words.trim();
+    let piped = piped.map(str::trim).filter(|text| !text.is_empty());
+    let prompt = match (words, piped) {
+        ("" | "-", Some(text)) => text.to_string(),
+        ("" | "-", None) => bail!("Nothing to ask: give a prompt or pipe one in"),
+        (words, Some(text)) => format!("{words}\n\n{text}"),
+        (words, None) => words.to_string(),
+    };
+    Ok(prompt)
+}
+
+/// Send `prompt` (after `system_prompt`, if any) to `model` and print the response to
+/// stdout as it streams in
+async fn ask(
+    client: &OllamaClient,
+    model: &str,
+    system_prompt: &str,
+    prompt: &str,
+    output: OutputFormat,
+) -> Result<()> {
+    let mut messages = Vec::new();
+    if !system_prompt.trim().is_empty() {
+        messages.push(ChatMessage::new("system", system_prompt.trim()));
+    }
+    messages.push(ChatMessage::new("user", prompt));
+    let request = ChatRequest {
+        model: model.to_string(),
+        messages,
+        stream: true,
+        options: None,
+    };
+    let stream = client.chat_stream(request).await?;
+    let mut answer = Answer {
+        model: model.to_string(),
+        ..Answer::default()
+    };
+    print_stream(stream, &mut std::io::stdout(), output, &mut answer).await
+}
+
+/// Write the response to `out` as it streams in, flushing every chunk: the text
+/// (thinking left out), or JSON lines. A stream that ends in an error or without
+/// finishing is an error.
+async fn print_stream(
+    mut stream: impl Stream<Item = Result<ChatResponse>> + Unpin,
+    out: &mut impl Write,
+    output: OutputFormat,
+    answer: &mut Answer,
+) -> Result<()> {
+    let mut thinking = String::new();
+    while let Some(response) = stream.next().await {
+        let response = response?;
+        let (content, thought) = (&response.message.content, &response.message.thinking);
+        answer.content.push_str(content);
+        thinking.push_str(thought);
+        match output {
+            OutputFormat::Text if !content.is_empty() => {
+                out.write_all(content.as_bytes())?;
+                out.flush()?;
+            }
+            OutputFormat::Json if !content.is_empty() || !thought.is_empty() => {
+                print_json(out, &JsonEvent::Delta { content, thinking: thought })?;
+            }
+            _ => {}
+        }
+        if response.done {
+            answer.finish(&response);
+            answer.thinking = Some(thinking).filter(|text| !text.is_empty());
+            match output {
+                OutputFormat::Text => {
+                    if !answer.content.is_empty() && !answer.content.ends_with('\n') {
+                        writeln!(out)?;
+                    }
+                }
+                OutputFormat::Json => print_json(out, &JsonEvent::Done(answer))?,
+            }
+            if answer.done_reason.as_deref() == Some("length") {
+                eprintln!("yumchat: the response hit the token limit");
+            }
+            return Ok(());
+        }
+    }
+    bail!("The response stream ended early")
+}
+
+fn print_json(out: &mut impl Write, event: &JsonEvent) -> Result<()> {
+    serde_json::to_writer(&mut *out, event)?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(line: &str) -> ChatResponse {
+        serde_json::from_str(line).unwrap()
+    }
+
+    #[test]
+    fn test_build_prompt() {
+        let words = |list: &[&str]| list.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(build_prompt(&words(&["why", "is", "rust"]), None).unwrap(), "why is rust");
+        assert_eq!(build_prompt(&words(&["review"]), Some("diff\n")).unwrap(), "review\n\ndiff");
+        assert_eq!(build_prompt(&words(&["-"]), Some("from stdin")).unwrap(), "from stdin");
+        assert_eq!(build_prompt(&words(&["hi"]), Some("  ")).unwrap(), "hi");
+        assert!(build_prompt(&[], None).is_err());
+        assert!(build_prompt(&[], Some("\n")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_print_stream() {
+        let stream = futures::stream::iter(vec![
+            Ok(response(r#"{"message":{"content":"","thinking":"hmm"},"done":false}"#)),
+            Ok(response(r#"{"message":{"content":"Hello"},"done":false}"#)),
+            Ok(response(r#"{"message":{"content":" world"},"done":false}"#)),
+            Ok(response(r#"{"done":true,"done_reason":"stop"}"#)),
+        ]);
+        let mut out = Vec::new();
+        print_stream(stream, &mut out, OutputFormat::Text, &mut Answer::default()).await.unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Hello world\n");
+
+        let failing = futures::stream::iter(vec![
+            Ok(response(r#"{"message":{"content":"Hel"},"done":false}"#)),
+            Err(anyhow::anyhow!("connection reset")),
+        ]);
+        assert!(print_stream(failing, &mut Vec::new(), OutputFormat::Text, &mut Answer::default()).await.is_err());
+        let unfinished = futures::stream::iter(vec![Ok(response(r#"{"message":{"content":"Hel"},"done":false}"#))]);
+        assert!(print_stream(unfinished, &mut Vec::new(), OutputFormat::Text, &mut Answer::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_print_stream_as_json_lines() {
+        let stream = futures::stream::iter(vec![
+            Ok(response(r#"{"message":{"content":"","thinking":"hmm"},"done":false}"#)),
+            Ok(response(r#"{"message":{"content":"Hi"},"done":false}"#)),
+            Ok(response(
+                r#"{"done":true,"done_reason":"stop","prompt_eval_count":12,"eval_count":50,
+                   "total_duration":3000000000,"eval_duration":2000000000}"#,
+            )),
+        ]);
+        let mut out = Vec::new();
+        let mut answer = Answer {
+            model: "qwen3:8b".to_string(),
+            ..Answer::default()
+        };
+        print_stream(stream, &mut out, OutputFormat::Json, &mut answer).await.unwrap();
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], serde_json::json!({"event": "delta", "thinking": "hmm"}));
+        assert_eq!(lines[1], serde_json::json!({"event": "delta", "content": "Hi"}));
+        let done = &lines[2];
+        assert_eq!(done["event"], "done");
+        assert_eq!(done["model"], "qwen3:8b");
+        assert_eq!(done["content"], "Hi");
+        assert_eq!(done["thinking"], "hmm");
+        assert_eq!(done["prompt_tokens"], 12);
+        assert_eq!(done["completion_tokens"], 50);
+        assert_eq!(done["total_duration_ms"], 3000.0);
+        assert_eq!(done["tokens_per_second"], 25.0);
+        assert!(done["load_duration_ms"].is_null());
+    }
+}
@@ -0,0 +1,28 @@
+// Opening web links in the system browser
+
+use anyhow::{bail, Context, Result};
+use std::process::{Command, Stdio};
+
+/// Open a web link in the default browser without waiting for it
+pub fn open(url: &str) -> Result<()> {
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        bail!("Not a web link: {url}");
+    }
+    let (program, args): (&str, &[&str]) = if cfg!(target_os = "macos") {
+        ("open", &[])
+    } else if cfg!(windows) {
+        ("rundll32", &["url.dll,FileProtocolHandler"])
+    } else {
+        ("xdg-open", &[])
+    };
+
+    Command::new(program)
+        .args(args)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run '{program}'"))?;
+    Ok(())
+}
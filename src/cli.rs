@@ -0,0 +1,180 @@
+// Command-line arguments
+
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::{Path, PathBuf};
+
+/// A terminal UI chat application for AI models
+#[derive(Debug, Default, PartialEq, Eq, Parser)]
+#[command(name = "yumchat", version)]
+pub struct Args {
+    /// Model to start with, instead of `default_model`
+    #[arg(short, long, global = true)]
+    pub model: Option<String>,
+    /// Ollama server URL, instead of `ollama_url`
+    #[arg(short, long, value_name = "URL", global = true)]
+    pub url: Option<String>,
+    /// Config file to use; its directory holds this profile's models, templates,
+    /// drafts and (by default) conversations
+    #[arg(long, value_name = "PATH", global = true)]
+    pub config: Option<PathBuf>,
+    /// System prompt for the conversation the session starts with
+    #[arg(short, long, value_name = "PROMPT", global = true)]
+    pub system: Option<String>,
+    /// Reopen the most recently updated conversation
+    #[arg(long = "continue")]
+    pub resume: bool,
+    /// Append every message of the session to PATH as it happens
+    #[arg(long, value_name = "PATH")]
+    pub tee: Option<PathBuf>,
+    /// Print the conversation as markdown once the interface closes, like
+    /// `print_on_exit`
+    #[arg(long)]
+    pub print_on_exit: bool,
+    /// Save every exchange with Ollama to PATH, to play back with `--replay`
+    #[arg(long, value_name = "PATH", global = true, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    /// Answer from a session saved with `--record` instead of asking Ollama, for
+    /// demos and reproducing bugs without a server
+    #[arg(long, value_name = "PATH", global = true)]
+    pub replay: Option<PathBuf>,
+    /// Log requests, stream errors and parse failures to `logs/` in the data
+    /// directory (`YUMCHAT_LOG` sets the filter, e.g. `yumchat=trace`)
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+    /// Same as the import command
+    #[arg(long, value_name = "PATH", hide = true)]
+    pub import: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Import conversations (ChatGPT/JSON export or markdown transcript)
+    Import { path: PathBuf },
+    /// Print the answer to a prompt and exit, without the interface. Text piped in
+    /// is added to the prompt; `-` reads the whole prompt from stdin.
+    Ask {
+        /// `json` prints JSON lines: a `delta` event per streamed chunk, then a `done`
+        /// event with the whole response, token counts and timings (or an `error` event)
+        #[arg(long, value_enum, default_value_t)]
+        output: OutputFormat,
+        prompt: Vec<String>,
+    },
+    /// Open a conversation read-only, without connecting to Ollama: a conversation
+    /// file, or the id of a saved one
+    View {
+        #[arg(value_name = "FILE_OR_ID")]
+        conversation: String,
+    },
+}
+
+/// What `ask` prints
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// The response text as it streams in
+    #[default]
+    Text,
+    Json,
+}
+
+impl Args {
+    /// Conversation to open read-only, from the view command
+    pub fn view_target(&self) -> Option<&str> {
+        match &self.command {
+            Some(Command::View { conversation }) => Some(conversation),
+            _ => None,
+        }
+    }
+
+    /// File to import, from the import command or `--import`
+    pub fn import_path(&self) -> Option<&Path> {
+        match &self.command {
+            Some(Command::Import { path }) => Some(path),
+            _ => self.import.as_deref(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from(std::iter::once("yumchat").chain(list.iter().copied()))
+    }
+
+    #[test]
+    fn test_parse_tee() {
+        assert_eq!(args(&[]).unwrap(), Args::default());
+        assert_eq!(args(&["--tee", "log.md"]).unwrap().tee, Some(PathBuf::from("log.md")));
+        assert_eq!(args(&["--tee=a b.md"]).unwrap().tee, Some(PathBuf::from("a b.md")));
+        assert_eq!(args(&["--help"]).unwrap_err().kind(), clap::error::ErrorKind::DisplayHelp);
+    }
+
+    #[test]
+    fn test_parse_session_options() {
+        let parsed = args(&["-m", "qwen3:8b", "--url", "http://gpu:11434", "--system", "Be brief", "--continue"])
+            .unwrap();
+        assert_eq!(parsed.model.as_deref(), Some("qwen3:8b"));
+        assert_eq!(parsed.url.as_deref(), Some("http://gpu:11434"));
+        assert_eq!(parsed.system.as_deref(), Some("Be brief"));
+        assert!(parsed.resume);
+        assert!(!parsed.verbose);
+        assert!(!parsed.print_on_exit);
+        assert!(args(&["--print-on-exit"]).unwrap().print_on_exit);
+        assert!(args(&["ask", "-v", "hi"]).unwrap().verbose);
+        assert_eq!(
+            args(&["--config", "work/config.toml"]).unwrap().config,
+            Some(PathBuf::from("work/config.toml"))
+        );
+    }
+
+    #[test]
+    fn test_parse_import() {
+        assert_eq!(
+            args(&["--import", "conversations.json"]).unwrap().import_path(),
+            Some(Path::new("conversations.json"))
+        );
+        assert_eq!(args(&["import", "chat.md"]).unwrap().import_path(), Some(Path::new("chat.md")));
+        assert!(args(&["import"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_ask() {
+        let parsed = args(&["ask", "--model", "qwen3:8b", "why", "is", "the", "sky", "blue"]).unwrap();
+        assert_eq!(parsed.model.as_deref(), Some("qwen3:8b"));
+        assert_eq!(
+            parsed.command,
+            Some(Command::Ask {
+                output: OutputFormat::Text,
+                prompt: ["why", "is", "the", "sky", "blue"].map(String::from).to_vec()
+            })
+        );
+        assert_eq!(
+            args(&["ask", "--output", "json", "hi"]).unwrap().command,
+            Some(Command::Ask { output: OutputFormat::Json, prompt: vec!["hi".to_string()] })
+        );
+        assert!(args(&["ask", "--output", "yaml"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_view() {
+        assert_eq!(args(&["view", "chat.md"]).unwrap().view_target(), Some("chat.md"));
+        assert_eq!(args(&[]).unwrap().view_target(), None);
+        assert!(args(&["view"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_record_and_replay() {
+        assert_eq!(args(&["--record", "demo.json"]).unwrap().record, Some(PathBuf::from("demo.json")));
+        assert_eq!(args(&["ask", "--replay", "demo.json", "hi"]).unwrap().replay, Some(PathBuf::from("demo.json")));
+        assert!(args(&["--record", "a.json", "--replay", "b.json"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(args(&["--tee"]).is_err());
+        assert!(args(&["--frobnicate"]).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+// System clipboard integration
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// How a copy request reached the clipboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyMethod {
+    /// Native clipboard (X11/Wayland/macOS/Windows) via arboard
+    System,
+    /// OSC 52 escape sequence, handled by the terminal emulator (works over SSH)
+    Osc52,
+}
+
+// Keep the clipboard handle alive for the lifetime of the process: on X11 the
+// owning process has to stay around to serve the contents to other apps.
+static CLIPBOARD: Mutex<Option<arboard::Clipboard>> = Mutex::new(None);
+
+/// Copy text to the clipboard, preferring OSC 52 in SSH sessions and falling back to
+/// it whenever the native clipboard is unavailable.
+pub fn copy(text: &str) -> Result<CopyMethod> {
+    if is_ssh_session() || copy_system(text).is_err() {
+        copy_osc52(text)?;
+        return Ok(CopyMethod::Osc52);
+    }
+    Ok(CopyMethod::System)
+}
+
+/// Run `f` with the shared native clipboard, opening it on first use
+fn with_system_clipboard<T>(f: impl FnOnce(&mut arboard::Clipboard) -> Result<T>) -> Result<T> {
+    let mut guard = CLIPBOARD
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Clipboard lock poisoned"))?;
+    if guard.is_none() {
+        *guard = Some(arboard::Clipboard::new().context("Failed to open system clipboard")?);
+    }
+    f(guard.as_mut().expect("clipboard initialized above"))
+}
+
+fn copy_system(text: &str) -> Result<()> {
+    with_system_clipboard(|clipboard| {
+        clipboard
+            .set_text(text.to_string())
+            .context("Failed to set clipboard contents")
+    })
+}
+
+/// Read text from the native clipboard. Unlike terminal paste this works even when
+/// the terminal doesn't forward it, as long as a display server is reachable.
+pub fn paste() -> Result<String> {
+    with_system_clipboard(|clipboard| clipboard.get_text().context("Clipboard has no text"))
+}
+
+fn copy_osc52(text: &str) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    stdout
+        .write_all(osc52_sequence(text).as_bytes())
+        .and_then(|()| stdout.flush())
+        .context("Failed to write OSC 52 sequence")
+}
+
+fn osc52_sequence(text: &str) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    format!("\x1b]52;c;{encoded}\x07")
+}
+
+fn is_ssh_session() -> bool {
+    std::env::var_os("SSH_TTY").is_some() || std::env::var_os("SSH_CONNECTION").is_some()
+}
+
+/// Remove `<thinking>...</thinking>` blocks from a response
+pub fn strip_thinking(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("<thinking>") {
+        result.push_str(&rest[..start]);
+        match rest[start..].find("</thinking>") {
+            Some(end) => rest = &rest[start + end + "</thinking>".len()..],
+            // Unterminated block: drop everything after the opening tag
+            None => rest = "",
+        }
+    }
+    result.push_str(rest);
+
+    result.trim().to_string()
+}
+
+/// Reduce markdown to plain text: drop fences, header markers, emphasis and inline code ticks
+pub fn strip_markdown(text: &str) -> String {
+    text.lines()
+        .filter(|line| !crate::ui::markdown::is_code_fence(line))
+        .map(|line| {
+            let trimmed = line.trim_start();
+            let line = if trimmed.starts_with('#') {
+                trimmed.trim_start_matches('#').trim_start()
+            } else {
+                line
+            };
+            line.replace("**", "").replace('`', "")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_thinking() {
+        let text = "<thinking>\nhmm\n</thinking>\nThe answer is 42";
+        assert_eq!(strip_thinking(text), "The answer is 42");
+        assert_eq!(strip_thinking("No thoughts here"), "No thoughts here");
+        assert_eq!(strip_thinking("Before <thinking>never closed"), "Before");
+    }
+
+    #[test]
+    fn test_strip_markdown() {
+        let text = "## Title\nSome **bold** and `code`\n```rust\nfn main() {}\n```";
+        assert_eq!(strip_markdown(text), "Title\nSome bold and code\nfn main() {}");
+    }
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(osc52_sequence("hi"), "\x1b]52;c;aGk=\x07");
+    }
+}
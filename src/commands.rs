@@ -0,0 +1,406 @@
+// Slash commands typed into the input field
+
+use anyhow::{bail, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Resume the last response where it stopped (token limit, abort)
+    Continue,
+    /// Re-run the last prompt once per value of a generation option and compare
+    Experiment {
+        parameter: String,
+        values: Vec<String>,
+    },
+    /// Override a generation option, or the system prompt (`system`), for this conversation
+    Set { name: String, value: String },
+    /// Remove a conversation override, or all of them (`all`)
+    Unset(String),
+    /// Queue the last response for later reading
+    ReadLater,
+    /// Open the read-later review screen
+    ReviewReadLater,
+    /// Search all saved conversations
+    Search(Option<String>),
+    /// Save a fact to memory
+    Remember(String),
+    /// Review and delete remembered facts
+    Memory,
+    /// List bookmarked messages to jump to
+    Bookmarks,
+    /// Switch between LaTeX math as Unicode and as written
+    Math,
+    /// Start a focus timer counting down in the status bar; `None` shows what is left
+    Timer(Option<std::time::Duration>),
+    /// Cancel the focus timer
+    TimerOff,
+    /// Copy code block N (1-based, as numbered in the history), or the last response
+    Copy(Option<usize>),
+    /// Export diagram code block N (or the most recent diagram) to an image and open it
+    Diagram(Option<usize>),
+    /// Index a file or directory for retrieval; `None` shows what is indexed
+    Rag(Option<String>),
+    /// Stop adding retrieved context to prompts
+    RagOff,
+    /// Open the retrieval collections screen
+    Collections,
+    /// Switch to a theme preset; `None` opens the picker with a live preview
+    Theme(Option<String>),
+    /// Switch to a model by name or alias; `None` opens the model picker
+    Model(Option<String>),
+    /// Open cited source N of the latest response in $EDITOR
+    Source(usize),
+    /// Export the conversation; without a path it goes to the current directory
+    Export {
+        format: crate::export::ExportFormat,
+        path: Option<String>,
+    },
+    /// Save code block N (or the latest one) to a file; without a path, ask for one
+    SaveCodeBlock {
+        block: Option<usize>,
+        path: Option<String>,
+    },
+    /// Save every code block of the last response into a directory
+    CodeSave(Option<String>),
+    /// Import conversations from an exported chat history or markdown transcript
+    Import(String),
+    /// Attach a file (an image for vision models, or text for context) to the next message
+    Attach(String),
+    /// Save CSV/TSV code block N (or the most recent table) to a file
+    SaveTable {
+        block: Option<usize>,
+        path: Option<String>,
+    },
+}
+
+impl Command {
+    /// Whether the command leaves the conversation, the model and saved data alone,
+    /// so it can run in `yumchat view`
+    pub const fn reads_only(&self) -> bool {
+        matches!(
+            self,
+            Self::Timer(_)
+                | Self::TimerOff
+                | Self::Math
+                | Self::Bookmarks
+                | Self::Copy(_)
+                | Self::Diagram(_)
+                | Self::Source(_)
+                | Self::Export { .. }
+                | Self::SaveCodeBlock { .. }
+                | Self::CodeSave(_)
+                | Self::SaveTable { .. }
+        )
+    }
+}
+
+/// Parse the input buffer as a slash command.
+/// Returns `None` when the input is a regular chat message.
+pub fn parse(input: &str) -> Option<Result<Command>> {
+    let input = input.trim();
+    let rest = input.strip_prefix('/')?;
+    // A lone slash or "//..." is treated as a normal message
+    if rest.is_empty() || rest.starts_with('/') {
+        return None;
+    }
+
+    let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    Some(parse_command(name, args.trim()))
+}
+
+fn parse_command(name: &str, args: &str) -> Result<Command> {
+    match name {
+        "continue" => Ok(Command::Continue),
+        "experiment" => {
+            let mut words = args.split_whitespace().map(ToString::to_string);
+            match (words.next(), words.collect::<Vec<_>>()) {
+                (Some(parameter), values) if !values.is_empty() => Ok(Command::Experiment { parameter, values }),
+                _ => bail!("Usage: /experiment <option> <value> <value>..., e.g. /experiment temperature 0.2 0.7 1.0"),
+            }
+        }
+        "set" => match args.split_once(char::is_whitespace) {
+            Some((name, value)) => Ok(Command::Set {
+                name: name.to_string(),
+                value: value.trim().to_string(),
+            }),
+            None => bail!("Usage: /set <option|system> <value>, e.g. /set temperature 0.2"),
+        },
+        "unset" => match args {
+            "" => bail!("Usage: /unset <option|system|all>"),
+            name => Ok(Command::Unset(name.to_string())),
+        },
+        "later" => match args {
+            "" => Ok(Command::ReadLater),
+            "review" => Ok(Command::ReviewReadLater),
+            _ => bail!("Usage: /later [review]"),
+        },
+        "search" => Ok(Command::Search(Some(args.to_string()).filter(|q| !q.is_empty()))),
+        "remember" => match args {
+            "" => bail!("Usage: /remember <fact>, e.g. /remember I deploy to Fly.io"),
+            fact => Ok(Command::Remember(fact.to_string())),
+        },
+        "memory" => Ok(Command::Memory),
+        "bookmarks" => Ok(Command::Bookmarks),
+        "math" => Ok(Command::Math),
+        "timer" => match args {
+            "" => Ok(Command::Timer(None)),
+            "off" => Ok(Command::TimerOff),
+            length => crate::timer::parse_duration(length).map(|length| Command::Timer(Some(length))),
+        },
+        "copy" => parse_block_number(args, "/copy").map(Command::Copy),
+        "diagram" => parse_block_number(args, "/diagram").map(Command::Diagram),
+        "rag" => Ok(match args {
+            "" => Command::Rag(None),
+            "off" => Command::RagOff,
+            path => Command::Rag(Some(path.to_string())),
+        }),
+        "collections" => Ok(Command::Collections),
+        "model" => Ok(Command::Model(Some(args.to_string()).filter(|name| !name.is_empty()))),
+        "theme" => Ok(Command::Theme(Some(args.to_lowercase()).filter(|name| !name.is_empty()))),
+        "source" => match args.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(Command::Source(n)),
+            _ => bail!("Usage: /source N"),
+        },
+        "export" => {
+            let (first, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            let format = match first {
+                "" => crate::export::ExportFormat::Markdown,
+                name => crate::export::ExportFormat::from_name(name)
+                    .ok_or_else(|| anyhow::anyhow!("Usage: /export [markdown|json|html|obsidian] [path]"))?,
+            };
+            Ok(Command::Export {
+                format,
+                path: Some(rest.trim().to_string()).filter(|p| !p.is_empty()),
+            })
+        }
+        "table" => {
+            let (block, path) = parse_block_and_path(args, "/table")?;
+            Ok(Command::SaveTable { block, path })
+        }
+        "import" if args.is_empty() => bail!("Usage: /import <file>"),
+        "import" => Ok(Command::Import(args.to_string())),
+        "attach" if args.is_empty() => bail!("Usage: /attach <file>"),
+        "attach" => Ok(Command::Attach(args.to_string())),
+        "codesave" => Ok(Command::CodeSave(Some(args.to_string()).filter(|dir| !dir.is_empty()))),
+        "save" => {
+            let (block, path) = parse_block_and_path(args, "/save")?;
+            Ok(Command::SaveCodeBlock { block, path })
+        }
+        _ => bail!("Unknown command: /{name}"),
+    }
+}
+
+/// Optional code block number followed by an optional file path, as in `/table 2 out.csv`
+fn parse_block_and_path(args: &str, command: &str) -> Result<(Option<usize>, Option<String>)> {
+    // Leading number is the block; anything else is the file path
+    let (first, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let (block, path) = match first.parse::<usize>() {
+        Ok(0) => bail!("Usage: {command} [code block number] [file]"),
+        Ok(n) => (Some(n), rest.trim()),
+        Err(_) => (None, args),
+    };
+    Ok((block, Some(path.to_string()).filter(|p| !p.is_empty())))
+}
+
+/// Optional 1-based code block number argument of `command`
+fn parse_block_number(args: &str, command: &str) -> Result<Option<usize>> {
+    if args.is_empty() {
+        return Ok(None);
+    }
+    match args.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(Some(n)),
+        _ => bail!("Usage: {command} [code block number]"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_messages_are_not_commands() {
+        assert!(parse("hello").is_none());
+        assert!(parse("/").is_none());
+        assert!(parse("//not a command").is_none());
+    }
+
+    #[test]
+    fn test_parse_copy() {
+        assert_eq!(parse("/copy").unwrap().unwrap(), Command::Copy(None));
+        assert_eq!(parse("/copy 2").unwrap().unwrap(), Command::Copy(Some(2)));
+        assert_eq!(parse("  /copy   3  ").unwrap().unwrap(), Command::Copy(Some(3)));
+        assert!(parse("/copy 0").unwrap().is_err());
+        assert!(parse("/copy two").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_diagram() {
+        assert_eq!(parse("/diagram").unwrap().unwrap(), Command::Diagram(None));
+        assert_eq!(parse("/diagram 4").unwrap().unwrap(), Command::Diagram(Some(4)));
+        assert!(parse("/diagram x").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_table() {
+        assert_eq!(
+            parse("/table").unwrap().unwrap(),
+            Command::SaveTable { block: None, path: None }
+        );
+        assert_eq!(
+            parse("/table 2 out/data file.csv").unwrap().unwrap(),
+            Command::SaveTable { block: Some(2), path: Some("out/data file.csv".to_string()) }
+        );
+        assert_eq!(
+            parse("/table sales.tsv").unwrap().unwrap(),
+            Command::SaveTable { block: None, path: Some("sales.tsv".to_string()) }
+        );
+        assert!(parse("/table 0").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_rag_and_source() {
+        assert_eq!(parse("/rag").unwrap().unwrap(), Command::Rag(None));
+        assert_eq!(parse("/rag off").unwrap().unwrap(), Command::RagOff);
+        assert_eq!(
+            parse("/rag ~/notes dir").unwrap().unwrap(),
+            Command::Rag(Some("~/notes dir".to_string()))
+        );
+        assert_eq!(parse("/collections").unwrap().unwrap(), Command::Collections);
+        assert_eq!(parse("/theme").unwrap().unwrap(), Command::Theme(None));
+        assert_eq!(parse("/theme Gruvbox").unwrap().unwrap(), Command::Theme(Some("gruvbox".to_string())));
+        assert_eq!(parse("/model").unwrap().unwrap(), Command::Model(None));
+        assert_eq!(parse("/model fast").unwrap().unwrap(), Command::Model(Some("fast".to_string())));
+        assert_eq!(parse("/continue").unwrap().unwrap(), Command::Continue);
+        assert_eq!(parse("/source 2").unwrap().unwrap(), Command::Source(2));
+        assert!(parse("/source").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_experiment() {
+        assert_eq!(
+            parse("/experiment temperature 0.2 0.7").unwrap().unwrap(),
+            Command::Experiment {
+                parameter: "temperature".to_string(),
+                values: vec!["0.2".to_string(), "0.7".to_string()],
+            }
+        );
+        assert!(parse("/experiment temperature").unwrap().is_err());
+        assert!(parse("/experiment").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_set_and_unset() {
+        assert_eq!(
+            parse("/set num_ctx 16384").unwrap().unwrap(),
+            Command::Set { name: "num_ctx".to_string(), value: "16384".to_string() }
+        );
+        assert_eq!(
+            parse("/set system You answer in French.").unwrap().unwrap(),
+            Command::Set { name: "system".to_string(), value: "You answer in French.".to_string() }
+        );
+        assert_eq!(parse("/unset all").unwrap().unwrap(), Command::Unset("all".to_string()));
+        assert!(parse("/set temperature").unwrap().is_err());
+        assert!(parse("/unset").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_save() {
+        assert_eq!(
+            parse("/save").unwrap().unwrap(),
+            Command::SaveCodeBlock { block: None, path: None }
+        );
+        assert_eq!(
+            parse("/save 3 src/main.rs").unwrap().unwrap(),
+            Command::SaveCodeBlock {
+                block: Some(3),
+                path: Some("src/main.rs".to_string())
+            }
+        );
+        assert!(parse("/save 0").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_export() {
+        use crate::export::ExportFormat;
+        assert_eq!(
+            parse("/export").unwrap().unwrap(),
+            Command::Export { format: ExportFormat::Markdown, path: None }
+        );
+        assert_eq!(
+            parse("/export json ~/chats/today.json").unwrap().unwrap(),
+            Command::Export {
+                format: ExportFormat::Json,
+                path: Some("~/chats/today.json".to_string())
+            }
+        );
+        assert!(parse("/export pdf").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_import() {
+        assert_eq!(
+            parse("/import ~/conversations.json").unwrap().unwrap(),
+            Command::Import("~/conversations.json".to_string())
+        );
+        assert!(parse("/import").unwrap().is_err());
+        assert_eq!(
+            parse("/attach ~/diagram.png").unwrap().unwrap(),
+            Command::Attach("~/diagram.png".to_string())
+        );
+        assert!(parse("/attach").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_search() {
+        assert_eq!(
+            parse("/search borrow checker").unwrap().unwrap(),
+            Command::Search(Some("borrow checker".to_string()))
+        );
+        assert_eq!(parse("/search").unwrap().unwrap(), Command::Search(None));
+    }
+
+    #[test]
+    fn test_parse_remember() {
+        assert_eq!(
+            parse("/remember I use Neovim").unwrap().unwrap(),
+            Command::Remember("I use Neovim".to_string())
+        );
+        assert!(parse("/remember").unwrap().is_err());
+        assert_eq!(parse("/memory").unwrap().unwrap(), Command::Memory);
+        assert_eq!(parse("/math").unwrap().unwrap(), Command::Math);
+        assert_eq!(parse("/bookmarks").unwrap().unwrap(), Command::Bookmarks);
+    }
+
+    #[test]
+    fn test_parse_timer() {
+        assert_eq!(
+            parse("/timer 25m").unwrap().unwrap(),
+            Command::Timer(Some(std::time::Duration::from_mins(25)))
+        );
+        assert_eq!(parse("/timer").unwrap().unwrap(), Command::Timer(None));
+        assert_eq!(parse("/timer off").unwrap().unwrap(), Command::TimerOff);
+        assert!(parse("/timer later").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_parse_later() {
+        assert_eq!(parse("/later").unwrap().unwrap(), Command::ReadLater);
+        assert_eq!(parse("/later review").unwrap().unwrap(), Command::ReviewReadLater);
+        assert!(parse("/later soon").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_commands_allowed_in_viewer() {
+        let reads_only = |input: &str| parse(input).unwrap().unwrap().reads_only();
+        assert!(reads_only("/export md"));
+        assert!(reads_only("/copy 2"));
+        assert!(!reads_only("/continue"));
+        assert!(!reads_only("/remember I use vim"));
+        assert!(!reads_only("/model fast"));
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let err = parse("/frobnicate").unwrap().unwrap_err();
+        assert!(err.to_string().contains("/frobnicate"));
+    }
+}
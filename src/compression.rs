@@ -0,0 +1,46 @@
+// Condensing the oldest messages of a long conversation into a summary
+
+use std::fmt::Write;
+
+use crate::models::{Message, MessageRole};
+
+/// Introduces the summary where it replaces the summarized messages in a request
+pub const SUMMARY_PREFIX: &str = "Summary of the earlier part of this conversation:\n";
+
+/// Ask for a summary of `messages`, folding in the summary of what came before them
+pub fn summary_prompt(previous: Option<&str>, messages: &[Message]) -> String {
+    let mut prompt = String::from(
+        "Summarize the conversation below so it can replace it as context. Keep facts, \
+         decisions, names, code identifiers and open questions; drop pleasantries. \
+         Reply with the summary only.\n\n",
+    );
+    if let Some(previous) = previous {
+        let _ = write!(prompt, "Earlier summary:\n{previous}\n\n");
+    }
+    for message in messages {
+        let role = match message.role {
+            MessageRole::User => "User",
+            MessageRole::Assistant => "Assistant",
+        };
+        let content = crate::clipboard::strip_thinking(&message.content);
+        let _ = write!(prompt, "{role}: {}\n\n", content.trim());
+    }
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_prompt_includes_previous_summary() {
+        let messages = vec![
+            Message::new(MessageRole::User, "Use Postgres".to_string(), 1),
+            Message::new(MessageRole::Assistant, "<thinking>\nok\n</thinking>\nNoted".to_string(), 1),
+        ];
+        let prompt = summary_prompt(Some("We picked Rust."), &messages);
+        assert!(prompt.contains("Earlier summary:\nWe picked Rust.\n"));
+        assert!(prompt.contains("User: Use Postgres\n\nAssistant: Noted\n"));
+        assert!(!summary_prompt(None, &messages).contains("Earlier summary"));
+    }
+}
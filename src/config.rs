@@ -2,22 +2,97 @@
 
 use anyhow::{Context, Result};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::api::model_matches;
+use crate::keymap::Keymap;
+use crate::models::{AppConfig, ModelInfo, ThemeConfig, THEME_PRESETS};
+use crate::ui::theme::parse_color;
+use crate::templates::{self, PromptTemplate};
+use std::collections::BTreeMap;
+
+/// Config file chosen with `--config`; its directory replaces the config directory
+static CONFIG_FILE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Use `path` as the config file from now on (`--config`, or another profile picked
+/// before the TUI starts)
+pub fn use_config_file(path: PathBuf) {
+    if let Ok(mut file) = CONFIG_FILE.write() {
+        *file = Some(path);
+    }
+}
 
-use crate::models::{AppConfig, ModelInfo};
+fn config_file() -> Option<PathBuf> {
+    CONFIG_FILE.read().ok()?.clone()
+}
 
 pub fn get_config_dir() -> Result<PathBuf> {
-    let config_dir = dirs::config_dir()
-        .ok_or_else(|| anyhow::anyhow!("Could not determine config directory"))?
-        .join("yumchat");
+    let config_dir = match config_file() {
+        Some(file) => file
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf(),
+        None => dirs::config_dir()
+            .ok_or_else(|| anyhow::anyhow!("Could not determine config directory"))?
+            .join("yumchat"),
+    };
 
     fs::create_dir_all(&config_dir).context("Failed to create config directory")?;
 
     Ok(config_dir)
 }
 
+/// Environment variable overriding where conversations are stored
+pub const DATA_DIR_ENV: &str = "YUMCHAT_DATA_DIR";
+
+/// Directory holding conversations (in its `chats` subdirectory) and their search
+/// index: `YUMCHAT_DATA_DIR`, then `data_dir` from the config, then the platform data
+/// directory (`~/.local/share/yumchat` on Linux). A profile chosen with `--config`
+/// keeps them next to its config file instead, apart from other profiles.
+pub fn get_data_dir(config: &AppConfig) -> Result<PathBuf> {
+    let config_dir = get_config_dir()?;
+    let default_dir = if config_file().is_some() {
+        Some(config_dir.clone())
+    } else {
+        dirs::data_dir().map(|dir| dir.join("yumchat"))
+    };
+    let data_dir = resolve_data_dir(
+        std::env::var(DATA_DIR_ENV).ok().as_deref(),
+        config.data_dir.as_deref(),
+        &config_dir,
+        default_dir,
+    )
+    .ok_or_else(|| anyhow::anyhow!("Could not determine data directory"))?;
+
+    fs::create_dir_all(&data_dir).context("Failed to create data directory")?;
+
+    Ok(data_dir)
+}
+
+/// Conversations saved by versions that kept them under the config directory stay
+/// there until a data directory is chosen explicitly
+fn resolve_data_dir(
+    env: Option<&str>,
+    configured: Option<&str>,
+    config_dir: &Path,
+    default_dir: Option<PathBuf>,
+) -> Option<PathBuf> {
+    if let Some(dir) = env.or(configured).filter(|dir| !dir.trim().is_empty()) {
+        return Some(crate::rag::expand_home(dir.trim()));
+    }
+    if config_dir.join("chats").is_dir() {
+        return Some(config_dir.to_path_buf());
+    }
+    default_dir
+}
+
 #[allow(dead_code)]
 pub fn get_config_path() -> Result<PathBuf> {
+    if let Some(file) = config_file() {
+        return Ok(file);
+    }
     Ok(get_config_dir()?.join("config.toml"))
 }
 
@@ -36,20 +111,250 @@ pub fn load_config() -> Result<AppConfig> {
         return Ok(default_config);
     }
 
-    let contents = fs::read_to_string(&config_path).context("Failed to read config file")?;
+    read_config(&config_path)
+}
+
+/// Parse the config file, without creating it when missing
+pub fn read_config(path: &Path) -> Result<AppConfig> {
+    let contents = fs::read_to_string(path).context("Failed to read config file")?;
 
     let config: AppConfig = toml::from_str(&contents).context("Failed to parse config file")?;
 
     Ok(config)
 }
 
+/// A setting in config.toml that can't be used, so its default applies instead
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    /// 1-based line in the file, when it can be found
+    pub line: Option<usize>,
+    /// Dotted key such as `theme.border_color`; empty when the whole file is affected
+    pub key: String,
+    pub message: String,
+}
+
+impl ConfigProblem {
+    fn whole_file(message: impl Into<String>) -> Self {
+        Self { line: None, key: String::new(), message: message.into() }
+    }
+}
+
+impl std::fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {line}: ")?;
+        }
+        if !self.key.is_empty() {
+            write!(f, "{}: ", self.key)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+/// Load the config like `load_config`, but never fail: each setting that can't be
+/// used is reported and left at its default. Everything is when the file can't be
+/// read or isn't valid TOML.
+pub fn load_config_checked() -> (AppConfig, Vec<ConfigProblem>) {
+    let config_path = match get_config_path() {
+        Ok(path) if path.exists() => path,
+        Ok(_) => return (load_config().unwrap_or_default(), Vec::new()),
+        Err(e) => return (AppConfig::default(), vec![ConfigProblem::whole_file(format!("{e:#}"))]),
+    };
+    match read_config_checked(&config_path) {
+        Ok(loaded) => loaded,
+        Err(problem) => (AppConfig::default(), vec![problem]),
+    }
+}
+
+/// Parse the config file, keeping every usable setting (see `parse_config`)
+pub fn read_config_checked(path: &Path) -> Result<(AppConfig, Vec<ConfigProblem>), ConfigProblem> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| ConfigProblem::whole_file(format!("Failed to read config file: {e}")))?;
+    let parsed = parse_config(&contents);
+    match &parsed {
+        Ok((_, problems)) => problems.iter().for_each(|problem| tracing::warn!(%problem, "config.toml")),
+        Err(problem) => tracing::warn!(%problem, "config.toml not parsed"),
+    }
+    parsed
+}
+
+/// Parse config.toml contents, dropping settings of the wrong type and resetting
+/// values that can't work (a zero timeout, an unknown color) to their defaults, each
+/// reported as a problem. Fails only when the contents aren't valid TOML.
+pub fn parse_config(contents: &str) -> Result<(AppConfig, Vec<ConfigProblem>), ConfigProblem> {
+    let syntax_error = |e: &toml::de::Error, text: &str| ConfigProblem {
+        line: e.span().map(|span| text[..span.start].lines().count().max(1)),
+        key: String::new(),
+        message: e.message().trim().to_string(),
+    };
+    let mut table: toml::Table = toml::from_str(contents).map_err(|e| syntax_error(&e, contents))?;
+
+    // Serde stops at the first bad value: drop it and try again with the rest
+    let mut problems = Vec::new();
+    let mut text = contents.to_string();
+    let mut config = loop {
+        let error = match toml::from_str::<AppConfig>(&text) {
+            Ok(config) => break config,
+            Err(e) => e,
+        };
+        let key = error
+            .span()
+            .and_then(|span| keyed_lines(&text).into_iter().rev().find(|(_, start, _)| *start <= span.start))
+            .map(|(_, _, key)| key);
+        problems.push(ConfigProblem {
+            line: key.as_deref().and_then(|key| line_of(contents, key)),
+            key: key.as_deref().map(|key| key.join(".")).unwrap_or_default(),
+            message: error.message().trim().to_string(),
+        });
+        if !key.is_some_and(|key| remove_key(&mut table, &key)) {
+            break AppConfig::default();
+        }
+        text = toml::to_string(&table).map_err(|e| ConfigProblem::whole_file(e.to_string()))?;
+    };
+
+    for (key, message) in check_values(&mut config) {
+        let path: Vec<String> = key.split('.').map(ToString::to_string).collect();
+        problems.push(ConfigProblem { line: line_of(contents, &path), key, message });
+    }
+    Ok((config, problems))
+}
+
+/// Values that parse but can't work, reset to their defaults, as (key, message)
+fn check_values(config: &mut AppConfig) -> Vec<(String, String)> {
+    use crate::settings::SettingsField;
+
+    let mut problems = Vec::new();
+    let defaults = AppConfig::default();
+    for (key, field) in [
+        ("ollama_url", SettingsField::OllamaUrl),
+        ("default_model", SettingsField::DefaultModel),
+        ("request_timeout", SettingsField::RequestTimeout),
+    ] {
+        if let Err(e) = field.set(config, &field.get(config)) {
+            problems.push((key.to_string(), e.to_string()));
+            let _ = field.set(config, &field.get(&defaults));
+        }
+    }
+
+    let preset = config.theme.preset.clone();
+    let mut base = preset.as_deref().and_then(ThemeConfig::preset).unwrap_or_default();
+    if let Some(name) = preset.filter(|name| ThemeConfig::preset(name).is_none()) {
+        problems.push((
+            "theme.preset".to_string(),
+            format!("Unknown theme '{name}' (available: {})", THEME_PRESETS.join(", ")),
+        ));
+        config.theme.preset = None;
+    }
+    for ((key, color), (_, fallback)) in config.theme.colors_mut().into_iter().zip(base.colors_mut()) {
+        if parse_color(color).is_none() {
+            problems.push((
+                format!("theme.{key}"),
+                format!("Unknown color '{color}' (use a name like 'blue' or hex like '#1e90ff')"),
+            ));
+            color.clone_from(fallback);
+        }
+    }
+
+    config.aliases.retain(|alias, model| {
+        let empty = model.trim().is_empty();
+        if empty {
+            problems.push((format!("aliases.{alias}"), "No model given".to_string()));
+        }
+        !empty
+    });
+
+    if let Err(e) = Keymap::from_config(&config.keys) {
+        problems.push(("keys".to_string(), e.to_string()));
+        config.keys.clear();
+    }
+    problems
+}
+
+/// Each `key = value` line and `[table]` header in TOML text, as its line number,
+/// byte offset and full dotted key. Continuation lines of multi-line values are
+/// skipped as long as they don't look like `key =` themselves.
+fn keyed_lines(text: &str) -> Vec<(usize, usize, Vec<String>)> {
+    let split = |key: &str| -> Vec<String> {
+        key.split('.')
+            .map(|part| part.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+            .collect()
+    };
+    let mut keyed = Vec::new();
+    let mut table = Vec::new();
+    let mut start = 0;
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            table = split(trimmed.trim_matches(|c| c == '[' || c == ']'));
+            keyed.push((index + 1, start, table.clone()));
+        } else if let Some((key, _)) = trimmed.split_once('=') {
+            let key = key.trim();
+            let bare = key.chars().all(|c| c.is_ascii_alphanumeric() || "_-.\"' ".contains(c));
+            if !key.is_empty() && bare {
+                let mut full = table.clone();
+                full.extend(split(key));
+                keyed.push((index + 1, start, full));
+            }
+        }
+        start += line.len();
+    }
+    keyed
+}
+
+fn line_of(text: &str, key: &[String]) -> Option<usize> {
+    keyed_lines(text)
+        .into_iter()
+        .find(|(_, _, full)| full == key)
+        .map(|(line, _, _)| line)
+}
+
+fn remove_key(table: &mut toml::Table, key: &[String]) -> bool {
+    match key {
+        [] => false,
+        [last] => table.remove(last).is_some(),
+        [first, rest @ ..] => match table.get_mut(first) {
+            Some(toml::Value::Table(inner)) => remove_key(inner, rest),
+            _ => false,
+        },
+    }
+}
+
+/// Call `on_change` whenever the config file is written or replaced, until the
+/// returned watcher is dropped. The directory is watched, since editors often save
+/// by renaming a new file over the old one.
+pub fn watch_config(on_change: impl Fn() + Send + 'static) -> Result<notify::RecommendedWatcher> {
+    use notify::{EventKind, RecursiveMode, Watcher};
+
+    let config_path = get_config_path()?;
+    let dir = config_path
+        .parent()
+        .context("Config file has no directory")?
+        .to_path_buf();
+    let name = config_path.file_name().map(ToOwned::to_owned);
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else { return };
+        let written = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+        if written && event.paths.iter().any(|path| path.file_name() == name.as_deref()) {
+            on_change();
+        }
+    })
+    .context("Failed to watch the config file")?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .context("Failed to watch the config file")?;
+    Ok(watcher)
+}
+
 #[allow(dead_code)]
 pub fn save_config(config: &AppConfig) -> Result<()> {
     let config_path = get_config_path()?;
 
     let contents = toml::to_string_pretty(config).context("Failed to serialize config")?;
 
-    fs::write(&config_path, contents).context("Failed to write config file")?;
+    // Written aside and renamed over, so a watcher never reads it half-written
+    let temp_path = config_path.with_extension("toml.tmp");
+    fs::write(&temp_path, contents).context("Failed to write config file")?;
+    fs::rename(&temp_path, &config_path).context("Failed to write config file")?;
 
     Ok(())
 }
@@ -58,20 +363,9 @@ pub fn save_config(config: &AppConfig) -> Result<()> {
 pub fn load_models() -> Result<Vec<ModelInfo>> {
     let models_path = get_models_path()?;
 
+    // Filled in from the installed models by `reconcile_models` at startup
     if !models_path.exists() {
-        // Return default models
-        let default_models = vec![
-            ModelInfo {
-                name: "llama2".to_string(),
-                context_window_size: 4096,
-            },
-            ModelInfo {
-                name: "mistral".to_string(),
-                context_window_size: 8192,
-            },
-        ];
-        save_models(&default_models)?;
-        return Ok(default_models);
+        return Ok(Vec::new());
     }
 
     let contents = fs::read_to_string(&models_path).context("Failed to read models file")?;
@@ -93,6 +387,87 @@ pub fn save_models(models: &[ModelInfo]) -> Result<()> {
     Ok(())
 }
 
+/// Bring models.json in line with the models Ollama has, given as their names and
+/// context windows (when `/api/show` reported one): installed models are added or get
+/// their context window updated, and entries no longer installed are flagged
+/// `missing`. Returns the names that went missing since the last check.
+pub fn reconcile_models(models: &mut Vec<ModelInfo>, installed: &[(String, Option<usize>)]) -> Vec<String> {
+    let mut seen = vec![false; models.len()];
+    for (name, context_window) in installed {
+        let position = models.iter().position(|model| model_matches(name, &model.name));
+        let model = if let Some(index) = position {
+            seen[index] = true;
+            &mut models[index]
+        } else {
+            models.push(ModelInfo {
+                name: name.clone(),
+                context_window_size: 0,
+                show_thinking: None,
+                missing: false,
+            });
+            seen.push(true);
+            models.last_mut().expect("just pushed")
+        };
+        model.missing = false;
+        if let Some(size) = context_window {
+            model.context_window_size = *size;
+        }
+    }
+
+    let mut gone = Vec::new();
+    for (model, seen) in models.iter_mut().zip(seen) {
+        if !seen && !model.missing {
+            model.missing = true;
+            gone.push(model.name.clone());
+        }
+    }
+    gone
+}
+
+/// Retrieval collections and their indexes live here, in the data directory
+pub fn get_collections_dir(config: &AppConfig) -> Result<PathBuf> {
+    let dir = get_data_dir(config)?.join("collections");
+    let old_dir = get_config_dir()?.join("collections");
+    if let Err(e) = migrate_collections(&old_dir, &dir) {
+        tracing::warn!(error = %e, "collections stay in the config directory");
+        return Ok(old_dir);
+    }
+    Ok(dir)
+}
+
+/// Collections saved by versions that kept them under the config directory move to
+/// the data directory once (their index paths are relative, so they keep working)
+fn migrate_collections(old_dir: &Path, dir: &Path) -> Result<()> {
+    if old_dir == dir || !old_dir.is_dir() || dir.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent).context("Failed to create data directory")?;
+    }
+    fs::rename(old_dir, dir).context("Failed to move collections to the data directory")
+}
+
+pub fn get_templates_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("templates.toml"))
+}
+
+/// Load prompt templates, writing a starter set on first run
+pub fn load_templates() -> Result<BTreeMap<String, PromptTemplate>> {
+    let templates_path = get_templates_path()?;
+
+    if !templates_path.exists() {
+        let default_templates = templates::default_templates();
+        let contents = toml::to_string_pretty(&default_templates)
+            .context("Failed to serialize templates")?;
+        fs::write(&templates_path, contents).context("Failed to write templates file")?;
+        return Ok(default_templates);
+    }
+
+    let contents = fs::read_to_string(&templates_path).context("Failed to read templates file")?;
+
+    toml::from_str(&contents).context("Failed to parse templates file")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -163,7 +538,92 @@ mod tests {
     }
 
     #[test]
-    fn test_load_models_creates_default() {
+    fn test_parse_config_reports_problems() {
+        let contents = r##"
+ollama_url = "http://gpu:11434"
+request_timeout = 0
+low_power = "sometimes"
+
+[theme]
+preset = "gruvbox"
+border_color = "blurple"
+user_message_color = "#ff0000"
+
+[keys]
+quit = "hyper+q"
+"##;
+        let (config, problems) = parse_config(contents).unwrap();
+        let found: Vec<(Option<usize>, &str)> =
+            problems.iter().map(|problem| (problem.line, problem.key.as_str())).collect();
+        assert_eq!(
+            found,
+            [
+                (Some(4), "low_power"),
+                (Some(3), "request_timeout"),
+                (Some(8), "theme.border_color"),
+                (Some(11), "keys"),
+            ]
+        );
+        assert!(problems[2].to_string().starts_with("line 8: theme.border_color: Unknown color 'blurple'"));
+
+        // Everything else is kept, and the bad values fall back to defaults
+        assert_eq!(config.ollama_url, "http://gpu:11434");
+        assert_eq!(config.request_timeout, AppConfig::default().request_timeout);
+        assert_eq!(config.low_power, AppConfig::default().low_power);
+        assert_eq!(config.theme.border_color, ThemeConfig::preset("gruvbox").unwrap().border_color);
+        assert_eq!(config.theme.user_message_color, "#ff0000");
+        assert!(config.keys.is_empty());
+
+        let (config, problems) = parse_config("[theme]\npreset = \"neon\"").unwrap();
+        assert_eq!(problems[0].key, "theme.preset");
+        assert_eq!(config.theme, ThemeConfig::default());
+
+        let syntax = parse_config("default_model = \"qwen3:4b\"\nrequest_timeout = = 5").unwrap_err();
+        assert_eq!(syntax.line, Some(2));
+        assert!(parse_config("").unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn test_migrate_collections() {
+        let temp_dir = setup_test_env();
+        let old_dir = temp_dir.path().join("config/collections");
+        let dir = temp_dir.path().join("share/yumchat/collections");
+        fs::create_dir_all(&old_dir).unwrap();
+        fs::write(old_dir.join("collections.json"), "{}").unwrap();
+
+        migrate_collections(&old_dir, &dir).unwrap();
+        assert!(!old_dir.exists());
+        assert_eq!(fs::read_to_string(dir.join("collections.json")).unwrap(), "{}");
+
+        // Once moved, a folder left behind in the config directory is not merged in
+        fs::create_dir_all(&old_dir).unwrap();
+        migrate_collections(&old_dir, &dir).unwrap();
+        assert!(old_dir.exists());
+        // A profile keeping its data next to its config has nothing to move
+        migrate_collections(&dir, &dir).unwrap();
+        assert!(dir.exists());
+    }
+
+    #[test]
+    fn test_resolve_data_dir() {
+        let temp_dir = setup_test_env();
+        let config_dir = temp_dir.path().join("config");
+        let platform = Some(temp_dir.path().join("share/yumchat"));
+        let resolve = |env, configured| resolve_data_dir(env, configured, &config_dir, platform.clone()).unwrap();
+
+        assert_eq!(resolve(None, None), temp_dir.path().join("share/yumchat"));
+        assert_eq!(resolve(None, Some("/sync/chats")), PathBuf::from("/sync/chats"));
+        assert_eq!(resolve(Some("/env"), Some("/sync/chats")), PathBuf::from("/env"));
+        assert_eq!(resolve(Some(""), None), temp_dir.path().join("share/yumchat"));
+
+        // Existing chats under the config directory are kept there
+        fs::create_dir_all(config_dir.join("chats")).unwrap();
+        assert_eq!(resolve(None, None), config_dir);
+        assert_eq!(resolve(None, Some("/sync/chats")), PathBuf::from("/sync/chats"));
+    }
+
+    #[test]
+    fn test_load_models_empty_without_file() {
         let _lock = ENV_MUTEX.lock().unwrap();
         let temp_dir = setup_test_env();
         
@@ -187,8 +647,8 @@ mod tests {
             models.as_ref().err(),
             temp_dir.path()
         );
-        let models = models.unwrap();
-        assert!(!models.is_empty());
+        // Nothing made up: the list is filled in from Ollama
+        assert!(models.unwrap().is_empty());
     }
 
     #[test]
@@ -200,6 +660,8 @@ mod tests {
         let models = vec![ModelInfo {
             name: "test-model".to_string(),
             context_window_size: 16384,
+            show_thinking: Some(true),
+            missing: false,
         }];
 
         let models_path = config_dir.join("models.json");
@@ -212,6 +674,7 @@ mod tests {
         assert_eq!(loaded_models.len(), 1);
         assert_eq!(loaded_models[0].name, "test-model");
         assert_eq!(loaded_models[0].context_window_size, 16384);
+        assert_eq!(loaded_models[0].show_thinking, Some(true));
     }
 
     #[test]
@@ -224,11 +687,64 @@ mod tests {
         assert!(deserialized.is_ok());
     }
 
+    #[test]
+    fn test_config_without_optional_sections() {
+        let contents = r#"
+ollama_url = "http://localhost:11434"
+default_model = "qwen3:4b"
+
+[theme]
+user_message_color = "blue"
+assistant_message_color = "green"
+border_color = "cyan"
+"#;
+        let config: AppConfig = toml::from_str(contents).unwrap();
+        assert_eq!(config.request_timeout, 600);
+        assert!(config.clipboard.strip_thinking);
+    }
+
+    #[test]
+    fn test_reconcile_models() {
+        let model = |name: &str, size, missing| ModelInfo {
+            name: name.to_string(),
+            context_window_size: size,
+            show_thinking: None,
+            missing,
+        };
+        let mut models = vec![
+            ModelInfo { show_thinking: Some(true), ..model("llama3", 4096, false) },
+            model("llama2", 4096, false),
+            model("mistral", 8192, true),
+        ];
+        let installed = [
+            ("llama3:latest".to_string(), Some(8192)),
+            ("qwen3:4b".to_string(), Some(40960)),
+            ("mistral:latest".to_string(), None),
+        ];
+        let gone = reconcile_models(&mut models, &installed);
+
+        assert_eq!(gone, ["llama2"]);
+        assert_eq!(
+            models,
+            [
+                // Hand-set options stay with the model
+                ModelInfo { show_thinking: Some(true), ..model("llama3", 8192, false) },
+                model("llama2", 4096, true),
+                model("mistral", 8192, false),
+                model("qwen3:4b", 40960, false),
+            ]
+        );
+        // Already flagged models aren't reported again
+        assert!(reconcile_models(&mut models, &installed).is_empty());
+    }
+
     #[test]
     fn test_models_serialization() {
         let models = vec![ModelInfo {
             name: "model1".to_string(),
             context_window_size: 2048,
+            show_thinking: None,
+            missing: false,
         }];
 
         let serialized = serde_json::to_string(&models);
@@ -237,4 +753,11 @@ mod tests {
         let deserialized: Result<Vec<ModelInfo>, _> = serde_json::from_str(&serialized.unwrap());
         assert!(deserialized.is_ok());
     }
+
+    #[test]
+    fn test_models_without_thinking_override() {
+        let models: Vec<ModelInfo> =
+            serde_json::from_str(r#"[{"name":"m","context_window_size":4096}]"#).unwrap();
+        assert!(models[0].show_thinking.is_none());
+    }
 }
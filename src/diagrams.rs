@@ -0,0 +1,170 @@
+// Export mermaid / graphviz code blocks to images via external tools
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::models::DiagramConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagramKind {
+    Mermaid,
+    Graphviz,
+}
+
+impl DiagramKind {
+    /// Diagram type for a fence language, if it is one we can export
+    pub fn from_language(language: &str) -> Option<Self> {
+        match language.to_ascii_lowercase().as_str() {
+            "mermaid" | "mmd" => Some(Self::Mermaid),
+            "dot" | "graphviz" | "gv" => Some(Self::Graphviz),
+            _ => None,
+        }
+    }
+
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Mermaid => "mmd",
+            Self::Graphviz => "dot",
+        }
+    }
+
+    fn command(self, config: &DiagramConfig) -> &str {
+        match self {
+            Self::Mermaid => &config.mermaid_command,
+            Self::Graphviz => &config.dot_command,
+        }
+    }
+}
+
+/// Split a command template into arguments and substitute placeholders.
+/// Substitution happens per argument, so paths containing spaces stay intact.
+fn expand(template: &str, vars: &[(&str, &str)]) -> Vec<String> {
+    template
+        .split_whitespace()
+        .map(|arg| {
+            vars.iter().fold(arg.to_string(), |arg, (name, value)| {
+                arg.replace(&format!("{{{name}}}"), value)
+            })
+        })
+        .collect()
+}
+
+fn run(args: &[String]) -> Result<()> {
+    let (program, args) = args.split_first().context("Empty diagram command")?;
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("Failed to run '{program}' (is it installed?)"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("'{program}' failed: {}", stderr.trim());
+    }
+    Ok(())
+}
+
+/// Write `source` to `dir/name.<ext>` and render it with the configured tool.
+/// Returns the path of the rendered image.
+pub fn export(
+    config: &DiagramConfig,
+    kind: DiagramKind,
+    source: &str,
+    dir: &Path,
+    name: &str,
+) -> Result<PathBuf> {
+    fs::create_dir_all(dir).context("Failed to create diagrams directory")?;
+    let input = dir.join(format!("{name}.{}", kind.extension()));
+    let output = dir.join(format!("{name}.{}", config.format));
+    fs::write(&input, source).context("Failed to write diagram source")?;
+
+    let input_str = input.to_string_lossy();
+    let output_str = output.to_string_lossy();
+    run(&expand(
+        kind.command(config),
+        &[
+            ("input", &input_str),
+            ("output", &output_str),
+            ("format", &config.format),
+        ],
+    ))?;
+
+    if !output.exists() {
+        bail!("Diagram tool did not produce {}", output.display());
+    }
+    Ok(output)
+}
+
+/// Open an exported file in the configured viewer without waiting for it
+pub fn open(config: &DiagramConfig, path: &Path) -> Result<()> {
+    let path_str = path.to_string_lossy();
+    let template = config.open_command.as_deref().unwrap_or(if cfg!(target_os = "macos") {
+        "open {output}"
+    } else if cfg!(windows) {
+        "cmd /C start {output}"
+    } else {
+        "xdg-open {output}"
+    });
+    let args = expand(template, &[("output", &path_str)]);
+    let (program, args) = args.split_first().context("Empty open command")?;
+
+    Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run '{program}'"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_from_language() {
+        assert_eq!(DiagramKind::from_language("Mermaid"), Some(DiagramKind::Mermaid));
+        assert_eq!(DiagramKind::from_language("dot"), Some(DiagramKind::Graphviz));
+        assert_eq!(DiagramKind::from_language("rust"), None);
+    }
+
+    #[test]
+    fn test_expand_keeps_paths_whole() {
+        let args = expand(
+            "dot -T{format} {input} -o {output}",
+            &[("input", "/tmp/my dir/a.dot"), ("output", "/tmp/my dir/a.svg"), ("format", "svg")],
+        );
+        assert_eq!(args, vec!["dot", "-Tsvg", "/tmp/my dir/a.dot", "-o", "/tmp/my dir/a.svg"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[allow(clippy::literal_string_with_formatting_args)]
+    fn test_export_with_configured_tool() {
+        let temp_dir = TempDir::new().unwrap();
+        // `cp` stands in for a renderer: the "image" is a copy of the source
+        let config = DiagramConfig {
+            dot_command: "cp {input} {output}".to_string(),
+            ..Default::default()
+        };
+
+        let output = export(&config, DiagramKind::Graphviz, "digraph { a -> b }", temp_dir.path(), "d1").unwrap();
+        assert_eq!(output, temp_dir.path().join("d1.svg"));
+        assert_eq!(fs::read_to_string(output).unwrap(), "digraph { a -> b }");
+    }
+
+    #[test]
+    fn test_export_reports_missing_tool() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = DiagramConfig {
+            mermaid_command: "yumchat-no-such-renderer {input} {output}".to_string(),
+            ..Default::default()
+        };
+
+        let err = export(&config, DiagramKind::Mermaid, "graph TD", temp_dir.path(), "d2").unwrap_err();
+        assert!(err.to_string().contains("yumchat-no-such-renderer"));
+    }
+}
@@ -13,4 +13,56 @@ pub enum AppEvent {
     ModelsLoaded(Vec<String>),
     /// Model info loaded
     ModelInfoLoaded(Box<crate::api::ShowResponse>),
+    /// Models installed on the server with their context windows, to reconcile
+    /// models.json with
+    ModelsInstalled(Vec<(String, Option<usize>)>),
+    /// Outcome of a background task to show in the bottom bar
+    Notify(String),
+    /// config.toml was written, by us or an editor
+    ConfigChanged,
+    /// A retrieval index finished building
+    RagIndexReady(std::sync::Arc<crate::rag::Index>),
+    /// Progress of a collection being (re-)indexed: (name, embedded chunks, total chunks)
+    IndexProgress(String, usize, usize),
+    /// A named collection finished (re-)indexing
+    CollectionIndexed(String, std::sync::Arc<crate::rag::Index>),
+    /// (Re-)indexing a collection failed: (name, error)
+    CollectionIndexFailed(String, String),
+    /// Output of an experiment run: (run index, text)
+    ExperimentChunk(usize, String),
+    /// An experiment run ended: (run index, error if it failed)
+    ExperimentRunDone(usize, Option<String>),
+    /// The model to use is not installed on the server
+    ModelMissing(String),
+    /// Status of a model download: (model, status line)
+    PullProgress(String, String),
+    /// A model finished downloading
+    ModelPulled(String),
+    /// Downloading a model failed: (model, error)
+    PullFailed(String, String),
+    /// Older messages were summarized: (summary request id, messages covered, summary)
+    ContextSummarized(uuid::Uuid, usize, String),
+    /// Reply of a memory extraction prompt, holding "- fact" lines
+    MemoryExtracted(String),
+    /// A conversation was classified: (conversation id, tag)
+    ConversationTagged(uuid::Uuid, String),
+    /// Sources retrieved for the response being generated
+    CitationsFound(Vec<crate::models::Citation>),
+}
+
+impl AppEvent {
+    /// One line for the debug overlay; indexes are summarized rather than dumped
+    pub fn describe(&self) -> String {
+        match self {
+            Self::RagIndexReady(index) => format!(
+                "RagIndexReady({} chunks, {})",
+                index.chunks.len(),
+                index.embedding_model
+            ),
+            Self::CollectionIndexed(name, index) => {
+                format!("CollectionIndexed({name:?}, {} chunks)", index.chunks.len())
+            }
+            event => format!("{event:?}"),
+        }
+    }
 }
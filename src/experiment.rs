@@ -0,0 +1,170 @@
+// Re-running the last prompt across parameter values to compare outputs side by side
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::api::ChatMessage;
+use crate::models::GenerationOptions;
+
+/// Columns that still fit side by side
+pub const MAX_RUNS: usize = 4;
+
+#[derive(Debug, Clone, Default)]
+pub struct ExperimentRun {
+    /// The parameter value as typed, e.g. "0.7"
+    pub value: String,
+    pub options: GenerationOptions,
+    pub output: String,
+    pub done: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Experiment {
+    pub parameter: String,
+    pub model: String,
+    /// Conversation up to and including the prompt being re-run
+    pub history: Vec<ChatMessage>,
+    pub runs: Vec<ExperimentRun>,
+    pub selected: usize,
+    pub scroll: u16,
+    pub task: Option<JoinHandle<()>>,
+}
+
+/// A kept experiment result, appended to `experiments.jsonl`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExperimentRecord {
+    pub timestamp: DateTime<Utc>,
+    pub model: String,
+    pub prompt: String,
+    pub parameter: String,
+    pub values: Vec<String>,
+    pub kept: String,
+}
+
+impl Experiment {
+    /// One run per value, each applied on top of `base`. Values are validated
+    /// up front so a typo doesn't waste the other runs.
+    pub fn new(
+        parameter: &str,
+        values: &[String],
+        base: &GenerationOptions,
+        model: String,
+        history: Vec<ChatMessage>,
+    ) -> Result<Self> {
+        if !(2..=MAX_RUNS).contains(&values.len()) {
+            bail!("Give 2 to {MAX_RUNS} values to compare");
+        }
+        let runs = values
+            .iter()
+            .map(|value| {
+                let mut options = base.clone();
+                options.set(parameter, value)?;
+                Ok(ExperimentRun {
+                    value: value.clone(),
+                    options,
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            parameter: parameter.to_string(),
+            model,
+            history,
+            runs,
+            selected: 0,
+            scroll: 0,
+            task: None,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.runs.iter().all(|run| run.done)
+    }
+
+    pub const fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % self.runs.len();
+    }
+
+    pub const fn select_previous(&mut self) {
+        self.selected = (self.selected + self.runs.len() - 1) % self.runs.len();
+    }
+
+    /// The selected run's output, if it finished successfully
+    pub fn selected_output(&self) -> Option<&ExperimentRun> {
+        self.runs
+            .get(self.selected)
+            .filter(|run| run.done && run.error.is_none())
+    }
+
+    pub fn record(&self) -> Option<ExperimentRecord> {
+        let kept = self.selected_output()?;
+        Some(ExperimentRecord {
+            timestamp: Utc::now(),
+            model: self.model.clone(),
+            prompt: self.history.last().map(|m| m.content.clone()).unwrap_or_default(),
+            parameter: self.parameter.clone(),
+            values: self.runs.iter().map(|run| run.value.clone()).collect(),
+            kept: kept.value.clone(),
+        })
+    }
+}
+
+impl Drop for Experiment {
+    fn drop(&mut self) {
+        // Closing the panel stops any runs still in progress
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(values: &[&str]) -> Vec<String> {
+        values.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_new_applies_each_value() {
+        let base = GenerationOptions {
+            top_p: Some(0.9),
+            ..Default::default()
+        };
+        let history = vec![ChatMessage::new("user", "Write a haiku")];
+        let mut experiment = Experiment::new(
+            "temperature",
+            &values(&["0.2", "1.0"]),
+            &base,
+            "qwen3:4b".to_string(),
+            history,
+        )
+        .unwrap();
+        assert_eq!(experiment.runs[1].options.temperature, Some(1.0));
+        assert_eq!(experiment.runs[1].options.top_p, Some(0.9));
+        assert!(experiment.record().is_none());
+
+        experiment.select_previous();
+        experiment.runs[1].done = true;
+        let record = experiment.record().unwrap();
+        assert_eq!(record.kept, "1.0");
+        assert_eq!(record.prompt, "Write a haiku");
+        assert!(!experiment.is_finished());
+    }
+
+    #[test]
+    fn test_new_rejects_bad_grids() {
+        let base = GenerationOptions::default();
+        let new = |parameter: &str, grid: &[&str]| {
+            Experiment::new(parameter, &values(grid), &base, String::new(), Vec::new())
+        };
+        assert!(new("temperature", &["0.2"]).is_err());
+        assert!(new("temperature", &["0.1", "0.2", "0.3", "0.4", "0.5"]).is_err());
+        assert!(new("temperature", &["0.2", "hot"]).is_err());
+        assert!(new("warmth", &["1", "2"]).is_err());
+    }
+}
@@ -0,0 +1,663 @@
+// Exporting conversations to files outside the chats directory
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::models::{Citation, ConversationMetadata, Message, MessageRole};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Title, date and `## User`/`## Assistant` sections, thinking removed
+    #[default]
+    Markdown,
+    /// Structured data (roles, content, thinking, tokens, model) for scripts and re-import
+    Json,
+    /// A standalone page with rendered markdown and highlighted code
+    Html,
+    /// A note in an Obsidian vault: tags in the frontmatter, attachments as wiki-links
+    /// and a daily index note linking the day's conversations
+    Obsidian,
+}
+
+impl ExportFormat {
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown | Self::Obsidian => "md",
+            Self::Json => "json",
+            Self::Html => "html",
+        }
+    }
+
+    /// The format named in `/export <format>`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "markdown" | "md" => Some(Self::Markdown),
+            "json" => Some(Self::Json),
+            "html" => Some(Self::Html),
+            "obsidian" => Some(Self::Obsidian),
+            _ => None,
+        }
+    }
+}
+
+/// What markdown exports contain (`[export.markdown]`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarkdownTemplate {
+    /// Keep the model's thinking, as a quote above the answer
+    pub include_thinking: bool,
+    /// Creation and last update times under the title
+    pub timestamps: bool,
+    /// A fenced `yaml` block with id, model, persona and token count under the title
+    pub metadata: bool,
+    /// Heading above each message; `{role}`, `{n}` (1-based) and `{tokens}` are filled in
+    pub heading: String,
+}
+
+impl Default for MarkdownTemplate {
+    fn default() -> Self {
+        Self {
+            include_thinking: false,
+            timestamps: true,
+            metadata: false,
+            heading: "## {role}".to_string(),
+        }
+    }
+}
+
+/// Layout of a JSON export; `version` changes when fields are removed or change meaning
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedConversation {
+    pub version: u32,
+    pub id: Uuid,
+    pub title: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub model: Option<String>,
+    pub persona: Option<String>,
+    pub total_tokens: usize,
+    pub messages: Vec<ExportedMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedMessage {
+    pub role: MessageRole,
+    /// The response with `<thinking>` blocks removed
+    pub content: String,
+    /// The model's reasoning, when it showed any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<String>,
+    pub tokens: usize,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<Citation>,
+}
+
+const JSON_EXPORT_VERSION: u32 = 1;
+
+/// Folder of the vault holding exported notes and their attachments
+pub const OBSIDIAN_FOLDER: &str = "yumchat";
+/// Subfolder of `OBSIDIAN_FOLDER` with one index note per day
+const OBSIDIAN_DAILY_FOLDER: &str = "Daily";
+
+/// Frontmatter of an exported Obsidian note; read back to build the daily index
+#[derive(Debug, Serialize, Deserialize)]
+struct ObsidianNote {
+    title: String,
+    created: DateTime<Utc>,
+    updated: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    persona: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    yumchat_id: Uuid,
+}
+
+/// The conversation's title: its summary, or "Conversation" before one exists
+fn title(metadata: &ConversationMetadata) -> &str {
+    metadata.summary.as_deref().unwrap_or("Conversation")
+}
+
+pub fn render(
+    format: ExportFormat,
+    template: &MarkdownTemplate,
+    metadata: &ConversationMetadata,
+    messages: &[Message],
+) -> String {
+    match format {
+        ExportFormat::Markdown => render_markdown(template, metadata, messages),
+        ExportFormat::Json => render_json(metadata, messages),
+        ExportFormat::Html => render_html(metadata, messages),
+        ExportFormat::Obsidian => render_obsidian(template, metadata, messages),
+    }
+}
+
+fn render_obsidian(template: &MarkdownTemplate, metadata: &ConversationMetadata, messages: &[Message]) -> String {
+    let note = ObsidianNote {
+        title: title(metadata).to_string(),
+        created: metadata.created_at,
+        updated: metadata.updated_at,
+        model: metadata.model.clone(),
+        persona: metadata.persona.clone(),
+        tags: std::iter::once(OBSIDIAN_FOLDER)
+            .chain(metadata.tag.as_deref())
+            .map(obsidian_tag)
+            .collect(),
+        yumchat_id: metadata.id,
+    };
+    // Dates are in the frontmatter already
+    let template = MarkdownTemplate {
+        timestamps: false,
+        metadata: false,
+        ..template.clone()
+    };
+    let body: Vec<String> = render_markdown(&template, metadata, messages)
+        .lines()
+        .map(|line| wiki_link(line).unwrap_or_else(|| line.to_string()))
+        .collect();
+    format!(
+        "---\n{}---\n\n{}\n",
+        serde_yaml::to_string(&note).unwrap_or_default(),
+        body.join("\n").trim_end()
+    )
+}
+
+/// Tags can't contain spaces or most punctuation
+fn obsidian_tag(tag: &str) -> String {
+    tag.trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '/') { c.to_ascii_lowercase() } else { '-' })
+        .collect()
+}
+
+/// An attachment link as a wiki-link (an embed, for images) to its copy in the vault
+fn wiki_link(line: &str) -> Option<String> {
+    let (name, target) = crate::storage::attachment_link(line)?;
+    let embed = if line.trim_start().starts_with('!') { "!" } else { "" };
+    Some(format!("{embed}[[{OBSIDIAN_FOLDER}/{target}|{}]]", wiki_text(name)))
+}
+
+/// Text safe inside `[[target|text]]`
+fn wiki_text(text: &str) -> String {
+    text.replace(['|', '[', ']'], " ")
+}
+
+/// Styles for the HTML export; code blocks carry their own inline colors
+const HTML_STYLE: &str = "
+body { font-family: -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; line-height: 1.5;
+       max-width: 50rem; margin: 2rem auto; padding: 0 1rem; color: #1f2328; }
+header { border-bottom: 1px solid #d0d7de; margin-bottom: 1.5rem; }
+.date { color: #656d76; }
+.message { margin-bottom: 1.5rem; }
+.message h2 { font-size: 0.85rem; text-transform: uppercase; letter-spacing: 0.05em; margin: 0 0 0.25rem; }
+.user h2 { color: #0969da; }
+.assistant h2 { color: #8250df; }
+.user { border-left: 3px solid #0969da; padding-left: 1rem; }
+pre { padding: 0.75rem; overflow-x: auto; border-radius: 6px; border: 1px solid #d0d7de; }
+code { font-family: ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; font-size: 0.9em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #d0d7de; padding: 0.25rem 0.75rem; }
+details { color: #656d76; margin-bottom: 0.5rem; }
+";
+
+fn render_html(metadata: &ConversationMetadata, messages: &[Message]) -> String {
+    let highlighter = Highlighter::new();
+    let title = escape_html(title(metadata));
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>{HTML_STYLE}</style>\n</head>\n<body>\n<header>\n<h1>{title}</h1>\n\
+         <p class=\"date\">{}</p>\n</header>\n",
+        metadata.created_at.format("%Y-%m-%d %H:%M UTC")
+    );
+    for message in messages {
+        let (class, role) = match message.role {
+            MessageRole::User => ("user", "User"),
+            MessageRole::Assistant => ("assistant", "Assistant"),
+        };
+        let _ = write!(out, "<section class=\"message {class}\">\n<h2>{role}</h2>\n");
+        if let Some(thinking) = thinking(&message.content) {
+            let _ = write!(
+                out,
+                "<details><summary>Thinking</summary>\n{}</details>\n",
+                markdown_to_html(&thinking, &highlighter)
+            );
+        }
+        let content = crate::clipboard::strip_thinking(&message.content);
+        out.push_str(&markdown_to_html(content.trim(), &highlighter));
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Markdown to HTML with tables and highlighted code blocks. Raw HTML in messages
+/// is shown as text, so a shared export can't run scripts.
+fn markdown_to_html(markdown: &str, highlighter: &Highlighter) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut events = Vec::new();
+    // Language and text of the code block being collected
+    let mut code_block: Option<(String, String)> = None;
+    for event in Parser::new_ext(markdown, options) {
+        if let Some((language, code)) = code_block.as_mut() {
+            match event {
+                Event::Text(text) => code.push_str(&text),
+                Event::End(TagEnd::CodeBlock) => {
+                    events.push(Event::Html(highlighter.highlight(language, code).into()));
+                    code_block = None;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or_default().to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code_block = Some((language, String::new()));
+            }
+            Event::Html(html) | Event::InlineHtml(html) => events.push(Event::Text(html)),
+            event => events.push(event),
+        }
+    }
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+    html
+}
+
+struct Highlighter {
+    syntaxes: syntect::parsing::SyntaxSet,
+    theme: syntect::highlighting::Theme,
+}
+
+impl Highlighter {
+    fn new() -> Self {
+        let mut themes = syntect::highlighting::ThemeSet::load_defaults();
+        Self {
+            syntaxes: syntect::parsing::SyntaxSet::load_defaults_newlines(),
+            theme: themes.themes.remove("InspiredGitHub").unwrap_or_default(),
+        }
+    }
+
+    /// A `<pre>` block with inline colors; unknown languages are left plain
+    fn highlight(&self, language: &str, code: &str) -> String {
+        let syntax = self
+            .syntaxes
+            .find_syntax_by_token(language)
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+        syntect::html::highlighted_html_for_string(code, &self.syntaxes, syntax, &self.theme)
+            .unwrap_or_else(|_| format!("<pre><code>{}</code></pre>\n", escape_html(code)))
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn render_json(metadata: &ConversationMetadata, messages: &[Message]) -> String {
+    let exported = ExportedConversation {
+        version: JSON_EXPORT_VERSION,
+        id: metadata.id,
+        title: metadata.summary.clone(),
+        created_at: metadata.created_at,
+        updated_at: metadata.updated_at,
+        model: metadata.model.clone(),
+        persona: metadata.persona.clone(),
+        total_tokens: metadata.total_tokens,
+        messages: messages
+            .iter()
+            .map(|message| ExportedMessage {
+                role: message.role.clone(),
+                content: crate::clipboard::strip_thinking(&message.content).trim().to_string(),
+                thinking: thinking(&message.content),
+                tokens: message.tokens,
+                pinned: message.pinned,
+                citations: message.citations.clone(),
+            })
+            .collect(),
+    };
+    let mut json = serde_json::to_string_pretty(&exported).unwrap_or_default();
+    json.push('\n');
+    json
+}
+
+/// The text of a response's `<thinking>` blocks, joined
+fn thinking(content: &str) -> Option<String> {
+    let mut blocks = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("<thinking>") {
+        rest = &rest[start + "<thinking>".len()..];
+        let end = rest.find("</thinking>").unwrap_or(rest.len());
+        blocks.push(rest[..end].trim());
+        rest = &rest[end..];
+    }
+    let text = blocks.join("\n\n");
+    (!text.is_empty()).then_some(text)
+}
+
+fn render_markdown(template: &MarkdownTemplate, metadata: &ConversationMetadata, messages: &[Message]) -> String {
+    const DATE_FORMAT: &str = "%Y-%m-%d %H:%M UTC";
+    let mut out = format!("# {}\n\n", title(metadata));
+    if template.timestamps {
+        let created = metadata.created_at.format(DATE_FORMAT).to_string();
+        let updated = metadata.updated_at.format(DATE_FORMAT).to_string();
+        if created == updated {
+            let _ = write!(out, "*{created}*\n\n");
+        } else {
+            let _ = write!(out, "*{created}, updated {updated}*\n\n");
+        }
+    }
+    if template.metadata {
+        let _ = writeln!(out, "```yaml\nid: {}", metadata.id);
+        if let Some(model) = &metadata.model {
+            let _ = writeln!(out, "model: {model}");
+        }
+        if let Some(persona) = &metadata.persona {
+            let _ = writeln!(out, "persona: {persona}");
+        }
+        let _ = write!(out, "tokens: {}\n```\n\n", metadata.total_tokens);
+    }
+    for (i, message) in messages.iter().enumerate() {
+        let role = match message.role {
+            MessageRole::User => "User",
+            MessageRole::Assistant => "Assistant",
+        };
+        #[allow(clippy::literal_string_with_formatting_args)]
+        let heading = template
+            .heading
+            .replace("{role}", role)
+            .replace("{n}", &(i + 1).to_string())
+            .replace("{tokens}", &message.tokens.to_string());
+        out.push_str(&heading);
+        out.push_str("\n\n");
+        if let Some(thinking) = thinking(&message.content).filter(|_| template.include_thinking) {
+            for line in thinking.lines() {
+                out.push_str(format!("> {line}").trim_end());
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+        let content = crate::clipboard::strip_thinking(&message.content);
+        out.push_str(content.trim());
+        out.push_str("\n\n");
+    }
+    out
+}
+
+/// Stable file name for a conversation: `2026-01-31-fixing-the-parser-1a2b3c4d.md`.
+/// Exporting again overwrites the previous export.
+pub fn file_name(metadata: &ConversationMetadata, format: ExportFormat) -> String {
+    let slug = title(metadata)
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .take(8)
+        .collect::<Vec<_>>()
+        .join("-");
+    let id = metadata.id.simple().to_string();
+    format!(
+        "{}-{}-{}.{}",
+        metadata.created_at.format("%Y-%m-%d"),
+        slug,
+        &id[..8],
+        format.extension()
+    )
+}
+
+/// Write the conversation to `path`, or into it under its usual file name when it is
+/// a directory. Returns the file written.
+pub fn export_to_path(
+    path: &Path,
+    format: ExportFormat,
+    template: &MarkdownTemplate,
+    metadata: &ConversationMetadata,
+    messages: &[Message],
+) -> Result<PathBuf> {
+    if format == ExportFormat::Obsidian {
+        return export_to_vault(path, template, metadata, messages);
+    }
+    if path.is_dir() {
+        return export_to_dir(path, format, template, metadata, messages);
+    }
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    crate::storage::write_atomic(path, &render(format, template, metadata, messages))?;
+    Ok(path.to_path_buf())
+}
+
+/// Write the conversation into `dir` (created if needed) and return the file path
+pub fn export_to_dir(
+    dir: &Path,
+    format: ExportFormat,
+    template: &MarkdownTemplate,
+    metadata: &ConversationMetadata,
+    messages: &[Message],
+) -> Result<PathBuf> {
+    if format == ExportFormat::Obsidian {
+        return export_to_vault(dir, template, metadata, messages);
+    }
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(file_name(metadata, format));
+    crate::storage::write_atomic(&path, &render(format, template, metadata, messages))?;
+    Ok(path)
+}
+
+/// Write the conversation as a note into the `yumchat` folder of the vault at `vault`
+/// and rewrite the index note of the day it started. Returns the note written.
+pub fn export_to_vault(
+    vault: &Path,
+    template: &MarkdownTemplate,
+    metadata: &ConversationMetadata,
+    messages: &[Message],
+) -> Result<PathBuf> {
+    let folder = vault.join(OBSIDIAN_FOLDER);
+    fs::create_dir_all(&folder).with_context(|| format!("Failed to create {}", folder.display()))?;
+    let path = folder.join(file_name(metadata, ExportFormat::Obsidian));
+    crate::storage::write_atomic(&path, &render_obsidian(template, metadata, messages))?;
+    write_daily_index(&folder, metadata.created_at.date_naive())?;
+    Ok(path)
+}
+
+/// Index note linking every exported conversation started on `day`, from the notes'
+/// frontmatter (so notes deleted in the vault drop out)
+fn write_daily_index(folder: &Path, day: chrono::NaiveDate) -> Result<()> {
+    let mut notes: Vec<(DateTime<Utc>, String, String)> = fs::read_dir(folder)
+        .with_context(|| format!("Failed to read {}", folder.display()))?
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "md") {
+                return None;
+            }
+            let contents = fs::read_to_string(&path).ok()?;
+            let frontmatter = contents.strip_prefix("---\n")?.split_once("\n---")?.0;
+            let note: ObsidianNote = serde_yaml::from_str(frontmatter).ok()?;
+            let stem = path.file_stem()?.to_string_lossy().to_string();
+            (note.created.date_naive() == day).then_some((note.created, stem, note.title))
+        })
+        .collect();
+    notes.sort();
+
+    let mut out = format!("---\ntags:\n  - {OBSIDIAN_FOLDER}\n---\n\n# Chats on {day}\n\n");
+    for (created, stem, title) in notes {
+        let _ = writeln!(out, "- {} [[{stem}|{}]]", created.format("%H:%M"), wiki_text(&title));
+    }
+    let daily = folder.join(OBSIDIAN_DAILY_FOLDER);
+    fs::create_dir_all(&daily).with_context(|| format!("Failed to create {}", daily.display()))?;
+    crate::storage::write_atomic(&daily.join(format!("{day}.md")), &out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_export_markdown() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut metadata = ConversationMetadata::new();
+        metadata.set_summary("Fixing the parser: a story".to_string());
+        let messages = vec![
+            Message::new(MessageRole::User, "Why does it fail?".to_string(), 1),
+            Message::new(
+                MessageRole::Assistant,
+                "<thinking>\nhmm\n</thinking>\nA missing token.".to_string(),
+                1,
+            ),
+        ];
+
+        let template = MarkdownTemplate::default();
+        let path = export_to_dir(&temp_dir.path().join("notes"), ExportFormat::Markdown, &template, &metadata, &messages)
+            .unwrap();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        assert!(name.contains("-fixing-the-parser-a-story-"));
+        assert_eq!(path.extension().unwrap(), "md");
+
+        let contents = fs::read_to_string(path).unwrap();
+        assert!(contents.starts_with("# Fixing the parser: a story\n"));
+        assert!(contents.contains("## Assistant\n\nA missing token.\n"));
+        assert!(!contents.contains("hmm"));
+    }
+
+    #[test]
+    fn test_markdown_template() {
+        let mut metadata = ConversationMetadata::new();
+        metadata.model = Some("qwen3:8b".to_string());
+        metadata.total_tokens = 10;
+        let messages = vec![
+            Message::new(MessageRole::User, "Hi".to_string(), 1),
+            Message::new(MessageRole::Assistant, "<thinking>\nstep one\n\nstep two\n</thinking>\nHello".to_string(), 9),
+        ];
+        let template = MarkdownTemplate {
+            include_thinking: true,
+            timestamps: false,
+            metadata: true,
+            heading: "### {n}. {role} ({tokens} tokens)".to_string(),
+        };
+
+        let markdown = render(ExportFormat::Markdown, &template, &metadata, &messages);
+        assert!(markdown.starts_with(&format!("# Conversation\n\n```yaml\nid: {}\nmodel: qwen3:8b\ntokens: 10\n```\n", metadata.id)));
+        assert!(!markdown.contains(" UTC*"));
+        assert!(markdown.contains("### 2. Assistant (9 tokens)\n\n> step one\n>\n> step two\n\nHello\n"));
+
+        let plain = render(ExportFormat::Markdown, &MarkdownTemplate::default(), &metadata, &messages);
+        assert!(plain.contains(" UTC*\n\n## User\n\nHi\n") && !plain.contains("step one"));
+    }
+
+    #[test]
+    fn test_export_html() {
+        let mut metadata = ConversationMetadata::new();
+        metadata.set_summary("Tables & <code>".to_string());
+        let messages = vec![
+            Message::new(MessageRole::User, "Show me <script>alert(1)</script>".to_string(), 1),
+            Message::new(
+                MessageRole::Assistant,
+                "<thinking>\nplan\n</thinking>\n| a | b |\n|---|---|\n| 1 | 2 |\n\n```rust\nfn main() {}\n```".to_string(),
+                1,
+            ),
+        ];
+
+        let html = render(ExportFormat::Html, &MarkdownTemplate::default(), &metadata, &messages);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Tables &amp; &lt;code&gt;</title>"));
+        assert!(html.contains("&lt;script&gt;") && !html.contains("<script>"));
+        assert!(html.contains("<table>") && html.contains("<td>2</td>"));
+        // Highlighted code has inline colors
+        assert!(html.contains("<pre style=") && html.contains("main"));
+        assert!(html.contains("<details><summary>Thinking</summary>\n<p>plan</p>"));
+    }
+
+    #[test]
+    fn test_export_json() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut metadata = ConversationMetadata::new();
+        metadata.model = Some("qwen3:8b".to_string());
+        let mut messages = vec![
+            Message::new(MessageRole::User, "Why does it fail?".to_string(), 4),
+            Message::new(
+                MessageRole::Assistant,
+                "<thinking>\nhmm\n</thinking>\nA missing token.".to_string(),
+                9,
+            ),
+        ];
+        messages[0].pinned = true;
+
+        let path = temp_dir.path().join("out/chat.json");
+        let template = MarkdownTemplate::default();
+        assert_eq!(export_to_path(&path, ExportFormat::Json, &template, &metadata, &messages).unwrap(), path);
+        let exported: ExportedConversation = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(exported.version, 1);
+        assert_eq!(exported.id, metadata.id);
+        assert_eq!(exported.model.as_deref(), Some("qwen3:8b"));
+        assert!(exported.messages[0].pinned);
+        assert_eq!(exported.messages[1].content, "A missing token.");
+        assert_eq!(exported.messages[1].thinking.as_deref(), Some("hmm"));
+        assert_eq!(exported.messages[1].tokens, 9);
+        assert_eq!(exported.messages[0].thinking, None);
+    }
+
+    #[test]
+    fn test_export_to_obsidian_vault() {
+        let temp_dir = TempDir::new().unwrap();
+        let vault = temp_dir.path().join("Notes");
+        let mut metadata = ConversationMetadata::new();
+        metadata.set_summary("Parser | lexer".to_string());
+        metadata.tag = Some("Rust Async".to_string());
+        let messages = vec![
+            Message::new(
+                MessageRole::User,
+                format!("What is this?\n\n![chart.png](assets/{}/chart.png)", metadata.id),
+                1,
+            ),
+            Message::new(MessageRole::Assistant, "A chart.".to_string(), 1),
+        ];
+        let template = MarkdownTemplate::default();
+        let note = export_to_path(&vault, ExportFormat::Obsidian, &template, &metadata, &messages).unwrap();
+        assert_eq!(note.parent().unwrap(), vault.join(OBSIDIAN_FOLDER));
+
+        let contents = fs::read_to_string(&note).unwrap();
+        assert!(contents.starts_with("---\ntitle: Parser | lexer\n"));
+        assert!(contents.contains("tags:\n- yumchat\n- rust-async\n"));
+        assert!(contents.contains(&format!("![[yumchat/assets/{}/chart.png|chart.png]]", metadata.id)));
+        assert!(contents.contains("## Assistant\n\nA chart.\n") && !contents.contains(" UTC*"));
+
+        // The daily index lists every note of the day, the other day's note apart
+        let mut later = ConversationMetadata::new();
+        later.set_summary("Follow-up".to_string());
+        export_to_dir(&vault, ExportFormat::Obsidian, &template, &later, &messages).unwrap();
+        let mut older = ConversationMetadata::new();
+        older.created_at -= chrono::Duration::days(2);
+        export_to_dir(&vault, ExportFormat::Obsidian, &template, &older, &messages).unwrap();
+
+        let day = metadata.created_at.date_naive();
+        let index = fs::read_to_string(vault.join(format!("yumchat/Daily/{day}.md"))).unwrap();
+        let stem = note.file_stem().unwrap().to_string_lossy();
+        assert!(index.contains(&format!("[[{stem}|Parser   lexer]]")));
+        assert!(index.contains("|Follow-up]]"));
+        assert!(!index.contains("|Conversation]]"));
+    }
+}
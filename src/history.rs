@@ -0,0 +1,183 @@
+// Shell-like recall of previously sent prompts, persisted across sessions
+
+use anyhow::{Context, Result};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::storage::{self, Cipher};
+
+pub const HISTORY_FILE: &str = "input_history.jsonl";
+/// Entries kept in memory; the file is compacted once it holds twice as many
+const MAX_ENTRIES: usize = 500;
+
+#[derive(Debug, Default)]
+pub struct InputHistory {
+    entries: Vec<String>,
+    /// Index into `entries` while recalling; `None` when not browsing
+    position: Option<usize>,
+    path: Option<PathBuf>,
+    /// Set while conversations are encrypted; the file is then encrypted as a whole
+    cipher: Option<Cipher>,
+}
+
+impl InputHistory {
+    /// Load history from `dir`. Each line of the file is one JSON-encoded prompt,
+    /// so multi-line prompts survive the round trip. With `cipher` the file is kept
+    /// encrypted like the conversations.
+    pub fn load(dir: &Path, cipher: Option<Cipher>) -> Result<Self> {
+        let path = dir.join(HISTORY_FILE);
+        let mut entries: Vec<String> = storage::read_private(&path, cipher.as_ref())
+            .context("Failed to read input history")?
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+
+        let compact = entries.len() > MAX_ENTRIES * 2;
+        if entries.len() > MAX_ENTRIES {
+            entries.drain(..entries.len() - MAX_ENTRIES);
+        }
+        let history = Self {
+            entries,
+            position: None,
+            path: Some(path),
+            cipher,
+        };
+        if compact {
+            history.rewrite()?;
+        }
+        Ok(history)
+    }
+
+    fn rewrite(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut contents = String::new();
+        for entry in &self.entries {
+            contents.push_str(&serde_json::to_string(entry)?);
+            contents.push('\n');
+        }
+        storage::write_private(path, &contents, self.cipher.as_ref())
+    }
+
+    /// Record a sent prompt. Blank input and immediate repeats are skipped.
+    pub fn push(&mut self, entry: &str) -> Result<()> {
+        self.position = None;
+        if entry.trim().is_empty() || self.entries.last().is_some_and(|last| last == entry) {
+            return Ok(());
+        }
+        self.entries.push(entry.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+
+        if self.cipher.is_some() {
+            return self.rewrite().context("Failed to write input history");
+        }
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .context("Failed to open input history")?;
+            writeln!(file, "{}", serde_json::to_string(entry)?).context("Failed to write input history")?;
+        }
+        Ok(())
+    }
+
+    pub const fn is_browsing(&self) -> bool {
+        self.position.is_some()
+    }
+
+    /// Stop browsing (e.g. once the recalled prompt is edited)
+    pub const fn reset(&mut self) {
+        self.position = None;
+    }
+
+    /// Step back to an older prompt; stays on the oldest one
+    pub fn previous(&mut self) -> Option<&str> {
+        let position = match self.position {
+            Some(position) => position.saturating_sub(1),
+            None => self.entries.len().checked_sub(1)?,
+        };
+        self.position = Some(position);
+        self.entries.get(position).map(String::as_str)
+    }
+
+    /// Step forward to a newer prompt. Returns `None` (and stops browsing)
+    /// when moving past the newest entry.
+    pub fn next(&mut self) -> Option<&str> {
+        let position = self.position? + 1;
+        if position >= self.entries.len() {
+            self.position = None;
+            return None;
+        }
+        self.position = Some(position);
+        self.entries.get(position).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_browse_previous_and_next() {
+        let mut history = InputHistory::default();
+        assert!(history.previous().is_none());
+
+        history.push("first").unwrap();
+        history.push("second").unwrap();
+        history.push("second").unwrap();
+        history.push("  ").unwrap();
+
+        assert_eq!(history.previous(), Some("second"));
+        assert_eq!(history.previous(), Some("first"));
+        assert_eq!(history.previous(), Some("first"));
+        assert_eq!(history.next(), Some("second"));
+        assert_eq!(history.next(), None);
+        assert!(!history.is_browsing());
+    }
+
+    #[test]
+    fn test_persists_across_sessions() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut history = InputHistory::load(temp_dir.path(), None).unwrap();
+        history.push("multi\nline").unwrap();
+        history.push("last").unwrap();
+
+        let mut reloaded = InputHistory::load(temp_dir.path(), None).unwrap();
+        assert_eq!(reloaded.previous(), Some("last"));
+        assert_eq!(reloaded.previous(), Some("multi\nline"));
+    }
+
+    #[test]
+    fn test_encrypted_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let cipher = Cipher::unlock(&temp_dir.path().join("encryption.json"), "passphrase").unwrap();
+        let mut history = InputHistory::load(temp_dir.path(), Some(cipher.clone())).unwrap();
+        history.push("secret prompt").unwrap();
+
+        let contents = fs::read_to_string(temp_dir.path().join(HISTORY_FILE)).unwrap();
+        assert!(!contents.contains("secret"));
+        let mut reloaded = InputHistory::load(temp_dir.path(), Some(cipher)).unwrap();
+        assert_eq!(reloaded.previous(), Some("secret prompt"));
+        assert!(InputHistory::load(temp_dir.path(), None).is_err());
+    }
+
+    #[test]
+    fn test_load_compacts_long_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let lines: String = (0..=MAX_ENTRIES * 2).map(|i| format!("\"p{i}\"")).collect::<Vec<_>>().join("\n");
+        fs::write(temp_dir.path().join(HISTORY_FILE), lines).unwrap();
+
+        let history = InputHistory::load(temp_dir.path(), None).unwrap();
+        assert_eq!(history.entries.len(), MAX_ENTRIES);
+        let contents = fs::read_to_string(temp_dir.path().join(HISTORY_FILE)).unwrap();
+        assert_eq!(contents.lines().count(), MAX_ENTRIES);
+    }
+}
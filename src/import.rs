@@ -0,0 +1,406 @@
+// Importing conversations from other tools and transcripts (`/import`, `yumchat import`)
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+use crate::models::{ConversationMetadata, Message, MessageRole};
+use crate::storage::Storage;
+
+/// A conversation read from an import file, before it is stored
+#[derive(Debug, Default)]
+pub struct ImportedConversation {
+    pub title: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub messages: Vec<Message>,
+}
+
+/// Store every conversation in `path` as a new yumchat conversation.
+/// Returns the metadata of the conversations created.
+pub fn import_file(storage: &Storage, path: &Path) -> Result<Vec<ConversationMetadata>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let is_markdown = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| matches!(extension.to_ascii_lowercase().as_str(), "md" | "markdown" | "txt"));
+    let conversations = if is_markdown {
+        let mut conversation = parse_markdown(&text)?;
+        if conversation.title.is_none() {
+            conversation.title = path.file_stem().map(|stem| stem.to_string_lossy().to_string());
+        }
+        vec![conversation]
+    } else {
+        parse_json(&text)?
+    };
+
+    let mut imported = Vec::new();
+    for conversation in conversations.into_iter().filter(|c| !c.messages.is_empty()) {
+        let mut metadata = ConversationMetadata::new();
+        metadata.summary = conversation.title.filter(|title| !title.trim().is_empty());
+        if let Some(created_at) = conversation.created_at {
+            metadata.created_at = created_at;
+        }
+        metadata.updated_at = conversation.updated_at.unwrap_or(metadata.created_at);
+        metadata.total_tokens = conversation.messages.iter().map(|m| m.tokens).sum();
+        storage.save(&metadata, &conversation.messages)?;
+        imported.push(metadata);
+    }
+    if imported.is_empty() {
+        bail!("No conversations with messages found in {}", path.display());
+    }
+    Ok(imported)
+}
+
+/// Conversations in a `conversations.json` export from chat.openai.com, a yumchat JSON
+/// export, or generic JSON: a list of `{role, content}` messages, a `{title, messages}` object,
+/// or a list of those
+pub fn parse_json(text: &str) -> Result<Vec<ImportedConversation>> {
+    let value: Value = serde_json::from_str(text).context("Not a JSON file")?;
+    match &value {
+        Value::Array(items) if items.iter().all(is_conversation) => Ok(items.iter().map(conversation).collect()),
+        Value::Array(items) => Ok(vec![ImportedConversation {
+            messages: items.iter().filter_map(generic_message).collect(),
+            ..ImportedConversation::default()
+        }]),
+        value if is_conversation(value) => Ok(vec![conversation(value)]),
+        _ => bail!("Unrecognized JSON: expected conversations or role/content messages"),
+    }
+}
+
+/// A markdown transcript: messages under `## User` / `## Assistant` headings (as in
+/// yumchat's markdown exports), or `> ` quoted prompts each followed by the answer.
+/// A `# Title` before the first message becomes the title.
+pub fn parse_markdown(text: &str) -> Result<ImportedConversation> {
+    let lines = markdown_lines(text);
+    let has_role_headings = lines.iter().any(|(_, line)| matches!(line, MarkdownLine::Heading(_, Some(_))));
+    let has_quotes = lines.iter().any(|(_, line)| matches!(line, MarkdownLine::Quote(_)));
+    if !has_role_headings && !has_quotes {
+        bail!("No `## User` / `## Assistant` headings or `> ` quoted prompts found");
+    }
+
+    let mut title = None;
+    let mut messages = Vec::new();
+    let mut current: Option<(MessageRole, Vec<&str>)> = None;
+    // Outside a quoted prompt, text is the answer, or part of the message under a heading
+    let text_role = if has_role_headings { None } else { Some(MessageRole::Assistant) };
+    for (raw, line) in lines {
+        let (role, text) = match line {
+            MarkdownLine::Heading(_, Some(role)) => {
+                push_message(&mut messages, current.take());
+                current = Some((role, Vec::new()));
+                continue;
+            }
+            MarkdownLine::Heading(heading, None) if current.is_none() && title.is_none() => {
+                title = Some(heading.to_string());
+                continue;
+            }
+            // With headings, quotes are part of a message (thinking in an export)
+            MarkdownLine::Quote(quoted) if !has_role_headings => (Some(MessageRole::User), quoted),
+            MarkdownLine::Blank => (None, ""),
+            MarkdownLine::Heading(..) | MarkdownLine::Quote(_) | MarkdownLine::Text => (text_role.clone(), raw),
+        };
+        match (&mut current, role) {
+            (Some((_, lines)), None) => lines.push(text),
+            (Some((current_role, lines)), Some(role)) if role == *current_role => lines.push(text),
+            (_, Some(role)) => {
+                push_message(&mut messages, current.take());
+                current = Some((role, vec![text]));
+            }
+            // Text before the first message
+            (None, None) => {}
+        }
+    }
+    push_message(&mut messages, current);
+
+    Ok(ImportedConversation {
+        title,
+        messages,
+        ..ImportedConversation::default()
+    })
+}
+
+enum MarkdownLine<'a> {
+    Blank,
+    /// A heading's text, and the role it names
+    Heading(&'a str, Option<MessageRole>),
+    /// A blockquote line without its `>`
+    Quote(&'a str),
+    /// Anything else, including every line inside a code fence
+    Text,
+}
+
+/// Each line of `text` with its kind
+fn markdown_lines(text: &str) -> Vec<(&str, MarkdownLine<'_>)> {
+    let mut in_fence = false;
+    text.lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_fence = !in_fence;
+                return (line, MarkdownLine::Text);
+            }
+            let kind = if in_fence {
+                MarkdownLine::Text
+            } else if trimmed.is_empty() {
+                MarkdownLine::Blank
+            } else if let Some(quoted) = trimmed.strip_prefix('>') {
+                MarkdownLine::Quote(quoted.strip_prefix(' ').unwrap_or(quoted))
+            } else if let Some(heading) = trimmed.strip_prefix('#').map(|rest| rest.trim_start_matches('#')) {
+                let heading = heading.trim();
+                MarkdownLine::Heading(heading, heading_role(heading))
+            } else {
+                MarkdownLine::Text
+            };
+            (line, kind)
+        })
+        .collect()
+}
+
+/// The role a heading like `User`, `## Assistant 3` or `Human:` names
+fn heading_role(heading: &str) -> Option<MessageRole> {
+    let word = heading
+        .split_whitespace()
+        .next()?
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_ascii_lowercase();
+    match word.as_str() {
+        "user" | "human" | "you" | "me" | "prompt" | "question" => Some(MessageRole::User),
+        "assistant" | "ai" | "bot" | "model" | "response" | "answer" | "chatgpt" | "claude" | "yumchat" => {
+            Some(MessageRole::Assistant)
+        }
+        _ => None,
+    }
+}
+
+fn push_message(messages: &mut Vec<Message>, message: Option<(MessageRole, Vec<&str>)>) {
+    let Some((role, lines)) = message else {
+        return;
+    };
+    let content = lines.join("\n").trim().to_string();
+    if !content.is_empty() {
+        let tokens = crate::tokens::estimate_tokens(&content);
+        messages.push(Message::new(role, content, tokens));
+    }
+}
+
+fn is_conversation(value: &Value) -> bool {
+    value.get("mapping").is_some_and(Value::is_object) || value.get("messages").is_some_and(Value::is_array)
+}
+
+fn conversation(value: &Value) -> ImportedConversation {
+    let title = ["title", "name", "summary"]
+        .iter()
+        .find_map(|key| value.get(*key).and_then(Value::as_str))
+        .map(ToString::to_string);
+    let created_at = timestamp(value, &["create_time", "created_at"]);
+    let updated_at = timestamp(value, &["update_time", "updated_at"]);
+    let messages = value.get("mapping").and_then(Value::as_object).map_or_else(
+        || {
+            value
+                .get("messages")
+                .and_then(Value::as_array)
+                .map(|messages| messages.iter().filter_map(generic_message).collect())
+                .unwrap_or_default()
+        },
+        |mapping| chatgpt_messages(mapping, value.get("current_node").and_then(Value::as_str)),
+    );
+    ImportedConversation {
+        title,
+        created_at,
+        updated_at,
+        messages,
+    }
+}
+
+/// Exports from chat.openai.com store each conversation as a tree of edits and
+/// regenerations; the shown branch is the path from `current_node` up to the root
+fn chatgpt_messages(mapping: &serde_json::Map<String, Value>, current_node: Option<&str>) -> Vec<Message> {
+    let mut nodes = Vec::new();
+    let mut next = current_node.map(ToString::to_string);
+    while let Some(node) = next.and_then(|id| mapping.get(&id)) {
+        // A cycle would mean a corrupt export; stop rather than loop forever
+        if nodes.len() > mapping.len() {
+            break;
+        }
+        nodes.push(node);
+        next = node.get("parent").and_then(Value::as_str).map(ToString::to_string);
+    }
+    if nodes.is_empty() {
+        // No current node: fall back to every node in creation order
+        nodes = mapping.values().collect();
+        nodes.sort_by(|a, b| {
+            let time = |node: &Value| node.pointer("/message/create_time").and_then(Value::as_f64).unwrap_or(0.0);
+            time(a).total_cmp(&time(b))
+        });
+    } else {
+        nodes.reverse();
+    }
+    nodes
+        .into_iter()
+        .filter_map(|node| node.get("message"))
+        .filter_map(generic_message)
+        .collect()
+}
+
+/// A user or assistant message from `{role, content}` (or `{author: {role}, content:
+/// {parts}}` in exports from chat.openai.com); other roles and empty messages are skipped
+fn generic_message(value: &Value) -> Option<Message> {
+    let role = value
+        .get("role")
+        .or_else(|| value.pointer("/author/role"))
+        .and_then(Value::as_str)?;
+    let role = match role.to_ascii_lowercase().as_str() {
+        "user" | "human" => MessageRole::User,
+        "assistant" | "model" | "ai" | "bot" => MessageRole::Assistant,
+        _ => return None,
+    };
+    let mut content = text_of(value.get("content")?);
+    if content.trim().is_empty() {
+        return None;
+    }
+    // Thinking from a yumchat JSON export goes back in front of the answer
+    if let Some(thinking) = value.get("thinking").and_then(Value::as_str).filter(|t| !t.is_empty()) {
+        content = format!("<thinking>\n{thinking}\n</thinking>\n{content}");
+    }
+    let tokens = value
+        .get("tokens")
+        .and_then(Value::as_u64)
+        .and_then(|tokens| usize::try_from(tokens).ok())
+        .unwrap_or_else(|| crate::tokens::estimate_tokens(&content));
+    Some(Message::new(role, content, tokens))
+}
+
+/// Text of a content value: a string, `{parts: [...]}`, or a list of
+/// strings / `{text}` parts
+fn text_of(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().map(text_of).filter(|t| !t.is_empty()).collect::<Vec<_>>().join("\n\n"),
+        Value::Object(object) => object
+            .get("parts")
+            .or_else(|| object.get("text"))
+            .map(text_of)
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// The first of `keys` holding Unix seconds or an RFC 3339 string
+fn timestamp(value: &Value, keys: &[&str]) -> Option<DateTime<Utc>> {
+    keys.iter().filter_map(|key| value.get(*key)).find_map(|time| match time {
+        #[allow(clippy::cast_possible_truncation)]
+        Value::Number(seconds) => DateTime::from_timestamp_millis((seconds.as_f64()? * 1000.0) as i64),
+        Value::String(text) => DateTime::parse_from_rfc3339(text).ok().map(|time| time.with_timezone(&Utc)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHATGPT_EXPORT: &str = r#"[{
+        "title": "Rust lifetimes",
+        "create_time": 1700000000.5,
+        "update_time": 1700000100.0,
+        "current_node": "c",
+        "mapping": {
+            "root": {"id": "root", "message": null, "parent": null, "children": ["sys"]},
+            "sys": {"id": "sys", "parent": "root", "children": ["a"],
+                    "message": {"author": {"role": "system"}, "content": {"content_type": "text", "parts": [""]}}},
+            "a": {"id": "a", "parent": "sys", "children": ["b", "old"],
+                  "message": {"author": {"role": "user"}, "content": {"content_type": "text", "parts": ["What is 'a?"]}}},
+            "old": {"id": "old", "parent": "a", "children": [],
+                    "message": {"author": {"role": "assistant"}, "content": {"parts": ["regenerated away"]}}},
+            "c": {"id": "c", "parent": "a", "children": [],
+                  "message": {"author": {"role": "assistant"}, "content": {"parts": ["A lifetime."]}}}
+        }
+    }]"#;
+
+    #[test]
+    fn test_parse_chatgpt_export_follows_current_branch() {
+        let conversations = parse_json(CHATGPT_EXPORT).unwrap();
+        assert_eq!(conversations.len(), 1);
+        let conversation = &conversations[0];
+        assert_eq!(conversation.title.as_deref(), Some("Rust lifetimes"));
+        assert_eq!(conversation.created_at.unwrap().timestamp_millis(), 1_700_000_000_500);
+        let contents: Vec<&str> = conversation.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["What is 'a?", "A lifetime."]);
+        assert_eq!(conversation.messages[1].role, MessageRole::Assistant);
+    }
+
+    #[test]
+    fn test_parse_generic_json() {
+        let messages = r#"[{"role": "user", "content": "Hi"}, {"role": "system", "content": "x"},
+                           {"role": "assistant", "content": [{"type": "text", "text": "Hello"}]}]"#;
+        let conversations = parse_json(messages).unwrap();
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].messages.len(), 2);
+        assert_eq!(conversations[0].messages[1].content, "Hello");
+
+        let exported = r#"{"title": "Mine", "created_at": "2026-01-02T03:04:05Z", "messages": [
+            {"role": "assistant", "content": "Answer", "thinking": "plan", "tokens": 7}]}"#;
+        let conversation = &parse_json(exported).unwrap()[0];
+        assert_eq!(conversation.title.as_deref(), Some("Mine"));
+        assert_eq!(conversation.messages[0].content, "<thinking>\nplan\n</thinking>\nAnswer");
+        assert_eq!(conversation.messages[0].tokens, 7);
+
+        assert!(parse_json("{\"foo\": 1}").is_err());
+        assert!(parse_json("not json").is_err());
+    }
+
+    #[test]
+    fn test_parse_markdown_headings() {
+        let markdown = "# Fixing the parser\n\n*2026-01-02 03:04 UTC*\n\n## User\n\nWhy does this fail?\n\n\
+                        ## Assistant 2\n\n> thinking it over\n\nBecause:\n\n```md\n## User\n```\n\n### Details\n\nMore.\n";
+        let conversation = parse_markdown(markdown).unwrap();
+        assert_eq!(conversation.title.as_deref(), Some("Fixing the parser"));
+        assert_eq!(conversation.messages.len(), 2);
+        assert_eq!(conversation.messages[0].role, MessageRole::User);
+        assert_eq!(conversation.messages[0].content, "Why does this fail?");
+        assert_eq!(
+            conversation.messages[1].content,
+            "> thinking it over\n\nBecause:\n\n```md\n## User\n```\n\n### Details\n\nMore."
+        );
+    }
+
+    #[test]
+    fn test_parse_markdown_quoted_prompts() {
+        let markdown = "> What is a monad?\n> Briefly.\n\nA monoid in the category of endofunctors.\n\nKidding.\n\n> Thanks\n";
+        let conversation = parse_markdown(markdown).unwrap();
+        let messages: Vec<(MessageRole, &str)> =
+            conversation.messages.iter().map(|m| (m.role.clone(), m.content.as_str())).collect();
+        assert_eq!(
+            messages,
+            vec![
+                (MessageRole::User, "What is a monad?\nBriefly."),
+                (MessageRole::Assistant, "A monoid in the category of endofunctors.\n\nKidding."),
+                (MessageRole::User, "Thanks"),
+            ]
+        );
+        assert!(parse_markdown("Just some notes.\n").is_err());
+    }
+
+    #[test]
+    fn test_import_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let storage = Storage::with_dir(temp_dir.path().join("config")).unwrap();
+        let path = temp_dir.path().join("conversations.json");
+        fs::write(&path, CHATGPT_EXPORT).unwrap();
+
+        let imported = import_file(&storage, &path).unwrap();
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].summary.as_deref(), Some("Rust lifetimes"));
+        assert_eq!(storage.load_conversation(&imported[0].id).unwrap().len(), 2);
+        assert_eq!(storage.list_conversations().unwrap().len(), 1);
+
+        let path = temp_dir.path().join("monads.md");
+        fs::write(&path, "> Hi\n\nHello!\n").unwrap();
+        let imported = import_file(&storage, &path).unwrap();
+        assert_eq!(imported[0].summary.as_deref(), Some("monads"));
+        assert_eq!(storage.load_conversation(&imported[0].id).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,127 @@
+// Single-instance detection via a lockfile in the config directory
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const LOCK_FILE: &str = "yumchat.lock";
+
+/// Ownership of the profile directory. Other instances may take it over,
+/// after which this instance must stop writing conversation files.
+#[derive(Debug)]
+pub struct InstanceLock {
+    path: PathBuf,
+    pid: u32,
+}
+
+#[derive(Debug)]
+pub enum LockStatus {
+    Acquired(InstanceLock),
+    /// Another live instance holds the lock
+    HeldBy(u32),
+}
+
+/// Try to become the owning instance for `dir`. Stale locks left behind by
+/// processes that no longer exist are reclaimed automatically.
+pub fn acquire(dir: &Path) -> Result<LockStatus> {
+    let path = dir.join(LOCK_FILE);
+    if let Some(pid) = read_pid(&path) {
+        if pid != std::process::id() && process_alive(pid) {
+            return Ok(LockStatus::HeldBy(pid));
+        }
+    }
+    take_over(dir).map(LockStatus::Acquired)
+}
+
+/// Claim the lock unconditionally, even if another instance holds it
+pub fn take_over(dir: &Path) -> Result<InstanceLock> {
+    let path = dir.join(LOCK_FILE);
+    let pid = std::process::id();
+    fs::write(&path, pid.to_string()).context("Failed to write lock file")?;
+    Ok(InstanceLock { path, pid })
+}
+
+impl InstanceLock {
+    /// Whether this instance still owns the lock (it has not been taken over)
+    pub fn is_owned(&self) -> bool {
+        read_pid(&self.path) == Some(self.pid)
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        if self.is_owned() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn read_pid(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    // 0 and values that wrap to negative pids address process groups, not a process
+    if pid == 0 || i32::try_from(pid).is_err() {
+        return false;
+    }
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(not(unix))]
+fn process_alive(pid: u32) -> bool {
+    // tasklist lists a live process as a CSV row with its pid quoted
+    let filter = format!("PID eq {pid}");
+    std::process::Command::new("tasklist")
+        .args(["/FI", &filter, "/FO", "CSV", "/NH"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        // Without tasklist, assume the holder is alive and let the user decide
+        .map_or(true, |output| String::from_utf8_lossy(&output.stdout).contains(&format!("\"{pid}\"")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_acquire_and_release() {
+        let temp_dir = TempDir::new().unwrap();
+        let lock = match acquire(temp_dir.path()).unwrap() {
+            LockStatus::Acquired(lock) => lock,
+            LockStatus::HeldBy(pid) => panic!("unexpectedly held by {pid}"),
+        };
+        assert!(lock.is_owned());
+        assert!(temp_dir.path().join(LOCK_FILE).exists());
+
+        drop(lock);
+        assert!(!temp_dir.path().join(LOCK_FILE).exists());
+    }
+
+    #[test]
+    fn test_stale_lock_is_reclaimed() {
+        let temp_dir = TempDir::new().unwrap();
+        // Far above any OS pid limit, so no such process can exist
+        fs::write(temp_dir.path().join(LOCK_FILE), "2147483646").unwrap();
+        assert!(matches!(acquire(temp_dir.path()).unwrap(), LockStatus::Acquired(_)));
+    }
+
+    #[test]
+    fn test_take_over_revokes_ownership() {
+        let temp_dir = TempDir::new().unwrap();
+        let original = take_over(temp_dir.path()).unwrap();
+        // Simulate another instance claiming the profile
+        fs::write(temp_dir.path().join(LOCK_FILE), "2147483646").unwrap();
+        assert!(!original.is_owned());
+
+        // Dropping a lock we no longer own must not delete the new owner's file
+        drop(original);
+        assert!(temp_dir.path().join(LOCK_FILE).exists());
+    }
+}
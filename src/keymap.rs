@@ -0,0 +1,352 @@
+// Remappable key bindings: `[keys]` in config.toml maps actions to key chords
+
+use anyhow::{bail, Result};
+use crossterm::event::{KeyCode, KeyModifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Something a key chord can be bound to in chat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    ToggleHelp,
+    ToggleInfo,
+    NewChat,
+    ListConversations,
+    SwitchModel,
+    SwitchPersona,
+    InsertTemplate,
+    Settings,
+    SelectMessage,
+    CopyLastResponse,
+    Undo,
+    ToggleThinking,
+    Find,
+    OpenLink,
+    Search,
+    Send,
+    Paste,
+    DeleteWord,
+    ClearInput,
+    ScrollUp,
+    ScrollDown,
+    ScrollTop,
+    ScrollBottom,
+    PreviousMessage,
+    NextMessage,
+    /// Debug overlay of raw protocol traffic, left out of the help
+    ToggleDebug,
+}
+
+impl Action {
+    /// Default chords, as they would be written in `[keys]`
+    const DEFAULTS: &'static [(Self, &'static str)] = &[
+        (Self::Quit, "ctrl+q"),
+        (Self::ToggleHelp, "ctrl+h"),
+        (Self::ToggleInfo, "ctrl+i"),
+        (Self::NewChat, "ctrl+n"),
+        (Self::ListConversations, "ctrl+l"),
+        (Self::SwitchModel, "ctrl+m"),
+        (Self::SwitchPersona, "ctrl+p"),
+        (Self::InsertTemplate, "ctrl+t"),
+        (Self::Settings, "ctrl+s"),
+        (Self::SelectMessage, "ctrl+k"),
+        (Self::CopyLastResponse, "ctrl+y"),
+        (Self::Undo, "ctrl+z"),
+        (Self::ToggleThinking, "tab"),
+        (Self::Find, "ctrl+f"),
+        (Self::OpenLink, "ctrl+o"),
+        (Self::Search, "ctrl+shift+f"),
+        (Self::Send, "enter"),
+        (Self::Paste, "ctrl+v"),
+        (Self::DeleteWord, "ctrl+w"),
+        (Self::ClearInput, "ctrl+u"),
+        (Self::ScrollUp, "pageup"),
+        (Self::ScrollDown, "pagedown"),
+        (Self::ScrollTop, "ctrl+home"),
+        (Self::ScrollBottom, "ctrl+end"),
+        (Self::PreviousMessage, "alt+up"),
+        (Self::NextMessage, "alt+down"),
+        (Self::ToggleDebug, "f12"),
+    ];
+
+    /// What the action does, in a word or two, for the bottom bar
+    pub const fn hint(self) -> &'static str {
+        match self {
+            Self::Quit => "Quit",
+            Self::ToggleHelp => "Help",
+            Self::ToggleInfo => "Info",
+            Self::NewChat => "New",
+            Self::ListConversations => "Chats",
+            Self::SwitchModel => "Model",
+            Self::SwitchPersona => "Persona",
+            Self::InsertTemplate => "Template",
+            Self::Settings => "Settings",
+            Self::SelectMessage => "Select",
+            Self::CopyLastResponse => "Copy",
+            Self::Undo => "Undo",
+            Self::ToggleThinking => "Thoughts",
+            Self::Find => "Find",
+            Self::OpenLink => "Links",
+            Self::Search => "Search",
+            Self::Send => "Send",
+            Self::Paste => "Paste",
+            Self::DeleteWord => "Delete word",
+            Self::ClearInput => "Clear",
+            Self::ScrollUp => "Scroll up",
+            Self::ScrollDown => "Scroll down",
+            Self::ScrollTop => "Top",
+            Self::ScrollBottom => "Bottom",
+            Self::PreviousMessage => "Prev message",
+            Self::NextMessage => "Next message",
+            Self::ToggleDebug => "Debug",
+        }
+    }
+}
+
+/// Actions shown in the bottom bar unless `bottom_bar` lists others
+pub fn default_bottom_bar() -> Vec<Action> {
+    vec![Action::NewChat, Action::Quit, Action::ToggleInfo, Action::ToggleThinking, Action::ToggleHelp]
+}
+
+/// `[keys]` values: one chord or a list of them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KeyBinding {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyBinding {
+    fn chords(&self) -> &[String] {
+        match self {
+            Self::One(chord) => std::slice::from_ref(chord),
+            Self::Many(chords) => chords,
+        }
+    }
+}
+
+/// A key with the modifiers held, normalized so that `Ctrl+Shift+F` matches however
+/// the terminal reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match code {
+            KeyCode::Char(c) if c.is_ascii_uppercase() => {
+                modifiers |= KeyModifiers::SHIFT;
+                KeyCode::Char(c.to_ascii_lowercase())
+            }
+            KeyCode::BackTab => {
+                modifiers |= KeyModifiers::SHIFT;
+                KeyCode::Tab
+            }
+            code => code,
+        };
+        Self { code, modifiers }
+    }
+
+    /// Parse `ctrl+h`, `alt+enter`, `ctrl+shift+f`, `f1`, `pagedown`
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim().to_ascii_lowercase();
+        let (modifier_names, key) = value.strip_suffix("++").map_or_else(
+            || value.rsplit_once('+').unwrap_or(("", &value)),
+            |mods| (mods, "+"),
+        );
+        let mut modifiers = KeyModifiers::NONE;
+        for name in modifier_names.split('+').filter(|name| !name.is_empty()) {
+            modifiers |= match name {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" | "option" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => bail!("Unknown modifier '{name}' in '{value}'"),
+            };
+        }
+        let code = match key {
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" | "ins" => KeyCode::Insert,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" | "pgup" => KeyCode::PageUp,
+            "pagedown" | "pgdn" => KeyCode::PageDown,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "space" => KeyCode::Char(' '),
+            "esc" | "escape" => bail!("Esc can't be rebound"),
+            key if key.len() > 1 && key.starts_with('f') => match key[1..].parse::<u8>() {
+                Ok(n @ 1..=12) => KeyCode::F(n),
+                _ => bail!("Unknown key '{key}' in '{value}'"),
+            },
+            key => {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => bail!("Unknown key '{key}' in '{value}'"),
+                }
+            }
+        };
+        let chord = Self::new(code, modifiers);
+        if chord == Self::new(KeyCode::Char('c'), KeyModifiers::CONTROL) {
+            bail!("Ctrl+C can't be rebound");
+        }
+        Ok(chord)
+    }
+
+    /// How the chord is shown in help: `Ctrl+Shift+F`, `PgUp`
+    pub fn label(&self) -> String {
+        let mut label = String::new();
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl+"),
+            (KeyModifiers::ALT, "Alt+"),
+            (KeyModifiers::SHIFT, "Shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                label.push_str(name);
+            }
+        }
+        let key = match self.code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) => c.to_ascii_uppercase().to_string(),
+            KeyCode::F(n) => format!("F{n}"),
+            KeyCode::PageUp => "PgUp".to_string(),
+            KeyCode::PageDown => "PgDn".to_string(),
+            code => format!("{code:?}"),
+        };
+        label + &key
+    }
+}
+
+/// Which action each chord triggers
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<KeyChord, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::from_config(&BTreeMap::new()).expect("default key bindings parse")
+    }
+}
+
+impl Keymap {
+    /// The defaults, with each action in `keys` bound to its chords instead
+    pub fn from_config(keys: &BTreeMap<Action, KeyBinding>) -> Result<Self> {
+        let mut bindings = HashMap::new();
+        for (action, chord) in Action::DEFAULTS {
+            if !keys.contains_key(action) {
+                bindings.insert(KeyChord::parse(chord)?, *action);
+            }
+        }
+        let mut configured: HashMap<KeyChord, Action> = HashMap::new();
+        for (action, binding) in keys {
+            for chord in binding.chords() {
+                let parsed = KeyChord::parse(chord)?;
+                if let Some(other) = configured.insert(parsed, *action).filter(|other| other != action) {
+                    bail!("{} is bound to both {other:?} and {action:?}", parsed.label());
+                }
+            }
+        }
+        // Configured chords win over defaults they collide with
+        bindings.extend(configured);
+        Ok(Self { bindings })
+    }
+
+    pub fn action(&self, code: KeyCode, modifiers: KeyModifiers) -> Option<Action> {
+        self.bindings.get(&KeyChord::new(code, modifiers)).copied()
+    }
+
+    pub fn is_bound(&self, action: Action) -> bool {
+        self.bindings.values().any(|bound| *bound == action)
+    }
+
+    /// The chords bound to `action` for help text, e.g. `Ctrl+H` or `F1/Ctrl+H`;
+    /// `unbound` when it has none
+    pub fn label(&self, action: Action) -> String {
+        let mut labels: Vec<String> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(chord, _)| chord.label())
+            .collect();
+        if labels.is_empty() {
+            return "unbound".to_string();
+        }
+        labels.sort();
+        labels.join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chords() {
+        let chord = |code, modifiers| KeyChord::new(code, modifiers);
+        assert_eq!(KeyChord::parse("ctrl+h").unwrap(), chord(KeyCode::Char('h'), KeyModifiers::CONTROL));
+        assert_eq!(
+            KeyChord::parse("Ctrl+Shift+F").unwrap(),
+            chord(KeyCode::Char('F'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(KeyChord::parse("alt+enter").unwrap(), chord(KeyCode::Enter, KeyModifiers::ALT));
+        assert_eq!(KeyChord::parse("f1").unwrap(), chord(KeyCode::F(1), KeyModifiers::NONE));
+        assert_eq!(KeyChord::parse("f").unwrap(), chord(KeyCode::Char('f'), KeyModifiers::NONE));
+        assert_eq!(KeyChord::parse("ctrl++").unwrap(), chord(KeyCode::Char('+'), KeyModifiers::CONTROL));
+        assert!(KeyChord::parse("hyper+x").is_err());
+        assert!(KeyChord::parse("f13").is_err());
+        assert!(KeyChord::parse("ctrl+c").is_err());
+        assert!(KeyChord::parse("esc").is_err());
+
+        assert_eq!(KeyChord::parse("ctrl+shift+f").unwrap().label(), "Ctrl+Shift+F");
+        assert_eq!(KeyChord::parse("pagedown").unwrap().label(), "PgDn");
+    }
+
+    #[test]
+    fn test_keymap_from_config() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.action(KeyCode::Char('h'), KeyModifiers::CONTROL), Some(Action::ToggleHelp));
+        assert_eq!(
+            keymap.action(KeyCode::Char('F'), KeyModifiers::CONTROL | KeyModifiers::SHIFT),
+            Some(Action::Search)
+        );
+        assert_eq!(keymap.action(KeyCode::Char('h'), KeyModifiers::NONE), None);
+
+        let keys: BTreeMap<Action, KeyBinding> = toml::from_str(
+            r#"
+toggle_help = "f1"
+toggle_thinking = ["ctrl+t", "alt+t"]
+"#,
+        )
+        .unwrap();
+        let keymap = Keymap::from_config(&keys).unwrap();
+        assert_eq!(keymap.action(KeyCode::F(1), KeyModifiers::NONE), Some(Action::ToggleHelp));
+        // Remapping frees the old chord, and takes over the chord of another default
+        assert_eq!(keymap.action(KeyCode::Char('h'), KeyModifiers::CONTROL), None);
+        assert_eq!(keymap.action(KeyCode::Tab, KeyModifiers::NONE), None);
+        assert_eq!(keymap.action(KeyCode::Char('t'), KeyModifiers::CONTROL), Some(Action::ToggleThinking));
+        assert_eq!(keymap.label(Action::ToggleThinking), "Alt+T/Ctrl+T");
+        assert_eq!(keymap.label(Action::InsertTemplate), "unbound");
+        assert!(!keymap.is_bound(Action::InsertTemplate));
+        assert!(keymap.is_bound(Action::ToggleHelp));
+
+        // Saving the config (from Settings) keeps them
+        let config = crate::models::AppConfig { keys: keys.clone(), ..Default::default() };
+        let saved: crate::models::AppConfig = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(saved.keys, keys);
+
+        let clash: BTreeMap<Action, KeyBinding> = toml::from_str("quit = \"f2\"\nnew_chat = \"f2\"").unwrap();
+        assert!(Keymap::from_config(&clash).is_err());
+        assert!(toml::from_str::<BTreeMap<Action, KeyBinding>>("launch_rockets = \"f3\"").is_err());
+    }
+}
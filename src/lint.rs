@@ -0,0 +1,57 @@
+// Cheap checks on a prompt before it is sent, shown as a hint under the input
+
+/// Pastes longer than this with no structure get a hint
+const LONG_PASTE_CHARS: usize = 2000;
+
+/// Words that need an earlier message to refer to
+const LEADING_PRONOUNS: [&str; 7] = ["it", "this", "that", "these", "those", "they", "them"];
+
+/// The most useful suggestion for `prompt`, if any. `has_history` is whether the
+/// conversation already has messages the prompt can lean on.
+pub fn lint(prompt: &str, has_history: bool) -> Option<&'static str> {
+    let prompt = prompt.trim();
+    if prompt.is_empty() {
+        return None;
+    }
+
+    if prompt.len() > LONG_PASTE_CHARS && !prompt.contains("```") && !prompt.contains("\n\n") {
+        return Some("Long paste: wrap it in ``` fences and say what you want done with it");
+    }
+    if has_history {
+        return None;
+    }
+
+    let mut words = prompt
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase());
+    let first = words.next().unwrap_or_default();
+    if LEADING_PRONOUNS.contains(&first.as_str()) {
+        return Some("Starts with a pronoun but there is no earlier message: say what it refers to");
+    }
+    if words.count() < 3 {
+        return Some("Short prompt with no context: add what you are working on or expect back");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_needs_context_only_without_history() {
+        assert!(lint("it doesn't work", false).unwrap().contains("pronoun"));
+        assert!(lint("fix bug", false).unwrap().contains("Short"));
+        assert_eq!(lint("fix bug", true), None);
+        assert_eq!(lint("Explain how Rust lifetimes interact with closures", false), None);
+        assert_eq!(lint("   ", false), None);
+    }
+
+    #[test]
+    fn test_lint_long_paste() {
+        let paste = "log line ".repeat(300);
+        assert!(lint(&paste, true).unwrap().contains("Long paste"));
+        assert_eq!(lint(&format!("Why does this fail?\n\n{paste}"), true), None);
+        assert_eq!(lint(&format!("```\n{paste}\n```"), true), None);
+    }
+}
@@ -0,0 +1,63 @@
+// Diagnostic log (`--verbose`): requests, stream errors and parse failures, written to
+// a daily rotated file since the terminal belongs to the interface
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::EnvFilter;
+
+/// Overrides what is logged, in `tracing` filter syntax (e.g. `yumchat=trace`)
+pub const LOG_FILTER_ENV: &str = "YUMCHAT_LOG";
+
+/// Days of logs kept before the oldest is deleted
+const KEPT_LOGS: usize = 7;
+
+/// Where the logs go: `logs/` in the data directory
+pub fn log_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("logs")
+}
+
+/// Start logging to `yumchat.<date>.log` under `dir`. Records are written from a
+/// background thread until the returned guard is dropped, which flushes them.
+pub fn init(dir: &Path) -> Result<WorkerGuard> {
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("yumchat")
+        .filename_suffix("log")
+        .max_log_files(KEPT_LOGS)
+        .build(dir)
+        .context("Failed to open the log file")?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    let filter = EnvFilter::try_from_env(LOG_FILTER_ENV).unwrap_or_else(|_| EnvFilter::new("yumchat=debug"));
+    tracing_subscriber::fmt()
+        .with_writer(writer)
+        .with_env_filter(filter)
+        .with_ansi(false)
+        .try_init()
+        .map_err(|e| anyhow::anyhow!("Failed to start logging: {e}"))?;
+    tracing::info!(version = env!("CARGO_PKG_VERSION"), "yumchat started");
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_written_to_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = log_dir(temp_dir.path());
+        let guard = init(&dir).unwrap();
+        tracing::warn!(status = 500, "chat request refused");
+        drop(guard);
+
+        let entry = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+        let path = entry.path();
+        assert_eq!(path.extension().unwrap(), "log");
+        assert!(entry.file_name().to_string_lossy().starts_with("yumchat."));
+        let contents = std::fs::read_to_string(path).unwrap();
+        assert!(contents.contains("yumchat started"));
+        assert!(contents.contains("chat request refused status=500"));
+    }
+}
@@ -1,79 +1,390 @@
 mod api;
 mod app;
+mod ask;
+mod browser;
+mod cli;
+mod clipboard;
+mod commands;
+mod compression;
 mod config;
+mod diagrams;
 mod events;
+mod experiment;
+mod export;
+mod history;
+mod import;
+mod instance;
+mod keymap;
+mod lint;
+mod logging;
+mod memory;
 mod models;
+mod monitor;
+mod power;
+mod rag;
+mod search;
+mod settings;
+mod snippets;
 mod storage;
+mod tagging;
+mod tee;
+mod templates;
+mod timer;
 mod tokens;
+mod traffic;
 mod ui;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind},
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, Event, KeyCode,
+        KeyEventKind, MouseButton, MouseEvent, MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use futures::StreamExt;
 use ratatui::{backend::Backend, prelude::*};
 use std::io;
-use std::time::{Duration, Instant};
+use std::time::Instant;
 use tokio::sync::mpsc;
 
 use app::App;
 use api::OllamaClient;
 use events::AppEvent;
+use keymap::Action;
 
 use tokio::task::JoinHandle;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = <cli::Args as clap::Parser>::parse();
+    if let Some(path) = &args.config {
+        config::use_config_file(path.clone());
+    }
+    // Held until exit: dropping it flushes the log
+    let _log_guard = args.verbose.then(start_logging).transpose()?;
+    if let Some(cli::Command::Ask { output, prompt }) = &args.command {
+        // One line on stderr, not a report: scripts show it to users as is
+        if let Err(e) = ask::run(&args, prompt, *output).await {
+            tracing::error!(error = format!("{e:#}"), "ask failed");
+            eprintln!("yumchat: {e:#}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some(path) = args.import_path() {
+        let storage = open_storage(&config::load_config_checked().0)?;
+        let imported = import::import_file(&storage, path)?;
+        println!("Imported {} conversation(s) from {}", imported.len(), path.display());
+        return Ok(());
+    }
+    let tee = args.tee.as_deref().map(tee::Tee::open).transpose()?;
+
+    // Refuse to share a profile with another running instance unless asked to;
+    // `view` changes nothing, so it runs alongside one
+    let instance_lock = match args.view_target() {
+        Some(_) => None,
+        None => match acquire_instance_lock()? {
+            Some(lock) => Some(lock),
+            None => return Ok(()),
+        },
+    };
+
+    // Load config; problems in it are shown once the TUI is up
+    let (config, config_problems) = config::load_config_checked();
+
+    // Open storage before the TUI so the passphrase can be asked for; without
+    // encryption, the app still runs (unsaved) if storage is unavailable
+    let storage = match open_storage(&config) {
+        Ok(storage) => Some(storage),
+        Err(e) if config.encrypt_conversations || args.view_target().is_some() => return Err(e),
+        Err(_) => None,
+    };
+    let viewed = args.view_target().map(|target| load_viewed(storage.as_ref(), target)).transpose()?;
+
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
+    execute!(stdout, EnterAlternateScreen, EnableBracketedPaste)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
     // Create app state and API client
     let mut app = App::new();
-    
-    // Load config
-    let config = config::load_config().unwrap_or_default();
+    app.storage = storage;
+    app.instance_lock = instance_lock;
+    app.tee = tee;
     
     // Update app with config
-    app.current_model = config.default_model.clone();
-    
-    let client = OllamaClient::new(config.ollama_url.clone(), config.request_timeout)?;
-
-    // Fetch model info
-    if let Ok(info) = client.show_model(&app.current_model).await {
-        app.model_capabilities = info.capabilities;
-        app.model_details = info.details;
-        
-        // Auto-enable thinking visibility if model supports thinking
-        if app.model_capabilities.contains(&"thinking".to_string()) {
-            app.show_thinking = false; // Keep default hidden, but user can toggle
-        }
+    let client = OllamaClient::new(args.url.clone().unwrap_or_else(|| config.ollama_url.clone()), config.request_timeout)?
+        .with_traffic(app.traffic.clone())
+        .with_cassette(api::cassette::Cassette::open(args.record.as_deref(), args.replay.as_deref())?);
+    app.current_model = match &args.model {
+        Some(model) => config.resolve_model(model),
+        None if viewed.is_some() => config.default_model_from(None),
+        None => client.pick_default_model(&config).await,
+    };
+    app.config = config.clone();
+    app.config_problems = config_problems;
+    // Bad `[keys]` were already reported and dropped with the config problems
+    app.keymap = keymap::Keymap::from_config(&config.keys).unwrap_or_default();
+    app.model_settings = config::load_models().unwrap_or_default();
+    app.templates = config::load_templates().unwrap_or_default();
+    if viewed.is_none() {
+        prepare_storage(&mut app, &config);
+    }
+    load_history_and_collections(&mut app, &config);
+    app.apply_model_settings();
+    if let Some((metadata, messages)) = viewed {
+        app.open_viewer(metadata, messages);
+    } else {
+        // Bring back whatever was being typed when the app last closed
+        app.restore_draft();
+        apply_session_args(&mut app, &args);
     }
 
     // Create channel for async events
     let (tx, mut rx) = mpsc::unbounded_channel::<AppEvent>();
+    if !app.read_only {
+        connect_model(&mut app, &client, &tx).await;
+    }
+
+    // Kept alive for the session: edits to config.toml apply without a restart
+    let config_tx = tx.clone();
+    let _config_watcher = config::watch_config(move || {
+        let _ = config_tx.send(AppEvent::ConfigChanged);
+    })
+    .map_err(|e| app.notify(format!("Config changes need a restart: {e:#}")))
+    .ok();
 
     // Run app
     let res = run_app(&mut terminal, &mut app, &client, &tx, &mut rx);
 
+    // Cancel in-flight work and flush state before leaving the TUI
+    let shutdown_res = app.shutdown();
+    let sync_res = app.storage.as_ref().map_or(Ok(()), storage::Storage::finish_sync);
+
     // Restore terminal
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(terminal.backend_mut(), DisableMouseCapture, DisableBracketedPaste, LeaveAlternateScreen)?;
     terminal.show_cursor()?;
 
+    // Back in the normal screen, so the conversation stays in the scrollback
+    let print_on_exit = args.print_on_exit || app.config.print_on_exit;
+    if let Some(transcript) = app.transcript().filter(|_| print_on_exit) {
+        println!("{transcript}");
+    }
     if let Err(err) = res {
         eprintln!("Error: {err:?}");
     }
+    if let Err(err) = shutdown_res {
+        eprintln!("Failed to save conversation on exit: {err:?}");
+    }
+    if let Err(err) = sync_res {
+        eprintln!("Failed to push conversations: {err:?}");
+    }
 
     Ok(())
 }
 
+/// Apply config.toml after it was edited
+fn reload_config(app: &mut App) {
+    let path = match config::get_config_path() {
+        Ok(path) => path,
+        Err(e) => return app.notify(format!("config.toml not reloaded: {e:#}")),
+    };
+    // A file that isn't valid TOML (often mid-edit) keeps the current config
+    match config::read_config_checked(&path) {
+        Ok((config, problems)) => {
+            if let Some(notice) = app.apply_config(config) {
+                app.notify(notice);
+            }
+            app.config_problems = problems;
+        }
+        Err(problem) => app.notify(format!("config.toml not reloaded: {problem}")),
+    }
+}
+
+/// The conversation for `yumchat view`: a conversation file, or the id of a saved one
+fn load_viewed(
+    storage: Option<&storage::Storage>,
+    target: &str,
+) -> Result<(models::ConversationMetadata, Vec<models::Message>)> {
+    let storage = storage.context("Conversations can't be read")?;
+    let path = std::path::Path::new(target);
+    let path = if path.is_file() {
+        path.to_path_buf()
+    } else {
+        let id = uuid::Uuid::parse_str(target)
+            .with_context(|| format!("{target} is neither a file nor a conversation id"))?;
+        let path = storage.get_conversation_path(&id);
+        anyhow::ensure!(path.exists(), "No saved conversation with id {id}");
+        path
+    };
+    let (metadata, messages) = storage.read_conversation_file(&path)?;
+    anyhow::ensure!(!messages.is_empty(), "No messages in {}", path.display());
+    // A bare transcript is titled after its file
+    let metadata = metadata.unwrap_or_else(|| {
+        let mut metadata = models::ConversationMetadata::new();
+        metadata.summary = path.file_stem().map(|stem| stem.to_string_lossy().to_string());
+        metadata
+    });
+    Ok((metadata, messages))
+}
+
+/// Fetch details of the starting model, sync models.json with the installed
+/// models, and offer to pull the starting model now rather than failing on the
+/// first send
+async fn connect_model(app: &mut App, client: &OllamaClient, event_tx: &mpsc::UnboundedSender<AppEvent>) {
+    if let Ok(info) = client.show_model(&app.current_model).await {
+        apply_model_info(app, info);
+    }
+    sync_model_settings(client, event_tx);
+    check_model_installed(app.current_model.clone(), client, event_tx);
+}
+
+/// Sent prompts for recall, and the retrieval collections
+fn load_history_and_collections(app: &mut App, config: &models::AppConfig) {
+    if let Ok(dir) = config::get_config_dir() {
+        let cipher = app.storage.as_ref().and_then(|storage| storage.cipher().cloned());
+        app.input_history = history::InputHistory::load(&dir, cipher).unwrap_or_default();
+    }
+    if let Ok(dir) = config::get_collections_dir(config) {
+        app.collections = rag::collections::Collections::load(&dir).unwrap_or_default();
+        let stale = app.collections.needing_migration(&config.rag.embedding_model).count();
+        if stale > 0 {
+            app.notify(format!("{stale} collection(s) need re-embedding after a model change (/collections)"));
+        }
+    }
+}
+
+/// Git sync, memory and retention cleanup of the conversation storage
+fn prepare_storage(app: &mut App, config: &models::AppConfig) {
+    if config.sync.git {
+        let synced = app.storage.as_mut().map(|storage| storage.enable_git_sync(&config.sync));
+        if let Some(Err(e)) = synced {
+            app.notify(format!("Git sync: {e}"));
+        }
+    }
+    if let Some(storage) = &app.storage {
+        app.memory = storage.load_memory().unwrap_or_default();
+        match storage.apply_retention(&config.retention, &config.export) {
+            Ok(pruned) if !pruned.is_empty() => app.notify(describe_pruned(&pruned, config.retention.action)),
+            Ok(_) => {}
+            Err(e) => app.notify(format!("Retention cleanup failed: {e}")),
+        }
+    }
+}
+
+/// Log to the data directory for `--verbose`
+fn start_logging() -> Result<tracing_appender::non_blocking::WorkerGuard> {
+    let data_dir = config::get_data_dir(&config::load_config_checked().0)?;
+    logging::init(&logging::log_dir(&data_dir))
+}
+
+/// `--continue` and `--system`, once the app is set up
+fn apply_session_args(app: &mut App, args: &cli::Args) {
+    if args.resume {
+        match app.open_latest_conversation() {
+            // An explicit --model wins over the one the conversation was held with
+            Ok(Some(model)) if args.model.is_none() => {
+                app.current_model = model;
+                app.apply_model_settings();
+            }
+            Ok(_) => {}
+            Err(e) => app.notify(format!("Nothing to continue: {e}")),
+        }
+    }
+    if let Some(system) = &args.system {
+        app.overrides.system_prompt = Some(system.clone());
+    }
+}
+
+/// Attempts at the passphrase before giving up
+const PASSPHRASE_ATTEMPTS: usize = 3;
+
+/// Storage for `config`, unlocked with a passphrase read from the terminal when
+/// conversations are encrypted. The first time, a new passphrase is chosen.
+fn open_storage(config: &models::AppConfig) -> Result<storage::Storage> {
+    let mut storage = storage::Storage::new(config)?;
+    if !config.encrypt_conversations {
+        return Ok(storage);
+    }
+
+    if !storage.encryption_set_up() {
+        println!("Conversations will be encrypted. Choose a passphrase; it can't be recovered if lost.");
+        loop {
+            let passphrase = rpassword::prompt_password("New passphrase: ")?;
+            if passphrase.is_empty() {
+                continue;
+            }
+            if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+                eprintln!("Passphrases don't match");
+                continue;
+            }
+            let encrypted = storage.unlock(&passphrase)?;
+            if encrypted > 0 {
+                println!("Encrypted {encrypted} existing conversation(s)");
+            }
+            return Ok(storage);
+        }
+    }
+
+    let mut attempts = 0;
+    loop {
+        let passphrase = rpassword::prompt_password("Passphrase: ")?;
+        attempts += 1;
+        match storage.unlock(&passphrase) {
+            Ok(_) => return Ok(storage),
+            Err(e) if attempts < PASSPHRASE_ATTEMPTS => eprintln!("{e}"),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Take ownership of the config directory. When another instance already owns it,
+/// ask (before the TUI starts) whether to take over, use another profile or quit.
+/// Returns `None` to quit.
+fn acquire_instance_lock() -> Result<Option<instance::InstanceLock>> {
+    loop {
+        let config_dir = config::get_config_dir()?;
+        let pid = match instance::acquire(&config_dir)? {
+            instance::LockStatus::Acquired(lock) => return Ok(Some(lock)),
+            instance::LockStatus::HeldBy(pid) => pid,
+        };
+        println!(
+            "Another yumchat instance (pid {pid}) is already using {}.",
+            config_dir.display()
+        );
+        println!("Running both can corrupt conversation files.");
+        let answer = read_answer("[t] Take over (the other instance stops saving)  [p] Use another profile  [q] Quit: ")?;
+        if answer.eq_ignore_ascii_case("t") {
+            return instance::take_over(&config_dir).map(Some);
+        }
+        if !answer.eq_ignore_ascii_case("p") {
+            return Ok(None);
+        }
+        println!("A profile is a config file; its directory holds that profile's conversations.");
+        println!("(Next time, start with: yumchat --config <path>)");
+        let path = read_answer("Config file of the profile to use, e.g. ~/.config/yumchat-work/config.toml: ")?;
+        if path.is_empty() {
+            return Ok(None);
+        }
+        config::use_config_file(rag::expand_home(&path));
+    }
+}
+
+/// A line typed in answer to `prompt`, trimmed
+fn read_answer(prompt: &str) -> Result<String> {
+    print!("{prompt}");
+    io::Write::flush(&mut io::stdout())?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+#[allow(clippy::too_many_lines)]
 fn handle_app_event(app: &mut App, event: AppEvent) {
     match event {
         AppEvent::AiResponseChunk(chunk) => {
@@ -129,7 +440,9 @@ fn handle_app_event(app: &mut App, event: AppEvent) {
                     }
                     
                     // Auto-scroll to bottom to show new content
-                    app.scroll_to_bottom();
+                    if app.config.auto_scroll {
+                        app.scroll_to_bottom();
+                    }
                 }
             }
         }
@@ -138,7 +451,13 @@ fn handle_app_event(app: &mut App, event: AppEvent) {
             app.is_thinking = false;
             app.generation_start_time = None;
             // Ensure we're scrolled to bottom when response completes
-            app.scroll_to_bottom();
+            if app.config.auto_scroll {
+                app.scroll_to_bottom();
+            }
+            app.tee_message(app.messages.len().saturating_sub(1));
+            if let Err(e) = app.save_conversation() {
+                app.notify(format!("Failed to save conversation: {e}"));
+            }
         }
         AppEvent::AiError(error) => {
             app.is_loading = false;
@@ -151,42 +470,92 @@ fn handle_app_event(app: &mut App, event: AppEvent) {
             ));
             // Auto-scroll to show error
             app.scroll_to_bottom();
+            app.tee_message(app.messages.len() - 1);
+            if let Err(e) = app.save_conversation() {
+                app.notify(format!("Failed to save conversation: {e}"));
+            }
         }
         AppEvent::ModelsLoaded(models) => {
             app.is_loading = false;
             app.available_models = models;
-            app.model_list_state.select(Some(0));
+            app.lists.model.select(Some(0));
             // Pre-select current model if available
             if let Some(pos) = app.available_models.iter().position(|m| m == &app.current_model) {
-                app.model_list_state.select(Some(pos));
+                app.lists.model.select(Some(pos));
             }
             app.mode = app::AppMode::ModelSelector;
         }
-        AppEvent::ModelInfoLoaded(info) => {
-            app.model_capabilities = info.capabilities;
-            app.model_details = info.details;
-            
-            // Auto-enable thinking visibility if model supports thinking
-            if app.model_capabilities.contains(&"thinking".to_string()) {
-                app.show_thinking = false; 
+        AppEvent::ModelInfoLoaded(info) => apply_model_info(app, *info),
+        AppEvent::ModelsInstalled(installed) => {
+            let saved = app.model_settings.clone();
+            let gone = config::reconcile_models(&mut app.model_settings, &installed);
+            if app.model_settings != saved {
+                if let Err(e) = config::save_models(&app.model_settings) {
+                    app.notify(format!("Failed to update models.json: {e}"));
+                }
+            }
+            if !gone.is_empty() {
+                app.notify(format!("No longer installed (flagged in models.json): {}", gone.join(", ")));
+            }
+            app.apply_context_window();
+        }
+        AppEvent::Notify(message) => app.notify(message),
+        AppEvent::ConfigChanged => app.config_changed(),
+        AppEvent::RagIndexReady(index) => {
+            app.notify(format!(
+                "Retrieval on: {} chunks indexed with {}",
+                index.chunks.len(),
+                index.embedding_model
+            ));
+            app.rag_index = Some(index);
+            app.active_collection = None;
+        }
+        AppEvent::IndexProgress(name, done, total) => app.index_progress = Some((name, done, total)),
+        AppEvent::CollectionIndexFailed(name, error) => {
+            app.index_progress = None;
+            app.notify(format!("Indexing '{name}' failed: {error}"));
+        }
+        AppEvent::CollectionIndexed(name, index) => {
+            let chunks = index.chunks.len();
+            match app.finish_collection_index(&name, index) {
+                Ok(()) => app.notify(format!("Collection '{name}' indexed: {chunks} chunks, retrieval on")),
+                Err(e) => app.notify(format!("Failed to save collection '{name}': {e}")),
+            }
+        }
+        AppEvent::CitationsFound(citations) => app.attach_citations(citations),
+        AppEvent::ContextSummarized(id, covers, text) => app.apply_context_summary(id, covers, text),
+        AppEvent::MemoryExtracted(reply) => match app.add_extracted_memory(&reply) {
+            Ok(0) => {}
+            Ok(n) => app.notify(format!("Remembered {n} new fact(s) (/memory to review)")),
+            Err(e) => app.notify(format!("Failed to save memory: {e}")),
+        },
+        AppEvent::ConversationTagged(id, tag) => {
+            if let Err(e) = app.set_conversation_tag(id, &tag) {
+                app.notify(format!("Failed to save conversation tag: {e}"));
             }
         }
+        AppEvent::ExperimentChunk(run, text) => app.experiment_chunk(run, &text),
+        AppEvent::ExperimentRunDone(run, error) => app.experiment_run_finished(run, error),
+        AppEvent::ModelMissing(model) => app.pull_prompt = Some(model),
+        AppEvent::PullProgress(model, status) => app.pull_progress = Some((model, status)),
+        AppEvent::ModelPulled(model) => {
+            app.pull_progress = None;
+            app.notify(format!("Pulled '{model}'"));
+        }
+        AppEvent::PullFailed(model, error) => {
+            app.pull_progress = None;
+            app.notify(format!("Pulling '{model}' failed: {error}"));
+        }
     }
 }
 
-const fn handle_help_keys(app: &mut App, key: KeyCode, modifiers: event::KeyModifiers) -> bool {
+fn handle_help_keys(app: &mut App, key: KeyCode, modifiers: event::KeyModifiers) -> bool {
     if !app.show_help {
         return false;
     }
 
-    match key {
-        KeyCode::Char('h') if modifiers.contains(event::KeyModifiers::CONTROL) => {
-            app.toggle_help();
-        }
-        KeyCode::Esc => {
-            app.show_help = false;
-        }
-        _ => {}
+    if key == KeyCode::Esc || app.keymap.action(key, modifiers) == Some(Action::ToggleHelp) {
+        app.show_help = false;
     }
     true
 }
@@ -218,6 +587,8 @@ fn handle_keyboard_input(
             } else if app.is_loading {
                 app.abort_generation();
                 return None; // Caller will handle task abortion
+            } else if app.prompt_hint().is_some() {
+                app.prompt_hint_dismissed = true;
             }
         }
         _ if app.exit_pending => {
@@ -232,6 +603,34 @@ fn handle_keyboard_input(
         return None; 
     }
 
+    // Problems found in config.toml stay up until any key is pressed
+    if !app.config_problems.is_empty() {
+        app.config_problems.clear();
+        return None;
+    }
+
+    // A pending "pull missing model?" question takes every key until answered
+    if let Some(model) = app.pull_prompt.take() {
+        match key {
+            KeyCode::Char('y' | 'Y') | KeyCode::Enter => pull_model(app, model, client, event_tx),
+            KeyCode::Char('m' | 'M') => open_model_picker(app, client, event_tx),
+            KeyCode::Char('n' | 'N') | KeyCode::Esc => {
+                app.notify(format!("'{model}' is not installed; choose another model with Ctrl+M"));
+            }
+            _ => app.pull_prompt = Some(model),
+        }
+        return None;
+    }
+
+    // Paste (Ctrl+V) puts the system clipboard into whichever field has focus
+    if app.keymap.action(key, modifiers) == Some(Action::Paste) {
+        match clipboard::paste() {
+            Ok(text) => app.paste(&text),
+            Err(e) => app.notify(format!("Paste failed: {e}")),
+        }
+        return None;
+    }
+
     // Handle ModelSelector specific input
     if app.mode == app::AppMode::ModelSelector {
         match key {
@@ -248,21 +647,9 @@ fn handle_keyboard_input(
                 return None;
             }
             KeyCode::Enter => {
-                if let Some(i) = app.model_list_state.selected() {
-                    if let Some(model) = app.available_models.get(i) {
-                        app.current_model = model.clone();
-                        app.model_details = None;
-                        app.model_capabilities.clear();
-                        
-                        // Spawn task to fetch model info
-                        let client_clone = client.clone();
-                        let model_name = model.clone();
-                        let tx = event_tx.clone();
-                        tokio::spawn(async move {
-                            if let Ok(info) = client_clone.show_model(&model_name).await {
-                                let _ = tx.send(AppEvent::ModelInfoLoaded(Box::new(info)));
-                            }
-                        });
+                if let Some(i) = app.lists.model.selected() {
+                    if let Some(model) = app.available_models.get(i).cloned() {
+                        switch_model(app, model, client, event_tx);
                     }
                 }
                 app.mode = app::AppMode::Chat;
@@ -272,64 +659,312 @@ fn handle_keyboard_input(
         }
     }
 
-    match key {
-        KeyCode::Char('q') if modifiers.contains(event::KeyModifiers::CONTROL) => {
-             // Keep Ctrl+Q as instant quit 
-            app.quit();
+    if app.mode == app::AppMode::Experiment {
+        handle_experiment_keys(app, key);
+        return None;
+    }
+
+    // Handle ConversationList specific input
+    if app.mode == app::AppMode::ConversationList {
+        match key {
+            KeyCode::Esc => app.mode = app::AppMode::Chat,
+            KeyCode::Up => app.select_previous_conversation(),
+            KeyCode::Down => app.select_next_conversation(),
+            KeyCode::Enter => match app.open_selected_conversation() {
+                // Resume with the model the conversation was held with
+                Ok(Some(model)) => {
+                    switch_model(app, model.clone(), client, event_tx);
+                    check_model_installed(model, client, event_tx);
+                }
+                Ok(None) => {}
+                Err(e) => app.notify(format!("Failed to open conversation: {e}")),
+            },
+            KeyCode::Char('p') => match app.toggle_selected_conversation_pin() {
+                Ok(true) => app.notify("Pinned to the top of the list"),
+                Ok(false) => app.notify("Unpinned"),
+                Err(e) => app.notify(e.to_string()),
+            },
+            KeyCode::Char('a') => match app.toggle_selected_conversation_archive() {
+                Ok(true) => app.notify("Archived"),
+                Ok(false) => app.notify("Restored from the archive"),
+                Err(e) => app.notify(format!("Archive failed: {e}")),
+            },
+            KeyCode::Tab => {
+                if let Err(e) = app.toggle_archived_view() {
+                    app.notify(format!("Failed to list conversations: {e}"));
+                }
+            }
+            _ => {}
+        }
+        return None;
+    }
+
+    // Handle PersonaSelector specific input
+    if app.mode == app::AppMode::PersonaSelector {
+        match key {
+            KeyCode::Esc => app.mode = app::AppMode::Chat,
+            KeyCode::Up => app.select_previous_persona(),
+            KeyCode::Down => app.select_next_persona(),
+            KeyCode::Enter => {
+                if let Some(model) = app.apply_selected_persona() {
+                    switch_model(app, model, client, event_tx);
+                }
+                let name = app.active_persona.as_deref().unwrap_or("none");
+                app.notify(format!("Persona: {name}"));
+            }
+            _ => {}
+        }
+        return None;
+    }
+
+    // Handle ThemeSelector specific input
+    if app.mode == app::AppMode::ThemeSelector {
+        match key {
+            KeyCode::Esc => app.mode = app::AppMode::Chat,
+            KeyCode::Up => app.select_previous_theme(),
+            KeyCode::Down => app.select_next_theme(),
+            KeyCode::Enter => {
+                if let Some(name) = app.previewed_theme().and_then(|theme| theme.preset) {
+                    apply_theme(app, &name);
+                }
+            }
+            _ => {}
         }
-        KeyCode::Char('h') if modifiers.contains(event::KeyModifiers::CONTROL) => {
-            app.toggle_help();
+        return None;
+    }
+
+    // Handle TemplateSelector specific input
+    if app.mode == app::AppMode::TemplateSelector {
+        match key {
+            KeyCode::Esc => app.mode = app::AppMode::Chat,
+            KeyCode::Up => app.select_previous_template(),
+            KeyCode::Down => app.select_next_template(),
+            KeyCode::Enter => app.choose_template(),
+            _ => {}
         }
-        KeyCode::Char('i') if modifiers.contains(event::KeyModifiers::CONTROL) => {
-            app.toggle_info();
+        return None;
+    }
+
+    // Handle TemplateForm specific input
+    if app.mode == app::AppMode::TemplateForm {
+        if let Some(form) = app.template_form.as_mut() {
+            match key {
+                KeyCode::Esc => app.cancel_template_form(),
+                KeyCode::Enter => app.finish_template_form(),
+                KeyCode::Tab | KeyCode::Down => form.next_field(),
+                KeyCode::BackTab | KeyCode::Up => form.previous_field(),
+                KeyCode::Backspace => form.backspace(),
+                KeyCode::Char(c) => form.push_char(c),
+                _ => {}
+            }
         }
-        KeyCode::Char('m') if modifiers.contains(event::KeyModifiers::CONTROL) => {
-            if !app.is_loading {
-                app.is_loading = true;
-                let client_clone = client.clone();
-                let tx = event_tx.clone();
-                tokio::spawn(async move {
-                    match client_clone.list_models().await {
-                        Ok(models) => {
-                            let names = models.into_iter().map(|m| m.name).collect();
-                            let _ = tx.send(AppEvent::ModelsLoaded(names));
-                        }
-                        Err(e) => {
-                            let _ = tx.send(AppEvent::AiError(e.to_string()));
-                        }
+        return None;
+    }
+
+    // Handle Collections specific input
+    if app.mode == app::AppMode::Collections {
+        handle_collections_keys(app, key, client, event_tx);
+        return None;
+    }
+
+    if app.mode == app::AppMode::Search {
+        handle_search_keys(app, key, client, event_tx);
+        return None;
+    }
+
+    if app.mode == app::AppMode::Find {
+        handle_find_keys(app, key, modifiers);
+        return None;
+    }
+
+    if app.mode == app::AppMode::LinkSelect {
+        handle_link_keys(app, key);
+        return None;
+    }
+
+    if app.mode == app::AppMode::SaveCodeBlock {
+        match key {
+            KeyCode::Esc => app.cancel_code_block_save(),
+            KeyCode::Enter => match app.commit_code_block_save() {
+                Ok(path) => app.notify(format!("Saved to {}", path.display())),
+                Err(e) => app.notify(e.to_string()),
+            },
+            KeyCode::Backspace => {
+                if let Some((_, path)) = app.code_block_save.as_mut() {
+                    path.pop();
+                }
+            }
+            KeyCode::Char(c) => {
+                if let Some((_, path)) = app.code_block_save.as_mut() {
+                    path.push(c);
+                }
+            }
+            _ => {}
+        }
+        return None;
+    }
+
+    if app.mode == app::AppMode::Memory {
+        match key {
+            KeyCode::Esc => app.mode = app::AppMode::Chat,
+            KeyCode::Up => app.select_previous_memory(),
+            KeyCode::Down => app.select_next_memory(),
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Err(e) = app.delete_selected_memory() {
+                    app.notify(e.to_string());
+                }
+            }
+            _ => {}
+        }
+        return None;
+    }
+
+    if app.mode == app::AppMode::Bookmarks {
+        match key {
+            KeyCode::Esc => app.mode = app::AppMode::Chat,
+            KeyCode::Up => app.select_previous_bookmark(),
+            KeyCode::Down => app.select_next_bookmark(),
+            KeyCode::Enter => app.jump_to_selected_bookmark(),
+            KeyCode::Char('d') | KeyCode::Delete if app.read_only => app.notify(app::VIEWER_NOTICE),
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Err(e) = app.remove_selected_bookmark() {
+                    app.notify(e.to_string());
+                }
+            }
+            _ => {}
+        }
+        return None;
+    }
+
+    if app.mode == app::AppMode::ReadLater {
+        handle_read_later_keys(app, key, client, event_tx);
+        return None;
+    }
+
+    // Handle Settings specific input
+    if app.mode == app::AppMode::Settings {
+        handle_settings_keys(app, key);
+        return None;
+    }
+
+    // Handle MessageSelect specific input
+    if app.mode == app::AppMode::MessageSelect {
+        match key {
+            KeyCode::Esc => {
+                app.selected_message = None;
+                app.mode = app::AppMode::Chat;
+                app.scroll_to_bottom();
+            }
+            KeyCode::Up => app.select_previous_message(),
+            KeyCode::Down => app.select_next_message(),
+            KeyCode::Char('f' | 'p' | 'm' | 'r') | KeyCode::Enter if app.read_only => app.notify(app::VIEWER_NOTICE),
+            KeyCode::Char('f') | KeyCode::Enter => {
+                if let Some(i) = app.selected_message {
+                    match app.fork_at(i) {
+                        Ok(()) => app.notify("Forked into a new conversation"),
+                        Err(e) => app.notify(format!("Fork failed: {e}")),
+                    }
+                }
+            }
+            KeyCode::Char('v') => app.start_copy_mode(),
+            KeyCode::Char('s') => match app.selected_message.and_then(|i| app.last_code_block_in(i)) {
+                Some(number) => app.start_code_block_save(Some(number)),
+                None => app.notify("No code block in this message"),
+            },
+            KeyCode::Char('p') => {
+                if let Some(i) = app.selected_message {
+                    match app.toggle_pin(i) {
+                        Ok(true) => app.notify("Pinned: always sent to the model"),
+                        Ok(false) => app.notify("Unpinned"),
+                        Err(e) => app.notify(e.to_string()),
+                    }
+                }
+            }
+            KeyCode::Char('m') => {
+                if let Some(i) = app.selected_message {
+                    match app.toggle_bookmark(i) {
+                        Ok(true) => app.notify("Bookmarked (/bookmarks to list)"),
+                        Ok(false) => app.notify("Bookmark removed"),
+                        Err(e) => app.notify(e.to_string()),
                     }
-                });
+                }
+            }
+            KeyCode::Char('r') => match app.send_to_read_later(app.selected_message) {
+                Ok(()) => app.notify(format!("Saved for later ({} queued)", app.read_later.len())),
+                Err(e) => app.notify(e.to_string()),
+            },
+            KeyCode::Char(c @ '1'..='9') => {
+                let number = c as usize - '0' as usize;
+                if let Err(e) = app.open_citation(app.selected_message, number) {
+                    app.notify(e.to_string());
+                }
             }
+            _ => {}
         }
-        KeyCode::Char('n') if modifiers.contains(event::KeyModifiers::CONTROL) => {
-            app.reset_conversation();
+        return None;
+    }
+
+    // Handle CopyMode specific input (vim-like cursor, selection and yank)
+    if app.mode == app::AppMode::CopyMode {
+        match key {
+            KeyCode::Esc | KeyCode::Char('q') => app.exit_copy_mode(),
+            KeyCode::Up | KeyCode::Char('k') => app.move_copy_cursor(-1),
+            KeyCode::Down | KeyCode::Char('j') => app.move_copy_cursor(1),
+            KeyCode::PageUp => app.move_copy_cursor(-10),
+            KeyCode::PageDown => app.move_copy_cursor(10),
+            KeyCode::Home | KeyCode::Char('g') => app.copy_cursor = 0,
+            KeyCode::End | KeyCode::Char('G') => app.copy_cursor_to_end(),
+            KeyCode::Char('v' | 'V') => app.toggle_copy_anchor(),
+            KeyCode::Char('y') | KeyCode::Enter => {
+                let (start, end) = app.copy_selection();
+                let text = ui::widgets::history_text(app, start, end);
+                match clipboard::copy(&text) {
+                    Ok(_) => app.notify(format!("Yanked {} line(s)", end - start + 1)),
+                    Err(e) => app.notify(format!("Copy failed: {e}")),
+                }
+                app.exit_copy_mode();
+            }
+            _ => {}
         }
-        KeyCode::Tab => {
-            // Toggle visibility of <thinking> blocks
-            app.toggle_thinking();
+        return None;
+    }
+
+    if let Some(action) = app.keymap.action(key, modifiers) {
+        return run_action(app, action, client, event_tx);
+    }
+
+    match key {
+        KeyCode::Char(c @ '1'..='9') if modifiers.contains(event::KeyModifiers::ALT) => {
+            copy_code_block(app, c as usize - '0' as usize);
         }
         
-        // Navigation keys ALWAYS scroll history
-        KeyCode::Up => app.scroll_up(1),
-        KeyCode::Down => app.scroll_down(1),
-        KeyCode::PageUp => app.scroll_up(10),
-        KeyCode::PageDown => app.scroll_down(10),
-        KeyCode::Home => app.scroll_to_top(),
-        KeyCode::End => app.scroll_to_bottom(),
+        // Up/Down recall sent prompts while the input is empty, otherwise scroll history
+        KeyCode::Up if !app.recall_previous_input() => app.scroll_up(1),
+        KeyCode::Down if !app.recall_next_input() => app.scroll_down(1),
+        // Home/End move within the input; with an empty input they scroll history
+        KeyCode::Home if app.input_buffer.is_empty() => app.scroll_to_top(),
+        KeyCode::End if app.input_buffer.is_empty() => app.scroll_to_bottom(),
+        KeyCode::Home => app.move_cursor_home(),
+        KeyCode::End => app.move_cursor_end(),
+        KeyCode::Left if modifiers.contains(event::KeyModifiers::CONTROL) => app.move_cursor_word_left(),
+        KeyCode::Right if modifiers.contains(event::KeyModifiers::CONTROL) => app.move_cursor_word_right(),
+        KeyCode::Left => app.move_cursor_left(),
+        KeyCode::Right => app.move_cursor_right(),
         
         // Editing keys ALWAYS affect input
         KeyCode::Backspace => {
-            app.input_buffer.pop();
-        },
-        KeyCode::Enter if !app.is_loading => {
-            if !app.input_buffer.is_empty() {
-                return Some(send_message(app, client, event_tx));
-            }
+            app.delete_before_cursor();
+            app.input_history.reset();
         },
+        KeyCode::Delete => {
+            app.delete_at_cursor();
+            app.input_history.reset();
+        }
         
         // Typing characters ALWAYS go to input
         KeyCode::Char(c) => {
-            app.input_buffer.push(c);
+            app.insert_char(c);
+            app.input_history.reset();
         }
         
         _ => {}
@@ -337,105 +972,1144 @@ fn handle_keyboard_input(
     None
 }
 
-fn send_message(
+/// Run a chat action bound in the keymap. Returns the response task when it sends
+/// a message.
+fn run_action(
     app: &mut App,
+    action: Action,
     client: &OllamaClient,
     event_tx: &mpsc::UnboundedSender<AppEvent>,
-) -> JoinHandle<()> {
-    let user_msg = app.input_buffer.clone();
-
-    // Add user message
-    app.messages
-        .push(models::Message::new_with_token_count(
-            models::MessageRole::User,
-            user_msg.clone(),
-        ));
+) -> Option<JoinHandle<()>> {
+    if app.read_only && changes_conversation(app, action) {
+        app.notify(app::VIEWER_NOTICE);
+        return None;
+    }
+    match action {
+        Action::Quit => app.quit(),
+        Action::ToggleHelp => app.toggle_help(),
+        Action::ToggleInfo => app.toggle_info(),
+        Action::Search => app.open_search(None),
+        Action::Find => app.start_find(),
+        Action::OpenLink => app.start_link_select(),
+        Action::SwitchModel if !app.is_loading => open_model_picker(app, client, event_tx),
+        Action::NewChat => app.reset_conversation(),
+        Action::SelectMessage => app.start_message_selection(),
+        Action::ListConversations => app.open_conversation_list(),
+        Action::Settings => app.open_settings(),
+        Action::SwitchPersona => app.open_persona_selector(),
+        Action::InsertTemplate => app.open_template_selector(),
+        Action::CopyLastResponse => copy_last_response(app),
+        Action::Undo => {
+            if let Err(e) = app.undo_last_exchange() {
+                app.notify(e.to_string());
+            }
+        }
+        // Toggle visibility of <thinking> blocks
+        Action::ToggleThinking => app.toggle_thinking(),
+        Action::ScrollUp => app.scroll_up(10),
+        Action::ScrollDown => app.scroll_down(10),
+        Action::ScrollTop => app.scroll_to_top(),
+        Action::ScrollBottom => app.scroll_to_bottom(),
+        Action::PreviousMessage => app.jump_to_previous_message(),
+        Action::NextMessage => app.jump_to_next_message(),
+        Action::DeleteWord => {
+            app.delete_word_before_cursor();
+            app.input_history.reset();
+        }
+        Action::ClearInput => {
+            app.take_input();
+            app.input_history.reset();
+        }
+        Action::Send if commands::parse(&app.input_buffer).is_some() => {
+            let input = app.take_input();
+            app.remember_input(&input);
+            if let Some(command) = commands::parse(&input) {
+                match command {
+                    Ok(command) => run_command(app, &command, client, event_tx),
+                    Err(e) => app.notify(e.to_string()),
+                }
+            }
+        }
+        Action::Send if app.is_loading && !app.input_buffer.is_empty() => app.queue_input(),
+        Action::Send if !app.input_buffer.is_empty() => {
+            if !app.confirm_token_budget() {
+                return None;
+            }
+            let input = app.take_input();
+            app.remember_input(&input);
+            return Some(send_message(app, input, client, event_tx));
+        },
+        // Paste and the debug overlay are handled before the mode-specific keys;
+        // switching models waits for the response, and sending for some input
+        Action::Paste | Action::ToggleDebug | Action::SwitchModel | Action::Send => {}
+    }
+    None
+}
 
-    // Add placeholder for AI response
-    app.messages.push(models::Message::new(
-        models::MessageRole::Assistant,
-        String::new(),
-        0,
-    ));
+/// Whether `action` would change the conversation or need the model, which
+/// `yumchat view` doesn't allow
+fn changes_conversation(app: &App, action: Action) -> bool {
+    match action {
+        Action::Send => !matches!(commands::parse(&app.input_buffer), Some(Ok(command)) if command.reads_only()),
+        Action::SwitchModel
+        | Action::NewChat
+        | Action::ListConversations
+        | Action::Settings
+        | Action::SwitchPersona
+        | Action::InsertTemplate
+        | Action::Undo => true,
+        _ => false,
+    }
+}
 
-    app.input_buffer.clear();
+/// List the installed models in the background, then open the picker
+fn open_model_picker(app: &mut App, client: &OllamaClient, event_tx: &mpsc::UnboundedSender<AppEvent>) {
     app.is_loading = true;
-    app.generation_start_time = None;
-    app.tokens_per_second = 0.0;
-    
-    // Auto-scroll to show user message and prepare for AI response
-    app.scroll_to_bottom();
-
-    // Spawn async task to get AI response
     let client_clone = client.clone();
-    let model = app.current_model.clone();
     let tx = event_tx.clone();
-
     tokio::spawn(async move {
-        let request = api::GenerateRequest {
-            model,
-            prompt: user_msg,
-            system: None,
-            stream: true,
-        };
-
-        match client_clone.generate_stream(request).await {
-            Ok(mut stream) => {
-                let mut received_done = false;
-                let mut in_thinking_block = false;
-                
-                while let Some(result) = stream.next().await {
-                    match result {
-                        Ok(response) => {
-                            // Handle thinking content
-                            if !response.thinking.is_empty() {
-                                if !in_thinking_block {
-                                    let _ = tx.send(AppEvent::AiResponseChunk("<thinking>\n".to_string()));
-                                    in_thinking_block = true;
-                                }
-                                let _ = tx.send(AppEvent::AiResponseChunk(response.thinking));
-                            } 
-                            
-                            // Handle regular response content
-                            if !response.response.is_empty() {
-                                if in_thinking_block {
-                                    let _ = tx.send(AppEvent::AiResponseChunk("\n</thinking>\n".to_string()));
-                                    in_thinking_block = false;
-                                }
-                                let _ = tx.send(AppEvent::AiResponseChunk(response.response));
-                            }
-                            
-                            if response.done {
-                                if in_thinking_block {
-                                    let _ = tx.send(AppEvent::AiResponseChunk("\n</thinking>\n".to_string()));
-                                    in_thinking_block = false; // Not strictly needed but good for correctness
-                                }
-                                let _ = tx.send(AppEvent::AiResponseDone);
-                                received_done = true;
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                            let _ = tx.send(AppEvent::AiError(e.to_string()));
-                            received_done = true;
-                            break;
-                        }
-                    }
-                }
-                
-                // If stream ended without explicit done signal or error, ensure we unblock UI
-                if !received_done {
-                    if in_thinking_block {
-                        let _ = tx.send(AppEvent::AiResponseChunk("\n</thinking>\n".to_string()));
-                    }
-                    let _ = tx.send(AppEvent::AiResponseDone);
-                }
+        match client_clone.list_models().await {
+            Ok(models) => {
+                let names = models.into_iter().map(|m| m.name).collect();
+                let _ = tx.send(AppEvent::ModelsLoaded(names));
             }
             Err(e) => {
                 let _ = tx.send(AppEvent::AiError(e.to_string()));
             }
         }
-    })
+    });
+}
+
+/// Make `model` current and fetch its details in the background
+fn switch_model(
+    app: &mut App,
+    model: String,
+    client: &OllamaClient,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) {
+    app.current_model.clone_from(&model);
+    app.model_details = None;
+    app.model_capabilities.clear();
+    app.apply_model_settings();
+
+    // Spawn task to fetch model info
+    let client_clone = client.clone();
+    let tx = event_tx.clone();
+    tokio::spawn(async move {
+        if let Ok(info) = client_clone.show_model(&model).await {
+            let _ = tx.send(AppEvent::ModelInfoLoaded(Box::new(info)));
+        }
+    });
+}
+
+/// Capabilities, details and context window of the current model, from `/api/show`
+fn apply_model_info(app: &mut App, info: api::ShowResponse) {
+    if let Some(size) = info.context_length() {
+        app.context_window_size = size;
+    }
+    app.model_capabilities = info.capabilities;
+    app.model_details = info.details;
+}
+
+/// Look up the installed models and their context windows in the background, for
+/// models.json to be reconciled with
+fn sync_model_settings(client: &OllamaClient, event_tx: &mpsc::UnboundedSender<AppEvent>) {
+    let client = client.clone();
+    let tx = event_tx.clone();
+    tokio::spawn(async move {
+        let Ok(models) = client.list_models().await else {
+            return;
+        };
+        let mut installed = Vec::new();
+        for model in models {
+            let context_window = client
+                .show_model(&model.name)
+                .await
+                .ok()
+                .and_then(|info| info.context_length());
+            installed.push((model.name, context_window));
+        }
+        let _ = tx.send(AppEvent::ModelsInstalled(installed));
+    });
+}
+
+/// Summarize the oldest messages in the background
+fn summarize_context(
+    client: &OllamaClient,
+    model: String,
+    id: uuid::Uuid,
+    covers: usize,
+    prompt: String,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) {
+    let client = client.clone();
+    let tx = event_tx.clone();
+    tokio::spawn(async move {
+        let request = api::GenerateRequest {
+            model,
+            prompt,
+            system: None,
+            stream: false,
+            options: None,
+        };
+        let event = match client.generate(request).await {
+            Ok(response) if !response.response.trim().is_empty() => {
+                AppEvent::ContextSummarized(id, covers, response.response.trim().to_string())
+            }
+            Ok(_) => AppEvent::Notify("Summarizing old messages failed: empty reply".to_string()),
+            Err(e) => AppEvent::Notify(format!("Summarizing old messages failed: {e}")),
+        };
+        let _ = tx.send(event);
+    });
+}
+
+/// Ask the model for facts worth remembering in the background
+fn extract_memory(
+    client: &OllamaClient,
+    model: String,
+    prompt: String,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) {
+    let client = client.clone();
+    let tx = event_tx.clone();
+    tokio::spawn(async move {
+        let request = api::GenerateRequest {
+            model,
+            prompt,
+            system: None,
+            stream: false,
+            options: None,
+        };
+        if let Ok(response) = client.generate(request).await {
+            let _ = tx.send(AppEvent::MemoryExtracted(response.response));
+        }
+    });
+}
+
+/// Classify a conversation in the background. A reply that names no known tag
+/// leaves the conversation untagged.
+fn tag_conversation(
+    client: &OllamaClient,
+    model: String,
+    id: uuid::Uuid,
+    prompt: String,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) {
+    let client = client.clone();
+    let tx = event_tx.clone();
+    tokio::spawn(async move {
+        let request = api::GenerateRequest {
+            model,
+            prompt,
+            system: None,
+            stream: false,
+            options: None,
+        };
+        if let Ok(response) = client.generate(request).await {
+            if let Some(tag) = tagging::parse_tag(&response.response) {
+                let _ = tx.send(AppEvent::ConversationTagged(id, tag.to_string()));
+            }
+        }
+    });
+}
+
+/// Run each experiment value in turn, streaming outputs into the panel's columns.
+/// Runs are sequential since a local server generates one response at a time anyway.
+fn run_experiment(app: &mut App, client: &OllamaClient, event_tx: &mpsc::UnboundedSender<AppEvent>) {
+    let Some(experiment) = app.experiment.as_mut() else {
+        return;
+    };
+    let requests: Vec<api::ChatRequest> = experiment
+        .runs
+        .iter()
+        .map(|run| api::ChatRequest {
+            model: experiment.model.clone(),
+            messages: experiment.history.clone(),
+            stream: true,
+            options: Some(run.options.clone()),
+        })
+        .collect();
+    let client = client.clone();
+    let tx = event_tx.clone();
+    experiment.task = Some(tokio::spawn(async move {
+        for (index, request) in requests.into_iter().enumerate() {
+            let result = async {
+                let mut stream = client.chat_stream(request).await?;
+                while let Some(response) = stream.next().await {
+                    let response = response?;
+                    // Thinking is left out so the columns compare answers
+                    if !response.message.content.is_empty() {
+                        let _ = tx.send(AppEvent::ExperimentChunk(index, response.message.content));
+                    }
+                    if response.done {
+                        break;
+                    }
+                }
+                anyhow::Ok(())
+            }
+            .await;
+            let _ = tx.send(AppEvent::ExperimentRunDone(index, result.err().map(|e| e.to_string())));
+        }
+    }));
+}
+
+fn handle_find_keys(app: &mut App, key: KeyCode, modifiers: event::KeyModifiers) {
+    let editing = app.find.as_ref().is_some_and(|find| find.editing);
+    match key {
+        KeyCode::Esc => {
+            app.close_find();
+            app.scroll_to_bottom();
+        }
+        KeyCode::Enter if editing => app.submit_find(),
+        // Enter on a match closes the search and stays there
+        KeyCode::Enter => app.close_find(),
+        KeyCode::Backspace if editing => {
+            if let Some(find) = app.find.as_mut() {
+                find.query.pop();
+            }
+        }
+        KeyCode::Char('f') if modifiers.contains(event::KeyModifiers::CONTROL) => app.start_find(),
+        KeyCode::Char(c) if editing => {
+            if let Some(find) = app.find.as_mut() {
+                find.query.push(c);
+            }
+        }
+        KeyCode::Char('n') => app.next_find_match(true),
+        KeyCode::Char('N') => app.next_find_match(false),
+        KeyCode::Char('/') => app.start_find(),
+        _ => {}
+    }
+}
+
+/// `mouse_enabled` applies as soon as config.toml is saved
+fn sync_mouse_capture(app: &App, captured: &mut bool) -> Result<()> {
+    if app.config.mouse_enabled != *captured {
+        *captured = app.config.mouse_enabled;
+        if *captured {
+            execute!(io::stdout(), EnableMouseCapture)?;
+        } else {
+            execute!(io::stdout(), DisableMouseCapture)?;
+        }
+    }
+    Ok(())
+}
+
+/// Mouse events, which only arrive with `mouse_enabled`
+fn handle_mouse(
+    app: &mut App,
+    mouse: MouseEvent,
+    client: &OllamaClient,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) -> Option<JoinHandle<()>> {
+    let position = Position::new(mouse.column, mouse.row);
+    match mouse.kind {
+        MouseEventKind::ScrollUp => app.mouse_wheel(true, position),
+        MouseEventKind::ScrollDown => app.mouse_wheel(false, position),
+        MouseEventKind::Down(MouseButton::Left) => {
+            // Clicking a list item picks it, as Enter would
+            if app.click_list_item(position) {
+                return handle_keyboard_input(app, KeyCode::Enter, event::KeyModifiers::NONE, client, event_tx);
+            }
+            if app.mode == app::AppMode::Chat && !app.click_input(position) {
+                if let Some(link) = ui::widgets::link_at(app, position) {
+                    match browser::open(&link) {
+                        Ok(()) => app.notify(format!("Opened {link}")),
+                        Err(e) => app.notify(e.to_string()),
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    None
+}
+
+fn handle_link_keys(app: &mut App, key: KeyCode) {
+    let chosen = match key {
+        KeyCode::Esc => return app.close_link_select(),
+        KeyCode::Backspace => {
+            if let Some(select) = app.link_select.as_mut() {
+                select.typed.pop();
+            }
+            return;
+        }
+        // Scrolling renumbers the links on screen
+        KeyCode::PageUp => return app.scroll_up(10),
+        KeyCode::PageDown => return app.scroll_down(10),
+        KeyCode::Enter => app.choose_link().map(Some),
+        KeyCode::Char(c) if c.is_ascii_digit() => app.type_link_digit(c),
+        _ => return,
+    };
+    match chosen {
+        Ok(Some(link)) => match browser::open(&link) {
+            Ok(()) => app.notify(format!("Opened {link}")),
+            Err(e) => app.notify(e.to_string()),
+        },
+        Ok(None) => {}
+        Err(e) => app.notify(e.to_string()),
+    }
+}
+
+fn handle_search_keys(
+    app: &mut App,
+    key: KeyCode,
+    client: &OllamaClient,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) {
+    match key {
+        KeyCode::Esc => app.mode = app::AppMode::Chat,
+        KeyCode::Up => app.select_previous_search_hit(),
+        KeyCode::Down => app.select_next_search_hit(),
+        // Enter searches for a new query, then opens the highlighted hit
+        KeyCode::Enter if app.search_query != app.searched_query => app.run_search(),
+        KeyCode::Enter => match app.open_selected_search_hit() {
+            Ok(Some(model)) => {
+                switch_model(app, model.clone(), client, event_tx);
+                check_model_installed(model, client, event_tx);
+            }
+            Ok(None) => {}
+            Err(e) => app.notify(format!("Failed to open conversation: {e}")),
+        },
+        KeyCode::Backspace => {
+            app.search_query.pop();
+        }
+        KeyCode::Char(c) => app.search_query.push(c),
+        _ => {}
+    }
+}
+
+fn handle_read_later_keys(
+    app: &mut App,
+    key: KeyCode,
+    client: &OllamaClient,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) {
+    match key {
+        KeyCode::Esc | KeyCode::Char('q') => app.mode = app::AppMode::Chat,
+        KeyCode::Left | KeyCode::Char('h') => app.select_previous_read_later(),
+        KeyCode::Right | KeyCode::Char('l') => app.select_next_read_later(),
+        KeyCode::Up | KeyCode::Char('k') => app.read_later_scroll = app.read_later_scroll.saturating_sub(1),
+        KeyCode::Down | KeyCode::Char('j') => app.read_later_scroll = app.read_later_scroll.saturating_add(1),
+        KeyCode::PageUp => app.read_later_scroll = app.read_later_scroll.saturating_sub(10),
+        KeyCode::PageDown => app.read_later_scroll = app.read_later_scroll.saturating_add(10),
+        KeyCode::Char('d') | KeyCode::Enter => {
+            if let Err(e) = app.mark_read_later_done() {
+                app.notify(e.to_string());
+            }
+        }
+        KeyCode::Char('o') => match app.open_read_later_source() {
+            Ok(Some(model)) => {
+                switch_model(app, model.clone(), client, event_tx);
+                check_model_installed(model, client, event_tx);
+            }
+            Ok(None) => {}
+            Err(e) => app.notify(e.to_string()),
+        },
+        _ => {}
+    }
+}
+
+fn handle_experiment_keys(app: &mut App, key: KeyCode) {
+    let Some(experiment) = app.experiment.as_mut() else {
+        app.close_experiment();
+        return;
+    };
+    match key {
+        KeyCode::Esc | KeyCode::Char('q') => app.close_experiment(),
+        KeyCode::Left | KeyCode::Char('h') => experiment.select_previous(),
+        KeyCode::Right | KeyCode::Char('l') | KeyCode::Tab => experiment.select_next(),
+        KeyCode::Up | KeyCode::Char('k') => experiment.scroll = experiment.scroll.saturating_sub(1),
+        KeyCode::Down | KeyCode::Char('j') => experiment.scroll = experiment.scroll.saturating_add(1),
+        KeyCode::Enter => {
+            if let Err(e) = app.keep_experiment_result() {
+                app.notify(e.to_string());
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Ask to pull `model` (via `ModelMissing`) if the server doesn't have it
+fn check_model_installed(model: String, client: &OllamaClient, event_tx: &mpsc::UnboundedSender<AppEvent>) {
+    let client = client.clone();
+    let tx = event_tx.clone();
+    tokio::spawn(async move {
+        if matches!(client.has_model(&model).await, Ok(false)) {
+            let _ = tx.send(AppEvent::ModelMissing(model));
+        }
+    });
+}
+
+/// Download `model` in the background, reporting progress in the status bar
+fn pull_model(app: &mut App, model: String, client: &OllamaClient, event_tx: &mpsc::UnboundedSender<AppEvent>) {
+    app.pull_progress = Some((model.clone(), "starting".to_string()));
+    let client = client.clone();
+    let tx = event_tx.clone();
+    tokio::spawn(async move {
+        let result = async {
+            let mut stream = client.pull_stream(&model).await?;
+            while let Some(progress) = stream.next().await {
+                let progress = progress?;
+                if let Some(error) = progress.error {
+                    anyhow::bail!(error);
+                }
+                let status = match progress.percent() {
+                    Some(percent) => format!("{percent}%"),
+                    None => progress.status,
+                };
+                let _ = tx.send(AppEvent::PullProgress(model.clone(), status));
+            }
+            Ok(())
+        }
+        .await;
+
+        match result {
+            Ok(()) => {
+                let _ = tx.send(AppEvent::ModelPulled(model.clone()));
+                if let Ok(info) = client.show_model(&model).await {
+                    let _ = tx.send(AppEvent::ModelInfoLoaded(Box::new(info)));
+                }
+            }
+            Err(e) => {
+                let _ = tx.send(AppEvent::PullFailed(model, e.to_string()));
+            }
+        }
+    });
+}
+
+fn handle_collections_keys(
+    app: &mut App,
+    key: KeyCode,
+    client: &OllamaClient,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) {
+    if let Some((_, input)) = app.collection_input.as_mut() {
+        match key {
+            KeyCode::Esc => app.collection_input = None,
+            KeyCode::Enter => {
+                if let Err(e) = app.commit_collection_input() {
+                    app.notify(e.to_string());
+                }
+            }
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) => input.push(c),
+            _ => {}
+        }
+        return;
+    }
+
+    let result = match key {
+        KeyCode::Esc => {
+            app.mode = app::AppMode::Chat;
+            Ok(())
+        }
+        KeyCode::Up => {
+            app.select_previous_collection_row();
+            Ok(())
+        }
+        KeyCode::Down => {
+            app.select_next_collection_row();
+            Ok(())
+        }
+        KeyCode::Char('n') => {
+            app.start_collection_input(app::CollectionPrompt::Name);
+            Ok(())
+        }
+        KeyCode::Char('a') => {
+            app.start_collection_input(app::CollectionPrompt::Path);
+            Ok(())
+        }
+        KeyCode::Char('d') | KeyCode::Delete => app.delete_selected_collection_row(),
+        KeyCode::Char('m') => {
+            app.start_collection_input(app::CollectionPrompt::Model);
+            Ok(())
+        }
+        KeyCode::Char('r') => {
+            reindex_collection(app, client, event_tx);
+            Ok(())
+        }
+        KeyCode::Char('e') => {
+            migrate_collection(app, client, event_tx);
+            Ok(())
+        }
+        KeyCode::Enter => app.selected_collection_name().map_or(Ok(()), |name| {
+            app.activate_collection(&name).map(|()| app.mode = app::AppMode::Chat)
+        }),
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        app.notify(e.to_string());
+    }
+}
+
+/// The highlighted collection, unless a background index job is already running
+fn collection_to_index(app: &mut App) -> Option<rag::collections::Collection> {
+    let collection = app
+        .selected_collection_name()
+        .and_then(|name| app.collections.get(&name).cloned())?;
+    if let Some((running, _, _)) = &app.index_progress {
+        app.notify(format!("Already indexing '{running}'"));
+        return None;
+    }
+    Some(collection)
+}
+
+/// Rebuild the highlighted collection's index from its files in the background
+fn reindex_collection(app: &mut App, client: &OllamaClient, event_tx: &mpsc::UnboundedSender<AppEvent>) {
+    let Some(collection) = collection_to_index(app) else {
+        return;
+    };
+    if collection.paths.is_empty() {
+        app.notify("Add a path first (a)");
+        return;
+    }
+
+    let mut config = app.config.rag.clone();
+    config.embedding_model = collection.target_model(&app.config.rag.embedding_model).to_string();
+    app.notify(format!("Indexing '{}' with {}...", collection.name, config.embedding_model));
+    app.index_progress = Some((collection.name.clone(), 0, 0));
+
+    let client = client.clone();
+    let tx = event_tx.clone();
+    tokio::spawn(async move {
+        let name = collection.name;
+        let progress = |done, total| {
+            let _ = tx.send(AppEvent::IndexProgress(name.clone(), done, total));
+        };
+        let event = match rag::build_index(&client, &config, &collection.paths, progress).await {
+            Ok(index) => AppEvent::CollectionIndexed(name, std::sync::Arc::new(index)),
+            Err(e) => AppEvent::CollectionIndexFailed(name, e.to_string()),
+        };
+        let _ = tx.send(event);
+    });
+}
+
+/// Re-embed the highlighted collection's stored chunks with its current embedding model
+fn migrate_collection(app: &mut App, client: &OllamaClient, event_tx: &mpsc::UnboundedSender<AppEvent>) {
+    let Some(collection) = collection_to_index(app) else {
+        return;
+    };
+    let model = collection.target_model(&app.config.rag.embedding_model).to_string();
+    if !collection.needs_migration(&app.config.rag.embedding_model) {
+        app.notify(format!("'{}' is already embedded with {model}", collection.name));
+        return;
+    }
+    let index = match app.collections.load_index(&collection.name) {
+        Ok(index) => index,
+        Err(e) => {
+            app.notify(e.to_string());
+            return;
+        }
+    };
+    app.notify(format!("Re-embedding '{}': {} → {model}...", collection.name, index.embedding_model));
+    app.index_progress = Some((collection.name.clone(), 0, index.chunks.len()));
+
+    let client = client.clone();
+    let tx = event_tx.clone();
+    tokio::spawn(async move {
+        let name = collection.name;
+        let progress = |done, total| {
+            let _ = tx.send(AppEvent::IndexProgress(name.clone(), done, total));
+        };
+        let event = match rag::reembed(&client, &index, &model, progress).await {
+            Ok(index) => AppEvent::CollectionIndexed(name, std::sync::Arc::new(index)),
+            Err(e) => AppEvent::CollectionIndexFailed(name, e.to_string()),
+        };
+        let _ = tx.send(event);
+    });
+}
+
+fn handle_settings_keys(app: &mut App, key: KeyCode) {
+    if let Some(input) = app.settings_input.as_mut() {
+        match key {
+            KeyCode::Esc => app.settings_input = None,
+            KeyCode::Enter => {
+                if let Err(e) = app.commit_setting_input() {
+                    app.notify(e.to_string());
+                }
+            }
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) => input.push(c),
+            _ => {}
+        }
+        return;
+    }
+
+    match key {
+        KeyCode::Esc => app.close_settings(),
+        KeyCode::Up => app.select_previous_setting(),
+        KeyCode::Down => app.select_next_setting(),
+        KeyCode::Enter => app.start_editing_setting(),
+        KeyCode::Char('s') => {
+            if let Some(config) = app.apply_settings() {
+                match config::save_config(config) {
+                    Ok(()) => app.notify("Settings saved"),
+                    Err(e) => app.notify(format!("Failed to save settings: {e}")),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Switch to a theme preset and save it to config.toml
+fn apply_theme(app: &mut App, name: &str) {
+    let saved = app.apply_theme_preset(name).map(config::save_config);
+    match saved {
+        Ok(Ok(())) => app.notify(format!("Theme: {name}")),
+        Ok(Err(e)) => app.notify(format!("Failed to save theme: {e}")),
+        Err(e) => app.notify(e.to_string()),
+    }
+}
+
+fn run_command(
+    app: &mut App,
+    command: &commands::Command,
+    client: &OllamaClient,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) {
+    match command {
+        commands::Command::Rag(Some(path)) => start_rag_index(app, path, client, event_tx),
+        commands::Command::Rag(None) => {
+            let status = app.rag_index.as_ref().map_or_else(
+                || "Retrieval off. Use /rag <path> to index files".to_string(),
+                |index| format!("Retrieval on: {} chunks ({})", index.chunks.len(), index.embedding_model),
+            );
+            app.notify(status);
+        }
+        commands::Command::Collections => app.open_collections(),
+        commands::Command::Model(_) if app.is_loading => app.notify("Wait for the response before switching models"),
+        commands::Command::Model(None) => open_model_picker(app, client, event_tx),
+        commands::Command::Model(Some(name)) => {
+            let model = app.config.resolve_model(name);
+            switch_model(app, model.clone(), client, event_tx);
+            check_model_installed(model, client, event_tx);
+        }
+        commands::Command::Theme(None) => app.open_theme_selector(),
+        commands::Command::Theme(Some(name)) => apply_theme(app, name),
+        commands::Command::RagOff => {
+            app.rag_index = None;
+            app.active_collection = None;
+            app.notify("Retrieval off");
+        }
+        commands::Command::Experiment { parameter, values } => match app.start_experiment(parameter, values) {
+            Ok(()) => run_experiment(app, client, event_tx),
+            Err(e) => app.notify(e.to_string()),
+        },
+        commands::Command::Continue => match continue_response(app, client, event_tx) {
+            Ok(handle) => app.current_task = Some(handle),
+            Err(e) => app.notify(e.to_string()),
+        },
+        commands::Command::Set { name, value } => match app.set_override(name, value) {
+            Ok(()) if name == "system" => app.notify("System prompt set for this conversation"),
+            Ok(()) => app.notify(format!("{name} = {value} for this conversation")),
+            Err(e) => app.notify(e.to_string()),
+        },
+        commands::Command::Unset(name) => match app.unset_override(name) {
+            Ok(()) if name == "all" => app.notify("Conversation overrides cleared"),
+            Ok(()) => app.notify(format!("{name} override cleared")),
+            Err(e) => app.notify(e.to_string()),
+        },
+        commands::Command::Remember(text) => match app.remember(text, memory::MemorySource::User) {
+            Ok(()) => app.notify("Remembered"),
+            Err(e) => app.notify(e.to_string()),
+        },
+        commands::Command::Memory => app.open_memory(),
+        commands::Command::Bookmarks => app.open_bookmarks(),
+        commands::Command::Math => app.toggle_math(),
+        commands::Command::Search(query) => app.open_search(query.as_deref()),
+        commands::Command::Timer(Some(length)) => {
+            app.focus_timer = Some(timer::FocusTimer::start(*length));
+            app.notify(format!("Timer set for {}", timer::format_clock(*length)));
+        }
+        commands::Command::Timer(None) => match app.focus_timer {
+            Some(focus) => app.notify(format!("{} left", timer::format_clock(focus.remaining()))),
+            None => app.notify("No timer running. Start one with /timer 25m"),
+        },
+        commands::Command::TimerOff => app.cancel_timer(),
+        commands::Command::ReadLater => match app.send_to_read_later(None) {
+            Ok(()) => app.notify(format!("Saved for later ({} queued)", app.read_later.len())),
+            Err(e) => app.notify(e.to_string()),
+        },
+        commands::Command::ReviewReadLater => {
+            if let Err(e) = app.open_read_later() {
+                app.notify(e.to_string());
+            }
+        }
+        commands::Command::Source(n) => {
+            if let Err(e) = app.open_citation(None, *n) {
+                app.notify(e.to_string());
+            }
+        }
+        commands::Command::Copy(None) => copy_last_response(app),
+        commands::Command::Copy(Some(n)) => copy_code_block(app, *n),
+        commands::Command::Diagram(n) => export_diagram(app, *n, event_tx),
+        commands::Command::SaveTable { block, path } => save_table(app, *block, path.as_deref()),
+        commands::Command::SaveCodeBlock { block, path: None } => app.start_code_block_save(*block),
+        commands::Command::CodeSave(dir) => match app.save_response_code(dir.as_deref()) {
+            Ok(saved) => app.notify(describe_saved_files(&saved)),
+            Err(e) => app.notify(format!("Code save failed: {e}")),
+        },
+        commands::Command::SaveCodeBlock { block, path: Some(path) } => match app.save_code_block(*block, path) {
+            Ok((number, path)) => app.notify(format!("Saved code block [{number}] to {}", path.display())),
+            Err(e) => app.notify(format!("Save failed: {e}")),
+        },
+        commands::Command::Export { format, path } => match app.export_conversation(*format, path.as_deref()) {
+            Ok(path) => app.notify(format!("Exported to {}", path.display())),
+            Err(e) => app.notify(format!("Export failed: {e}")),
+        },
+        commands::Command::Import(path) => match app.import_conversations(path) {
+            Ok(count) => app.notify(format!("Imported {count} conversation(s) (Ctrl+L to browse)")),
+            Err(e) => app.notify(format!("Import failed: {e}")),
+        },
+        commands::Command::Attach(path) => match app.attach_file(path) {
+            Ok(name) => app.notify(format!("Attached {name} to your next message")),
+            Err(e) => app.notify(format!("Attach failed: {e}")),
+        },
+    }
+}
+
+/// "Archived 3 old conversations: Parser bug, Trip ideas, +1 more"
+fn describe_pruned(pruned: &[models::ConversationMetadata], action: models::RetentionAction) -> String {
+    const SHOWN: usize = 2;
+    let verb = match action {
+        models::RetentionAction::Archive => "Archived",
+        models::RetentionAction::Delete => "Deleted",
+    };
+    let mut titles: Vec<String> = pruned
+        .iter()
+        .take(SHOWN)
+        .map(|c| c.summary.clone().unwrap_or_else(|| "Untitled".to_string()))
+        .collect();
+    if pruned.len() > SHOWN {
+        titles.push(format!("+{} more", pruned.len() - SHOWN));
+    }
+    let noun = if pruned.len() == 1 { "conversation" } else { "conversations" };
+    format!("{verb} {} old {noun}: {}", pruned.len(), titles.join(", "))
+}
+
+/// "Created src/main.rs, snippet.py (skipped existing: Cargo.toml)"
+fn describe_saved_files(saved: &snippets::SavedFiles) -> String {
+    let list = |paths: &[std::path::PathBuf]| {
+        paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
+    };
+    match (saved.created.is_empty(), saved.skipped.is_empty()) {
+        (false, true) => format!("Created {}", list(&saved.created)),
+        (false, false) => format!("Created {} (skipped existing: {})", list(&saved.created), list(&saved.skipped)),
+        (true, _) => format!("Nothing created; already exist: {}", list(&saved.skipped)),
+    }
+}
+
+/// Build a retrieval index for `path` in the background
+fn start_rag_index(
+    app: &mut App,
+    path: &str,
+    client: &OllamaClient,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) {
+    let root = rag::expand_home(path);
+    app.notify(format!("Indexing {} with {}...", root.display(), app.config.rag.embedding_model));
+
+    let client = client.clone();
+    let config = app.config.rag.clone();
+    let tx = event_tx.clone();
+    tokio::spawn(async move {
+        let event = match rag::build_index(&client, &config, std::slice::from_ref(&root), |_, _| {}).await {
+            Ok(index) => AppEvent::RagIndexReady(std::sync::Arc::new(index)),
+            Err(e) => AppEvent::Notify(format!("Indexing failed: {e}")),
+        };
+        let _ = tx.send(event);
+    });
+}
+
+/// Suspend the TUI and open a cited file at its first line in $VISUAL / $EDITOR
+fn open_in_editor<B: Backend>(terminal: &mut Terminal<B>, citation: &models::Citation) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+
+    disable_raw_mode()?;
+    execute!(io::stdout(), DisableBracketedPaste, LeaveAlternateScreen)?;
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(format!("+{}", citation.start_line))
+        .arg(&citation.path)
+        .status();
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, EnableBracketedPaste)?;
+    terminal.clear()?;
+
+    let status = status.map_err(|e| anyhow::anyhow!("Failed to run {program}: {e}"))?;
+    if !status.success() {
+        anyhow::bail!("{program} exited with {status}");
+    }
+    Ok(())
+}
+
+/// Write a CSV/TSV code block to `path` (default `table-N.csv` / `.tsv` in the working directory)
+fn save_table(app: &mut App, number: Option<usize>, path: Option<&str>) {
+    let Some((number, block)) = app.table_block(number) else {
+        app.notify(number.map_or_else(
+            || "No CSV/TSV code block to save".to_string(),
+            |n| format!("Code block [{n}] is not CSV/TSV data"),
+        ));
+        return;
+    };
+    let extension = if ui::markdown::detect_delimiter(&block.code) == Some('\t') { "tsv" } else { "csv" };
+    let path = path.map_or_else(
+        || std::path::PathBuf::from(format!("table-{number}.{extension}")),
+        std::path::PathBuf::from,
+    );
+
+    let mut contents = block.code;
+    contents.push('\n');
+    match std::fs::write(&path, contents) {
+        Ok(()) => app.notify(format!("Saved table [{number}] to {}", path.display())),
+        Err(e) => app.notify(format!("Failed to save table: {e}")),
+    }
+}
+
+/// Render a diagram code block with the configured tool in the background and open it
+fn export_diagram(app: &mut App, number: Option<usize>, event_tx: &mpsc::UnboundedSender<AppEvent>) {
+    let Some((number, block)) = app.diagram_block(number) else {
+        app.notify(number.map_or_else(
+            || "No mermaid or graphviz code block to export".to_string(),
+            |n| format!("Code block [{n}] is not a mermaid or graphviz diagram"),
+        ));
+        return;
+    };
+    let Some(kind) = block.language.as_deref().and_then(diagrams::DiagramKind::from_language) else {
+        return;
+    };
+    let dir = match config::get_config_dir() {
+        Ok(dir) => dir.join("diagrams"),
+        Err(e) => {
+            app.notify(format!("Diagram export failed: {e}"));
+            return;
+        }
+    };
+    let name = format!(
+        "{}-{number}",
+        app.current_conversation.as_ref().map_or_else(|| "unsaved".to_string(), |c| c.id.to_string())
+    );
+
+    app.notify(format!("Rendering diagram [{number}]..."));
+    let config = app.config.diagrams.clone();
+    let tx = event_tx.clone();
+    tokio::task::spawn_blocking(move || {
+        let message = match diagrams::export(&config, kind, &block.code, &dir, &name) {
+            Ok(path) => match diagrams::open(&config, &path) {
+                Ok(()) => format!("Opened {}", path.display()),
+                Err(e) => format!("Saved {} (could not open: {e})", path.display()),
+            },
+            Err(e) => format!("Diagram export failed: {e}"),
+        };
+        let _ = tx.send(AppEvent::Notify(message));
+    });
+}
+
+fn copy_code_block(app: &mut App, number: usize) {
+    let Some(code) = app.code_block_text(number) else {
+        app.notify(format!("No code block [{number}]"));
+        return;
+    };
+    match clipboard::copy(&code) {
+        Ok(_) => app.notify(format!("Copied code block [{number}] to clipboard")),
+        Err(e) => app.notify(format!("Copy failed: {e}")),
+    }
+}
+
+fn copy_last_response(app: &mut App) {
+    let Some(text) = app.last_response_for_clipboard() else {
+        app.notify("No response to copy");
+        return;
+    };
+    match clipboard::copy(&text) {
+        Ok(clipboard::CopyMethod::System) => app.notify("Copied last response to clipboard"),
+        Ok(clipboard::CopyMethod::Osc52) => app.notify("Copied last response via terminal (OSC 52)"),
+        Err(e) => app.notify(format!("Copy failed: {e}")),
+    }
+}
+
+/// Add the excerpts most relevant to `question` (by embedding and keyword match) to the prompt and report them as citations.
+/// Falls back to the bare question if embedding fails.
+async fn retrieve_context(
+    client: &OllamaClient,
+    index: &rag::Index,
+    config: &models::RagConfig,
+    question: &str,
+    tx: &mpsc::UnboundedSender<AppEvent>,
+) -> String {
+    let query = match client.embed(&index.embedding_model, &[question.to_string()]).await {
+        Ok(mut embeddings) => embeddings.pop().unwrap_or_default(),
+        Err(e) => {
+            let _ = tx.send(AppEvent::Notify(format!("Retrieval failed: {e}")));
+            return question.to_string();
+        }
+    };
+    let chunks = index.search(&query, question, config.top_k, config.keyword_weight);
+    let _ = tx.send(AppEvent::CitationsFound(
+        chunks.iter().map(|chunk| chunk.citation.clone()).collect(),
+    ));
+    rag::build_prompt(question, &chunks)
+}
+
+fn send_message(
+    app: &mut App,
+    user_msg: String,
+    client: &OllamaClient,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) -> JoinHandle<()> {
+    // Add user message
+    app.messages
+        .push(models::Message::new_with_token_count(
+            models::MessageRole::User,
+            user_msg.clone(),
+        ).sent_by(None));
+    if let Err(e) = app.store_pending_attachments() {
+        app.notify(format!("Failed to store attachments: {e}"));
+    }
+    app.tee_message(app.messages.len() - 1);
+
+    let persona = app.generation_settings();
+    let mut history = app.chat_history(&persona.system_prompt);
+
+    // Add placeholder for AI response
+    app.messages.push(models::Message::new(
+        models::MessageRole::Assistant,
+        String::new(),
+        0,
+    ).sent_by(Some(&app.current_model)));
+    app.start_generation();
+
+    // Spawn async task to get AI response
+    let client_clone = client.clone();
+    let model = app.current_model.clone();
+    let rag_index = app.rag_index.clone();
+    let rag_config = app.config.rag.clone();
+    let stream = app.config.streaming;
+    let tx = event_tx.clone();
+
+    tokio::spawn(async move {
+        // Retrieved excerpts are added to this turn only, not stored in the conversation
+        if let (Some(index), Some(last)) = (rag_index, history.last_mut()) {
+            last.content = retrieve_context(&client_clone, &index, &rag_config, &user_msg, &tx).await;
+        }
+        let request = api::ChatRequest {
+            model,
+            messages: history,
+            stream,
+            options: Some(persona.options).filter(|o| !o.is_empty()),
+        };
+        stream_chat(&client_clone, request, &tx).await;
+    })
+}
+
+/// Resume a truncated response: send the conversation with an instruction to carry
+/// on, appending the continuation to the same assistant message
+fn continue_response(
+    app: &mut App,
+    client: &OllamaClient,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) -> anyhow::Result<JoinHandle<()>> {
+    app.prepare_continuation()?;
+    let persona = app.generation_settings();
+    let mut history = app.chat_history(&persona.system_prompt);
+    history.push(api::ChatMessage::new("user", app::CONTINUE_PROMPT));
+    app.start_generation();
+
+    let client = client.clone();
+    let request = api::ChatRequest {
+        model: app.current_model.clone(),
+        messages: history,
+        stream: app.config.streaming,
+        options: Some(persona.options).filter(|o| !o.is_empty()),
+    };
+    let tx = event_tx.clone();
+    Ok(tokio::spawn(async move {
+        stream_chat(&client, request, &tx).await;
+    }))
+}
+
+/// Stream a chat response into the last message via `AiResponseChunk` events.
+/// Thinking output is wrapped in `<thinking>` tags.
+async fn stream_chat(client: &OllamaClient, request: api::ChatRequest, tx: &mpsc::UnboundedSender<AppEvent>) {
+    match client.chat_stream(request).await {
+        Ok(mut stream) => {
+            let mut received_done = false;
+            let mut in_thinking_block = false;
+
+            while let Some(result) = stream.next().await {
+                match result {
+                    Ok(response) => {
+                        // Handle thinking content
+                        if !response.message.thinking.is_empty() {
+                            if !in_thinking_block {
+                                let _ = tx.send(AppEvent::AiResponseChunk("<thinking>\n".to_string()));
+                                in_thinking_block = true;
+                            }
+                            let _ = tx.send(AppEvent::AiResponseChunk(response.message.thinking));
+                        }
+
+                        // Handle regular response content
+                        if !response.message.content.is_empty() {
+                            if in_thinking_block {
+                                let _ = tx.send(AppEvent::AiResponseChunk("\n</thinking>\n".to_string()));
+                                in_thinking_block = false;
+                            }
+                            let _ = tx.send(AppEvent::AiResponseChunk(response.message.content));
+                        }
+
+                        if response.done {
+                            tracing::debug!(
+                                done_reason = ?response.done_reason,
+                                prompt_tokens = ?response.prompt_eval_count,
+                                tokens = ?response.eval_count,
+                                "chat response done"
+                            );
+                            if in_thinking_block {
+                                let _ = tx.send(AppEvent::AiResponseChunk("\n</thinking>\n".to_string()));
+                                in_thinking_block = false; // Not strictly needed but good for correctness
+                            }
+                            if response.done_reason.as_deref() == Some("length") {
+                                let _ = tx.send(AppEvent::Notify(
+                                    "Response hit the token limit; /continue resumes it".to_string(),
+                                ));
+                            }
+                            let _ = tx.send(AppEvent::AiResponseDone);
+                            received_done = true;
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::error!(error = format!("{e:#}"), "chat stream failed");
+                        let _ = tx.send(AppEvent::AiError(e.to_string()));
+                        received_done = true;
+                        break;
+                    }
+                }
+            }
+
+            // If stream ended without explicit done signal or error, ensure we unblock UI
+            if !received_done {
+                tracing::warn!("chat stream ended without a done message");
+                if in_thinking_block {
+                    let _ = tx.send(AppEvent::AiResponseChunk("\n</thinking>\n".to_string()));
+                }
+                let _ = tx.send(AppEvent::AiResponseDone);
+            }
+        }
+        Err(e) => {
+            tracing::error!(error = format!("{e:#}"), "chat request failed");
+            let _ = tx.send(AppEvent::AiError(e.to_string()));
+        }
+    }
 }
 
 fn run_app<B: Backend>(
@@ -445,26 +2119,47 @@ fn run_app<B: Backend>(
     event_tx: &mpsc::UnboundedSender<AppEvent>,
     event_rx: &mut mpsc::UnboundedReceiver<AppEvent>,
 ) -> Result<()> {
+    let mut mouse_captured = false;
     loop {
+        sync_mouse_capture(app, &mut mouse_captured)?;
         terminal.draw(|f| ui::render(f, app))?;
 
-        // Check for app events (AI responses) first
-        if let Ok(app_event) = event_rx.try_recv() {
+        // Check for app events (AI responses) first; drain them all so streaming
+        // keeps up at the low-power frame rate
+        while let Ok(app_event) = event_rx.try_recv() {
+            app.traffic.record(traffic::Direction::Event, || app_event.describe());
             handle_app_event(app, app_event);
         }
 
         // Check for keyboard input with shorter timeout for better responsiveness
-        if event::poll(Duration::from_millis(16))? {  // ~60fps for smooth scrolling
-            if let Event::Key(key) = event::read()? {
+        if event::poll(app.frame_interval())? {
+            let event = event::read()?;
+            // Bracketed paste: the whole snippet arrives at once, newlines intact,
+            // without being replayed as key presses that could trigger shortcuts
+            if let Event::Paste(text) = &event {
+                app.paste(text);
+            }
+            if let Event::Mouse(mouse) = event {
+                if let Some(handle) = handle_mouse(app, mouse, client, event_tx) {
+                    app.current_task = Some(handle);
+                }
+            }
+            if let Event::Key(key) = event {
                 if key.kind == KeyEventKind::Press {
+                    app.dismiss_finished_timer();
+                    // The debug overlay toggles from anywhere, over whatever is open
+                    if app.keymap.action(key.code, key.modifiers) == Some(Action::ToggleDebug) {
+                        app.toggle_debug();
+                        continue;
+                    }
                     // Handle help window first
                     if handle_help_keys(app, key.code, key.modifiers) {
                         continue;
                     }
                     
                     // Handle info window
-                    if app.show_info && (key.code == KeyCode::Esc || 
-                           (key.code == KeyCode::Char('i') && key.modifiers.contains(event::KeyModifiers::CONTROL))) {
+                    if app.show_info && (key.code == KeyCode::Esc ||
+                           app.keymap.action(key.code, key.modifiers) == Some(Action::ToggleInfo)) {
                         app.show_info = false;
                         continue;
                     }
@@ -506,6 +2201,40 @@ fn run_app<B: Backend>(
             }
         }
 
+        // Send the next queued prompt once the previous response is done
+        if let Some(prompt) = app.next_queued_prompt() {
+            app.current_task = Some(send_message(app, prompt, client, event_tx));
+        }
+
+        if let Some((id, covers, prompt)) = app.compression_due() {
+            summarize_context(client, app.current_model.clone(), id, covers, prompt, event_tx);
+        }
+        if let Some(prompt) = app.memory_extraction_due() {
+            extract_memory(client, app.current_model.clone(), prompt, event_tx);
+        }
+        if let Some((id, prompt)) = app.conversation_to_tag() {
+            tag_conversation(client, app.current_model.clone(), id, prompt, event_tx);
+        }
+
+        if app.check_timer() {
+            // Terminal bell, for when yumchat is in a background tab
+            print!("\x07");
+            let _ = io::Write::flush(&mut io::stdout());
+        }
+
+        app.autosave_draft();
+        if app.config_reload_due() {
+            reload_config(app);
+        }
+        app.update_power_state();
+        app.sample_resources();
+
+        if let Some(citation) = app.pending_editor.take() {
+            if let Err(e) = open_in_editor(terminal, &citation) {
+                app.notify(e.to_string());
+            }
+        }
+
         if app.should_quit {
             break;
         }
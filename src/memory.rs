@@ -0,0 +1,95 @@
+// Facts remembered across conversations (`/remember`, `/memory`)
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Longest fact kept from a model extraction; anything longer is not a "fact"
+const MAX_EXTRACTED_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemorySource {
+    /// Saved with `/remember`
+    User,
+    /// Extracted from a conversation by the model
+    Model,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    pub text: String,
+    pub source: MemorySource,
+    pub added_at: DateTime<Utc>,
+}
+
+impl MemoryEntry {
+    pub fn new(text: &str, source: MemorySource) -> Self {
+        Self {
+            text: text.trim().to_string(),
+            source,
+            added_at: Utc::now(),
+        }
+    }
+}
+
+/// `system_prompt` followed by the remembered facts, if there are any
+pub fn with_memory(system_prompt: &str, entries: &[MemoryEntry]) -> String {
+    if entries.is_empty() {
+        return system_prompt.to_string();
+    }
+    let mut prompt = system_prompt.trim_end().to_string();
+    if !prompt.is_empty() {
+        prompt.push_str("\n\n");
+    }
+    prompt.push_str("Things you know about the user from earlier conversations:\n");
+    for entry in entries {
+        let _ = writeln!(prompt, "- {}", entry.text);
+    }
+    prompt
+}
+
+/// Ask the model for lasting facts in the user's latest message
+pub fn extraction_prompt(user_message: &str) -> String {
+    format!(
+        "List lasting facts about the user worth remembering in future conversations \
+         (preferences, projects, tools, constraints) stated in the message below, one \
+         per line starting with \"- \". Reply NONE if there are none.\n\nMessage:\n{user_message}"
+    )
+}
+
+/// The "- fact" lines of an extraction reply, skipping ones already remembered
+pub fn parse_extracted(reply: &str, known: &[MemoryEntry]) -> Vec<String> {
+    reply
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("- "))
+        .map(str::trim)
+        .filter(|fact| !fact.is_empty() && fact.chars().count() <= MAX_EXTRACTED_CHARS)
+        .filter(|fact| !known.iter().any(|entry| entry.text.eq_ignore_ascii_case(fact)))
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_memory() {
+        assert_eq!(with_memory("Be terse.", &[]), "Be terse.");
+        let entries = vec![MemoryEntry::new(" Uses NixOS ", MemorySource::User)];
+        assert_eq!(
+            with_memory("Be terse.\n", &entries),
+            "Be terse.\n\nThings you know about the user from earlier conversations:\n- Uses NixOS\n"
+        );
+        assert!(with_memory("", &entries).starts_with("Things you know"));
+    }
+
+    #[test]
+    fn test_parse_extracted() {
+        let known = vec![MemoryEntry::new("Uses NixOS", MemorySource::User)];
+        let reply = "Sure:\n- Prefers Rust\n- uses nixos\n-\nNONE";
+        assert_eq!(parse_extracted(reply, &known), vec!["Prefers Rust".to_string()]);
+        assert!(parse_extracted("NONE", &known).is_empty());
+    }
+}
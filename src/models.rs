@@ -1,15 +1,63 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
 use uuid::Uuid;
 
+use crate::export::ExportFormat;
+use crate::keymap::{Action, KeyBinding};
+use crate::power::LowPowerMode;
+
 #[allow(dead_code)]
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ConversationMetadata {
     pub id: Uuid,
     pub summary: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub total_tokens: usize,
+    /// Conversation this one was forked from, if any
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,
+    /// Persona active in this conversation
+    #[serde(default)]
+    pub persona: Option<String>,
+    /// Model last used in this conversation, restored when it is reopened
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Settings set with `/set` that apply to this conversation only
+    #[serde(default, skip_serializing_if = "ConversationOverrides::is_empty")]
+    pub overrides: ConversationOverrides,
+    /// Intent tag (coding, writing, ...) shown in the conversation list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Summary sent in place of the oldest messages once the context filled up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_summary: Option<ContextSummary>,
+    /// Citations of retrieval-augmented responses, keyed by message index
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub citations: BTreeMap<usize, Vec<Citation>>,
+    /// Indexes of pinned messages
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub pinned_messages: BTreeSet<usize>,
+    /// Indexes of bookmarked messages
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub bookmarks: BTreeSet<usize>,
+    /// When each message was sent, keyed by message index
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub message_times: BTreeMap<usize, DateTime<Utc>>,
+    /// Model that wrote each response, keyed by message index
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub message_models: BTreeMap<usize, String>,
+    /// Token count of each message, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub message_tokens: Vec<usize>,
+    /// Pinned conversations are listed first
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Archived conversations live in `chats/archive` and are hidden from the list
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
 }
 
 #[allow(dead_code)]
@@ -22,9 +70,150 @@ impl ConversationMetadata {
             created_at: now,
             updated_at: now,
             total_tokens: 0,
+            parent_id: None,
+            persona: None,
+            model: None,
+            overrides: ConversationOverrides::default(),
+            tag: None,
+            context_summary: None,
+            citations: BTreeMap::new(),
+            pinned_messages: BTreeSet::new(),
+            bookmarks: BTreeSet::new(),
+            message_times: BTreeMap::new(),
+            message_models: BTreeMap::new(),
+            message_tokens: Vec::new(),
+            pinned: false,
+            archived: false,
+        }
+    }
+
+    /// Create metadata for a new conversation branched off this one
+    pub fn fork(&self) -> Self {
+        let mut forked = Self::new();
+        forked.parent_id = Some(self.id);
+        forked.persona.clone_from(&self.persona);
+        forked.model.clone_from(&self.model);
+        forked.overrides.clone_from(&self.overrides);
+        forked.tag.clone_from(&self.tag);
+        forked.summary = self.summary.as_ref().map(|s| format!("Fork of {s}"));
+        forked
+    }
+
+    /// Record the citations carried by `messages` (the transcript file only holds text)
+    pub fn store_citations(&mut self, messages: &[Message]) {
+        self.citations = messages
+            .iter()
+            .enumerate()
+            .filter(|(_, message)| !message.citations.is_empty())
+            .map(|(i, message)| (i, message.citations.clone()))
+            .collect();
+    }
+
+    /// Re-attach stored citations to messages loaded from the transcript
+    pub fn restore_citations(&self, messages: &mut [Message]) {
+        for (i, citations) in &self.citations {
+            if let Some(message) = messages.get_mut(*i) {
+                message.citations.clone_from(citations);
+            }
+        }
+    }
+
+    /// Record which of `messages` are pinned
+    pub fn store_pins(&mut self, messages: &[Message]) {
+        self.pinned_messages = messages
+            .iter()
+            .enumerate()
+            .filter(|(_, message)| message.pinned)
+            .map(|(i, _)| i)
+            .collect();
+    }
+
+    /// Re-apply stored pins to messages loaded from the transcript
+    pub fn restore_pins(&self, messages: &mut [Message]) {
+        for i in &self.pinned_messages {
+            if let Some(message) = messages.get_mut(*i) {
+                message.pinned = true;
+            }
+        }
+    }
+
+    /// Record which of `messages` are bookmarked
+    pub fn store_bookmarks(&mut self, messages: &[Message]) {
+        self.bookmarks = messages
+            .iter()
+            .enumerate()
+            .filter(|(_, message)| message.bookmarked)
+            .map(|(i, _)| i)
+            .collect();
+    }
+
+    /// Re-apply stored bookmarks to messages loaded from the transcript
+    pub fn restore_bookmarks(&self, messages: &mut [Message]) {
+        for i in &self.bookmarks {
+            if let Some(message) = messages.get_mut(*i) {
+                message.bookmarked = true;
+            }
+        }
+    }
+
+    /// Record when each of `messages` was sent and which model wrote it
+    pub fn store_origins(&mut self, messages: &[Message]) {
+        self.message_times = messages
+            .iter()
+            .enumerate()
+            .filter_map(|(i, message)| message.sent_at.map(|at| (i, at)))
+            .collect();
+        self.message_models = messages
+            .iter()
+            .enumerate()
+            .filter_map(|(i, message)| message.model.clone().map(|model| (i, model)))
+            .collect();
+    }
+
+    /// Re-apply stored send times and models to messages loaded from the transcript
+    pub fn restore_origins(&self, messages: &mut [Message]) {
+        for (i, at) in &self.message_times {
+            if let Some(message) = messages.get_mut(*i) {
+                message.sent_at = Some(*at);
+            }
+        }
+        for (i, model) in &self.message_models {
+            if let Some(message) = messages.get_mut(*i) {
+                message.model = Some(model.clone());
+            }
+        }
+    }
+
+    /// Record the token count of each of `messages`
+    pub fn store_tokens(&mut self, messages: &[Message]) {
+        self.message_tokens = messages.iter().map(|message| message.tokens).collect();
+    }
+
+    /// Re-apply stored token counts to messages loaded from the transcript
+    pub fn restore_tokens(&self, messages: &mut [Message]) {
+        for (message, tokens) in messages.iter_mut().zip(&self.message_tokens) {
+            message.tokens = *tokens;
         }
     }
 
+    /// Record everything about `messages` the transcript file doesn't hold
+    pub fn store_message_data(&mut self, messages: &[Message]) {
+        self.store_citations(messages);
+        self.store_pins(messages);
+        self.store_bookmarks(messages);
+        self.store_origins(messages);
+        self.store_tokens(messages);
+    }
+
+    /// Re-apply what `store_message_data` recorded to messages loaded from the transcript
+    pub fn restore_message_data(&self, messages: &mut [Message]) {
+        self.restore_citations(messages);
+        self.restore_pins(messages);
+        self.restore_bookmarks(messages);
+        self.restore_origins(messages);
+        self.restore_tokens(messages);
+    }
+
     pub fn update_tokens(&mut self, tokens: usize) {
         self.total_tokens += tokens;
         self.updated_at = Utc::now();
@@ -36,6 +225,27 @@ impl ConversationMetadata {
     }
 }
 
+/// A response queued for reading later, linked back to its conversation
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReadLaterItem {
+    pub conversation_id: Option<Uuid>,
+    pub message_index: usize,
+    /// Conversation title when the response was queued
+    pub title: String,
+    /// The question the response answers
+    pub prompt: String,
+    pub content: String,
+    pub added_at: DateTime<Utc>,
+}
+
+/// The first `covers` messages of a conversation, condensed by the model
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContextSummary {
+    pub covers: usize,
+    pub text: String,
+    pub tokens: usize,
+}
+
 impl Default for ConversationMetadata {
     fn default() -> Self {
         Self::new()
@@ -48,6 +258,35 @@ pub struct Message {
     pub role: MessageRole,
     pub content: String,
     pub tokens: usize,
+    /// Retrieved file excerpts the response was grounded on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<Citation>,
+    /// Always sent to the model, even when older messages are summarized
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Listed in the bookmarks popup for jumping back to
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bookmarked: bool,
+    /// When the message was sent; unknown for ones saved before this was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sent_at: Option<DateTime<Utc>>,
+    /// Model that wrote the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+/// A line range of a local file that was retrieved as context for a response
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Citation {
+    pub path: PathBuf,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl Citation {
+    pub fn label(&self) -> String {
+        format!("{}:{}-{}", self.path.display(), self.start_line, self.end_line)
+    }
 }
 
 #[allow(dead_code)]
@@ -65,6 +304,11 @@ impl Message {
             role,
             content,
             tokens,
+            citations: Vec::new(),
+            pinned: false,
+            bookmarked: false,
+            sent_at: None,
+            model: None,
         }
     }
 
@@ -78,18 +322,215 @@ impl Message {
             role,
             content,
             tokens,
+            citations: Vec::new(),
+            pinned: false,
+            bookmarked: false,
+            sent_at: None,
+            model: None,
         }
     }
+
+    /// The message stamped as sent now, by `model` when a model wrote it
+    pub fn sent_by(mut self, model: Option<&str>) -> Self {
+        self.sent_at = Some(Utc::now());
+        self.model = model.map(ToString::to_string);
+        self
+    }
 }
 
 #[allow(dead_code)]
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
+    #[serde(default = "default_ollama_url")]
     pub ollama_url: String,
-    pub default_model: String,
+    /// One model, or a list to take the first installed one from at startup
+    #[serde(default)]
+    pub default_model: DefaultModel,
     #[serde(default = "default_timeout")]
     pub request_timeout: u64,
+    /// System prompt of conversations whose persona has none (`/set system` overrides it)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub default_system_prompt: String,
+    #[serde(default)]
     pub theme: ThemeConfig,
+    #[serde(default)]
+    pub clipboard: ClipboardConfig,
+    /// Whether thinking blocks start out visible (overridable per model in models.json)
+    #[serde(default)]
+    pub show_thinking_default: bool,
+    /// Take mouse events from the terminal (selecting text then needs Shift)
+    #[serde(default)]
+    pub mouse_enabled: bool,
+    /// Keep the history scrolled to the bottom while a response streams in
+    #[serde(default = "default_true")]
+    pub auto_scroll: bool,
+    /// Name whose message is at the top of the history in a line above it
+    #[serde(default = "default_true")]
+    pub sticky_header: bool,
+    /// Most messages the chat history shows, the latest ones; 0 shows them all.
+    /// Hidden messages are still sent to the model.
+    #[serde(default)]
+    pub scrollback_limit: usize,
+    /// Show responses as they're generated, rather than all at once when done
+    #[serde(default = "default_true")]
+    pub streaming: bool,
+    /// Show LaTeX math (`\( \)`, `\[ \]`, `$$ $$`, `$ $`) as Unicode rather than as
+    /// written; `/math` switches for the session
+    #[serde(default = "default_true")]
+    pub render_math: bool,
+    /// Named presets selectable with the persona picker
+    #[serde(default)]
+    pub personas: BTreeMap<String, Persona>,
+    /// How fenced code blocks are displayed, keyed by fence language
+    #[serde(default = "default_code_render")]
+    pub code_render: BTreeMap<String, CodeRenderMode>,
+    #[serde(default)]
+    pub diagrams: DiagramConfig,
+    #[serde(default)]
+    pub rag: RagConfig,
+    /// Show CPU/RAM/GPU usage in the status bar while a response is generating
+    #[serde(default)]
+    pub resource_monitor: bool,
+    /// Lower frame rate and no animations: `auto` (on battery), `on` or `off`
+    #[serde(default)]
+    pub low_power: LowPowerMode,
+    /// Hint under the input when a prompt looks like it lacks context
+    #[serde(default = "default_true")]
+    pub prompt_lint: bool,
+    /// Classify conversations (coding, writing, ...) in the background for the list view
+    #[serde(default)]
+    pub tag_conversations: bool,
+    /// Print the conversation as markdown to the terminal after the interface closes
+    #[serde(default)]
+    pub print_on_exit: bool,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    #[serde(default)]
+    pub memory: MemoryConfig,
+    #[serde(default)]
+    pub token_budget: TokenBudgetConfig,
+    #[serde(default)]
+    pub export: ExportConfig,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    /// Encrypt conversation files with a passphrase asked for at startup
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypt_conversations: bool,
+    /// Where conversations are stored, instead of the platform data directory
+    /// (`YUMCHAT_DATA_DIR` overrides this)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_dir: Option<String>,
+    /// Default generation options, under those of the persona and `/set`
+    #[serde(default, skip_serializing_if = "GenerationOptions::is_empty")]
+    pub options: GenerationOptions,
+    /// Key chords for actions, replacing their defaults (see `keymap`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<Action, KeyBinding>,
+    /// Short names for models, accepted wherever a model name is (`fast = "qwen3:4b"`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
+    /// Actions whose keys the bottom bar shows while chatting, in order
+    #[serde(default = "crate::keymap::default_bottom_bar")]
+    pub bottom_bar: Vec<Action>,
+}
+
+impl AppConfig {
+    /// Render mode for a fence language (case-insensitive), `code` when unmapped
+    pub fn code_render_mode(&self, language: Option<&str>) -> CodeRenderMode {
+        language
+            .and_then(|lang| {
+                self.code_render
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(lang))
+            })
+            .map_or(CodeRenderMode::Code, |(_, mode)| *mode)
+    }
+
+    /// The model an alias in `[aliases]` stands for, or `name` itself
+    pub fn resolve_model(&self, name: &str) -> String {
+        let name = name.trim();
+        self.aliases.get(name).map_or(name, String::as_str).to_string()
+    }
+
+    /// The first default model (aliases resolved) that is among `installed`; the
+    /// first one when none is or the installed models aren't known
+    pub fn default_model_from(&self, installed: Option<&[String]>) -> String {
+        let candidates: Vec<String> = self
+            .default_model
+            .candidates()
+            .iter()
+            .map(|model| self.resolve_model(model))
+            .collect();
+        installed
+            .and_then(|installed| {
+                candidates.iter().find(|model| {
+                    installed.iter().any(|name| crate::api::model_matches(name, model))
+                })
+            })
+            .or_else(|| candidates.first())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Aliases of `model`, for showing next to it
+    pub fn aliases_of(&self, model: &str) -> Vec<&str> {
+        self.aliases
+            .iter()
+            .filter(|(_, target)| crate::api::model_matches(model, target))
+            .map(|(alias, _)| alias.as_str())
+            .collect()
+    }
+}
+
+fn default_ollama_url() -> String {
+    "http://localhost:11434".to_string()
+}
+
+/// `default_model`: a model name (or alias), or several in order of preference
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DefaultModel {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for DefaultModel {
+    fn default() -> Self {
+        Self::One("qwen3:4b".to_string())
+    }
+}
+
+impl DefaultModel {
+    /// From a comma-separated list, as edited in Settings
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut models: Vec<String> = value
+            .split(',')
+            .map(str::trim)
+            .filter(|model| !model.is_empty())
+            .map(ToString::to_string)
+            .collect();
+        match models.len() {
+            0 => None,
+            1 => models.pop().map(Self::One),
+            _ => Some(Self::Many(models)),
+        }
+    }
+
+    pub fn candidates(&self) -> &[String] {
+        match self {
+            Self::One(model) => std::slice::from_ref(model),
+            Self::Many(models) => models,
+        }
+    }
+}
+
+impl std::fmt::Display for DefaultModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.candidates().join(", "))
+    }
 }
 
 const fn default_timeout() -> u64 {
@@ -99,37 +540,670 @@ const fn default_timeout() -> u64 {
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
-            ollama_url: "http://localhost:11434".to_string(),
-            default_model: "qwen3:4b".to_string(),
+            ollama_url: default_ollama_url(),
+            default_model: DefaultModel::default(),
             request_timeout: default_timeout(),
+            default_system_prompt: String::new(),
             theme: ThemeConfig::default(),
+            clipboard: ClipboardConfig::default(),
+            show_thinking_default: false,
+            mouse_enabled: false,
+            auto_scroll: true,
+            sticky_header: true,
+            scrollback_limit: 0,
+            streaming: true,
+            render_math: true,
+            personas: BTreeMap::new(),
+            code_render: default_code_render(),
+            diagrams: DiagramConfig::default(),
+            rag: RagConfig::default(),
+            resource_monitor: false,
+            low_power: LowPowerMode::default(),
+            prompt_lint: true,
+            tag_conversations: false,
+            print_on_exit: false,
+            compression: CompressionConfig::default(),
+            memory: MemoryConfig::default(),
+            token_budget: TokenBudgetConfig::default(),
+            export: ExportConfig::default(),
+            retention: RetentionConfig::default(),
+            sync: SyncConfig::default(),
+            encrypt_conversations: false,
+            data_dir: None,
+            options: GenerationOptions::default(),
+            keys: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            bottom_bar: crate::keymap::default_bottom_bar(),
         }
     }
 }
 
-#[allow(dead_code, clippy::struct_field_names)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Display behavior for a fenced code block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeRenderMode {
+    /// Regular code block styling
+    #[default]
+    Code,
+    /// Unstyled text exactly as written
+    Raw,
+    /// Raw source of a diagram (mermaid, dot) that can be exported
+    Diagram,
+    /// Comma/tab separated values shown as an aligned table
+    Table,
+    /// JSON pretty-printed
+    Json,
+}
+
+fn default_code_render() -> BTreeMap<String, CodeRenderMode> {
+    [
+        ("mermaid", CodeRenderMode::Diagram),
+        ("dot", CodeRenderMode::Diagram),
+        ("graphviz", CodeRenderMode::Diagram),
+        ("csv", CodeRenderMode::Table),
+        ("tsv", CodeRenderMode::Table),
+        ("json", CodeRenderMode::Json),
+    ]
+    .into_iter()
+    .map(|(lang, mode)| (lang.to_string(), mode))
+    .collect()
+}
+
+/// Interface colors: names (`blue`, `light-red`, `dark-gray`), hex (`#1e90ff`) or
+/// 256-color indexes (`208`). Keys left out come from `preset` (or the default
+/// dark theme); see `ui::theme::Theme`.
+#[allow(clippy::struct_field_names)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "ThemeFile")]
 pub struct ThemeConfig {
+    /// One of `THEME_PRESETS`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
     pub user_message_color: String,
     pub assistant_message_color: String,
     pub border_color: String,
+    /// Popup borders, the selected field, pins and bold text
+    pub accent_color: String,
+    /// The current item in lists
+    pub selection_color: String,
+    pub text_color: String,
+    /// Hints, timestamps and hidden thoughts
+    pub muted_color: String,
+    pub heading_color: String,
+    pub thinking_color: String,
+    pub code_color: String,
+    pub link_color: String,
+    pub error_color: String,
+    pub warning_color: String,
+    pub success_color: String,
+    /// Background of the current find match
+    pub highlight_color: String,
 }
 
 impl Default for ThemeConfig {
     fn default() -> Self {
         Self {
+            preset: None,
             user_message_color: "blue".to_string(),
             assistant_message_color: "green".to_string(),
             border_color: "cyan".to_string(),
+            accent_color: "yellow".to_string(),
+            selection_color: "green".to_string(),
+            text_color: "white".to_string(),
+            muted_color: "dark-gray".to_string(),
+            heading_color: "cyan".to_string(),
+            thinking_color: "magenta".to_string(),
+            code_color: "green".to_string(),
+            link_color: "blue".to_string(),
+            error_color: "red".to_string(),
+            warning_color: "yellow".to_string(),
+            success_color: "green".to_string(),
+            highlight_color: "light-red".to_string(),
         }
     }
 }
 
-#[allow(dead_code)]
+/// Built-in themes, chosen with `preset` in `[theme]` or `/theme`
+pub const THEME_PRESETS: &[&str] = &["dark", "light", "solarized", "gruvbox", "monochrome"];
+
+impl ThemeConfig {
+    /// The colors of a built-in theme
+    pub fn preset(name: &str) -> Option<Self> {
+        // user, assistant, border, accent, selection, text, muted, heading,
+        // thinking, code, link, error, warning, success, highlight
+        let colors = match name {
+            "dark" => return Some(Self { preset: Some(name.to_string()), ..Self::default() }),
+            "light" => [
+                "blue", "black", "blue", "magenta", "blue", "black", "gray", "blue",
+                "magenta", "#2e7d32", "blue", "red", "#b58900", "#2e7d32", "light-yellow",
+            ],
+            "solarized" => [
+                "#268bd2", "#839496", "#2aa198", "#b58900", "#859900", "#93a1a1", "#586e75", "#2aa198",
+                "#d33682", "#859900", "#6c71c4", "#dc322f", "#cb4b16", "#859900", "#cb4b16",
+            ],
+            "gruvbox" => [
+                "#83a598", "#ebdbb2", "#8ec07c", "#fabd2f", "#b8bb26", "#ebdbb2", "#928374", "#8ec07c",
+                "#d3869b", "#b8bb26", "#83a598", "#fb4934", "#fe8019", "#b8bb26", "#fe8019",
+            ],
+            "monochrome" => [
+                "white", "gray", "gray", "white", "white", "gray", "dark-gray", "white",
+                "gray", "gray", "white", "white", "white", "gray", "gray",
+            ],
+            _ => return None,
+        }
+        .map(ToString::to_string);
+        let [user, assistant, border, accent, selection, text, muted, heading, thinking, code, link, error, warning, success, highlight] =
+            colors;
+        Some(Self {
+            preset: Some(name.to_string()),
+            user_message_color: user,
+            assistant_message_color: assistant,
+            border_color: border,
+            accent_color: accent,
+            selection_color: selection,
+            text_color: text,
+            muted_color: muted,
+            heading_color: heading,
+            thinking_color: thinking,
+            code_color: code,
+            link_color: link,
+            error_color: error,
+            warning_color: warning,
+            success_color: success,
+            highlight_color: highlight,
+        })
+    }
+
+    /// Every color with its key in `[theme]`
+    pub const fn colors_mut(&mut self) -> [(&'static str, &mut String); 15] {
+        [
+            ("user_message_color", &mut self.user_message_color),
+            ("assistant_message_color", &mut self.assistant_message_color),
+            ("border_color", &mut self.border_color),
+            ("accent_color", &mut self.accent_color),
+            ("selection_color", &mut self.selection_color),
+            ("text_color", &mut self.text_color),
+            ("muted_color", &mut self.muted_color),
+            ("heading_color", &mut self.heading_color),
+            ("thinking_color", &mut self.thinking_color),
+            ("code_color", &mut self.code_color),
+            ("link_color", &mut self.link_color),
+            ("error_color", &mut self.error_color),
+            ("warning_color", &mut self.warning_color),
+            ("success_color", &mut self.success_color),
+            ("highlight_color", &mut self.highlight_color),
+        ]
+    }
+}
+
+/// `[theme]` as written in config.toml, where every color is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ThemeFile {
+    preset: Option<String>,
+    user_message_color: Option<String>,
+    assistant_message_color: Option<String>,
+    border_color: Option<String>,
+    accent_color: Option<String>,
+    selection_color: Option<String>,
+    text_color: Option<String>,
+    muted_color: Option<String>,
+    heading_color: Option<String>,
+    thinking_color: Option<String>,
+    code_color: Option<String>,
+    link_color: Option<String>,
+    error_color: Option<String>,
+    warning_color: Option<String>,
+    success_color: Option<String>,
+    highlight_color: Option<String>,
+}
+
+impl From<ThemeFile> for ThemeConfig {
+    /// Colors given explicitly win over the preset's
+    fn from(file: ThemeFile) -> Self {
+        let base = file
+            .preset
+            .as_deref()
+            .and_then(Self::preset)
+            .unwrap_or_default();
+        Self {
+            preset: file.preset,
+            user_message_color: file.user_message_color.unwrap_or(base.user_message_color),
+            assistant_message_color: file.assistant_message_color.unwrap_or(base.assistant_message_color),
+            border_color: file.border_color.unwrap_or(base.border_color),
+            accent_color: file.accent_color.unwrap_or(base.accent_color),
+            selection_color: file.selection_color.unwrap_or(base.selection_color),
+            text_color: file.text_color.unwrap_or(base.text_color),
+            muted_color: file.muted_color.unwrap_or(base.muted_color),
+            heading_color: file.heading_color.unwrap_or(base.heading_color),
+            thinking_color: file.thinking_color.unwrap_or(base.thinking_color),
+            code_color: file.code_color.unwrap_or(base.code_color),
+            link_color: file.link_color.unwrap_or(base.link_color),
+            error_color: file.error_color.unwrap_or(base.error_color),
+            warning_color: file.warning_color.unwrap_or(base.warning_color),
+            success_color: file.success_color.unwrap_or(base.success_color),
+            highlight_color: file.highlight_color.unwrap_or(base.highlight_color),
+        }
+    }
+}
+
+/// Model sampling options passed through to Ollama (`options` in the API).
+/// Unset fields use the model's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+}
+
+impl GenerationOptions {
+    pub const fn is_empty(&self) -> bool {
+        self.temperature.is_none()
+            && self.top_p.is_none()
+            && self.top_k.is_none()
+            && self.num_ctx.is_none()
+            && self.num_predict.is_none()
+            && self.seed.is_none()
+    }
+
+    /// Set an option by its API name from text, e.g. `("temperature", "0.7")`
+    pub fn set(&mut self, name: &str, value: &str) -> anyhow::Result<()> {
+        fn parse<T: std::str::FromStr>(name: &str, value: &str) -> anyhow::Result<Option<T>> {
+            value
+                .parse()
+                .map(Some)
+                .map_err(|_| anyhow::anyhow!("Invalid value '{value}' for {name}"))
+        }
+        match name {
+            "temperature" => self.temperature = parse(name, value)?,
+            "top_p" => self.top_p = parse(name, value)?,
+            "top_k" => self.top_k = parse(name, value)?,
+            "num_ctx" => self.num_ctx = parse(name, value)?,
+            "num_predict" => self.num_predict = parse(name, value)?,
+            "seed" => self.seed = parse(name, value)?,
+            _ => anyhow::bail!(
+                "Unknown option '{name}' (temperature, top_p, top_k, num_ctx, num_predict, seed)"
+            ),
+        }
+        Ok(())
+    }
+
+    /// Clear an option by its API name so the model default (or persona) applies again
+    pub fn unset(&mut self, name: &str) -> anyhow::Result<()> {
+        match name {
+            "temperature" => self.temperature = None,
+            "top_p" => self.top_p = None,
+            "top_k" => self.top_k = None,
+            "num_ctx" => self.num_ctx = None,
+            "num_predict" => self.num_predict = None,
+            "seed" => self.seed = None,
+            _ => anyhow::bail!(
+                "Unknown option '{name}' (temperature, top_p, top_k, num_ctx, num_predict, seed)"
+            ),
+        }
+        Ok(())
+    }
+
+    /// These options with every field set in `other` taking precedence
+    pub fn overlay(&self, other: &Self) -> Self {
+        Self {
+            temperature: other.temperature.or(self.temperature),
+            top_p: other.top_p.or(self.top_p),
+            top_k: other.top_k.or(self.top_k),
+            num_ctx: other.num_ctx.or(self.num_ctx),
+            num_predict: other.num_predict.or(self.num_predict),
+            seed: other.seed.or(self.seed),
+        }
+    }
+
+    /// The set options as `(name, value)` pairs, for display
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        let mut entries = Vec::new();
+        let mut push = |name, value: Option<String>| {
+            if let Some(value) = value {
+                entries.push((name, value));
+            }
+        };
+        push("temperature", self.temperature.map(|v| v.to_string()));
+        push("top_p", self.top_p.map(|v| v.to_string()));
+        push("top_k", self.top_k.map(|v| v.to_string()));
+        push("num_ctx", self.num_ctx.map(|v| v.to_string()));
+        push("num_predict", self.num_predict.map(|v| v.to_string()));
+        push("seed", self.seed.map(|v| v.to_string()));
+        entries
+    }
+}
+
+/// Per-conversation settings layered over the active persona
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConversationOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "GenerationOptions::is_empty")]
+    pub options: GenerationOptions,
+}
+
+impl ConversationOverrides {
+    pub const fn is_empty(&self) -> bool {
+        self.system_prompt.is_none() && self.options.is_empty()
+    }
+
+    /// `name` is `system` or a generation option
+    pub fn set(&mut self, name: &str, value: &str) -> anyhow::Result<()> {
+        if name == "system" {
+            self.system_prompt = Some(value.to_string());
+            return Ok(());
+        }
+        self.options.set(name, value)
+    }
+
+    /// `name` is `system`, a generation option, or `all`
+    pub fn unset(&mut self, name: &str) -> anyhow::Result<()> {
+        match name {
+            "all" => *self = Self::default(),
+            "system" => self.system_prompt = None,
+            _ => self.options.unset(name)?,
+        }
+        Ok(())
+    }
+
+    /// The persona with these overrides applied
+    pub fn apply(&self, persona: &Persona) -> Persona {
+        Persona {
+            system_prompt: self
+                .system_prompt
+                .clone()
+                .unwrap_or_else(|| persona.system_prompt.clone()),
+            model: persona.model.clone(),
+            options: persona.options.overlay(&self.options),
+        }
+    }
+}
+
+/// A named preset: system prompt, optional model and sampling options
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Persona {
+    #[serde(default)]
+    pub system_prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "GenerationOptions::is_empty")]
+    pub options: GenerationOptions,
+}
+
+/// What to strip from a response before copying it to the clipboard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipboardConfig {
+    #[serde(default = "default_true")]
+    pub strip_thinking: bool,
+    #[serde(default)]
+    pub strip_markdown: bool,
+}
+
+const fn default_true() -> bool {
+    true
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            strip_thinking: true,
+            strip_markdown: false,
+        }
+    }
+}
+
+/// External tools used to export diagram code blocks.
+/// `{input}`, `{output}` and `{format}` in commands are substituted per export.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagramConfig {
+    /// Output file type, e.g. `svg` or `png`
+    #[serde(default = "default_diagram_format")]
+    pub format: String,
+    #[serde(default = "default_mermaid_command")]
+    pub mermaid_command: String,
+    #[serde(default = "default_dot_command")]
+    pub dot_command: String,
+    /// Viewer for the exported file; the platform default opener when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_command: Option<String>,
+}
+
+fn default_diagram_format() -> String {
+    "svg".to_string()
+}
+
+fn default_mermaid_command() -> String {
+    "mmdc -i {input} -o {output}".to_string()
+}
+
+fn default_dot_command() -> String {
+    "dot -T{format} {input} -o {output}".to_string()
+}
+
+impl Default for DiagramConfig {
+    fn default() -> Self {
+        Self {
+            format: default_diagram_format(),
+            mermaid_command: default_mermaid_command(),
+            dot_command: default_dot_command(),
+            open_command: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportConfig {
+    /// Export automatically whenever a conversation is closed or archived
+    #[serde(default)]
+    pub on_close: Option<AutoExport>,
+    /// Layout of markdown exports
+    #[serde(default)]
+    pub markdown: crate::export::MarkdownTemplate,
+}
+
+/// Where (and how) conversations are exported automatically
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoExport {
+    /// Target directory; `~/` is expanded
+    pub dir: String,
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Keeping the chats directory in a git repository
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncConfig {
+    /// Commit every change to a conversation
+    #[serde(default)]
+    pub git: bool,
+    /// Repository URL pulled from at startup and pushed to after each commit
+    #[serde(default)]
+    pub remote: Option<String>,
+}
+
+/// Pruning old conversations at startup. Pinned conversations are always kept.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    /// Conversations not updated for this many days are pruned
+    #[serde(default)]
+    pub retention_days: Option<u32>,
+    /// Only this many of the most recently updated conversations are kept
+    #[serde(default)]
+    pub max_conversations: Option<usize>,
+    #[serde(default)]
+    pub action: RetentionAction,
+}
+
+/// What happens to conversations outside the retention policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionAction {
+    /// Move to `chats/archive`, out of the list
+    #[default]
+    Archive,
+    Delete,
+}
+
+impl RetentionConfig {
+    pub const fn is_enabled(&self) -> bool {
+        self.retention_days.is_some() || self.max_conversations.is_some()
+    }
+
+    /// Which of `conversations` fall outside the policy at `now`
+    pub fn expired<'a>(
+        &self,
+        conversations: &'a [ConversationMetadata],
+        now: DateTime<Utc>,
+    ) -> Vec<&'a ConversationMetadata> {
+        let cutoff = self.retention_days.map(|days| now - chrono::Duration::days(i64::from(days)));
+        // Pinned conversations count towards the limit even though they always stay
+        let pinned = conversations.iter().filter(|c| c.pinned).count();
+        let mut by_recency: Vec<&ConversationMetadata> = conversations.iter().collect();
+        by_recency.sort_by_key(|c| std::cmp::Reverse(c.updated_at));
+        by_recency
+            .into_iter()
+            .filter(|c| !c.pinned)
+            .enumerate()
+            .filter(|(rank, c)| {
+                cutoff.is_some_and(|cutoff| c.updated_at < cutoff)
+                    || self.max_conversations.is_some_and(|max| pinned + rank >= max)
+            })
+            .map(|(_, c)| c)
+            .collect()
+    }
+}
+
+/// Checking a prompt's size against the context window before sending it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenBudgetConfig {
+    /// Context usage (percent) a prompt may push the conversation to without a warning
+    #[serde(default = "default_budget_threshold")]
+    pub threshold: f64,
+    /// Ask for a second Enter before sending an oversized prompt; otherwise just warn
+    #[serde(default = "default_true")]
+    pub confirm: bool,
+}
+
+impl Default for TokenBudgetConfig {
+    fn default() -> Self {
+        Self {
+            threshold: default_budget_threshold(),
+            confirm: true,
+        }
+    }
+}
+
+const fn default_budget_threshold() -> f64 {
+    95.0
+}
+
+/// Facts remembered across conversations and added to the system prompt
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemoryConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Let the model pick out facts from your messages as well
+    #[serde(default)]
+    pub auto_extract: bool,
+}
+
+/// Summarizing old messages when the context window fills up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Context usage (percent) at which the oldest messages are summarized
+    #[serde(default = "default_compression_threshold")]
+    pub threshold: f64,
+    /// Most recent messages that are always sent as they are
+    #[serde(default = "default_keep_recent")]
+    pub keep_recent: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: default_compression_threshold(),
+            keep_recent: default_keep_recent(),
+        }
+    }
+}
+
+const fn default_compression_threshold() -> f64 {
+    80.0
+}
+
+const fn default_keep_recent() -> usize {
+    6
+}
+
+/// Retrieval settings for `/rag`
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RagConfig {
+    /// Ollama model used to embed documents and questions
+    #[serde(default = "default_embedding_model")]
+    pub embedding_model: String,
+    /// Number of chunks added to each prompt
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+    /// Lines per indexed chunk
+    #[serde(default = "default_chunk_lines")]
+    pub chunk_lines: usize,
+    /// Share of BM25 keyword matching in chunk ranking (0 = embeddings only, 1 = keywords only)
+    #[serde(default = "default_keyword_weight")]
+    pub keyword_weight: f32,
+}
+
+fn default_embedding_model() -> String {
+    "nomic-embed-text".to_string()
+}
+
+const fn default_top_k() -> usize {
+    4
+}
+
+const fn default_chunk_lines() -> usize {
+    40
+}
+
+const fn default_keyword_weight() -> f32 {
+    0.3
+}
+
+impl Default for RagConfig {
+    fn default() -> Self {
+        Self {
+            embedding_model: default_embedding_model(),
+            top_k: default_top_k(),
+            chunk_lines: default_chunk_lines(),
+            keyword_weight: default_keyword_weight(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ModelInfo {
     pub name: String,
+    /// Tokens the model can attend to, as reported by Ollama; 0 when unknown
     pub context_window_size: usize,
+    /// Overrides `show_thinking_default` for this model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub show_thinking: Option<bool>,
+    /// Set when Ollama no longer has the model; the entry is kept in case it's pulled again
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub missing: bool,
 }
 
 #[cfg(test)]
@@ -159,6 +1233,25 @@ mod tests {
         assert_eq!(meta.summary, Some("Test summary".to_string()));
     }
 
+    #[test]
+    fn test_conversation_metadata_fork() {
+        let mut meta = ConversationMetadata::new();
+        meta.set_summary("Original".to_string());
+        let forked = meta.fork();
+        assert_ne!(forked.id, meta.id);
+        assert_eq!(forked.parent_id, Some(meta.id));
+        assert_eq!(forked.summary, Some("Fork of Original".to_string()));
+    }
+
+    #[test]
+    fn test_conversation_metadata_without_parent_deserializes() {
+        let meta = ConversationMetadata::new();
+        let mut json = serde_json::to_value(&meta).unwrap();
+        json.as_object_mut().unwrap().remove("parent_id");
+        let loaded: ConversationMetadata = serde_json::from_value(json).unwrap();
+        assert!(loaded.parent_id.is_none());
+    }
+
     #[test]
     fn test_message_creation() {
         let msg = Message::new(MessageRole::User, "Hello".to_string(), 10);
@@ -175,10 +1268,260 @@ mod tests {
         assert!(msg.tokens > 0);
     }
 
+    #[test]
+    fn test_personas_from_toml() {
+        let config: AppConfig = toml::from_str(
+            r#"
+ollama_url = "http://localhost:11434"
+default_model = "qwen3:4b"
+
+[theme]
+user_message_color = "blue"
+assistant_message_color = "green"
+border_color = "cyan"
+
+[personas.rust-reviewer]
+system_prompt = "You review Rust code."
+model = "qwen3:8b"
+options = { temperature = 0.2 }
+
+[personas.sql-tutor]
+system_prompt = "You teach SQL."
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.personas.len(), 2);
+        let reviewer = &config.personas["rust-reviewer"];
+        assert_eq!(reviewer.model.as_deref(), Some("qwen3:8b"));
+        assert_eq!(reviewer.options.temperature, Some(0.2));
+        assert!(config.personas["sql-tutor"].options.is_empty());
+    }
+
+    #[test]
+    fn test_citations_round_trip_through_metadata() {
+        let citation = Citation {
+            path: PathBuf::from("src/main.rs"),
+            start_line: 3,
+            end_line: 9,
+        };
+        let mut answer = Message::new(MessageRole::Assistant, "See [1]".to_string(), 2);
+        answer.citations.push(citation.clone());
+        let messages = vec![Message::new(MessageRole::User, "Q".to_string(), 1), answer];
+
+        let mut metadata = ConversationMetadata::new();
+        metadata.store_citations(&messages);
+        let json = serde_json::to_string(&metadata).unwrap();
+        let loaded: ConversationMetadata = serde_json::from_str(&json).unwrap();
+
+        let mut reloaded: Vec<Message> = messages.iter().map(|m| Message::new(m.role.clone(), m.content.clone(), 0)).collect();
+        loaded.restore_citations(&mut reloaded);
+        assert!(reloaded[0].citations.is_empty());
+        assert_eq!(reloaded[1].citations, vec![citation]);
+        assert_eq!(reloaded[1].citations[0].label(), "src/main.rs:3-9");
+    }
+
+    #[test]
+    fn test_pins_round_trip_through_metadata() {
+        let mut messages = vec![
+            Message::new(MessageRole::User, "Requirements".to_string(), 1),
+            Message::new(MessageRole::Assistant, "Noted".to_string(), 1),
+        ];
+        messages[0].pinned = true;
+        let mut metadata = ConversationMetadata::new();
+        metadata.store_pins(&messages);
+        let loaded: ConversationMetadata =
+            serde_json::from_str(&serde_json::to_string(&metadata).unwrap()).unwrap();
+
+        let mut reloaded: Vec<Message> = messages.iter().map(|m| Message::new(m.role.clone(), m.content.clone(), 0)).collect();
+        loaded.restore_pins(&mut reloaded);
+        assert!(reloaded[0].pinned);
+        assert!(!reloaded[1].pinned);
+    }
+
+    #[test]
+    fn test_bookmarks_round_trip_through_metadata() {
+        let mut messages = vec![
+            Message::new(MessageRole::User, "Question".to_string(), 1),
+            Message::new(MessageRole::Assistant, "Answer".to_string(), 1),
+        ];
+        messages[1].bookmarked = true;
+        let mut metadata = ConversationMetadata::new();
+        metadata.store_message_data(&messages);
+        assert_eq!(metadata.bookmarks, BTreeSet::from([1]));
+        let loaded: ConversationMetadata =
+            serde_json::from_str(&serde_json::to_string(&metadata).unwrap()).unwrap();
+
+        let mut reloaded: Vec<Message> = messages.iter().map(|m| Message::new(m.role.clone(), m.content.clone(), 0)).collect();
+        loaded.restore_message_data(&mut reloaded);
+        assert!(!reloaded[0].bookmarked);
+        assert!(reloaded[1].bookmarked);
+    }
+
+    #[test]
+    fn test_origins_round_trip_through_metadata() {
+        let messages = vec![
+            Message::new(MessageRole::User, "Question".to_string(), 1).sent_by(None),
+            Message::new(MessageRole::Assistant, "Answer".to_string(), 1).sent_by(Some("qwen3:4b")),
+        ];
+        let mut metadata = ConversationMetadata::new();
+        metadata.store_message_data(&messages);
+        let loaded: ConversationMetadata =
+            serde_json::from_str(&serde_json::to_string(&metadata).unwrap()).unwrap();
+
+        let mut reloaded: Vec<Message> = messages.iter().map(|m| Message::new(m.role.clone(), m.content.clone(), 0)).collect();
+        loaded.restore_message_data(&mut reloaded);
+        assert_eq!(reloaded[0].sent_at, messages[0].sent_at);
+        assert_eq!(reloaded[0].model, None);
+        assert_eq!(reloaded[1].model.as_deref(), Some("qwen3:4b"));
+    }
+
+    #[test]
+    fn test_retention_expired() {
+        let now = Utc::now();
+        let conversation = |days_old: i64, pinned: bool| {
+            let mut metadata = ConversationMetadata::new();
+            metadata.updated_at = now - chrono::Duration::days(days_old);
+            metadata.pinned = pinned;
+            metadata
+        };
+        let conversations = vec![
+            conversation(400, true),
+            conversation(1, false),
+            conversation(10, false),
+            conversation(100, false),
+        ];
+        let ids = |policy: &RetentionConfig| -> Vec<Uuid> {
+            policy.expired(&conversations, now).iter().map(|c| c.id).collect()
+        };
+
+        let mut policy = RetentionConfig::default();
+        assert!(!policy.is_enabled());
+        assert!(ids(&policy).is_empty());
+
+        policy.retention_days = Some(30);
+        assert_eq!(ids(&policy), vec![conversations[3].id]);
+
+        // The pinned conversation is kept and takes one of the two places
+        policy.retention_days = None;
+        policy.max_conversations = Some(2);
+        assert_eq!(ids(&policy), vec![conversations[2].id, conversations[3].id]);
+    }
+
+    #[test]
+    fn test_code_render_mode_lookup() {
+        let mut config = AppConfig::default();
+        assert_eq!(config.code_render_mode(Some("JSON")), CodeRenderMode::Json);
+        assert_eq!(config.code_render_mode(Some("mermaid")), CodeRenderMode::Diagram);
+        assert_eq!(config.code_render_mode(Some("rust")), CodeRenderMode::Code);
+        assert_eq!(config.code_render_mode(None), CodeRenderMode::Code);
+
+        config.code_render = toml::from_str::<BTreeMap<String, CodeRenderMode>>("text = \"raw\"").unwrap();
+        assert_eq!(config.code_render_mode(Some("text")), CodeRenderMode::Raw);
+        assert_eq!(config.code_render_mode(Some("json")), CodeRenderMode::Code);
+    }
+
+    #[test]
+    fn test_model_aliases() {
+        let config: AppConfig = toml::from_str(
+            r#"
+[aliases]
+fast = "qwen3:4b"
+smart = "llama3.1:70b"
+also-fast = "qwen3:4b"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.resolve_model("smart"), "llama3.1:70b");
+        assert_eq!(config.resolve_model(" fast "), "qwen3:4b");
+        assert_eq!(config.resolve_model("mistral:7b"), "mistral:7b");
+        assert_eq!(config.aliases_of("qwen3:4b"), ["also-fast", "fast"]);
+        assert!(config.aliases_of("llama3.1:8b").is_empty());
+    }
+
+    #[test]
+    fn test_default_model_fallback_chain() {
+        let config: AppConfig = toml::from_str(
+            r#"
+default_model = ["smart", "qwen3:8b", "qwen3:4b"]
+
+[aliases]
+smart = "llama3.1:70b"
+"#,
+        )
+        .unwrap();
+        let installed = |names: &[&str]| names.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(config.default_model_from(Some(&installed(&["qwen3:4b", "qwen3:8b"]))), "qwen3:8b");
+        assert_eq!(config.default_model_from(Some(&installed(&["llama3.1:70b"]))), "llama3.1:70b");
+        // Nothing installed, or no server: the first one, as with a single model
+        assert_eq!(config.default_model_from(Some(&[])), "llama3.1:70b");
+        assert_eq!(config.default_model_from(None), "llama3.1:70b");
+
+        assert_eq!(config.default_model.to_string(), "smart, qwen3:8b, qwen3:4b");
+        assert_eq!(DefaultModel::parse(" qwen3:4b "), Some(DefaultModel::One("qwen3:4b".to_string())));
+        assert_eq!(DefaultModel::parse("a, b,"), Some(DefaultModel::Many(vec!["a".to_string(), "b".to_string()])));
+        assert_eq!(DefaultModel::parse(" , "), None);
+        // A single model is still saved as a plain string
+        let saved = toml::to_string(&AppConfig::default()).unwrap();
+        assert!(saved.contains("default_model = \"qwen3:4b\""));
+    }
+
+    #[test]
+    fn test_generation_options_skip_unset() {
+        let options = GenerationOptions {
+            temperature: Some(0.5),
+            ..Default::default()
+        };
+        assert_eq!(serde_json::to_string(&options).unwrap(), r#"{"temperature":0.5}"#);
+    }
+
+    #[test]
+    fn test_generation_options_set_by_name() {
+        let mut options = GenerationOptions::default();
+        options.set("temperature", "0.7").unwrap();
+        options.set("num_ctx", "8192").unwrap();
+        assert_eq!(options.temperature, Some(0.7));
+        assert_eq!(options.num_ctx, Some(8192));
+        assert!(options.set("top_k", "-1").is_err());
+        assert!(options.set("warmth", "1").is_err());
+    }
+
+    #[test]
+    fn test_conversation_overrides_apply() {
+        let persona = Persona {
+            system_prompt: "You are terse.".to_string(),
+            model: None,
+            options: GenerationOptions {
+                temperature: Some(0.7),
+                top_p: Some(0.9),
+                ..Default::default()
+            },
+        };
+        let mut overrides = ConversationOverrides::default();
+        assert_eq!(overrides.apply(&persona), persona);
+
+        overrides.set("temperature", "0.2").unwrap();
+        overrides.set("num_ctx", "16384").unwrap();
+        overrides.set("system", "You are a pirate.").unwrap();
+        let applied = overrides.apply(&persona);
+        assert_eq!(applied.system_prompt, "You are a pirate.");
+        assert_eq!(applied.options.temperature, Some(0.2));
+        assert_eq!(applied.options.top_p, Some(0.9));
+        assert_eq!(applied.options.num_ctx, Some(16384));
+
+        overrides.unset("system").unwrap();
+        assert_eq!(overrides.apply(&persona).system_prompt, "You are terse.");
+        assert!(overrides.unset("warmth").is_err());
+        overrides.unset("all").unwrap();
+        assert!(overrides.is_empty());
+    }
+
     #[test]
     fn test_app_config_default() {
         let config = AppConfig::default();
         assert_eq!(config.ollama_url, "http://localhost:11434");
-        assert_eq!(config.default_model, "qwen3:4b");
+        assert_eq!(config.default_model, DefaultModel::One("qwen3:4b".to_string()));
+        assert!(config.clipboard.strip_thinking);
+        assert!(!config.clipboard.strip_markdown);
     }
 }
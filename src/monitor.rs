@@ -0,0 +1,133 @@
+// CPU, RAM and GPU usage sampled while a response is generating
+
+use nvml_wrapper::Nvml;
+use std::fmt::Write;
+use sysinfo::{MemoryRefreshKind, System};
+
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpuSample {
+    /// Busiest GPU's utilization
+    pub util_percent: u32,
+    pub vram_used: u64,
+    pub vram_total: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResourceSample {
+    pub cpu_percent: f32,
+    pub mem_used: u64,
+    pub mem_total: u64,
+    /// `None` without an NVIDIA GPU (or driver)
+    pub gpu: Option<GpuSample>,
+}
+
+pub struct ResourceMonitor {
+    system: System,
+    /// NVML is loaded at runtime; missing drivers just mean no GPU readout
+    nvml: Option<Nvml>,
+}
+
+impl std::fmt::Debug for ResourceMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResourceMonitor")
+            .field("gpu", &self.nvml.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResourceMonitor {
+    pub fn new() -> Self {
+        Self {
+            system: System::new(),
+            nvml: Nvml::init().ok(),
+        }
+    }
+
+    /// Take a sample. CPU usage is measured since the previous call, so the first
+    /// reading after creation is 0.
+    pub fn sample(&mut self) -> ResourceSample {
+        self.system.refresh_cpu_usage();
+        self.system.refresh_memory_specifics(MemoryRefreshKind::nothing().with_ram());
+        ResourceSample {
+            cpu_percent: self.system.global_cpu_usage(),
+            mem_used: self.system.used_memory(),
+            mem_total: self.system.total_memory(),
+            gpu: self.nvml.as_ref().and_then(sample_gpus),
+        }
+    }
+}
+
+/// Highest utilization and summed VRAM across all GPUs
+fn sample_gpus(nvml: &Nvml) -> Option<GpuSample> {
+    let mut total: Option<GpuSample> = None;
+    for index in 0..nvml.device_count().ok()? {
+        let Ok(device) = nvml.device_by_index(index) else {
+            continue;
+        };
+        let (Ok(util), Ok(memory)) = (device.utilization_rates(), device.memory_info()) else {
+            continue;
+        };
+        let sample = total.get_or_insert(GpuSample {
+            util_percent: 0,
+            vram_used: 0,
+            vram_total: 0,
+        });
+        sample.util_percent = sample.util_percent.max(util.gpu);
+        sample.vram_used += memory.used;
+        sample.vram_total += memory.total;
+    }
+    total
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn gib(bytes: u64) -> f64 {
+    bytes as f64 / GIB
+}
+
+/// Compact readout for the status bar: "CPU 45% RAM 12.3/31.2G GPU 98% VRAM 7.1/8.0G"
+pub fn format_sample(sample: &ResourceSample) -> String {
+    let mut text = format!(
+        "CPU {:.0}% RAM {:.1}/{:.1}G",
+        sample.cpu_percent,
+        gib(sample.mem_used),
+        gib(sample.mem_total)
+    );
+    if let Some(gpu) = sample.gpu {
+        let _ = write!(
+            text,
+            " GPU {}% VRAM {:.1}/{:.1}G",
+            gpu.util_percent,
+            gib(gpu.vram_used),
+            gib(gpu.vram_total)
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_sample() {
+        let mut sample = ResourceSample {
+            cpu_percent: 45.4,
+            mem_used: 12 * 1024 * 1024 * 1024,
+            mem_total: 32 * 1024 * 1024 * 1024,
+            gpu: None,
+        };
+        assert_eq!(format_sample(&sample), "CPU 45% RAM 12.0/32.0G");
+
+        sample.gpu = Some(GpuSample {
+            util_percent: 98,
+            vram_used: 7 * 1024 * 1024 * 1024,
+            vram_total: 8 * 1024 * 1024 * 1024,
+        });
+        assert_eq!(
+            format_sample(&sample),
+            "CPU 45% RAM 12.0/32.0G GPU 98% VRAM 7.0/8.0G"
+        );
+    }
+}
@@ -0,0 +1,113 @@
+// Battery detection for low-power mode. sysinfo has no power-source API, so this
+// reads the platform's own sources.
+
+use serde::{Deserialize, Serialize};
+
+/// When low-power mode is active
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LowPowerMode {
+    /// While running on battery
+    #[default]
+    Auto,
+    On,
+    Off,
+}
+
+impl LowPowerMode {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::On => "on",
+            Self::Off => "off",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "auto" => Some(Self::Auto),
+            "on" | "true" => Some(Self::On),
+            "off" | "false" => Some(Self::Off),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the machine is running on battery. Unknown (desktops, unsupported
+/// platforms) counts as mains power.
+pub fn on_battery() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        linux_on_battery(std::path::Path::new("/sys/class/power_supply"))
+    }
+    #[cfg(target_os = "macos")]
+    {
+        std::process::Command::new("pmset")
+            .args(["-g", "batt"])
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("'Battery Power'"))
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        false
+    }
+}
+
+/// On battery when a battery is discharging, or when no mains adapter is online
+/// but a battery exists
+#[cfg(target_os = "linux")]
+fn linux_on_battery(dir: &std::path::Path) -> bool {
+    let read = |path: std::path::PathBuf| std::fs::read_to_string(path).unwrap_or_default();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return false;
+    };
+    let mut has_battery = false;
+    let mut mains_online = false;
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        match read(path.join("type")).trim() {
+            "Battery" => {
+                has_battery = true;
+                if read(path.join("status")).trim() == "Discharging" {
+                    return true;
+                }
+            }
+            "Mains" | "USB" => mains_online |= read(path.join("online")).trim() == "1",
+            _ => {}
+        }
+    }
+    has_battery && !mains_online
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(LowPowerMode::parse("AUTO"), Some(LowPowerMode::Auto));
+        assert_eq!(LowPowerMode::parse("off"), Some(LowPowerMode::Off));
+        assert_eq!(LowPowerMode::parse("sometimes"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_linux_power_supply() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let supply = |name: &str, files: &[(&str, &str)]| {
+            let dir = temp_dir.path().join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            for (file, contents) in files {
+                std::fs::write(dir.join(file), contents).unwrap();
+            }
+        };
+        assert!(!linux_on_battery(temp_dir.path()));
+
+        supply("AC", &[("type", "Mains\n"), ("online", "1\n")]);
+        supply("BAT0", &[("type", "Battery\n"), ("status", "Charging\n")]);
+        assert!(!linux_on_battery(temp_dir.path()));
+
+        supply("BAT0", &[("status", "Discharging\n")]);
+        assert!(linux_on_battery(temp_dir.path()));
+    }
+}
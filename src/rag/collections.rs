@@ -0,0 +1,217 @@
+// Named retrieval collections: a set of paths plus the index built from them
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::Index;
+
+const COLLECTIONS_FILE: &str = "collections.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Collection {
+    pub name: String,
+    pub paths: Vec<PathBuf>,
+    /// Embedding model to index with; `None` uses `[rag] embedding_model`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Model the stored index was embedded with; `None` until first indexed
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+    #[serde(default)]
+    pub chunk_count: usize,
+    #[serde(default)]
+    pub indexed_at: Option<DateTime<Utc>>,
+}
+
+impl Collection {
+    /// Embedding model this collection should be indexed with
+    pub fn target_model<'a>(&'a self, default: &'a str) -> &'a str {
+        self.model.as_deref().unwrap_or(default)
+    }
+
+    /// The stored index was embedded with a different model than the configured one
+    pub fn needs_migration(&self, default: &str) -> bool {
+        self.embedding_model
+            .as_deref()
+            .is_some_and(|model| model != self.target_model(default))
+    }
+}
+
+/// All collections, stored as `collections.json` with one index file per
+/// collection in the same directory
+#[derive(Debug, Default)]
+pub struct Collections {
+    pub items: Vec<Collection>,
+    dir: Option<PathBuf>,
+}
+
+impl Collections {
+    pub fn load(dir: &Path) -> Result<Self> {
+        let items = match fs::read_to_string(dir.join(COLLECTIONS_FILE)) {
+            Ok(contents) => serde_json::from_str(&contents).context("Failed to parse collections.json")?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).context("Failed to read collections.json"),
+        };
+        Ok(Self {
+            items,
+            dir: Some(dir.to_path_buf()),
+        })
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir).context("Failed to create collections directory")?;
+        let contents = serde_json::to_string_pretty(&self.items)?;
+        crate::storage::write_atomic(&dir.join(COLLECTIONS_FILE), &contents)
+    }
+
+    fn index_path(&self, name: &str) -> Option<PathBuf> {
+        Some(self.dir.as_ref()?.join(format!("{name}.index.json")))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Collection> {
+        self.items.iter().find(|c| c.name == name)
+    }
+
+    fn get_mut(&mut self, name: &str) -> Result<&mut Collection> {
+        self.items
+            .iter_mut()
+            .find(|c| c.name == name)
+            .with_context(|| format!("No collection named '{name}'"))
+    }
+
+    pub fn create(&mut self, name: &str) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            bail!("Invalid collection name '{name}'");
+        }
+        if self.get(name).is_some() {
+            bail!("Collection '{name}' already exists");
+        }
+        self.items.push(Collection {
+            name: name.to_string(),
+            ..Default::default()
+        });
+        self.save()
+    }
+
+    /// Delete a collection and its stored index
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        self.items.retain(|c| c.name != name);
+        if let Some(path) = self.index_path(name) {
+            if path.exists() {
+                fs::remove_file(path).context("Failed to delete collection index")?;
+            }
+        }
+        self.save()
+    }
+
+    pub fn add_path(&mut self, name: &str, path: PathBuf) -> Result<()> {
+        let collection = self.get_mut(name)?;
+        if !collection.paths.contains(&path) {
+            collection.paths.push(path);
+        }
+        self.save()
+    }
+
+    /// Change the embedding model (`None` for the default). The stored index keeps
+    /// its old embeddings until the collection is migrated.
+    pub fn set_model(&mut self, name: &str, model: Option<String>) -> Result<()> {
+        self.get_mut(name)?.model = model;
+        self.save()
+    }
+
+    /// Collections whose index no longer matches their embedding model
+    pub fn needing_migration<'a>(&'a self, default: &'a str) -> impl Iterator<Item = &'a Collection> {
+        self.items.iter().filter(move |c| c.needs_migration(default))
+    }
+
+    pub fn remove_path(&mut self, name: &str, index: usize) -> Result<()> {
+        let collection = self.get_mut(name)?;
+        if index < collection.paths.len() {
+            collection.paths.remove(index);
+        }
+        self.save()
+    }
+
+    /// Store a freshly built index and record its stats
+    pub fn save_index(&mut self, name: &str, index: &Index) -> Result<()> {
+        let collection = self.get_mut(name)?;
+        collection.embedding_model = Some(index.embedding_model.clone());
+        collection.chunk_count = index.chunks.len();
+        collection.indexed_at = Some(Utc::now());
+        if let Some(path) = self.index_path(name) {
+            crate::storage::write_atomic(&path, &serde_json::to_string(index)?)?;
+        }
+        self.save()
+    }
+
+    pub fn load_index(&self, name: &str) -> Result<Index> {
+        let path = self.index_path(name).context("Collections are not stored on disk")?;
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Collection '{name}' has not been indexed yet"))?;
+        serde_json::from_str(&contents).context("Failed to parse collection index")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Citation;
+    use crate::rag::Chunk;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_collection_lifecycle() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut collections = Collections::load(temp_dir.path()).unwrap();
+        collections.create("notes").unwrap();
+        assert!(collections.create("notes").is_err());
+        assert!(collections.create("../x").is_err());
+
+        collections.add_path("notes", PathBuf::from("/a")).unwrap();
+        collections.add_path("notes", PathBuf::from("/b")).unwrap();
+        collections.add_path("notes", PathBuf::from("/a")).unwrap();
+        collections.remove_path("notes", 0).unwrap();
+        assert!(collections.load_index("notes").is_err());
+
+        let index = Index {
+            embedding_model: "nomic-embed-text".to_string(),
+            chunks: vec![Chunk {
+                citation: Citation {
+                    path: PathBuf::from("/b/x.md"),
+                    start_line: 1,
+                    end_line: 1,
+                },
+                text: "x".to_string(),
+                embedding: vec![1.0],
+            }],
+        };
+        collections.save_index("notes", &index).unwrap();
+
+        let reloaded = Collections::load(temp_dir.path()).unwrap();
+        let notes = reloaded.get("notes").unwrap();
+        assert_eq!(notes.paths, vec![PathBuf::from("/b")]);
+        assert_eq!(notes.chunk_count, 1);
+        assert_eq!(notes.embedding_model.as_deref(), Some("nomic-embed-text"));
+        assert_eq!(reloaded.load_index("notes").unwrap().chunks.len(), 1);
+
+        let mut reloaded = reloaded;
+        assert_eq!(reloaded.needing_migration("nomic-embed-text").count(), 0);
+        // Changing the default or the collection's own model makes the index stale
+        assert_eq!(reloaded.needing_migration("mxbai-embed-large").count(), 1);
+        reloaded.set_model("notes", Some("all-minilm".to_string())).unwrap();
+        let notes = reloaded.get("notes").unwrap();
+        assert_eq!(notes.target_model("nomic-embed-text"), "all-minilm");
+        assert!(notes.needs_migration("nomic-embed-text"));
+
+        reloaded.remove("notes").unwrap();
+        assert!(reloaded.items.is_empty());
+        assert!(!temp_dir.path().join("notes.index.json").exists());
+    }
+}
@@ -0,0 +1,99 @@
+// BM25 keyword scoring, used alongside embeddings so exact identifiers
+// (function names, error codes) still match
+
+use std::collections::{HashMap, HashSet};
+
+/// Standard BM25 term-frequency saturation and length normalization
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+/// Lowercased words; `_`, `:` and `.` stay inside tokens so identifiers like
+/// `parse_config`, `std::fs` or `E0502` are matched whole
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | ':' | '.')))
+        .map(|token| token.trim_matches(|c| matches!(c, ':' | '.')))
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// BM25 score of every document for `query` (0 when no query term occurs)
+pub fn bm25_scores(query: &str, documents: &[&str]) -> Vec<f32> {
+    let query_terms: HashSet<String> = tokenize(query).into_iter().collect();
+    if query_terms.is_empty() || documents.is_empty() {
+        return vec![0.0; documents.len()];
+    }
+
+    // Only frequencies of query terms matter
+    let term_counts: Vec<(usize, HashMap<&str, usize>)> = documents
+        .iter()
+        .map(|doc| {
+            let tokens = tokenize(doc);
+            let mut counts = HashMap::new();
+            for token in &tokens {
+                if let Some(term) = query_terms.get(token) {
+                    *counts.entry(term.as_str()).or_insert(0) += 1;
+                }
+            }
+            (tokens.len(), counts)
+        })
+        .collect();
+
+    #[allow(clippy::cast_precision_loss)]
+    let doc_count = documents.len() as f32;
+    #[allow(clippy::cast_precision_loss)]
+    let average_length = term_counts.iter().map(|(len, _)| *len).sum::<usize>() as f32 / doc_count;
+
+    let idf: HashMap<&str, f32> = query_terms
+        .iter()
+        .map(|term| {
+            #[allow(clippy::cast_precision_loss)]
+            let containing = term_counts.iter().filter(|(_, counts)| counts.contains_key(term.as_str())).count() as f32;
+            (term.as_str(), ((doc_count - containing + 0.5) / (containing + 0.5)).ln_1p())
+        })
+        .collect();
+
+    term_counts
+        .iter()
+        .map(|(length, counts)| {
+            #[allow(clippy::cast_precision_loss)]
+            let length_norm = 1.0 - B + B * (*length as f32) / average_length.max(1.0);
+            counts
+                .iter()
+                .map(|(term, count)| {
+                    #[allow(clippy::cast_precision_loss)]
+                    let tf = *count as f32;
+                    idf[term] * tf * (K1 + 1.0) / K1.mul_add(length_norm, tf)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_keeps_identifiers() {
+        assert_eq!(
+            tokenize("Call parse_config() in std::fs. Error E0502!"),
+            vec!["call", "parse_config", "in", "std::fs", "error", "e0502"]
+        );
+    }
+
+    #[test]
+    fn test_bm25_prefers_rare_exact_terms() {
+        let docs = [
+            "the config file is loaded at startup",
+            "fn parse_config reads the config file",
+            "the the the the",
+        ];
+        // "parse_config" occurs in one document, "file" in two
+        let scores = bm25_scores("which file calls parse_config", &docs);
+        assert!(scores[1] > scores[0]);
+        assert!(scores[0] > 0.0);
+        assert!(scores[2].abs() < f32::EPSILON);
+        assert_eq!(bm25_scores("", &docs), vec![0.0; 3]);
+    }
+}
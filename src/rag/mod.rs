@@ -0,0 +1,314 @@
+// Retrieval-augmented generation: index local files and add relevant excerpts to prompts
+
+pub mod collections;
+pub mod keyword;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::api::OllamaClient;
+use crate::models::{Citation, RagConfig};
+
+/// Directories that never contain useful context
+const SKIPPED_DIRS: [&str; 4] = ["target", "node_modules", "dist", "build"];
+/// Files larger than this are skipped (generated code, data dumps)
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+/// Chunks embedded per API request
+const EMBED_BATCH: usize = 16;
+
+/// An excerpt of a file with its embedding
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    pub citation: Citation,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Index {
+    pub embedding_model: String,
+    pub chunks: Vec<Chunk>,
+}
+
+/// Expand a leading `~/` to the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Text files under `root` (or `root` itself), skipping hidden and build directories
+pub fn collect_files(root: &Path) -> Vec<PathBuf> {
+    if root.is_file() {
+        return vec![root.to_path_buf()];
+    }
+
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if !SKIPPED_DIRS.contains(&name.as_str()) {
+                    pending.push(path);
+                }
+            } else if file_type.is_file()
+                && entry.metadata().is_ok_and(|meta| meta.len() <= MAX_FILE_BYTES)
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Split a file into chunks of `chunk_lines` lines (1-based, inclusive ranges).
+/// Blank chunks are dropped.
+pub fn chunk_text(path: &Path, text: &str, chunk_lines: usize) -> Vec<(Citation, String)> {
+    let lines: Vec<&str> = text.lines().collect();
+    lines
+        .chunks(chunk_lines.max(1))
+        .enumerate()
+        .filter(|(_, chunk)| chunk.iter().any(|line| !line.trim().is_empty()))
+        .map(|(i, chunk)| {
+            let start_line = i * chunk_lines.max(1) + 1;
+            (
+                Citation {
+                    path: path.to_path_buf(),
+                    start_line,
+                    end_line: start_line + chunk.len() - 1,
+                },
+                chunk.join("\n"),
+            )
+        })
+        .collect()
+}
+
+/// Embed `pieces` in batches, reporting (embedded, total) after each batch
+async fn embed_pieces(
+    client: &OllamaClient,
+    model: &str,
+    pieces: Vec<(Citation, String)>,
+    progress: impl Fn(usize, usize),
+) -> Result<Vec<Chunk>> {
+    let total = pieces.len();
+    let mut chunks = Vec::with_capacity(total);
+    for batch in pieces.chunks(EMBED_BATCH) {
+        let texts: Vec<String> = batch.iter().map(|(_, text)| text.clone()).collect();
+        let embeddings = client.embed(model, &texts).await?;
+        chunks.extend(batch.iter().cloned().zip(embeddings).map(|((citation, text), embedding)| {
+            Chunk {
+                citation,
+                text,
+                embedding,
+            }
+        }));
+        progress(chunks.len(), total);
+    }
+    Ok(chunks)
+}
+
+/// Read, chunk and embed every text file under `roots`
+pub async fn build_index(
+    client: &OllamaClient,
+    config: &RagConfig,
+    roots: &[PathBuf],
+    progress: impl Fn(usize, usize),
+) -> Result<Index> {
+    let mut pieces = Vec::new();
+    for root in roots {
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("Cannot read {}", root.display()))?;
+        for path in collect_files(&root) {
+            // Non-UTF-8 files are binaries as far as we are concerned
+            if let Ok(text) = fs::read_to_string(&path) {
+                pieces.extend(chunk_text(&path, &text, config.chunk_lines));
+            }
+        }
+    }
+    if pieces.is_empty() {
+        bail!("No text files found");
+    }
+
+    Ok(Index {
+        embedding_model: config.embedding_model.clone(),
+        chunks: embed_pieces(client, &config.embedding_model, pieces, progress).await?,
+    })
+}
+
+/// Re-embed the chunks of an existing index with another model. The stored chunk
+/// text is reused, so the source files don't need to be available.
+pub async fn reembed(
+    client: &OllamaClient,
+    index: &Index,
+    model: &str,
+    progress: impl Fn(usize, usize),
+) -> Result<Index> {
+    let pieces = index
+        .chunks
+        .iter()
+        .map(|chunk| (chunk.citation.clone(), chunk.text.clone()))
+        .collect();
+    Ok(Index {
+        embedding_model: model.to_string(),
+        chunks: embed_pieces(client, model, pieces, progress).await?,
+    })
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+impl Index {
+    /// The `top_k` best chunks for a question, best first. Scores blend vector
+    /// similarity to `query` with BM25 keyword matches on `question` (normalized
+    /// to the best match); `keyword_weight` 0 is pure vector search.
+    pub fn search(&self, query: &[f32], question: &str, top_k: usize, keyword_weight: f32) -> Vec<&Chunk> {
+        let keyword_weight = keyword_weight.clamp(0.0, 1.0);
+        let keyword_scores = if keyword_weight > 0.0 {
+            let texts: Vec<&str> = self.chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+            keyword::bm25_scores(question, &texts)
+        } else {
+            vec![0.0; self.chunks.len()]
+        };
+        let best_keyword = keyword_scores.iter().copied().fold(0.0_f32, f32::max);
+
+        let mut scored: Vec<(f32, &Chunk)> = self
+            .chunks
+            .iter()
+            .zip(&keyword_scores)
+            .map(|(chunk, keyword)| {
+                let keyword = if best_keyword > 0.0 { keyword / best_keyword } else { 0.0 };
+                let vector = cosine_similarity(query, &chunk.embedding);
+                ((1.0 - keyword_weight).mul_add(vector, keyword_weight * keyword), chunk)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().take(top_k).map(|(_, chunk)| chunk).collect()
+    }
+}
+
+/// Prompt with the retrieved excerpts placed before the question, numbered as cited
+pub fn build_prompt(question: &str, chunks: &[&Chunk]) -> String {
+    let mut prompt = String::from(
+        "Use the following excerpts from local files to answer. \
+         Cite them by number, like [1], when you rely on them.\n\n",
+    );
+    for (i, chunk) in chunks.iter().enumerate() {
+        let _ = write!(prompt, "[{}] {}\n```\n{}\n```\n\n", i + 1, chunk.citation.label(), chunk.text);
+    }
+    prompt.push_str("Question: ");
+    prompt.push_str(question);
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn chunk(path: &str, embedding: Vec<f32>) -> Chunk {
+        Chunk {
+            citation: Citation {
+                path: PathBuf::from(path),
+                start_line: 1,
+                end_line: 2,
+            },
+            text: "text".to_string(),
+            embedding,
+        }
+    }
+
+    #[test]
+    fn test_chunk_text_line_ranges() {
+        let text = "a\nb\nc\n\n\n\ng";
+        let chunks = chunk_text(Path::new("f.rs"), text, 3);
+        // Lines 4-6 are blank and dropped
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].0.start_line, chunks[0].0.end_line), (1, 3));
+        assert_eq!(chunks[0].1, "a\nb\nc");
+        assert_eq!((chunks[1].0.start_line, chunks[1].0.end_line), (7, 7));
+    }
+
+    #[test]
+    fn test_collect_files_skips_hidden_and_build_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("README.md"), "# hi").unwrap();
+        fs::write(root.join("target/debug/out.txt"), "x").unwrap();
+        fs::write(root.join(".git/HEAD"), "ref").unwrap();
+
+        let files = collect_files(root);
+        assert_eq!(files, vec![root.join("README.md"), root.join("src/main.rs")]);
+        assert_eq!(collect_files(&root.join("README.md")).len(), 1);
+    }
+
+    #[test]
+    fn test_search_ranks_by_similarity() {
+        let index = Index {
+            embedding_model: "test".to_string(),
+            chunks: vec![
+                chunk("far.rs", vec![0.0, 1.0]),
+                chunk("near.rs", vec![1.0, 0.1]),
+                chunk("zero.rs", vec![0.0, 0.0]),
+            ],
+        };
+        let results = index.search(&[1.0, 0.0], "", 2, 0.0);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].citation.path, PathBuf::from("near.rs"));
+        assert_eq!(results[1].citation.path, PathBuf::from("far.rs"));
+    }
+
+    #[test]
+    fn test_hybrid_search_boosts_exact_identifiers() {
+        let mut exact = chunk("exact.rs", vec![0.6, 0.8]);
+        exact.text = "fn load_widget_cache() {}".to_string();
+        let mut similar = chunk("similar.rs", vec![0.9, 0.1]);
+        similar.text = "caching of widgets".to_string();
+        let index = Index {
+            embedding_model: "test".to_string(),
+            chunks: vec![exact, similar],
+        };
+
+        let question = "what does load_widget_cache do";
+        let vector_only = index.search(&[1.0, 0.0], question, 1, 0.0);
+        assert_eq!(vector_only[0].citation.path, PathBuf::from("similar.rs"));
+        let hybrid = index.search(&[1.0, 0.0], question, 1, 0.5);
+        assert_eq!(hybrid[0].citation.path, PathBuf::from("exact.rs"));
+    }
+
+    #[test]
+    fn test_build_prompt_numbers_excerpts() {
+        let first = chunk("src/a.rs", vec![]);
+        let prompt = build_prompt("What does a do?", &[&first]);
+        assert!(prompt.contains("[1] src/a.rs:1-2"));
+        assert!(prompt.ends_with("Question: What does a do?"));
+    }
+}
@@ -0,0 +1,152 @@
+// Searching saved conversations for text (`/search`, Ctrl+Shift+F)
+
+use anyhow::Result;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::models::ConversationMetadata;
+use crate::storage::Storage;
+
+/// Most hits listed; a broad query stops scanning here
+const MAX_HITS: usize = 200;
+/// Characters of context on each side of a match in a snippet
+const SNIPPET_CONTEXT: usize = 40;
+
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub conversation: ConversationMetadata,
+    pub message_index: usize,
+    pub snippet: String,
+}
+
+/// Messages matching `query` across all saved conversations, most relevant first.
+/// Uses the storage's full-text index, or scans every conversation without one.
+pub fn search_conversations(storage: &Storage, query: &str) -> Result<Vec<SearchHit>> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(indexed) = storage.search_index(query, MAX_HITS) else {
+        return scan_conversations(storage, query);
+    };
+
+    let mut conversations: HashMap<Uuid, Option<ConversationMetadata>> = HashMap::new();
+    let mut hits = Vec::new();
+    for hit in indexed? {
+        let conversation = conversations
+            .entry(hit.conversation_id)
+            .or_insert_with(|| storage.load_metadata(&hit.conversation_id).ok());
+        // Indexed but since deleted outside the app
+        let Some(conversation) = conversation else {
+            continue;
+        };
+        hits.push(SearchHit {
+            conversation: conversation.clone(),
+            message_index: hit.message_index,
+            snippet: hit.snippet,
+        });
+    }
+    Ok(hits)
+}
+
+/// Messages containing `query` (case-insensitive), in conversation list order with
+/// archived conversations last
+fn scan_conversations(storage: &Storage, query: &str) -> Result<Vec<SearchHit>> {
+    let mut hits = Vec::new();
+    let mut conversations = storage.list_conversations()?;
+    conversations.extend(storage.list_archived_conversations()?);
+    for conversation in conversations {
+        let Ok(messages) = storage.load_conversation(&conversation.id) else {
+            continue;
+        };
+        for (message_index, message) in messages.iter().enumerate() {
+            let Some(snippet) = snippet(&message.content, query) else {
+                continue;
+            };
+            hits.push(SearchHit {
+                conversation: conversation.clone(),
+                message_index,
+                snippet,
+            });
+            if hits.len() == MAX_HITS {
+                return Ok(hits);
+            }
+        }
+    }
+    Ok(hits)
+}
+
+/// One line of text around the first case-insensitive match of `query`
+pub fn snippet(text: &str, query: &str) -> Option<String> {
+    let start = find_ignore_case(text, query)?;
+    let before: Vec<char> = text[..start].chars().rev().take(SNIPPET_CONTEXT).collect();
+    let after_start = start + query.len();
+    let rest: String = text[after_start..].chars().take(SNIPPET_CONTEXT).collect();
+    let matched = &text[start..after_start];
+
+    let mut snippet = String::new();
+    if before.len() == SNIPPET_CONTEXT {
+        snippet.push('…');
+    }
+    snippet.extend(before.iter().rev());
+    snippet.push_str(matched);
+    snippet.push_str(&rest);
+    if text[after_start..].chars().count() > SNIPPET_CONTEXT {
+        snippet.push('…');
+    }
+    Some(snippet.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Byte offset of the first case-insensitive match of `query` in `text`.
+/// Only ASCII letters are folded, so offsets line up with `text`.
+pub fn find_ignore_case(text: &str, query: &str) -> Option<usize> {
+    if query.is_empty() {
+        return None;
+    }
+    let query = query.as_bytes();
+    text.as_bytes()
+        .windows(query.len())
+        .enumerate()
+        .find(|(i, window)| text.is_char_boundary(*i) && window.eq_ignore_ascii_case(query))
+        .map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Message, MessageRole};
+
+    #[test]
+    fn test_snippet() {
+        assert_eq!(snippet("Use the Borrow checker", "borrow").unwrap(), "Use the Borrow checker");
+        let long = format!("{} needle {}", "a".repeat(100), "b".repeat(100));
+        let found = snippet(&long, "NEEDLE").unwrap();
+        assert!(found.starts_with('…') && found.ends_with('…'));
+        assert!(found.contains(" needle "));
+        assert!(snippet("nothing here", "needle").is_none());
+        assert_eq!(find_ignore_case("héllo Wörld", "wö"), Some(7));
+    }
+
+    #[test]
+    fn test_search_conversations() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let storage = Storage::with_dir(temp_dir.path().to_path_buf()).unwrap();
+        let metadata = ConversationMetadata::new();
+        let messages = vec![
+            Message::new(MessageRole::User, "How do lifetimes work?".to_string(), 1),
+            Message::new(MessageRole::Assistant, "Lifetimes describe scopes".to_string(), 1),
+        ];
+        storage.save(&metadata, &messages).unwrap();
+
+        let hits = search_conversations(&storage, "LIFETIMES").unwrap();
+        assert_eq!(hits.len(), 2);
+        // Ranked: the shorter message is the better match
+        assert_eq!(hits[0].message_index, 1);
+        assert_eq!(hits[0].conversation.id, metadata.id);
+        assert!(search_conversations(&storage, "  ").unwrap().is_empty());
+
+        let hits = scan_conversations(&storage, "lifetimes").unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[1].message_index, 1);
+    }
+}
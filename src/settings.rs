@@ -0,0 +1,250 @@
+// Editable settings shown in the Settings screen
+
+use anyhow::{bail, Context, Result};
+
+use crate::models::{AppConfig, DefaultModel, ThemeConfig, THEME_PRESETS};
+use crate::power::LowPowerMode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsField {
+    OllamaUrl,
+    DefaultModel,
+    RequestTimeout,
+    ThemePreset,
+    UserMessageColor,
+    AssistantMessageColor,
+    BorderColor,
+    /// A default generation option under `[options]`, by its API name
+    Option(&'static str),
+    ShowThinking,
+    PromptLint,
+    TagConversations,
+    ResourceMonitor,
+    LowPower,
+}
+
+impl SettingsField {
+    pub const ALL: [Self; 18] = [
+        Self::OllamaUrl,
+        Self::DefaultModel,
+        Self::RequestTimeout,
+        Self::ThemePreset,
+        Self::UserMessageColor,
+        Self::AssistantMessageColor,
+        Self::BorderColor,
+        Self::Option("temperature"),
+        Self::Option("top_p"),
+        Self::Option("top_k"),
+        Self::Option("num_ctx"),
+        Self::Option("num_predict"),
+        Self::Option("seed"),
+        Self::ShowThinking,
+        Self::PromptLint,
+        Self::TagConversations,
+        Self::ResourceMonitor,
+        Self::LowPower,
+    ];
+
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::OllamaUrl => "Ollama URL (next start)",
+            Self::DefaultModel => "Default model (next start)",
+            Self::RequestTimeout => "Timeout secs (next start)",
+            Self::ThemePreset => "Theme preset",
+            Self::UserMessageColor => "User message color",
+            Self::AssistantMessageColor => "Assistant message color",
+            Self::BorderColor => "Border color",
+            // Shown under their `[options]` names
+            Self::Option(name) => name,
+            Self::ShowThinking => "Show thinking (on/off)",
+            Self::PromptLint => "Prompt hints (on/off)",
+            Self::TagConversations => "Tag conversations (on/off)",
+            Self::ResourceMonitor => "Resource monitor (on/off)",
+            Self::LowPower => "Low-power (auto/on/off)",
+        }
+    }
+
+    /// Whether the field holds a theme color (shown with a swatch and live preview)
+    pub const fn is_color(self) -> bool {
+        matches!(
+            self,
+            Self::UserMessageColor | Self::AssistantMessageColor | Self::BorderColor
+        )
+    }
+
+    /// The value as shown and edited; unset generation options are empty
+    pub fn get(self, config: &AppConfig) -> String {
+        match self {
+            Self::OllamaUrl => config.ollama_url.clone(),
+            Self::DefaultModel => config.default_model.to_string(),
+            Self::RequestTimeout => config.request_timeout.to_string(),
+            Self::ThemePreset => config.theme.preset.clone().unwrap_or_default(),
+            Self::UserMessageColor => config.theme.user_message_color.clone(),
+            Self::AssistantMessageColor => config.theme.assistant_message_color.clone(),
+            Self::BorderColor => config.theme.border_color.clone(),
+            Self::Option(name) => config
+                .options
+                .entries()
+                .into_iter()
+                .find_map(|(option, value)| (option == name).then_some(value))
+                .unwrap_or_default(),
+            Self::ShowThinking => on_off(config.show_thinking_default).to_string(),
+            Self::PromptLint => on_off(config.prompt_lint).to_string(),
+            Self::TagConversations => on_off(config.tag_conversations).to_string(),
+            Self::ResourceMonitor => on_off(config.resource_monitor).to_string(),
+            Self::LowPower => config.low_power.as_str().to_string(),
+        }
+    }
+
+    /// Validate and store `value`, leaving the config untouched on error
+    pub fn set(self, config: &mut AppConfig, value: &str) -> Result<()> {
+        let value = value.trim();
+        if self.is_color() && crate::ui::theme::parse_color(value).is_none() {
+            bail!("Unknown color '{value}' (use a name like 'blue' or hex like '#1e90ff')");
+        }
+        let target = match self {
+            Self::UserMessageColor => &mut config.theme.user_message_color,
+            Self::AssistantMessageColor => &mut config.theme.assistant_message_color,
+            Self::BorderColor => &mut config.theme.border_color,
+            Self::OllamaUrl => {
+                if !value.starts_with("http://") && !value.starts_with("https://") {
+                    bail!("Expected an http:// or https:// URL, got '{value}'");
+                }
+                &mut config.ollama_url
+            }
+            Self::DefaultModel => {
+                // Several, separated by commas, are tried in order at startup
+                config.default_model = DefaultModel::parse(value).context("The default model can't be empty")?;
+                return Ok(());
+            }
+            Self::RequestTimeout => {
+                config.request_timeout = value
+                    .parse()
+                    .ok()
+                    .filter(|seconds| *seconds > 0)
+                    .with_context(|| format!("Expected a number of seconds, got '{value}'"))?;
+                return Ok(());
+            }
+            Self::ThemePreset => {
+                // Choosing a preset replaces the colors with its own
+                config.theme = ThemeConfig::preset(&value.to_ascii_lowercase()).with_context(|| {
+                    format!("Unknown theme '{value}' (available: {})", THEME_PRESETS.join(", "))
+                })?;
+                return Ok(());
+            }
+            Self::Option(name) => {
+                // Empty leaves it to the model (or persona)
+                if value.is_empty() {
+                    config.options.unset(name)?;
+                } else {
+                    config.options.set(name, value)?;
+                }
+                return Ok(());
+            }
+            Self::ShowThinking => {
+                config.show_thinking_default = parse_on_off(value)?;
+                return Ok(());
+            }
+            Self::PromptLint => {
+                config.prompt_lint = parse_on_off(value)?;
+                return Ok(());
+            }
+            Self::TagConversations => {
+                config.tag_conversations = parse_on_off(value)?;
+                return Ok(());
+            }
+            Self::ResourceMonitor => {
+                config.resource_monitor = parse_on_off(value)?;
+                return Ok(());
+            }
+            Self::LowPower => {
+                config.low_power = LowPowerMode::parse(value)
+                    .with_context(|| format!("Expected 'auto', 'on' or 'off', got '{value}'"))?;
+                return Ok(());
+            }
+        };
+        *target = value.to_string();
+        Ok(())
+    }
+}
+
+const fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn parse_on_off(value: &str) -> Result<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" | "true" | "yes" => Ok(true),
+        "off" | "false" | "no" => Ok(false),
+        _ => bail!("Expected 'on' or 'off', got '{value}'"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_and_set_color() {
+        let mut config = AppConfig::default();
+        assert_eq!(SettingsField::BorderColor.get(&config), "cyan");
+
+        SettingsField::BorderColor.set(&mut config, " #ff8800 ").unwrap();
+        assert_eq!(config.theme.border_color, "#ff8800");
+    }
+
+    #[test]
+    fn test_set_toggle() {
+        let mut config = AppConfig::default();
+        assert_eq!(SettingsField::ResourceMonitor.get(&config), "off");
+        SettingsField::ResourceMonitor.set(&mut config, "ON").unwrap();
+        assert!(config.resource_monitor);
+        assert!(SettingsField::ResourceMonitor.set(&mut config, "maybe").is_err());
+        assert!(config.resource_monitor);
+
+        SettingsField::LowPower.set(&mut config, "on").unwrap();
+        assert_eq!(config.low_power, LowPowerMode::On);
+        assert_eq!(SettingsField::LowPower.get(&config), "on");
+    }
+
+    #[test]
+    fn test_set_connection_and_options() {
+        let mut config = AppConfig::default();
+        SettingsField::OllamaUrl.set(&mut config, "http://gpu:11434").unwrap();
+        assert_eq!(config.ollama_url, "http://gpu:11434");
+        assert!(SettingsField::OllamaUrl.set(&mut config, "gpu:11434").is_err());
+        SettingsField::RequestTimeout.set(&mut config, "120").unwrap();
+        assert_eq!(config.request_timeout, 120);
+        assert!(SettingsField::RequestTimeout.set(&mut config, "0").is_err());
+
+        let temperature = SettingsField::Option("temperature");
+        assert_eq!(temperature.get(&config), "");
+        temperature.set(&mut config, "0.3").unwrap();
+        assert_eq!(config.options.temperature, Some(0.3));
+        assert_eq!(temperature.get(&config), "0.3");
+        assert!(temperature.set(&mut config, "warm").is_err());
+        temperature.set(&mut config, "").unwrap();
+        assert!(config.options.is_empty());
+    }
+
+    #[test]
+    fn test_set_theme_preset() {
+        let mut config = AppConfig::default();
+        assert_eq!(SettingsField::ThemePreset.get(&config), "");
+        SettingsField::ThemePreset.set(&mut config, "Solarized").unwrap();
+        assert_eq!(SettingsField::ThemePreset.get(&config), "solarized");
+        assert_eq!(config.theme.border_color, "#2aa198");
+        assert!(SettingsField::ThemePreset.set(&mut config, "neon").is_err());
+    }
+
+    #[test]
+    fn test_set_rejects_unknown_color() {
+        let mut config = AppConfig::default();
+        assert!(SettingsField::UserMessageColor.set(&mut config, "blurple").is_err());
+        assert_eq!(config.theme.user_message_color, "blue");
+    }
+}
@@ -0,0 +1,244 @@
+// Writing code blocks from responses to files (`/save`, `/codesave`)
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::ui::markdown::CodeBlock;
+
+/// Extensions a word must end in to be taken as a file name, so prose like
+/// "e.g." or "v1.2" is not
+const KNOWN_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "ts", "tsx", "jsx", "mjs", "go", "c", "h", "cpp", "hpp", "cc", "cs", "java", "kt",
+    "swift", "rb", "php", "sh", "bash", "zsh", "ps1", "md", "yaml", "yml", "toml", "json", "html", "css",
+    "scss", "sql", "txt", "xml", "ini", "cfg", "conf", "env", "vue", "svelte", "lua", "zig", "ex", "exs",
+    "hs", "ml", "r", "dart", "scala", "proto", "graphql", "nix", "tf", "csv", "tsv",
+];
+
+/// File extension for a fence language; unknown languages keep their own name, cut
+/// down to characters that are safe in one (`txt` when none are left).
+/// Languages whose files have a conventional name (`Dockerfile`) return that name.
+fn extension_for(language: Option<&str>) -> String {
+    let Some(language) = language.map(str::to_ascii_lowercase).filter(|l| !l.is_empty()) else {
+        return "txt".to_string();
+    };
+    let extension = match language.as_str() {
+        "rust" => "rs",
+        "python" | "py" => "py",
+        "javascript" | "js" | "node" => "js",
+        "typescript" | "ts" => "ts",
+        "tsx" => "tsx",
+        "jsx" => "jsx",
+        "golang" | "go" => "go",
+        "c" => "c",
+        "cpp" | "c++" | "cxx" => "cpp",
+        "csharp" | "c#" | "cs" => "cs",
+        "java" => "java",
+        "kotlin" | "kt" => "kt",
+        "swift" => "swift",
+        "ruby" | "rb" => "rb",
+        "php" => "php",
+        "bash" | "sh" | "shell" | "zsh" | "console" => "sh",
+        "powershell" | "ps1" => "ps1",
+        "markdown" | "md" => "md",
+        "yaml" | "yml" => "yaml",
+        "text" | "plaintext" | "txt" => "txt",
+        "dockerfile" => return "Dockerfile".to_string(),
+        "makefile" | "make" => return "Makefile".to_string(),
+        other => {
+            let safe: String = other
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '_' | '-'))
+                .collect();
+            return if safe.is_empty() { "txt".to_string() } else { safe };
+        }
+    };
+    extension.to_string()
+}
+
+/// Suggested file name for a block: `snippet.rs`, or a conventional name like `Dockerfile`
+pub fn file_name_for(language: Option<&str>) -> String {
+    let extension = extension_for(language);
+    if extension.starts_with(char::is_uppercase) {
+        extension
+    } else {
+        format!("snippet.{extension}")
+    }
+}
+
+/// A file name for a block: named in a comment on its first line (`// src/main.rs`)
+/// or in the prose introducing it ("Create `src/main.rs`:")
+pub fn infer_file_name(block: &CodeBlock) -> Option<String> {
+    let first_line = block.code.lines().next().unwrap_or_default().trim();
+    let is_comment = ["//", "#", "--", "/*", "<!--", ";"].iter().any(|marker| first_line.starts_with(marker));
+    is_comment
+        .then(|| file_name_in(first_line))
+        .flatten()
+        .or_else(|| block.intro.as_deref().and_then(file_name_in))
+}
+
+/// The last word in `text` that looks like a relative file path
+fn file_name_in(text: &str) -> Option<String> {
+    text.split_whitespace()
+        .map(|word| {
+            word.trim_matches(|c: char| matches!(c, '`' | '*' | '"' | '\'' | '(' | ')' | ':' | ',' | ';'))
+                .trim_end_matches('.')
+        })
+        .rfind(|word| looks_like_file_name(word))
+        .map(ToString::to_string)
+}
+
+fn looks_like_file_name(word: &str) -> bool {
+    if matches!(word, "Dockerfile" | "Makefile") {
+        return true;
+    }
+    let Some((stem, extension)) = word.rsplit_once('.') else {
+        return false;
+    };
+    !stem.is_empty()
+        && !stem.ends_with('/')
+        && KNOWN_EXTENSIONS.contains(&extension)
+        && word.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/'))
+        && safe_relative_path(word).is_some()
+}
+
+/// `name` as a path that stays inside the target directory
+pub fn safe_relative_path(name: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(name);
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_)))
+        .then_some(path)
+}
+
+/// Files written by `save_all`, and those left alone because they already existed
+#[derive(Debug, Default)]
+pub struct SavedFiles {
+    pub created: Vec<PathBuf>,
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Write every block into `dir` under its inferred name, or `snippet.<ext>` (numbered
+/// when repeated). Existing files are skipped rather than overwritten.
+pub fn save_all(dir: &Path, blocks: &[CodeBlock]) -> Result<SavedFiles> {
+    let mut saved = SavedFiles::default();
+    let mut used = HashSet::new();
+    for block in blocks {
+        let name = infer_file_name(block).unwrap_or_else(|| file_name_for(block.language.as_deref()));
+        let name = unique_name(&name, &used);
+        if safe_relative_path(&name).is_none() {
+            bail!("Refusing to write {name} outside {}", dir.display());
+        }
+        used.insert(name.clone());
+        let path = dir.join(&name);
+        if path.exists() {
+            saved.skipped.push(path);
+            continue;
+        }
+        write_new(&path, &block.code)?;
+        saved.created.push(path);
+    }
+    Ok(saved)
+}
+
+/// `name`, or `name-2.ext`, `name-3.ext`... if already taken
+fn unique_name(name: &str, used: &HashSet<String>) -> String {
+    if !used.contains(name) {
+        return name.to_string();
+    }
+    let (stem, extension) = name.rsplit_once('.').map_or((name, String::new()), |(s, e)| (s, format!(".{e}")));
+    // At most `used.len()` candidates can be taken
+    (2..=used.len() + 1)
+        .map(|n| format!("{stem}-{n}{extension}"))
+        .find(|candidate| !used.contains(candidate))
+        .unwrap_or_default()
+}
+
+/// Write `code` to a new file at `path`, creating parent directories. Existing files
+/// are never overwritten.
+pub fn write_new(path: &Path, code: &str) -> Result<()> {
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let mut contents = code.to_string();
+    if !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name_for() {
+        assert_eq!(file_name_for(Some("rust")), "snippet.rs");
+        assert_eq!(file_name_for(Some("Python")), "snippet.py");
+        assert_eq!(file_name_for(Some("zig")), "snippet.zig");
+        assert_eq!(file_name_for(Some("dockerfile")), "Dockerfile");
+        assert_eq!(file_name_for(None), "snippet.txt");
+        // The language is untrusted model output
+        assert_eq!(file_name_for(Some("/../../x")), "snippet.x");
+        assert_eq!(file_name_for(Some("..")), "snippet.txt");
+    }
+
+    fn block(language: &str, code: &str, intro: Option<&str>) -> CodeBlock {
+        CodeBlock {
+            language: Some(language.to_string()),
+            code: code.to_string(),
+            intro: intro.map(ToString::to_string),
+        }
+    }
+
+    #[test]
+    fn test_infer_file_name() {
+        assert_eq!(infer_file_name(&block("rust", "// src/main.rs\nfn main() {}", None)).unwrap(), "src/main.rs");
+        assert_eq!(infer_file_name(&block("python", "# app.py", Some("Run it"))).unwrap(), "app.py");
+        assert_eq!(
+            infer_file_name(&block("toml", "[package]", Some("Update **`Cargo.toml`**:"))).unwrap(),
+            "Cargo.toml"
+        );
+        assert_eq!(infer_file_name(&block("rust", "let x = 1;", Some("For example, e.g. this:"))), None);
+        assert_eq!(infer_file_name(&block("sh", "# ../../etc/passwd.sh", None)), None);
+        assert_eq!(infer_file_name(&block("sh", "# /etc/rc.sh", None)), None);
+    }
+
+    #[test]
+    fn test_save_all() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("snippet.py"), "old").unwrap();
+        let blocks = vec![
+            block("rust", "// src/lib.rs\npub fn f() {}", None),
+            block("rust", "fn main() {}", None),
+            block("rust", "fn other() {}", None),
+            block("python", "print(1)", None),
+        ];
+        let saved = save_all(temp_dir.path(), &blocks).unwrap();
+        let names: Vec<_> = saved.created.iter().map(|p| p.strip_prefix(temp_dir.path()).unwrap().to_path_buf()).collect();
+        assert_eq!(names, vec![PathBuf::from("src/lib.rs"), PathBuf::from("snippet.rs"), PathBuf::from("snippet-2.rs")]);
+        assert_eq!(saved.skipped, vec![temp_dir.path().join("snippet.py")]);
+        assert_eq!(fs::read_to_string(temp_dir.path().join("snippet.py")).unwrap(), "old");
+    }
+
+    #[test]
+    fn test_save_all_stays_in_dir() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path().join("out");
+        let saved = save_all(&dir, &[block("/../../x", "payload", None), block("..", "more", None)]).unwrap();
+        assert_eq!(saved.created, vec![dir.join("snippet.x"), dir.join("snippet.txt")]);
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_write_new_refuses_to_overwrite() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("src/main.rs");
+        write_new(&path, "fn main() {}").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn main() {}\n");
+        assert!(write_new(&path, "other").is_err());
+    }
+}
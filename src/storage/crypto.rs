@@ -0,0 +1,147 @@
+// Passphrase-based encryption of conversation files (XChaCha20-Poly1305, Argon2id key)
+
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::Argon2;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use chacha20poly1305::aead::rand_core::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// First line of an encrypted file; the rest is base64 of nonce and ciphertext
+const HEADER: &str = "yumchat-encrypted-v1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// Encrypted with the key so a wrong passphrase is caught before any file is read
+const CHECK_TEXT: &str = "yumchat";
+
+/// Salt of the key derivation and a check value, kept next to the conversations
+#[derive(Debug, Serialize, Deserialize)]
+struct KeyFile {
+    salt: String,
+    check: String,
+}
+
+#[derive(Clone)]
+pub struct Cipher {
+    cipher: XChaCha20Poly1305,
+}
+
+impl std::fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Cipher")
+    }
+}
+
+impl Cipher {
+    /// The cipher for `passphrase`. Without a key file at `key_path` one is created,
+    /// making this the passphrase from now on.
+    pub fn unlock(key_path: &Path, passphrase: &str) -> Result<Self> {
+        let base64 = base64::engine::general_purpose::STANDARD;
+        if key_path.exists() {
+            let contents = fs::read_to_string(key_path).context("Failed to read encryption key file")?;
+            let key_file: KeyFile = serde_json::from_str(&contents).context("Failed to parse encryption key file")?;
+            let salt = base64.decode(&key_file.salt).context("Failed to parse encryption key file")?;
+            let cipher = Self::derive(passphrase, &salt)?;
+            if cipher.decrypt(&key_file.check).ok().as_deref() != Some(CHECK_TEXT) {
+                bail!("Wrong passphrase");
+            }
+            return Ok(cipher);
+        }
+
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = Self::derive(passphrase, &salt)?;
+        let key_file = KeyFile {
+            salt: base64.encode(salt),
+            check: cipher.encrypt(CHECK_TEXT)?,
+        };
+        super::write_atomic(key_path, &serde_json::to_string_pretty(&key_file)?)
+            .context("Failed to write encryption key file")?;
+        Ok(cipher)
+    }
+
+    fn derive(passphrase: &str, salt: &[u8]) -> Result<Self> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| anyhow!("Failed to derive encryption key: {e}"))?;
+        let cipher = XChaCha20Poly1305::new_from_slice(&key).map_err(|e| anyhow!("Invalid encryption key: {e}"))?;
+        Ok(Self { cipher })
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        self.encrypt_bytes(plaintext.as_bytes())
+    }
+
+    pub fn decrypt(&self, text: &str) -> Result<String> {
+        String::from_utf8(self.decrypt_bytes(text)?).context("Damaged encrypted file")
+    }
+
+    /// Encrypt binary contents (attachments) into the same text format
+    pub fn encrypt_bytes(&self, plaintext: &[u8]) -> Result<String> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow!("Failed to encrypt"))?;
+        let mut payload = nonce.to_vec();
+        payload.extend(ciphertext);
+        Ok(format!("{HEADER}\n{}\n", base64::engine::general_purpose::STANDARD.encode(payload)))
+    }
+
+    pub fn decrypt_bytes(&self, text: &str) -> Result<Vec<u8>> {
+        let payload = text
+            .strip_prefix(HEADER)
+            .map(str::trim)
+            .ok_or_else(|| anyhow!("Not an encrypted file"))?;
+        let payload = base64::engine::general_purpose::STANDARD
+            .decode(payload)
+            .context("Damaged encrypted file")?;
+        if payload.len() < NONCE_LEN {
+            bail!("Damaged encrypted file");
+        }
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt: damaged file or different passphrase"))
+    }
+}
+
+pub fn is_encrypted(text: &str) -> bool {
+    text.starts_with(HEADER)
+}
+
+pub fn is_encrypted_bytes(contents: &[u8]) -> bool {
+    contents.starts_with(HEADER.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlock_encrypt_and_decrypt() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let key_path = temp_dir.path().join("encryption.json");
+        let cipher = Cipher::unlock(&key_path, "correct horse").unwrap();
+        assert!(key_path.exists());
+
+        let encrypted = cipher.encrypt("## User\n\nsecret plans").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("secret"));
+        // A fresh nonce every time
+        assert_ne!(encrypted, cipher.encrypt("## User\n\nsecret plans").unwrap());
+
+        let reopened = Cipher::unlock(&key_path, "correct horse").unwrap();
+        assert_eq!(reopened.decrypt(&encrypted).unwrap(), "## User\n\nsecret plans");
+        assert!(Cipher::unlock(&key_path, "wrong").unwrap_err().to_string().contains("Wrong passphrase"));
+
+        let mut tampered = encrypted.into_bytes();
+        let last = tampered.len() - 3;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert!(reopened.decrypt(&String::from_utf8(tampered).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+// Git history (and optional remote sync) of the chats directory, using the git CLI
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+/// Identity for commits when git has none configured
+const FALLBACK_IDENTITY: [&str; 4] = ["-c", "user.name=yumchat", "-c", "user.email=yumchat@localhost"];
+/// Name of the remote pointed at the configured URL
+const REMOTE: &str = "origin";
+
+#[derive(Debug)]
+pub struct GitSync {
+    dir: PathBuf,
+    /// Whether a remote URL is configured to pull from and push to
+    has_remote: bool,
+    /// Held while a background push runs, so pushes never overlap
+    pushing: Arc<Mutex<()>>,
+}
+
+impl GitSync {
+    /// Sync `dir`, making it a repository (with everything in it committed) if it isn't
+    /// one yet, and pointing its `origin` at `remote_url`
+    pub fn open(dir: &Path, remote_url: Option<&str>) -> Result<Self> {
+        let sync = Self {
+            dir: dir.to_path_buf(),
+            has_remote: remote_url.is_some(),
+            pushing: Arc::new(Mutex::new(())),
+        };
+        if !dir.join(".git").exists() {
+            git(dir, &["init", "-q"])?;
+            std::fs::write(dir.join(".gitignore"), "*.tmp\n").context("Failed to write .gitignore")?;
+            sync.commit("Start conversation history")?;
+        }
+        if let Some(url) = remote_url {
+            match git(dir, &["remote", "get-url", REMOTE]) {
+                Ok(current) if current == url => {}
+                Ok(_) => {
+                    git(dir, &["remote", "set-url", REMOTE, url])?;
+                }
+                Err(_) => {
+                    git(dir, &["remote", "add", REMOTE, url])?;
+                }
+            }
+        }
+        Ok(sync)
+    }
+
+    /// Commit every change in the directory. Returns false when there was nothing to commit.
+    pub fn commit(&self, message: &str) -> Result<bool> {
+        git(&self.dir, &["add", "-A"])?;
+        if git(&self.dir, &["diff", "--cached", "--quiet"]).is_ok() {
+            return Ok(false);
+        }
+        let mut args = self.identity();
+        args.extend(["commit", "-q", "-m", message]);
+        git(&self.dir, &args)?;
+        Ok(true)
+    }
+
+    /// Arguments giving commits an identity when git has none configured
+    fn identity(&self) -> Vec<&'static str> {
+        if git(&self.dir, &["config", "user.email"]).is_ok() {
+            Vec::new()
+        } else {
+            FALLBACK_IDENTITY.to_vec()
+        }
+    }
+
+    /// Merge the remote's history of the current branch, if it has any. A conflicting
+    /// merge is abandoned, leaving the local history as it was.
+    pub fn pull(&self) -> Result<()> {
+        if !self.has_remote {
+            return Ok(());
+        }
+        git(&self.dir, &["fetch", "-q", REMOTE])?;
+        let tracking = format!("{REMOTE}/{}", self.branch()?);
+        if git(&self.dir, &["rev-parse", "--verify", "-q", &tracking]).is_err() {
+            return Ok(());
+        }
+        // A second machine starts its own history before it first pulls
+        let mut merge = self.identity();
+        merge.extend(["merge", "-q", "--no-edit", "--allow-unrelated-histories", &tracking]);
+        if let Err(e) = git(&self.dir, &merge) {
+            let _ = git(&self.dir, &["merge", "--abort"]);
+            bail!("Could not merge {tracking}: {e}");
+        }
+        Ok(())
+    }
+
+    pub fn push(&self) -> Result<()> {
+        if !self.has_remote {
+            return Ok(());
+        }
+        let _pushing = self.pushing.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        git(&self.dir, &["push", "-q", REMOTE, "HEAD"])?;
+        Ok(())
+    }
+
+    /// Push without waiting. Skipped while another push is still running; the next
+    /// push carries these commits too.
+    pub fn push_in_background(&self) {
+        if !self.has_remote {
+            return;
+        }
+        let dir = self.dir.clone();
+        let pushing = Arc::clone(&self.pushing);
+        std::thread::spawn(move || {
+            if let Ok(_pushing) = pushing.try_lock() {
+                // Failures are retried by the next push (at the latest, on exit)
+                let _ = git(&dir, &["push", "-q", REMOTE, "HEAD"]);
+            }
+        });
+    }
+
+    fn branch(&self) -> Result<String> {
+        git(&self.dir, &["rev-parse", "--abbrev-ref", "HEAD"])
+    }
+}
+
+/// Run git in `dir`, returning its trimmed output
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        // Never wait for credentials on a terminal the TUI owns
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .context("Failed to run git (is it installed?)")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("git {} failed: {}", args.first().unwrap_or(&""), stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git_available() -> bool {
+        Command::new("git").arg("--version").output().is_ok()
+    }
+
+    #[test]
+    fn test_commit_and_sync_through_a_remote() {
+        if !git_available() {
+            return;
+        }
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let remote = temp_dir.path().join("remote.git");
+        git(temp_dir.path(), &["init", "-q", "--bare", remote.to_str().unwrap()]).unwrap();
+
+        let laptop_dir = temp_dir.path().join("laptop");
+        std::fs::create_dir_all(&laptop_dir).unwrap();
+        std::fs::write(laptop_dir.join("a.md"), "one").unwrap();
+        let laptop = GitSync::open(&laptop_dir, remote.to_str()).unwrap();
+        assert!(!laptop.commit("Nothing changed").unwrap());
+        std::fs::write(laptop_dir.join("a.md"), "two").unwrap();
+        std::fs::write(laptop_dir.join("a.md.tmp"), "partial").unwrap();
+        assert!(laptop.commit("Update a").unwrap());
+        laptop.push().unwrap();
+
+        // A second machine starts its own history and merges the remote's into it
+        let desktop_dir = temp_dir.path().join("desktop");
+        std::fs::create_dir_all(&desktop_dir).unwrap();
+        std::fs::write(desktop_dir.join("b.md"), "desktop").unwrap();
+        let desktop = GitSync::open(&desktop_dir, remote.to_str()).unwrap();
+        desktop.pull().unwrap();
+        assert_eq!(std::fs::read_to_string(desktop_dir.join("a.md")).unwrap(), "two");
+        assert!(!desktop_dir.join("a.md.tmp").exists());
+        desktop.push().unwrap();
+
+        laptop.pull().unwrap();
+        assert_eq!(std::fs::read_to_string(laptop_dir.join("b.md")).unwrap(), "desktop");
+    }
+}
@@ -0,0 +1,138 @@
+// Full-text index of saved messages (SQLite FTS5), kept up to date by `Storage`
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::models::Message;
+
+/// Tokens of context around the matched terms in a snippet
+const SNIPPET_TOKENS: i32 = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexHit {
+    pub conversation_id: Uuid,
+    pub message_index: usize,
+    pub snippet: String,
+}
+
+#[derive(Debug)]
+pub struct SearchIndex {
+    connection: Connection,
+}
+
+impl SearchIndex {
+    /// Open (or create) the index at `path`. The flag is true when it was just created
+    /// and still has to be filled from the saved conversations.
+    pub fn open(path: &Path) -> Result<(Self, bool)> {
+        let created = !path.exists();
+        let connection = Connection::open(path).context("Failed to open search index")?;
+        connection
+            .execute_batch(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS messages USING fts5(
+                     conversation_id UNINDEXED, message_index UNINDEXED, content
+                 );",
+            )
+            .context("Failed to create search index")?;
+        Ok((Self { connection }, created))
+    }
+
+    /// Replace everything indexed for conversation `id` with `messages`
+    pub fn index_conversation(&self, id: &Uuid, messages: &[Message]) -> Result<()> {
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute("DELETE FROM messages WHERE conversation_id = ?1", params![id.to_string()])?;
+        {
+            let mut insert = transaction
+                .prepare("INSERT INTO messages (conversation_id, message_index, content) VALUES (?1, ?2, ?3)")?;
+            for (i, message) in messages.iter().enumerate() {
+                insert.execute(params![id.to_string(), i64::try_from(i)?, message.content])?;
+            }
+        }
+        transaction.commit().context("Failed to update search index")
+    }
+
+    pub fn remove_conversation(&self, id: &Uuid) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM messages WHERE conversation_id = ?1", params![id.to_string()])
+            .context("Failed to update search index")?;
+        Ok(())
+    }
+
+    /// Best matches for `query`, most relevant first
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<IndexHit>> {
+        let Some(expression) = match_expression(query) else {
+            return Ok(Vec::new());
+        };
+        let mut statement = self.connection.prepare(
+            "SELECT conversation_id, message_index, snippet(messages, 2, '', '', '…', ?3)
+             FROM messages WHERE messages MATCH ?1 ORDER BY rank LIMIT ?2",
+        )?;
+        let rows = statement.query_map(params![expression, i64::try_from(limit)?, SNIPPET_TOKENS], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(2)?))
+        })?;
+
+        let mut hits = Vec::new();
+        for row in rows {
+            let (id, index, snippet) = row.context("Failed to read search index")?;
+            // Rows that don't parse can only come from a damaged index; skip them
+            let (Ok(conversation_id), Ok(message_index)) = (Uuid::parse_str(&id), usize::try_from(index)) else {
+                continue;
+            };
+            hits.push(IndexHit {
+                conversation_id,
+                message_index,
+                snippet: snippet.split_whitespace().collect::<Vec<_>>().join(" "),
+            });
+        }
+        Ok(hits)
+    }
+}
+
+/// The user's words as an FTS5 query: every word must occur, as a word or a word
+/// prefix. Quoting keeps punctuation and FTS5 operators in the text literal.
+fn match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MessageRole;
+
+    #[test]
+    fn test_match_expression_quotes_words() {
+        assert_eq!(match_expression("  "), None);
+        assert_eq!(match_expression("borrow checker").unwrap(), "\"borrow\"* \"checker\"*");
+        assert_eq!(match_expression("say \"hi\" OR").unwrap(), "\"say\"* \"\"\"hi\"\"\"* \"OR\"*");
+    }
+
+    #[test]
+    fn test_index_ranks_and_replaces() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let (index, created) = SearchIndex::open(&temp_dir.path().join("search.db")).unwrap();
+        assert!(created);
+        let id = Uuid::new_v4();
+        let messages = vec![
+            Message::new(MessageRole::User, "Tell me about trait objects and dyn dispatch".to_string(), 1),
+            Message::new(MessageRole::Assistant, "Trait objects".to_string(), 1),
+        ];
+        index.index_conversation(&id, &messages).unwrap();
+
+        let hits = index.search("TRAIT obj", 10).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].message_index, 1);
+        assert_eq!(hits[0].conversation_id, id);
+        // Punctuation is literal, not FTS5 syntax
+        assert_eq!(index.search("dispatch?", 10).unwrap().len(), 1);
+
+        index.index_conversation(&id, &messages[..1]).unwrap();
+        assert_eq!(index.search("trait", 10).unwrap().len(), 1);
+        index.remove_conversation(&id).unwrap();
+        assert!(index.search("trait", 10).unwrap().is_empty());
+    }
+}
@@ -1,63 +1,351 @@
 // Storage layer for conversations and config
 
+mod crypto;
+mod git;
+mod index;
+
 use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
-use crate::models::{ConversationMetadata, Message};
+use crate::memory::MemoryEntry;
+use crate::models::{ConversationMetadata, Message, MessageRole, ReadLaterItem};
+pub use crypto::Cipher;
+use git::GitSync;
+pub use index::IndexHit;
+use index::SearchIndex;
+
+const DRAFTS_FILE: &str = "drafts.json";
+const EXPERIMENTS_FILE: &str = "experiments.jsonl";
+const READ_LATER_FILE: &str = "read_later.json";
+const MEMORY_FILE: &str = "memory.json";
+/// Files next to the config holding conversation content, encrypted along with it
+const PRIVATE_FILES: [&str; 5] = [DRAFTS_FILE, EXPERIMENTS_FILE, READ_LATER_FILE, MEMORY_FILE, crate::history::HISTORY_FILE];
+const SEARCH_INDEX_FILE: &str = "search.db";
+/// Key derivation salt and passphrase check, next to the encrypted conversations
+const ENCRYPTION_KEY_FILE: &str = "encryption.json";
+/// Subdirectory of `chats` holding archived conversations
+const ARCHIVE_DIR: &str = "archive";
+/// Subdirectory next to a conversation file holding its attachments, one folder per
+/// conversation: `chats/assets/<id>/`
+pub const ASSETS_DIR: &str = "assets";
+/// Draft key of a conversation that has not been saved yet
+const NEW_CONVERSATION_DRAFT: &str = "new";
+const ENCRYPTED_WITHOUT_PASSPHRASE: &str =
+    "Conversation is encrypted: set encrypt_conversations = true to be asked for the passphrase";
+/// Line opening and closing the YAML frontmatter of a conversation file
+const FRONTMATTER_DELIMITER: &str = "---";
 
 #[allow(dead_code)]
+#[derive(Debug)]
 pub struct Storage {
     config_dir: PathBuf,
+    data_dir: PathBuf,
+    /// Conversations, in the `chats` subdirectory of the data directory
     chats_dir: PathBuf,
+    /// Full-text index of saved messages; `None` if it could not be opened (or the
+    /// conversations are encrypted), in which case search falls back to reading every
+    /// conversation
+    index: Option<SearchIndex>,
+    /// Set once unlocked with the passphrase; conversation files are then encrypted
+    cipher: Option<Cipher>,
+    /// Commits every change to the chats directory when git sync is on
+    git: Option<GitSync>,
 }
 
 #[allow(dead_code)]
 impl Storage {
-    pub fn new() -> Result<Self> {
-        let config_dir = dirs::config_dir()
-            .ok_or_else(|| anyhow::anyhow!("Could not determine config directory"))?
-            .join("yumchat");
+    /// Storage in the config directory, with conversations in the data directory
+    /// `config` points at
+    pub fn new(config: &crate::models::AppConfig) -> Result<Self> {
+        let mut storage = Self::without_index(crate::config::get_config_dir()?, crate::config::get_data_dir(config)?)?;
+        // A plaintext index of encrypted conversations would defeat the encryption
+        if !config.encrypt_conversations {
+            storage.open_index();
+        }
+        Ok(storage)
+    }
 
-        let chats_dir = config_dir.join("chats");
+    /// Storage rooted at `config_dir`, with conversations in its `chats` subdirectory
+    pub fn with_dir(config_dir: PathBuf) -> Result<Self> {
+        let data_dir = config_dir.clone();
+        Self::with_dirs(config_dir, &data_dir)
+    }
+
+    /// Storage with drafts, memory and other state in `config_dir`, and conversations
+    /// and their search index in `data_dir`
+    pub fn with_dirs(config_dir: PathBuf, data_dir: &Path) -> Result<Self> {
+        let mut storage = Self::without_index(config_dir, data_dir.to_path_buf())?;
+        storage.open_index();
+        Ok(storage)
+    }
+
+    fn without_index(config_dir: PathBuf, data_dir: PathBuf) -> Result<Self> {
+        let chats_dir = data_dir.join("chats");
 
         fs::create_dir_all(&config_dir).context("Failed to create config directory")?;
         fs::create_dir_all(&chats_dir).context("Failed to create chats directory")?;
 
         Ok(Self {
             config_dir,
+            data_dir,
             chats_dir,
+            index: None,
+            cipher: None,
+            git: None,
         })
     }
 
+    fn open_index(&mut self) {
+        let opened = SearchIndex::open(&self.data_dir.join(SEARCH_INDEX_FILE)).ok();
+        let created = opened.as_ref().is_some_and(|(_, created)| *created);
+        self.index = opened.map(|(index, _)| index);
+        if created {
+            // Best effort: an incomplete index only means fewer search results
+            let _ = self.rebuild_index();
+        }
+    }
+
+    /// Keep the chats directory in git from now on, first merging the remote's history
+    /// (if a remote is configured)
+    pub fn enable_git_sync(&mut self, config: &crate::models::SyncConfig) -> Result<()> {
+        let git = GitSync::open(&self.chats_dir, config.remote.as_deref())?;
+        // Changes made while sync was off
+        git.commit("Save changes made outside yumchat")?;
+        let pulled = git.pull();
+        self.git = Some(git);
+        pulled?;
+        // Pulled conversations have to be searchable too
+        if self.index.is_some() {
+            let _ = self.rebuild_index();
+        }
+        Ok(())
+    }
+
+    /// Push what is left to push before exiting
+    pub fn finish_sync(&self) -> Result<()> {
+        self.git.as_ref().map_or(Ok(()), GitSync::push)
+    }
+
+    /// Commit the chats directory (and push in the background). Failures are ignored:
+    /// the change is saved, and the next commit picks it up.
+    fn record_change(&self, message: &str) {
+        if let Some(git) = &self.git {
+            if matches!(git.commit(message), Ok(true)) {
+                git.push_in_background();
+            }
+        }
+    }
+
+    /// How a conversation is named in commit messages: its summary, or only its id
+    /// when conversations are encrypted
+    fn commit_name(&self, id: &Uuid, metadata: Option<&ConversationMetadata>) -> String {
+        metadata
+            .and_then(|metadata| metadata.summary.clone())
+            .filter(|_| self.cipher.is_none())
+            .unwrap_or_else(|| id.to_string())
+    }
+
+    /// Whether a passphrase has been chosen for these conversations
+    pub fn encryption_set_up(&self) -> bool {
+        self.data_dir.join(ENCRYPTION_KEY_FILE).exists()
+    }
+
+    /// The cipher conversations are encrypted with, once unlocked
+    pub const fn cipher(&self) -> Option<&Cipher> {
+        self.cipher.as_ref()
+    }
+
+    /// Encrypt conversations with `passphrase` from now on (making it the passphrase
+    /// if none was set up yet). Conversations and private files (drafts, memory, ...)
+    /// still in plaintext are encrypted right away and the plaintext search index is
+    /// deleted. Returns how many conversations were encrypted.
+    pub fn unlock(&mut self, passphrase: &str) -> Result<usize> {
+        self.cipher = Some(Cipher::unlock(&self.data_dir.join(ENCRYPTION_KEY_FILE), passphrase)?);
+        self.index = None;
+        let index_path = self.data_dir.join(SEARCH_INDEX_FILE);
+        if index_path.exists() {
+            fs::remove_file(index_path).context("Failed to remove search index")?;
+        }
+        self.encrypt_private_files()?;
+
+        let mut encrypted = 0;
+        let mut conversations = self.list_conversations()?;
+        conversations.extend(self.list_archived_conversations()?);
+        for metadata in conversations {
+            let path = self.get_conversation_path(&metadata.id);
+            let content = if path.exists() { fs::read_to_string(&path)? } else { String::new() };
+            if !crypto::is_encrypted(&content) {
+                self.write_document(&metadata.id, Some(&metadata), split_frontmatter(&content).1)?;
+                encrypted += 1;
+            }
+            self.encrypt_attachments(&metadata.id)?;
+        }
+        Ok(encrypted)
+    }
+
+    fn encrypt_private_files(&self) -> Result<()> {
+        for name in PRIVATE_FILES {
+            let path = self.config_dir.join(name);
+            let Ok(contents) = fs::read_to_string(&path) else {
+                continue;
+            };
+            if !crypto::is_encrypted(&contents) {
+                write_private(&path, &contents, self.cipher.as_ref()).with_context(|| format!("Failed to encrypt {name}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encrypt_attachments(&self, id: &Uuid) -> Result<()> {
+        let (Some(cipher), Ok(entries)) = (&self.cipher, fs::read_dir(self.assets_dir(id))) else {
+            return Ok(());
+        };
+        for entry in entries.flatten() {
+            let contents = fs::read(entry.path()).context("Failed to read attachment")?;
+            if !crypto::is_encrypted_bytes(&contents) {
+                write_atomic(&entry.path(), &cipher.encrypt_bytes(&contents)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A conversation file's text, decrypted if needed
+    fn read_file(&self, path: &Path) -> Result<String> {
+        let content = fs::read_to_string(path).context("Failed to read conversation file")?;
+        if !crypto::is_encrypted(&content) {
+            return Ok(content);
+        }
+        let Some(cipher) = &self.cipher else {
+            anyhow::bail!("{ENCRYPTED_WITHOUT_PASSPHRASE}");
+        };
+        cipher.decrypt(&content)
+    }
+
+    /// Index every saved conversation, archived ones included
+    pub fn rebuild_index(&self) -> Result<()> {
+        let Some(index) = &self.index else {
+            return Ok(());
+        };
+        let mut conversations = self.list_conversations()?;
+        conversations.extend(self.list_archived_conversations()?);
+        for conversation in conversations {
+            index.index_conversation(&conversation.id, &self.load_conversation(&conversation.id)?)?;
+        }
+        Ok(())
+    }
+
+    /// Ranked full-text matches for `query`, or `None` without an index
+    pub fn search_index(&self, query: &str, limit: usize) -> Option<Result<Vec<IndexHit>>> {
+        self.index.as_ref().map(|index| index.search(query, limit))
+    }
+
+    /// Keep the index in step with a conversation just written. Failures are ignored:
+    /// the transcript itself is saved, and a stale index only affects search.
+    fn reindex(&self, id: &Uuid, messages: &[Message]) {
+        if let Some(index) = &self.index {
+            let _ = index.index_conversation(id, messages);
+        }
+    }
+
+    fn archive_dir(&self) -> PathBuf {
+        self.chats_dir.join(ARCHIVE_DIR)
+    }
+
+    /// Where a conversation's file lives: `chats/archive` once archived, else `chats`
+    fn conversation_dir(&self, id: &Uuid) -> PathBuf {
+        let archive_dir = self.archive_dir();
+        let archived = archive_dir.join(format!("{id}.md")).exists()
+            || archive_dir.join(format!("{id}_meta.json")).exists();
+        if archived {
+            archive_dir
+        } else {
+            self.chats_dir.clone()
+        }
+    }
+
+    /// The conversation file: metadata as YAML frontmatter, then the transcript
     pub fn get_conversation_path(&self, id: &Uuid) -> PathBuf {
-        self.chats_dir.join(format!("{id}.md"))
+        self.conversation_dir(id).join(format!("{id}.md"))
+    }
+
+    /// Metadata file written next to the transcript by older versions. It is still
+    /// read when the transcript has no frontmatter, and removed on the next save.
+    fn legacy_metadata_path(&self, id: &Uuid) -> PathBuf {
+        self.conversation_dir(id).join(format!("{id}_meta.json"))
     }
 
-    pub fn get_metadata_path(&self, id: &Uuid) -> PathBuf {
-        self.chats_dir.join(format!("{id}_meta.json"))
+    /// Save a conversation and its metadata together, as one file written to a
+    /// temporary path and renamed into place, so a crash can't leave a truncated
+    /// transcript or metadata that doesn't match it.
+    pub fn save(&self, metadata: &ConversationMetadata, messages: &[Message]) -> Result<()> {
+        let mut metadata = metadata.clone();
+        metadata.store_message_data(messages);
+
+        self.write_document(&metadata.id, Some(&metadata), &Self::serialize_conversation(messages))?;
+        self.reindex(&metadata.id, messages);
+        Ok(())
     }
 
+    /// Replace the transcript, keeping the metadata already saved with it
     pub fn save_conversation(&self, id: &Uuid, messages: &[Message]) -> Result<()> {
-        let path = self.get_conversation_path(id);
+        let mut metadata = self.load_metadata(id).ok();
+        if let Some(metadata) = &mut metadata {
+            metadata.store_message_data(messages);
+        }
+        self.write_document(id, metadata.as_ref(), &Self::serialize_conversation(messages))?;
+        self.reindex(id, messages);
+        Ok(())
+    }
+
+    /// Write the conversation file for `id`. Once written, a legacy metadata file is
+    /// no longer needed.
+    fn write_document(&self, id: &Uuid, metadata: Option<&ConversationMetadata>, body: &str) -> Result<()> {
+        let mut content = String::new();
+        if let Some(metadata) = metadata {
+            content.push_str(FRONTMATTER_DELIMITER);
+            content.push('\n');
+            content.push_str(&serde_yaml::to_string(metadata).context("Failed to serialize metadata")?);
+            content.push_str(FRONTMATTER_DELIMITER);
+            content.push_str("\n\n");
+        }
+        content.push_str(body);
+        if let Some(cipher) = &self.cipher {
+            content = cipher.encrypt(&content)?;
+        }
+        write_atomic(&self.get_conversation_path(id), &content).context("Failed to write conversation file")?;
+
+        let legacy_path = self.legacy_metadata_path(id);
+        if metadata.is_some() && legacy_path.exists() {
+            fs::remove_file(legacy_path).context("Failed to remove old metadata file")?;
+        }
+        self.record_change(&format!("Save {}", self.commit_name(id, metadata)));
+        Ok(())
+    }
+
+    /// Each message under a `## User` / `## Assistant` heading. Content lines that
+    /// would read as such a heading are escaped with a backslash, so any text survives
+    /// the round trip.
+    fn serialize_conversation(messages: &[Message]) -> String {
         let mut content = String::new();
 
         for message in messages {
-            let role = match message.role {
-                crate::models::MessageRole::User => "User",
-                crate::models::MessageRole::Assistant => "Assistant",
-            };
             content.push_str("## ");
-            content.push_str(role);
+            content.push_str(role_heading(&message.role));
             content.push_str("\n\n");
-            content.push_str(&message.content);
+            for line in message.content.split_inclusive('\n') {
+                if is_heading(line.trim_end().trim_start_matches('\\')) {
+                    content.push('\\');
+                }
+                content.push_str(line);
+            }
             content.push_str("\n\n");
         }
 
-        fs::write(&path, content).context("Failed to write conversation file")?;
-
-        Ok(())
+        content
     }
 
     pub fn load_conversation(&self, id: &Uuid) -> Result<Vec<Message>> {
@@ -67,89 +355,451 @@ impl Storage {
             return Ok(Vec::new());
         }
 
-        let content = fs::read_to_string(&path).context("Failed to read conversation file")?;
-
-        let messages = Self::parse_conversation(&content);
+        let content = self.read_file(&path)?;
+
+        let (frontmatter, body) = split_frontmatter(&content);
+        let mut messages = Self::parse_conversation(body);
+        // Token counts, citations and pins are kept in the metadata
+        let metadata = frontmatter.map_or_else(
+            || self.read_metadata(&self.conversation_dir(id), id).ok().flatten(),
+            |frontmatter| {
+                serde_yaml::from_str::<ConversationMetadata>(frontmatter)
+                    .inspect_err(|e| tracing::warn!(%id, error = %e, "unparseable conversation frontmatter"))
+                    .ok()
+            },
+        );
+        if let Some(metadata) = metadata {
+            metadata.restore_message_data(&mut messages);
+        }
         Ok(messages)
     }
 
+    /// A conversation file named by path, e.g. one copied from another machine for
+    /// `yumchat view`: its metadata (when it has frontmatter) and messages
+    pub fn read_conversation_file(&self, path: &Path) -> Result<(Option<ConversationMetadata>, Vec<Message>)> {
+        let content = self.read_file(path)?;
+        let (frontmatter, body) = split_frontmatter(&content);
+        let mut messages = Self::parse_conversation(body);
+        let metadata = frontmatter
+            .map(serde_yaml::from_str::<ConversationMetadata>)
+            .transpose()
+            .context("Failed to parse conversation frontmatter")?;
+        if let Some(metadata) = &metadata {
+            metadata.restore_message_data(&mut messages);
+        }
+        Ok((metadata, messages))
+    }
+
+    /// Inverse of `serialize_conversation`. Text before the first heading is ignored;
+    /// hand-edited messages whose spacing doesn't match what is written are trimmed.
     fn parse_conversation(content: &str) -> Vec<Message> {
         let mut messages = Vec::new();
-        let sections: Vec<&str> = content.split("## ").collect();
-
-        for section in sections.iter().skip(1) {
-            if let Some((role_line, msg_content)) = section.split_once('\n') {
-                let role = if role_line.trim() == "User" {
-                    crate::models::MessageRole::User
-                } else {
-                    crate::models::MessageRole::Assistant
-                };
-
-                let msg_content = msg_content.trim().to_string();
-                // Token count will be calculated properly in token counter
-                messages.push(Message::new(role, msg_content, 0));
+        let mut current: Option<(MessageRole, String)> = None;
+
+        for line in content.split_inclusive('\n') {
+            if let Some(role) = heading_role(line.trim_end()) {
+                messages.extend(current.take().map(|(role, body)| Self::parsed_message(role, &body)));
+                current = Some((role, String::new()));
+            } else if let Some((_, body)) = &mut current {
+                let unescaped = line.strip_prefix('\\').filter(|rest| is_heading(rest.trim_end().trim_start_matches('\\')));
+                body.push_str(unescaped.unwrap_or(line));
             }
         }
+        messages.extend(current.map(|(role, body)| Self::parsed_message(role, &body)));
 
         messages
     }
 
-    pub fn save_metadata(&self, metadata: &ConversationMetadata) -> Result<()> {
-        let path = self.get_metadata_path(&metadata.id);
-        let content =
-            serde_json::to_string_pretty(metadata).context("Failed to serialize metadata")?;
-
-        fs::write(&path, content).context("Failed to write metadata file")?;
+    /// A message from the text between its heading and the next. Token counts are
+    /// restored from the metadata.
+    fn parsed_message(role: MessageRole, body: &str) -> Message {
+        let content = body
+            .strip_prefix('\n')
+            .and_then(|body| body.strip_suffix("\n\n"))
+            .map_or_else(|| body.trim().to_string(), ToString::to_string);
+        Message::new(role, content, 0)
+    }
 
-        Ok(())
+    /// Replace the metadata, keeping the transcript saved with it
+    pub fn save_metadata(&self, metadata: &ConversationMetadata) -> Result<()> {
+        let path = self.get_conversation_path(&metadata.id);
+        let content = if path.exists() {
+            self.read_file(&path)?
+        } else {
+            String::new()
+        };
+        self.write_document(&metadata.id, Some(metadata), split_frontmatter(&content).1)
     }
 
     pub fn load_metadata(&self, id: &Uuid) -> Result<ConversationMetadata> {
-        let path = self.get_metadata_path(id);
+        self.read_metadata(&self.conversation_dir(id), id)?.ok_or_else(|| anyhow::anyhow!("Metadata file not found"))
+    }
 
-        if !path.exists() {
-            anyhow::bail!("Metadata file not found");
+    /// Metadata of conversation `id` in `dir`: the frontmatter of its file, or the
+    /// legacy `_meta.json` next to it
+    fn read_metadata(&self, dir: &Path, id: &Uuid) -> Result<Option<ConversationMetadata>> {
+        let path = dir.join(format!("{id}.md"));
+        let frontmatter = if self.cipher.is_some() && path.exists() {
+            split_frontmatter(&self.read_file(&path)?).0.map(ToString::to_string)
+        } else {
+            read_frontmatter(&path)?
+        };
+        if let Some(frontmatter) = frontmatter {
+            let metadata = serde_yaml::from_str(&frontmatter).context("Failed to parse conversation frontmatter")?;
+            return Ok(Some(metadata));
         }
 
-        let content = fs::read_to_string(&path).context("Failed to read metadata file")?;
+        let legacy_path = dir.join(format!("{id}_meta.json"));
+        if !legacy_path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&legacy_path).context("Failed to read metadata file")?;
+        let metadata = serde_json::from_str(&content).context("Failed to parse metadata file")?;
+        Ok(Some(metadata))
+    }
 
-        let metadata: ConversationMetadata =
-            serde_json::from_str(&content).context("Failed to parse metadata file")?;
+    pub fn list_conversations(&self) -> Result<Vec<ConversationMetadata>> {
+        self.list_dir(&self.chats_dir)
+    }
 
-        Ok(metadata)
+    /// Conversations moved into `chats/archive`, in the same order as `list_conversations`
+    pub fn list_archived_conversations(&self) -> Result<Vec<ConversationMetadata>> {
+        self.list_dir(&self.archive_dir())
     }
 
-    pub fn list_conversations(&self) -> Result<Vec<ConversationMetadata>> {
+    fn list_dir(&self, dir: &Path) -> Result<Vec<ConversationMetadata>> {
         let mut conversations = Vec::new();
 
-        if !self.chats_dir.exists() {
+        if !dir.exists() {
             return Ok(conversations);
         }
 
-        for entry in fs::read_dir(&self.chats_dir).context("Failed to read chats directory")? {
+        // Conversation files and legacy metadata files, once per conversation
+        let mut ids = HashSet::new();
+        for entry in fs::read_dir(dir).context("Failed to read chats directory")? {
             let entry = entry?;
             let path = entry.path();
 
             if let Some(filename) = path.file_name() {
                 let filename = filename.to_string_lossy();
-                if filename.ends_with("_meta.json") {
-                    let content = fs::read_to_string(&path)?;
-                    if let Ok(metadata) = serde_json::from_str::<ConversationMetadata>(&content) {
-                        conversations.push(metadata);
-                    }
+                let id = filename.strip_suffix(".md").or_else(|| filename.strip_suffix("_meta.json"));
+                if let Some(id) = id.and_then(|id| Uuid::parse_str(id).ok()) {
+                    ids.insert(id);
                 }
             }
         }
+        for id in ids {
+            // Files that can't be read or parsed are left out rather than failing the list
+            match self.read_metadata(dir, &id) {
+                Ok(Some(metadata)) => conversations.push(metadata),
+                Ok(None) => {}
+                Err(e) => tracing::warn!(%id, error = format!("{e:#}"), "conversation left out of the list"),
+            }
+        }
 
-        // Sort by updated_at, most recent first
-        conversations.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        // Pinned first, then by updated_at, most recent first
+        conversations.sort_by_key(|c| (std::cmp::Reverse(c.pinned), std::cmp::Reverse(c.updated_at)));
 
         Ok(conversations)
     }
 
+    /// Write a saved conversation to `path` (or into it, if it is a directory) as
+    /// structured JSON. Returns the file written.
+    pub fn export_json(&self, id: &Uuid, path: &Path) -> Result<PathBuf> {
+        let metadata = self.load_metadata(id)?;
+        let messages = self.load_conversation(id)?;
+        crate::export::export_to_path(
+            path,
+            crate::export::ExportFormat::Json,
+            &crate::export::MarkdownTemplate::default(),
+            &metadata,
+            &messages,
+        )
+    }
+
+    /// Attachments of a conversation, next to its file (so links in it resolve)
+    fn assets_dir(&self, id: &Uuid) -> PathBuf {
+        self.conversation_dir(id).join(ASSETS_DIR).join(id.to_string())
+    }
+
+    /// Copy `source` into the conversation's assets folder (encrypted like the
+    /// conversation). Returns the link to it relative to the conversation file.
+    pub fn store_attachment(&self, id: &Uuid, source: &Path) -> Result<String> {
+        let contents = fs::read(source).with_context(|| format!("Failed to read {}", source.display()))?;
+        let file_name = source
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Not a file: {}", source.display()))?;
+        let dir = self.assets_dir(id);
+        fs::create_dir_all(&dir).context("Failed to create assets directory")?;
+
+        // Links are markdown: keep names free of spaces and brackets
+        let safe: String = file_name
+            .chars()
+            .map(|c| if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '-' })
+            .collect();
+        let (stem, extension) = safe.rsplit_once('.').map_or((safe.as_str(), None), |(s, e)| (s, Some(e)));
+        let mut name = safe.clone();
+        let mut copy = 1;
+        while dir.join(&name).exists() {
+            copy += 1;
+            name = extension.map_or_else(|| format!("{stem}-{copy}"), |e| format!("{stem}-{copy}.{e}"));
+        }
+
+        let path = dir.join(&name);
+        match &self.cipher {
+            Some(cipher) => write_atomic(&path, &cipher.encrypt_bytes(&contents)?)?,
+            None => fs::write(&path, contents).context("Failed to write attachment")?,
+        }
+        Ok(format!("{ASSETS_DIR}/{id}/{name}"))
+    }
+
+    /// Contents of an attachment linked from the conversation as `link`, decrypted
+    pub fn read_attachment(&self, id: &Uuid, link: &str) -> Result<Vec<u8>> {
+        let name = link
+            .strip_prefix(&format!("{ASSETS_DIR}/{id}/"))
+            .filter(|name| !name.is_empty() && !name.contains(['/', '\\']) && *name != "..")
+            .ok_or_else(|| anyhow::anyhow!("Not an attachment of this conversation: {link}"))?;
+        let contents = fs::read(self.assets_dir(id).join(name)).with_context(|| format!("Missing attachment {name}"))?;
+        if !crypto::is_encrypted_bytes(&contents) {
+            return Ok(contents);
+        }
+        let Some(cipher) = &self.cipher else {
+            anyhow::bail!("{ENCRYPTED_WITHOUT_PASSPHRASE}");
+        };
+        cipher.decrypt_bytes(&String::from_utf8_lossy(&contents))
+    }
+
+    /// Copy a conversation's attachments (decrypted) next to an export of it in `dir`,
+    /// so the links in the export resolve. Returns how many were copied.
+    pub fn export_attachments(&self, id: &Uuid, dir: &Path) -> Result<usize> {
+        let source = self.assets_dir(id);
+        let Ok(entries) = fs::read_dir(&source) else {
+            return Ok(0);
+        };
+        let target = dir.join(ASSETS_DIR).join(id.to_string());
+        fs::create_dir_all(&target).with_context(|| format!("Failed to create {}", target.display()))?;
+        let mut copied = 0;
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let contents = self.read_attachment(id, &format!("{ASSETS_DIR}/{id}/{name}"))?;
+            fs::write(target.join(&name), contents).with_context(|| format!("Failed to write {name}"))?;
+            copied += 1;
+        }
+        Ok(copied)
+    }
+
+    /// Perform the configured `[export.on_close]` export of saved conversation `id`.
+    /// Returns the file written, or `None` when automatic export is off.
+    pub fn auto_export(&self, id: &Uuid, export: &crate::models::ExportConfig) -> Result<Option<PathBuf>> {
+        let Some(auto) = &export.on_close else {
+            return Ok(None);
+        };
+        let metadata = self.load_metadata(id)?;
+        let messages = self.load_conversation(id)?;
+        let dir = crate::rag::expand_home(&auto.dir);
+        let path = crate::export::export_to_dir(&dir, auto.format, &export.markdown, &metadata, &messages)?;
+        if let Some(dir) = path.parent() {
+            self.export_attachments(id, dir)?;
+        }
+        Ok(Some(path))
+    }
+
+    /// Archive a conversation (moving its files into `chats/archive`) or bring it back.
+    /// Returns the updated metadata.
+    pub fn set_archived(&self, id: &Uuid, archived: bool) -> Result<ConversationMetadata> {
+        let mut metadata = self.load_metadata(id)?;
+        let target_dir = if archived { self.archive_dir() } else { self.chats_dir.clone() };
+        fs::create_dir_all(&target_dir).context("Failed to create archive directory")?;
+
+        let conversation_path = self.get_conversation_path(id);
+        let legacy_path = self.legacy_metadata_path(id);
+        let assets_dir = self.assets_dir(id);
+        if assets_dir.exists() {
+            let target_assets = target_dir.join(ASSETS_DIR);
+            fs::create_dir_all(&target_assets).context("Failed to create assets directory")?;
+            fs::rename(&assets_dir, target_assets.join(id.to_string())).context("Failed to move attachments")?;
+        }
+        if conversation_path.exists() {
+            fs::rename(&conversation_path, target_dir.join(format!("{id}.md")))
+                .context("Failed to move conversation file")?;
+        }
+        if legacy_path.exists() {
+            fs::rename(legacy_path, target_dir.join(format!("{id}_meta.json")))
+                .context("Failed to move metadata file")?;
+        }
+
+        metadata.archived = archived;
+        self.save_metadata(&metadata)?;
+        Ok(metadata)
+    }
+
+    /// Archive or delete the listed conversations outside `policy`. Returns those pruned.
+    pub fn apply_retention(
+        &self,
+        policy: &crate::models::RetentionConfig,
+        export: &crate::models::ExportConfig,
+    ) -> Result<Vec<ConversationMetadata>> {
+        if !policy.is_enabled() {
+            return Ok(Vec::new());
+        }
+        let conversations = self.list_conversations()?;
+        let expired = policy.expired(&conversations, chrono::Utc::now());
+        for metadata in &expired {
+            match policy.action {
+                crate::models::RetentionAction::Archive => {
+                    self.set_archived(&metadata.id, true)?;
+                    // Archiving closes the conversation for good, so it is exported like one
+                    if let Err(e) = self.auto_export(&metadata.id, export) {
+                        tracing::warn!(id = %metadata.id, error = %e, "export of archived conversation failed");
+                    }
+                }
+                crate::models::RetentionAction::Delete => self.delete_conversation(&metadata.id)?,
+            }
+        }
+        Ok(expired.into_iter().cloned().collect())
+    }
+
+    /// Create a new conversation containing `messages[..=upto]`, branched off `parent`.
+    /// The parent conversation is left untouched.
+    pub fn fork_conversation(
+        &self,
+        parent: &ConversationMetadata,
+        messages: &[Message],
+        upto: usize,
+    ) -> Result<ConversationMetadata> {
+        if upto >= messages.len() {
+            anyhow::bail!("Cannot fork at message {upto}: conversation has {} messages", messages.len());
+        }
+
+        let mut metadata = parent.fork();
+        let mut history = messages[..=upto].to_vec();
+        metadata.total_tokens = history.iter().map(|m| m.tokens).sum();
+
+        // The fork gets its own copy of the attachments, so either can be deleted
+        let parent_assets = self.assets_dir(&parent.id);
+        if let Ok(entries) = fs::read_dir(&parent_assets) {
+            let assets = self.assets_dir(&metadata.id);
+            fs::create_dir_all(&assets).context("Failed to create assets directory")?;
+            for entry in entries.flatten() {
+                fs::copy(entry.path(), assets.join(entry.file_name())).context("Failed to copy attachment")?;
+            }
+            let (from, to) = (
+                format!("]({ASSETS_DIR}/{}/", parent.id),
+                format!("]({ASSETS_DIR}/{}/", metadata.id),
+            );
+            for message in &mut history {
+                message.content = message.content.replace(&from, &to);
+            }
+        }
+
+        self.save(&metadata, &history)?;
+
+        Ok(metadata)
+    }
+
+    fn load_drafts(&self) -> BTreeMap<String, String> {
+        read_private(&self.config_dir.join(DRAFTS_FILE), self.cipher.as_ref())
+            .ok()
+            .flatten()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn draft_key(id: Option<&Uuid>) -> String {
+        id.map_or_else(|| NEW_CONVERSATION_DRAFT.to_string(), ToString::to_string)
+    }
+
+    /// Remember unsent input for a conversation (`None` for a new, unsaved one).
+    /// Empty text removes the draft.
+    pub fn save_draft(&self, id: Option<&Uuid>, text: &str) -> Result<()> {
+        let mut drafts = self.load_drafts();
+        let key = Self::draft_key(id);
+        if text.is_empty() {
+            if drafts.remove(&key).is_none() {
+                return Ok(());
+            }
+        } else {
+            drafts.insert(key, text.to_string());
+        }
+        let contents = serde_json::to_string_pretty(&drafts).context("Failed to serialize drafts")?;
+        write_private(&self.config_dir.join(DRAFTS_FILE), &contents, self.cipher.as_ref()).context("Failed to write drafts")
+    }
+
+    pub fn load_draft(&self, id: Option<&Uuid>) -> Option<String> {
+        self.load_drafts().remove(&Self::draft_key(id))
+    }
+
+    /// Saved conversations that have a draft waiting
+    pub fn conversations_with_drafts(&self) -> HashSet<Uuid> {
+        self.load_drafts()
+            .keys()
+            .filter_map(|key| Uuid::parse_str(key).ok())
+            .collect()
+    }
+
+    /// Append which parameter value was kept in an experiment to `experiments.jsonl`.
+    /// An encrypted log can't be appended to, so it is rewritten.
+    pub fn record_experiment(&self, record: &crate::experiment::ExperimentRecord) -> Result<()> {
+        let path = self.config_dir.join(EXPERIMENTS_FILE);
+        let line = serde_json::to_string(record)?;
+        if self.cipher.is_some() {
+            let mut contents = read_private(&path, self.cipher.as_ref())
+                .context("Failed to read experiments log")?
+                .unwrap_or_default();
+            contents.push_str(&line);
+            contents.push('\n');
+            return write_private(&path, &contents, self.cipher.as_ref()).context("Failed to write experiments log");
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .context("Failed to open experiments log")?;
+        writeln!(file, "{line}").context("Failed to write experiments log")
+    }
+
+    /// The read-later queue, oldest first. A missing file is an empty queue.
+    pub fn load_read_later(&self) -> Result<Vec<ReadLaterItem>> {
+        let Some(contents) = read_private(&self.config_dir.join(READ_LATER_FILE), self.cipher.as_ref())
+            .context("Failed to read read-later queue")?
+        else {
+            return Ok(Vec::new());
+        };
+        serde_json::from_str(&contents).context("Failed to parse read-later queue")
+    }
+
+    pub fn save_read_later(&self, items: &[ReadLaterItem]) -> Result<()> {
+        let contents = serde_json::to_string_pretty(items).context("Failed to serialize read-later queue")?;
+        write_private(&self.config_dir.join(READ_LATER_FILE), &contents, self.cipher.as_ref())
+            .context("Failed to write read-later queue")
+    }
+
+    /// Remembered facts, oldest first. A missing file means nothing is remembered.
+    pub fn load_memory(&self) -> Result<Vec<MemoryEntry>> {
+        let Some(contents) = read_private(&self.config_dir.join(MEMORY_FILE), self.cipher.as_ref())
+            .context("Failed to read memory")?
+        else {
+            return Ok(Vec::new());
+        };
+        serde_json::from_str(&contents).context("Failed to parse memory")
+    }
+
+    pub fn save_memory(&self, entries: &[MemoryEntry]) -> Result<()> {
+        let contents = serde_json::to_string_pretty(entries).context("Failed to serialize memory")?;
+        write_private(&self.config_dir.join(MEMORY_FILE), &contents, self.cipher.as_ref()).context("Failed to write memory")
+    }
+
     pub fn delete_conversation(&self, id: &Uuid) -> Result<()> {
         let conv_path = self.get_conversation_path(id);
-        let meta_path = self.get_metadata_path(id);
+        let meta_path = self.legacy_metadata_path(id);
+        let assets_dir = self.assets_dir(id);
+        let name = self.commit_name(id, self.load_metadata(id).ok().as_ref());
+        self.save_draft(Some(id), "")?;
+
+        if assets_dir.exists() {
+            fs::remove_dir_all(assets_dir).context("Failed to delete attachments")?;
+        }
 
         if conv_path.exists() {
             fs::remove_file(conv_path).context("Failed to delete conversation file")?;
@@ -159,13 +809,163 @@ impl Storage {
             fs::remove_file(meta_path).context("Failed to delete metadata file")?;
         }
 
+        if let Some(index) = &self.index {
+            index.remove_conversation(id)?;
+        }
+        self.record_change(&format!("Delete {name}"));
+
         Ok(())
     }
 }
 
+/// A file written to a temporary sibling path, moved over the target on `commit`.
+/// Dropping it without committing discards the temporary file.
+struct StagedWrite {
+    temp_path: PathBuf,
+    target: PathBuf,
+    committed: bool,
+}
+
+impl StagedWrite {
+    fn new(target: PathBuf, contents: &str) -> Result<Self> {
+        let mut file_name = target.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".tmp");
+        let staged = Self {
+            temp_path: target.with_file_name(file_name),
+            target,
+            committed: false,
+        };
+
+        let mut file = fs::File::create(&staged.temp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+
+        Ok(staged)
+    }
+
+    fn commit(mut self) -> Result<()> {
+        fs::rename(&self.temp_path, &self.target)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for StagedWrite {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+const fn role_heading(role: &MessageRole) -> &'static str {
+    match role {
+        MessageRole::User => "User",
+        MessageRole::Assistant => "Assistant",
+    }
+}
+
+/// The role a `## User` / `## Assistant` line starts
+fn heading_role(line: &str) -> Option<MessageRole> {
+    match line {
+        "## User" => Some(MessageRole::User),
+        "## Assistant" => Some(MessageRole::Assistant),
+        _ => None,
+    }
+}
+
+fn is_heading(line: &str) -> bool {
+    heading_role(line).is_some()
+}
+
+/// Split a conversation file into its YAML frontmatter (if any) and the transcript
+fn split_frontmatter(content: &str) -> (Option<&str>, &str) {
+    let Some(rest) = content
+        .strip_prefix(FRONTMATTER_DELIMITER)
+        .and_then(|rest| rest.strip_prefix("\r\n").or_else(|| rest.strip_prefix('\n')))
+    else {
+        return (None, content);
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == FRONTMATTER_DELIMITER {
+            return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    // An unclosed block is not frontmatter
+    (None, content)
+}
+
+/// The frontmatter of the file at `path`, reading no further than its closing line
+fn read_frontmatter(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let file = fs::File::open(path).context("Failed to read conversation file")?;
+    let mut lines = BufReader::new(file).lines();
+    match lines.next() {
+        Some(Ok(line)) if line.trim_end() == FRONTMATTER_DELIMITER => {}
+        Some(Ok(line)) if crypto::is_encrypted(&line) => anyhow::bail!("{ENCRYPTED_WITHOUT_PASSPHRASE}"),
+        _ => return Ok(None),
+    }
+    let mut frontmatter = String::new();
+    for line in lines {
+        let line = line.context("Failed to read conversation file")?;
+        if line.trim_end() == FRONTMATTER_DELIMITER {
+            return Ok(Some(frontmatter));
+        }
+        frontmatter.push_str(&line);
+        frontmatter.push('\n');
+    }
+    Ok(None)
+}
+
+/// Name and target of an attachment linked on its own line: `![name](assets/...)` for
+/// images, `[name](assets/...)` for other files
+pub fn attachment_link(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    let inner = line.strip_prefix('!').unwrap_or(line).strip_prefix('[')?.strip_suffix(')')?;
+    let (name, target) = inner.split_once("](")?;
+    target
+        .strip_prefix(ASSETS_DIR)
+        .is_some_and(|rest| rest.starts_with('/'))
+        .then_some((name, target))
+}
+
+/// Replace `path` with `contents` via temp file + rename
+pub fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    StagedWrite::new(path.to_path_buf(), contents)?.commit()
+}
+
+/// The text of a private file (drafts, memory, ...), decrypted with `cipher` when it
+/// was encrypted. `None` when the file doesn't exist.
+pub fn read_private(path: &Path, cipher: Option<&Cipher>) -> Result<Option<String>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if !crypto::is_encrypted(&contents) {
+        return Ok(Some(contents));
+    }
+    let Some(cipher) = cipher else {
+        anyhow::bail!("{ENCRYPTED_WITHOUT_PASSPHRASE}");
+    };
+    cipher.decrypt(&contents).map(Some)
+}
+
+/// Replace a private file with `contents`, encrypted when `cipher` is set
+pub fn write_private(path: &Path, contents: &str, cipher: Option<&Cipher>) -> Result<()> {
+    match cipher {
+        Some(cipher) => write_atomic(path, &cipher.encrypt(contents)?),
+        None => write_atomic(path, contents),
+    }
+}
+
 impl Default for Storage {
     fn default() -> Self {
-        Self::new().expect("Failed to create storage")
+        Self::new(&crate::models::AppConfig::default()).expect("Failed to create storage")
     }
 }
 
@@ -182,8 +982,12 @@ mod tests {
         fs::create_dir_all(&chats_dir).unwrap();
 
         let storage = Storage {
-            config_dir,
+            config_dir: config_dir.clone(),
+            data_dir: config_dir,
             chats_dir,
+            index: None,
+            cipher: None,
+            git: None,
         };
 
         (temp_dir, storage)
@@ -198,7 +1002,7 @@ mod tests {
         let original_home = std::env::var("HOME").ok();
         std::env::set_var("HOME", temp_dir.path());
         
-        let result = Storage::new();
+        let result = Storage::new(&crate::models::AppConfig::default());
         
         // Restore original HOME
         if let Some(home) = original_home {
@@ -274,6 +1078,220 @@ mod tests {
         assert_eq!(conversations.len(), 2);
     }
 
+    #[test]
+    fn test_pinned_conversations_listed_first() {
+        let (_temp, storage) = setup_test_storage();
+
+        let mut older = ConversationMetadata::new();
+        older.pinned = true;
+        older.updated_at -= chrono::Duration::days(30);
+        storage.save_metadata(&older).unwrap();
+        let newer = ConversationMetadata::new();
+        storage.save_metadata(&newer).unwrap();
+
+        let ids: Vec<Uuid> = storage.list_conversations().unwrap().iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![older.id, newer.id]);
+    }
+
+    #[test]
+    fn test_archive_moves_conversation_out_of_the_list() {
+        let (_temp, storage) = setup_test_storage();
+        let metadata = ConversationMetadata::new();
+        let messages = vec![Message::new(crate::models::MessageRole::User, "Old".to_string(), 1)];
+        storage.save(&metadata, &messages).unwrap();
+
+        let archived = storage.set_archived(&metadata.id, true).unwrap();
+        assert!(archived.archived);
+        assert!(storage.list_conversations().unwrap().is_empty());
+        assert_eq!(storage.list_archived_conversations().unwrap().len(), 1);
+        let archive_dir = storage.chats_dir.join(ARCHIVE_DIR);
+        assert!(archive_dir.join(format!("{}.md", metadata.id)).exists());
+        // Archived conversations still load and save in place
+        assert_eq!(storage.load_conversation(&metadata.id).unwrap().len(), 1);
+        storage.save(&archived, &messages).unwrap();
+        assert!(!storage.chats_dir.join(format!("{}.md", metadata.id)).exists());
+
+        storage.set_archived(&metadata.id, false).unwrap();
+        assert!(storage.list_archived_conversations().unwrap().is_empty());
+        assert!(!storage.list_conversations().unwrap()[0].archived);
+    }
+
+    #[test]
+    fn test_search_index_follows_saves_and_deletes() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = Storage::with_dir(temp_dir.path().to_path_buf()).unwrap();
+        let metadata = ConversationMetadata::new();
+        let messages = vec![Message::new(crate::models::MessageRole::User, "Tokio runtime".to_string(), 1)];
+        storage.save(&metadata, &messages).unwrap();
+        assert_eq!(storage.search_index("tokio", 10).unwrap().unwrap().len(), 1);
+
+        // A missing index is rebuilt from the saved conversations
+        drop(storage);
+        fs::remove_file(temp_dir.path().join(SEARCH_INDEX_FILE)).unwrap();
+        let storage = Storage::with_dir(temp_dir.path().to_path_buf()).unwrap();
+        assert_eq!(storage.search_index("runtime", 10).unwrap().unwrap()[0].conversation_id, metadata.id);
+
+        storage.delete_conversation(&metadata.id).unwrap();
+        assert!(storage.search_index("tokio", 10).unwrap().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_conversations_live_in_the_data_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = temp_dir.path().join("Sync/yumchat");
+        let storage = Storage::with_dirs(temp_dir.path().join("config"), &data_dir).unwrap();
+        let metadata = ConversationMetadata::new();
+        storage.save(&metadata, &[]).unwrap();
+        storage.save_draft(Some(&metadata.id), "draft").unwrap();
+
+        assert!(data_dir.join(format!("chats/{}.md", metadata.id)).exists());
+        assert!(data_dir.join(SEARCH_INDEX_FILE).exists());
+        assert!(temp_dir.path().join("config").join(DRAFTS_FILE).exists());
+        assert!(!temp_dir.path().join("config/chats").exists());
+    }
+
+    #[test]
+    fn test_unlock_encrypts_conversations() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = Storage::with_dir(temp_dir.path().to_path_buf()).unwrap();
+        let mut metadata = ConversationMetadata::new();
+        metadata.set_summary("Merger talks".to_string());
+        let messages = vec![Message::new(crate::models::MessageRole::User, "Confidential".to_string(), 4)];
+        storage.save(&metadata, &messages).unwrap();
+        assert!(!storage.encryption_set_up());
+
+        // Existing plaintext is encrypted and the plaintext index removed
+        assert_eq!(storage.unlock("passphrase").unwrap(), 1);
+        assert!(storage.encryption_set_up());
+        assert!(!temp_dir.path().join(SEARCH_INDEX_FILE).exists());
+        let path = storage.get_conversation_path(&metadata.id);
+        let on_disk = fs::read_to_string(&path).unwrap();
+        assert!(!on_disk.contains("Confidential") && !on_disk.contains("Merger"));
+        assert_eq!(storage.load_conversation(&metadata.id).unwrap(), messages);
+        assert_eq!(storage.list_conversations().unwrap()[0].summary.as_deref(), Some("Merger talks"));
+
+        // Without the passphrase the conversation can't be read
+        let locked = Storage::with_dir(temp_dir.path().to_path_buf()).unwrap();
+        assert!(locked.list_conversations().unwrap().is_empty());
+        assert!(locked.load_conversation(&metadata.id).unwrap_err().to_string().contains("encrypted"));
+    }
+
+    #[test]
+    fn test_unlock_encrypts_private_files() {
+        let (temp_dir, mut storage) = setup_test_storage();
+        let config_dir = temp_dir.path().join(".config/yumchat");
+        storage.save_draft(None, "Unsent secret").unwrap();
+        fs::write(config_dir.join(crate::history::HISTORY_FILE), "\"Sent secret\"\n").unwrap();
+
+        // Plaintext written before encryption was turned on is encrypted in place
+        storage.unlock("passphrase").unwrap();
+        for name in [DRAFTS_FILE, crate::history::HISTORY_FILE] {
+            let on_disk = fs::read_to_string(config_dir.join(name)).unwrap();
+            assert!(crypto::is_encrypted(&on_disk) && !on_disk.contains("secret"), "{name}");
+        }
+
+        // New drafts are written encrypted and read back
+        storage.save_draft(None, "Another secret").unwrap();
+        let on_disk = fs::read_to_string(config_dir.join(DRAFTS_FILE)).unwrap();
+        assert!(crypto::is_encrypted(&on_disk) && !on_disk.contains("secret"));
+        assert_eq!(storage.load_draft(None).as_deref(), Some("Another secret"));
+
+        let locked = Storage { cipher: None, ..storage };
+        assert_eq!(locked.load_draft(None), None);
+    }
+
+    #[test]
+    fn test_attachments_follow_the_conversation() {
+        let (temp_dir, storage) = setup_test_storage();
+        let source = temp_dir.path().join("my photo.png");
+        fs::write(&source, [0x89, b'P', b'N', b'G']).unwrap();
+        let parent = ConversationMetadata::new();
+        let link = storage.store_attachment(&parent.id, &source).unwrap();
+        assert_eq!(link, format!("assets/{}/my-photo.png", parent.id));
+        let second = storage.store_attachment(&parent.id, &source).unwrap();
+        assert!(second.ends_with("/my-photo-2.png"));
+        assert_eq!(storage.read_attachment(&parent.id, &link).unwrap(), [0x89, b'P', b'N', b'G']);
+        assert!(storage.read_attachment(&parent.id, "assets/../config.toml").is_err());
+
+        let messages = vec![Message::new(MessageRole::User, format!("Look\n\n![my photo.png]({link})"), 4)];
+        storage.save(&parent, &messages).unwrap();
+
+        // Forks link their own copies
+        let fork = storage.fork_conversation(&parent, &messages, 0).unwrap();
+        let forked = storage.load_conversation(&fork.id).unwrap();
+        assert!(forked[0].content.contains(&format!("assets/{}/my-photo.png", fork.id)));
+
+        // Archiving moves them along, exports get a copy, deleting removes them
+        storage.set_archived(&parent.id, true).unwrap();
+        assert!(storage.chats_dir.join(format!("archive/{link}")).exists());
+        assert_eq!(storage.read_attachment(&parent.id, &link).unwrap().len(), 4);
+        let export_dir = temp_dir.path().join("export");
+        assert_eq!(storage.export_attachments(&parent.id, &export_dir).unwrap(), 2);
+        assert!(export_dir.join(&link).exists());
+        storage.delete_conversation(&parent.id).unwrap();
+        assert!(!storage.chats_dir.join(format!("archive/assets/{}", parent.id)).exists());
+        assert!(storage.read_attachment(&fork.id, &format!("assets/{}/my-photo.png", fork.id)).is_ok());
+    }
+
+    #[test]
+    fn test_apply_retention() {
+        let (temp, storage) = setup_test_storage();
+        let mut old = ConversationMetadata::new();
+        old.updated_at -= chrono::Duration::days(60);
+        storage.save_metadata(&old).unwrap();
+        let recent = ConversationMetadata::new();
+        storage.save_metadata(&recent).unwrap();
+
+        let export_dir = temp.path().join("notes");
+        let export = crate::models::ExportConfig {
+            on_close: Some(crate::models::AutoExport {
+                dir: export_dir.to_string_lossy().to_string(),
+                format: crate::export::ExportFormat::Markdown,
+            }),
+            ..crate::models::ExportConfig::default()
+        };
+        let mut policy = crate::models::RetentionConfig::default();
+        assert!(storage.apply_retention(&policy, &export).unwrap().is_empty());
+
+        policy.retention_days = Some(30);
+        let pruned = storage.apply_retention(&policy, &export).unwrap();
+        assert_eq!(pruned.iter().map(|c| c.id).collect::<Vec<_>>(), vec![old.id]);
+        assert_eq!(storage.list_archived_conversations().unwrap()[0].id, old.id);
+        // Archived conversations are exported as they are closed
+        assert_eq!(fs::read_dir(&export_dir).unwrap().count(), 1);
+
+        policy.max_conversations = Some(0);
+        policy.action = crate::models::RetentionAction::Delete;
+        assert_eq!(storage.apply_retention(&policy, &export).unwrap().len(), 1);
+        // Deleted ones are not
+        assert_eq!(fs::read_dir(&export_dir).unwrap().count(), 1);
+        assert!(storage.list_conversations().unwrap().is_empty());
+        // Archived conversations are left alone
+        assert_eq!(storage.list_archived_conversations().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_git_sync_commits_saves() {
+        if std::process::Command::new("git").arg("--version").output().is_err() {
+            return;
+        }
+        let (_temp, mut storage) = setup_test_storage();
+        storage.enable_git_sync(&crate::models::SyncConfig { git: true, remote: None }).unwrap();
+        let mut metadata = ConversationMetadata::new();
+        metadata.set_summary("Trip ideas".to_string());
+        storage.save(&metadata, &[]).unwrap();
+        storage.delete_conversation(&metadata.id).unwrap();
+
+        let log = std::process::Command::new("git")
+            .arg("-C")
+            .arg(&storage.chats_dir)
+            .args(["log", "--format=%s"])
+            .output()
+            .unwrap();
+        let log = String::from_utf8(log.stdout).unwrap();
+        assert_eq!(log.lines().collect::<Vec<_>>(), vec!["Delete Trip ideas", "Save Trip ideas", "Start conversation history"]);
+    }
+
     #[test]
     fn test_delete_conversation() {
         let (_temp, storage) = setup_test_storage();
@@ -298,6 +1316,94 @@ mod tests {
         assert!(loaded.unwrap().is_empty());
     }
 
+    #[test]
+    fn test_fork_conversation() {
+        let (_temp, storage) = setup_test_storage();
+        let mut parent = ConversationMetadata::new();
+        parent.set_summary("Parent".to_string());
+
+        let messages = vec![
+            Message::new(crate::models::MessageRole::User, "First".to_string(), 5),
+            Message::new(crate::models::MessageRole::Assistant, "Reply".to_string(), 7),
+            Message::new(crate::models::MessageRole::User, "Second".to_string(), 5),
+        ];
+        storage.save_conversation(&parent.id, &messages).unwrap();
+        storage.save_metadata(&parent).unwrap();
+
+        let forked = storage.fork_conversation(&parent, &messages, 1).unwrap();
+        assert_eq!(forked.parent_id, Some(parent.id));
+        assert_eq!(forked.total_tokens, 12);
+
+        let forked_messages = storage.load_conversation(&forked.id).unwrap();
+        assert_eq!(forked_messages.len(), 2);
+        assert_eq!(forked_messages[1].content, "Reply");
+
+        // Original is untouched
+        assert_eq!(storage.load_conversation(&parent.id).unwrap().len(), 3);
+        assert_eq!(storage.list_conversations().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_fork_conversation_out_of_range() {
+        let (_temp, storage) = setup_test_storage();
+        let parent = ConversationMetadata::new();
+        let messages = vec![Message::new(crate::models::MessageRole::User, "Only".to_string(), 5)];
+        assert!(storage.fork_conversation(&parent, &messages, 3).is_err());
+    }
+
+    #[test]
+    fn test_drafts_per_conversation() {
+        let (_temp_dir, storage) = setup_test_storage();
+        let id = Uuid::new_v4();
+        assert!(storage.load_draft(None).is_none());
+
+        storage.save_draft(None, "half a thought").unwrap();
+        storage.save_draft(Some(&id), "multi\nline draft").unwrap();
+        assert_eq!(storage.load_draft(None).as_deref(), Some("half a thought"));
+        assert_eq!(storage.load_draft(Some(&id)).as_deref(), Some("multi\nline draft"));
+        assert_eq!(storage.conversations_with_drafts(), HashSet::from([id]));
+
+        storage.save_draft(None, "").unwrap();
+        assert!(storage.load_draft(None).is_none());
+        storage.delete_conversation(&id).unwrap();
+        assert!(storage.load_draft(Some(&id)).is_none());
+    }
+
+    #[test]
+    fn test_save_writes_both_files_without_leftovers() {
+        let (_temp, storage) = setup_test_storage();
+        let mut metadata = ConversationMetadata::new();
+        metadata.set_summary("Batched".to_string());
+        let messages = vec![Message::new(crate::models::MessageRole::User, "Hi".to_string(), 3)];
+
+        storage.save(&metadata, &messages).unwrap();
+        // Overwriting an existing pair goes through the same path
+        storage.save(&metadata, &messages).unwrap();
+
+        assert_eq!(storage.load_conversation(&metadata.id).unwrap().len(), 1);
+        assert_eq!(storage.load_metadata(&metadata.id).unwrap().summary, metadata.summary);
+
+        let leftovers: Vec<_> = fs::read_dir(&storage.chats_dir)
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "tmp"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn test_uncommitted_staged_write_leaves_target_untouched() {
+        let (_temp, storage) = setup_test_storage();
+        let target = storage.chats_dir.join("file.md");
+        fs::write(&target, "original").unwrap();
+
+        let staged = StagedWrite::new(target.clone(), "replacement").unwrap();
+        drop(staged);
+
+        assert_eq!(fs::read_to_string(&target).unwrap(), "original");
+        assert!(!storage.chats_dir.join("file.md.tmp").exists());
+    }
+
     #[test]
     fn test_parse_conversation() {
         let content = "## User\n\nHello world\n\n## Assistant\n\nHi there!\n\n";
@@ -308,6 +1414,60 @@ mod tests {
         assert_eq!(messages[1].content, "Hi there!");
     }
 
+    #[test]
+    fn test_save_load_round_trip_is_exact() {
+        use crate::models::{Citation, MessageRole};
+        let (_temp, storage) = setup_test_storage();
+        let contents = [
+            "plain",
+            "## User\nnot a new message",
+            "## Assistant",
+            "\\## User\n\\\\## Assistant\n\\not escaped",
+            "## Notes\ntext with ## inside, and ## User mid-line",
+            "  leading and trailing whitespace \n\n",
+            "\n\nstarts with blank lines",
+            "",
+            "---\nid: fake frontmatter\n---",
+            "windows\r\nline endings\r\n",
+            "ends with backslash \\",
+        ];
+        let mut messages: Vec<Message> = contents
+            .iter()
+            .enumerate()
+            .map(|(i, content)| {
+                let role = if i % 2 == 0 { MessageRole::User } else { MessageRole::Assistant };
+                Message::new(role, (*content).to_string(), i * 7 + 1)
+            })
+            .collect();
+        messages[1].pinned = true;
+        messages[2].citations.push(Citation {
+            path: "src/lib.rs".into(),
+            start_line: 1,
+            end_line: 4,
+        });
+
+        let metadata = ConversationMetadata::new();
+        storage.save(&metadata, &messages).unwrap();
+        assert_eq!(storage.load_conversation(&metadata.id).unwrap(), messages);
+
+        // Rewriting only the transcript keeps the per-message data in step
+        messages.truncate(3);
+        messages[0].tokens = 99;
+        storage.save_conversation(&metadata.id, &messages).unwrap();
+        assert_eq!(storage.load_conversation(&metadata.id).unwrap(), messages);
+    }
+
+    #[test]
+    fn test_parse_conversation_only_splits_on_role_headings() {
+        let content = "## User\n\nQuestion\n## Summary\n\n## Assistant\n\n\n  Answer  \n\n\n## Tool\n";
+        let messages = Storage::parse_conversation(content);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].content, "Question\n## Summary");
+        // Spacing that doesn't match what is written is trimmed
+        assert_eq!(messages[1].role, crate::models::MessageRole::Assistant);
+        assert_eq!(messages[1].content, "Answer  \n\n\n## Tool");
+    }
+
     #[test]
     fn test_conversation_paths() {
         let (_temp, storage) = setup_test_storage();
@@ -316,9 +1476,76 @@ mod tests {
         let conv_path = storage.get_conversation_path(&id);
         assert!(conv_path.to_string_lossy().contains(&id.to_string()));
         assert!(conv_path.to_string_lossy().ends_with(".md"));
+    }
+
+    #[test]
+    fn test_metadata_is_frontmatter_of_the_conversation_file() {
+        let (_temp, storage) = setup_test_storage();
+        let mut metadata = ConversationMetadata::new();
+        metadata.set_summary("One file".to_string());
+        let messages = vec![Message::new(crate::models::MessageRole::User, "Hi\n---\nthere".to_string(), 3)];
+        storage.save(&metadata, &messages).unwrap();
+
+        let content = fs::read_to_string(storage.get_conversation_path(&metadata.id)).unwrap();
+        assert!(content.starts_with("---\nid: "));
+        assert!(content.contains("summary: One file\n"));
+        assert!(content.contains("---\n\n## User\n\nHi\n---\nthere"));
+        assert_eq!(fs::read_dir(&storage.chats_dir).unwrap().count(), 1);
+
+        // Saving either half keeps the other
+        metadata.set_summary("Renamed".to_string());
+        storage.save_metadata(&metadata).unwrap();
+        assert_eq!(storage.load_conversation(&metadata.id).unwrap()[0].content, "Hi\n---\nthere");
+        storage.save_conversation(&metadata.id, &messages[..0]).unwrap();
+        assert_eq!(storage.load_metadata(&metadata.id).unwrap().summary.as_deref(), Some("Renamed"));
+        assert!(storage.load_conversation(&metadata.id).unwrap().is_empty());
+    }
 
-        let meta_path = storage.get_metadata_path(&id);
-        assert!(meta_path.to_string_lossy().contains(&id.to_string()));
-        assert!(meta_path.to_string_lossy().ends_with("_meta.json"));
+    #[test]
+    fn test_read_conversation_file() {
+        let (temp, storage) = setup_test_storage();
+        let mut metadata = ConversationMetadata::new();
+        metadata.set_summary("Copied over".to_string());
+        let messages = vec![Message::new(crate::models::MessageRole::User, "Hi".to_string(), 3)];
+        storage.save(&metadata, &messages).unwrap();
+        let copy = temp.path().join("copy.md");
+        fs::copy(storage.get_conversation_path(&metadata.id), &copy).unwrap();
+
+        let (read, read_messages) = storage.read_conversation_file(&copy).unwrap();
+        assert_eq!(read.unwrap().summary.as_deref(), Some("Copied over"));
+        assert_eq!(read_messages, messages);
+
+        // A bare transcript has no metadata
+        fs::write(&copy, "## User\n\nHello\n\n").unwrap();
+        let (read, read_messages) = storage.read_conversation_file(&copy).unwrap();
+        assert!(read.is_none());
+        assert_eq!(read_messages[0].content, "Hello");
+    }
+
+    #[test]
+    fn test_legacy_metadata_file_is_read_and_migrated() {
+        let (_temp, storage) = setup_test_storage();
+        let mut metadata = ConversationMetadata::new();
+        metadata.set_summary("Old pair".to_string());
+        let id = metadata.id;
+        let legacy_path = storage.chats_dir.join(format!("{id}_meta.json"));
+        fs::write(storage.chats_dir.join(format!("{id}.md")), "## User\n\nHello\n\n").unwrap();
+        fs::write(&legacy_path, serde_json::to_string_pretty(&metadata).unwrap()).unwrap();
+
+        let listed = storage.list_conversations().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].summary.as_deref(), Some("Old pair"));
+        assert_eq!(storage.load_conversation(&id).unwrap()[0].content, "Hello");
+
+        storage.save(&listed[0], &storage.load_conversation(&id).unwrap()).unwrap();
+        assert!(!legacy_path.exists());
+        assert_eq!(storage.load_metadata(&id).unwrap().summary.as_deref(), Some("Old pair"));
+    }
+
+    #[test]
+    fn test_split_frontmatter() {
+        assert_eq!(split_frontmatter("---\nid: 1\n---\n\n## User"), (Some("id: 1\n"), "\n## User"));
+        assert_eq!(split_frontmatter("## User\n---\n"), (None, "## User\n---\n"));
+        assert_eq!(split_frontmatter("---\nunclosed"), (None, "---\nunclosed"));
     }
 }
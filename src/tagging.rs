@@ -0,0 +1,63 @@
+// Classifying conversations by intent for the conversation list
+
+use crate::models::{Message, MessageRole};
+
+pub const TAGS: [&str; 4] = ["coding", "writing", "troubleshooting", "brainstorm"];
+
+/// How much of the conversation the classifier sees
+const EXCERPT_CHARS: usize = 1500;
+
+/// A prompt asking for exactly one tag for the start of the conversation
+pub fn classification_prompt(messages: &[Message]) -> String {
+    let mut excerpt = String::new();
+    for message in messages.iter().take(2) {
+        let role = match message.role {
+            MessageRole::User => "User",
+            MessageRole::Assistant => "Assistant",
+        };
+        let content = crate::clipboard::strip_thinking(&message.content);
+        excerpt.push_str(role);
+        excerpt.push_str(": ");
+        excerpt.extend(content.trim().chars().take(EXCERPT_CHARS));
+        excerpt.push('\n');
+    }
+    format!(
+        "Classify the intent of this conversation as exactly one of: {}.\n\
+         Reply with that single word only.\n\n{excerpt}",
+        TAGS.join(", ")
+    )
+}
+
+/// The first known tag mentioned in the model's reply
+pub fn parse_tag(reply: &str) -> Option<&'static str> {
+    let reply = reply.to_lowercase();
+    TAGS.iter()
+        .filter_map(|tag| reply.find(tag).map(|position| (position, *tag)))
+        .min()
+        .map(|(_, tag)| tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tag() {
+        assert_eq!(parse_tag("Coding"), Some("coding"));
+        assert_eq!(parse_tag("**Troubleshooting** (not coding)"), Some("troubleshooting"));
+        assert_eq!(parse_tag("chit-chat"), None);
+    }
+
+    #[test]
+    fn test_classification_prompt_uses_opening_exchange() {
+        let messages = vec![
+            Message::new(MessageRole::User, "My build fails".to_string(), 1),
+            Message::new(MessageRole::Assistant, "<thinking>\nhmm\n</thinking>\nShow the error".to_string(), 1),
+            Message::new(MessageRole::User, "later question".to_string(), 1),
+        ];
+        let prompt = classification_prompt(&messages);
+        assert!(prompt.contains("User: My build fails\nAssistant: Show the error\n"));
+        assert!(!prompt.contains("later question"));
+        assert!(!prompt.contains("hmm"));
+    }
+}
@@ -0,0 +1,62 @@
+// Append-only transcript of the session (`--tee`), independent of conversation storage
+
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::models::{Message, MessageRole};
+
+#[derive(Debug)]
+pub struct Tee {
+    file: File,
+    path: PathBuf,
+}
+
+impl Tee {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open tee file {}", path.display()))?;
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Append one message as a markdown section and flush it to disk right away
+    pub fn write(&mut self, message: &Message) -> Result<()> {
+        let role = match message.role {
+            MessageRole::User => "User",
+            MessageRole::Assistant => "Assistant",
+        };
+        let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+        write!(self.file, "## {role} ({time})\n\n{}\n\n", message.content.trim_end())
+            .and_then(|()| self.file.flush())
+            .with_context(|| format!("Failed to write tee file {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_appends_messages() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("session.md");
+        std::fs::write(&path, "earlier\n\n").unwrap();
+
+        let mut tee = Tee::open(&path).unwrap();
+        tee.write(&Message::new(MessageRole::User, "Hi?".to_string(), 1)).unwrap();
+        tee.write(&Message::new(MessageRole::Assistant, "Hello!\n".to_string(), 1)).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("earlier\n\n## User ("));
+        assert!(contents.contains(")\n\nHi?\n\n## Assistant ("));
+        assert!(contents.ends_with(")\n\nHello!\n\n"));
+    }
+}
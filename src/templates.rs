@@ -0,0 +1,202 @@
+// Prompt templates with `{placeholder}` fields, loaded from templates.toml
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A reusable prompt. Each `{name}` in the prompt is a field filled in before inserting.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+pub fn default_templates() -> BTreeMap<String, PromptTemplate> {
+    [
+        (
+            "commit-message",
+            "Write a concise git commit message for this diff:\n\n{diff}",
+            "Summarize a diff as a commit message",
+        ),
+        (
+            "explain-code",
+            "Explain what this {language} code does:\n\n{code}",
+            "Walk through a snippet",
+        ),
+        (
+            "explain-error",
+            "Explain this error and how to fix it: {error}",
+            "Diagnose an error message",
+        ),
+    ]
+    .into_iter()
+    .map(|(name, prompt, description)| {
+        (
+            name.to_string(),
+            PromptTemplate {
+                prompt: prompt.to_string(),
+                description: Some(description.to_string()),
+            },
+        )
+    })
+    .collect()
+}
+
+/// Character allowed in a placeholder name; other braces (JSON, code) stay literal
+fn is_placeholder_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_' || ch == '-'
+}
+
+/// Split a prompt into literal text and placeholder names
+fn segments(prompt: &str) -> Vec<(bool, &str)> {
+    let mut parts = Vec::new();
+    let mut rest = prompt;
+
+    while let Some(open) = rest.find('{') {
+        let after = &rest[open + 1..];
+        let name_len = after.find(|ch| !is_placeholder_char(ch)).unwrap_or(after.len());
+        if name_len > 0 && after[name_len..].starts_with('}') {
+            parts.push((false, &rest[..open]));
+            parts.push((true, &after[..name_len]));
+            rest = &after[name_len + 1..];
+        } else {
+            parts.push((false, &rest[..=open]));
+            rest = after;
+        }
+    }
+    parts.push((false, rest));
+    parts
+}
+
+/// Distinct placeholder names in order of first appearance
+pub fn placeholders(prompt: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for (is_placeholder, text) in segments(prompt) {
+        if is_placeholder && !names.iter().any(|name| name == text) {
+            names.push(text.to_string());
+        }
+    }
+    names
+}
+
+/// In-progress values for a template's placeholders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateForm {
+    pub name: String,
+    pub prompt: String,
+    pub fields: Vec<(String, String)>,
+    pub active: usize,
+}
+
+impl TemplateForm {
+    pub fn new(name: &str, template: &PromptTemplate) -> Self {
+        Self {
+            name: name.to_string(),
+            prompt: template.prompt.clone(),
+            fields: placeholders(&template.prompt)
+                .into_iter()
+                .map(|field| (field, String::new()))
+                .collect(),
+            active: 0,
+        }
+    }
+
+    pub const fn next_field(&mut self) {
+        if !self.fields.is_empty() {
+            self.active = (self.active + 1) % self.fields.len();
+        }
+    }
+
+    pub const fn previous_field(&mut self) {
+        if !self.fields.is_empty() {
+            self.active = (self.active + self.fields.len() - 1) % self.fields.len();
+        }
+    }
+
+    pub fn push_char(&mut self, ch: char) {
+        if let Some((_, value)) = self.fields.get_mut(self.active) {
+            value.push(ch);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some((_, value)) = self.fields.get_mut(self.active) {
+            value.pop();
+        }
+    }
+
+    /// The prompt with every placeholder replaced by its value
+    pub fn fill(&self) -> String {
+        segments(&self.prompt)
+            .into_iter()
+            .map(|(is_placeholder, text)| {
+                if is_placeholder {
+                    self.fields
+                        .iter()
+                        .find(|(name, _)| name == text)
+                        .map_or(text, |(_, value)| value.as_str())
+                } else {
+                    text
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(prompt: &str) -> PromptTemplate {
+        PromptTemplate {
+            prompt: prompt.to_string(),
+            description: None,
+        }
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            placeholders("Explain {error} in {lang}, then {error} again"),
+            vec!["error", "lang"]
+        );
+        // JSON-like braces and empty braces are not fields
+        assert!(placeholders(r#"Return {"ok": true} or {}"#).is_empty());
+        assert_eq!(placeholders("{a}{b-c}"), vec!["a", "b-c"]);
+    }
+
+    #[test]
+    fn test_form_fill_and_navigation() {
+        let mut form = TemplateForm::new("t", &template("{x} + {y} = {x}{y} {not json}"));
+        assert_eq!(form.fields.len(), 2);
+
+        form.push_char('1');
+        form.next_field();
+        form.push_char('2');
+        form.push_char('3');
+        form.backspace();
+        assert_eq!(form.fill(), "1 + 2 = 12 {not json}");
+
+        form.next_field();
+        assert_eq!(form.active, 0);
+        form.previous_field();
+        assert_eq!(form.active, 1);
+    }
+
+    #[test]
+    fn test_templates_toml() {
+        let templates: BTreeMap<String, PromptTemplate> = toml::from_str(
+            r#"
+[review]
+prompt = "Review: {code}"
+description = "Code review"
+"#,
+        )
+        .unwrap();
+        assert_eq!(templates["review"].prompt, "Review: {code}");
+
+        let serialized = toml::to_string(&default_templates()).unwrap();
+        let parsed: BTreeMap<String, PromptTemplate> = toml::from_str(&serialized).unwrap();
+        assert_eq!(parsed, default_templates());
+    }
+}
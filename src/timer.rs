@@ -0,0 +1,100 @@
+// Focus timer counting down in the status bar (`/timer 25m`)
+
+use anyhow::{bail, Result};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+pub struct FocusTimer {
+    pub length: Duration,
+    deadline: Instant,
+    /// Completion was announced; the timer stays visible until the next key press
+    pub announced: bool,
+}
+
+impl FocusTimer {
+    pub fn start(length: Duration) -> Self {
+        Self {
+            length,
+            deadline: Instant::now() + length,
+            announced: false,
+        }
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    pub fn is_done(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+}
+
+/// Parse "25m", "90s", "1h30m" or a bare number of minutes
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let text = text.trim().to_ascii_lowercase();
+    if let Ok(minutes) = text.parse::<u64>() {
+        return checked_duration(minutes, 60, &text);
+    }
+    let mut total = Duration::ZERO;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => bail!("Invalid duration '{text}' (e.g. 25m, 90s, 1h30m)"),
+        };
+        let Ok(value) = number.parse::<u64>() else {
+            bail!("Invalid duration '{text}' (e.g. 25m, 90s, 1h30m)");
+        };
+        total += checked_duration(value, unit, &text)?;
+        number.clear();
+    }
+    if !number.is_empty() || total.is_zero() {
+        bail!("Invalid duration '{text}' (e.g. 25m, 90s, 1h30m)");
+    }
+    Ok(total)
+}
+
+fn checked_duration(value: u64, unit: u64, text: &str) -> Result<Duration> {
+    match value.checked_mul(unit) {
+        Some(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+        _ => bail!("Invalid duration '{text}' (e.g. 25m, 90s, 1h30m)"),
+    }
+}
+
+/// "24:13", or "1:02:03" from an hour up
+pub fn format_clock(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("25m").unwrap(), Duration::from_mins(25));
+        assert_eq!(parse_duration("25").unwrap(), Duration::from_mins(25));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_mins(90));
+        assert_eq!(parse_duration("90S").unwrap(), Duration::from_secs(90));
+        for invalid in ["", "0", "0m", "m", "25x", "1h30", "soon"] {
+            assert!(parse_duration(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_format_clock() {
+        assert_eq!(format_clock(Duration::from_secs(24 * 60 + 13)), "24:13");
+        assert_eq!(format_clock(Duration::from_secs(3723)), "1:02:03");
+    }
+}
@@ -0,0 +1,119 @@
+// Raw protocol traffic for the debug overlay (F12): requests sent to Ollama, the JSON
+// lines streamed back and the `AppEvent`s dispatched, recorded only while it's open
+
+use chrono::{DateTime, Local};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Entries kept; older ones are dropped
+const CAPACITY: usize = 1000;
+
+/// Longest text kept per entry (whole conversations are sent with each request)
+const MAX_ENTRY_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// A request body sent to Ollama
+    Sent,
+    /// A line of a streamed response
+    Received,
+    /// An `AppEvent` handled by the interface
+    Event,
+}
+
+impl Direction {
+    pub const fn arrow(self) -> &'static str {
+        match self {
+            Self::Sent => "→",
+            Self::Received => "←",
+            Self::Event => "•",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub at: DateTime<Local>,
+    pub direction: Direction,
+    pub text: String,
+}
+
+/// Shared between the interface and the API client's streams, which record into it
+/// from their own tasks
+#[derive(Debug, Clone, Default)]
+pub struct Traffic {
+    recording: Arc<AtomicBool>,
+    entries: Arc<Mutex<VecDeque<Entry>>>,
+}
+
+impl Traffic {
+    pub fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::Relaxed)
+    }
+
+    pub fn set_recording(&self, recording: bool) {
+        self.recording.store(recording, Ordering::Relaxed);
+    }
+
+    /// Keep `text` (cut to `MAX_ENTRY_CHARS`) while recording; `text` is only built then
+    pub fn record(&self, direction: Direction, text: impl FnOnce() -> String) {
+        if !self.is_recording() {
+            return;
+        }
+        let mut text = text();
+        if let Some((cut, _)) = text.char_indices().nth(MAX_ENTRY_CHARS) {
+            text.truncate(cut);
+            text.push('…');
+        }
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        if entries.len() == CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(Entry { at: Local::now(), direction, text });
+    }
+
+    /// The last `count` entries, oldest first
+    pub fn latest(&self, count: usize) -> Vec<Entry> {
+        self.entries.lock().map_or_else(
+            |_| Vec::new(),
+            |entries| entries.iter().skip(entries.len().saturating_sub(count)).cloned().collect(),
+        )
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_only_while_recording() {
+        let traffic = Traffic::default();
+        traffic.record(Direction::Received, || unreachable!("not built when not recording"));
+        assert!(traffic.latest(10).is_empty());
+
+        traffic.set_recording(true);
+        let shared = traffic.clone();
+        for n in 0..CAPACITY + 5 {
+            shared.record(Direction::Event, || format!("event {n}"));
+        }
+        let latest = traffic.latest(2);
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[1].text, format!("event {}", CAPACITY + 4));
+        assert_eq!(traffic.latest(usize::MAX).len(), CAPACITY);
+
+        traffic.record(Direction::Received, || "x".repeat(MAX_ENTRY_CHARS + 10));
+        assert_eq!(traffic.latest(1)[0].text.chars().count(), MAX_ENTRY_CHARS + 1);
+
+        traffic.clear();
+        assert!(traffic.latest(10).is_empty());
+    }
+}
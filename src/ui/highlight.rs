@@ -0,0 +1,161 @@
+// Syntax highlighting of fenced code blocks in the chat history
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use ratatui::style::{Color, Style};
+use ratatui::text::Span;
+use syntect::easy::ScopeRangeIterator;
+use syntect::parsing::{ParseState, ScopeStack, SyntaxSet};
+
+use super::theme::Theme;
+
+/// Most highlighted blocks kept between frames
+const CACHE_LIMIT: usize = 256;
+
+/// What a token is, coloured from the app theme so code follows `[theme]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Plain,
+    Comment,
+    String,
+    Keyword,
+    Number,
+    Function,
+    Type,
+}
+
+impl TokenKind {
+    /// The innermost scope that means something decides the kind
+    fn of(stack: &ScopeStack) -> Self {
+        stack
+            .as_slice()
+            .iter()
+            .rev()
+            .find_map(|scope| {
+                let name = scope.build_string();
+                let starts = |prefix: &str| name.starts_with(prefix);
+                if starts("comment") {
+                    Some(Self::Comment)
+                } else if starts("string") {
+                    Some(Self::String)
+                } else if starts("constant.numeric") || starts("constant.language") || starts("constant.character") {
+                    Some(Self::Number)
+                } else if starts("keyword") || starts("storage.modifier") {
+                    Some(Self::Keyword)
+                } else if starts("storage.type") || starts("entity.name.type") || starts("support.type")
+                    || starts("support.class") || starts("entity.name.class")
+                {
+                    Some(Self::Type)
+                } else if starts("entity.name.function") || starts("support.function") || starts("variable.function") {
+                    Some(Self::Function)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(Self::Plain)
+    }
+
+    pub const fn color(self, theme: &Theme) -> Color {
+        match self {
+            Self::Plain => theme.code,
+            Self::Comment => theme.muted,
+            Self::String => theme.success,
+            Self::Keyword => theme.accent,
+            Self::Number => theme.warning,
+            Self::Function => theme.heading,
+            Self::Type => theme.link,
+        }
+    }
+}
+
+/// Tokens of one line of code
+pub type TokenLine = Vec<(TokenKind, String)>;
+
+type Cache = HashMap<(String, String), Option<Vec<TokenLine>>>;
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// Tokens of each line of `code`, or None when the language isn't known
+pub fn tokenize(language: &str, code: &[&str]) -> Option<Vec<TokenLine>> {
+    // The history is rebuilt every frame, so finished blocks are parsed once
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    let key = (language.to_ascii_lowercase(), code.join("\n"));
+    let cache = CACHE.get_or_init(Mutex::default);
+    if let Some(tokens) = cache.lock().unwrap_or_else(std::sync::PoisonError::into_inner).get(&key) {
+        return tokens.clone();
+    }
+    let tokens = parse(&key.0, code);
+    let mut cache = cache.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+    if cache.len() >= CACHE_LIMIT {
+        cache.clear();
+    }
+    cache.insert(key, tokens.clone());
+    drop(cache);
+    tokens
+}
+
+fn parse(language: &str, code: &[&str]) -> Option<Vec<TokenLine>> {
+    let syntaxes = syntaxes();
+    let syntax = syntaxes.find_syntax_by_token(language)?;
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut lines = Vec::with_capacity(code.len());
+    for line in code {
+        let text = format!("{line}\n");
+        let ops = state.parse_line(&text, syntaxes).ok()?;
+        let mut tokens: TokenLine = Vec::new();
+        for (range, op) in ScopeRangeIterator::new(&ops, &text) {
+            stack.apply(op).ok()?;
+            let piece = text[range].trim_end_matches('\n');
+            if piece.is_empty() {
+                continue;
+            }
+            let kind = TokenKind::of(&stack);
+            match tokens.last_mut() {
+                Some((last, joined)) if *last == kind => joined.push_str(piece),
+                _ => tokens.push((kind, piece.to_string())),
+            }
+        }
+        lines.push(tokens);
+    }
+    Some(lines)
+}
+
+/// Coloured spans for a line of tokens
+pub fn spans(tokens: &TokenLine, theme: &Theme) -> Vec<Span<'static>> {
+    tokens
+        .iter()
+        .map(|(kind, text)| Span::styled(text.clone(), Style::default().fg(kind.color(theme))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_rust() {
+        let lines = tokenize("rust", &["fn main() { // hi", "    let x = \"s\";", "}"]).unwrap();
+        assert_eq!(lines.len(), 3);
+        // Nothing is lost: the tokens put the line back together
+        let joined: String = lines[1].iter().map(|(_, text)| text.as_str()).collect();
+        assert_eq!(joined, "    let x = \"s\";");
+        let kind_of = |line: &TokenLine, text: &str| {
+            line.iter().find(|(_, piece)| piece.contains(text)).map(|(kind, _)| *kind)
+        };
+        assert_eq!(kind_of(&lines[0], "// hi"), Some(TokenKind::Comment));
+        assert_eq!(kind_of(&lines[0], "fn"), Some(TokenKind::Type));
+        assert_eq!(kind_of(&lines[1], "\"s\""), Some(TokenKind::String));
+        // Aliases work too
+        assert!(tokenize("py", &["def f(): pass"]).is_some());
+    }
+
+    #[test]
+    fn test_unknown_language_is_not_highlighted() {
+        assert!(tokenize("klingon", &["qapla'"]).is_none());
+    }
+}
@@ -1,19 +1,276 @@
-// Simple markdown rendering for terminal display
+// Markdown rendering for terminal display
 
 use ratatui::{
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Line, Span},
 };
 
-/// Convert markdown text to ratatui Lines with styling
-pub fn render_markdown_to_lines(markdown: &str) -> Vec<Line<'static>> {
-    let mut lines = Vec::new();
-    
-    for line in markdown.lines() {
-        lines.push(render_markdown_line(line));
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use super::theme::Theme;
+use crate::models::CodeRenderMode;
+
+/// Convert markdown text to ratatui Lines with styling; plain text is drawn in
+/// the theme's assistant color. Every line of the text starts a new Line, and
+/// indented lines, list items and quotes longer than `width` (0 for no limit)
+/// wrap under their marker, so a source line can become several Lines.
+pub fn render_markdown_to_lines(markdown: &str, theme: &Theme, width: usize) -> Vec<Line<'static>> {
+    // The history renders a line at a time, so the indent of the first line is
+    // kept as an indent rather than read as an indented code block
+    let text = markdown.trim_start_matches([' ', '\t']);
+    let indent = markdown[..markdown.len() - text.len()]
+        .chars()
+        .map(|ch| if ch == '\t' { 4 } else { 1 })
+        .sum();
+    let mut renderer = Renderer {
+        theme,
+        width,
+        indent,
+        lines: Vec::new(),
+        spans: Vec::new(),
+        styles: Vec::new(),
+        lists: Vec::new(),
+        items: Vec::new(),
+        marker: None,
+        quotes: 0,
+        link: None,
+        in_code_block: false,
+    };
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    for event in Parser::new_ext(text, options) {
+        renderer.event(event);
     }
-    
-    lines
+    renderer.finish()
+}
+
+/// Builds Lines from pulldown-cmark events
+struct Renderer<'a> {
+    theme: &'a Theme,
+    width: usize,
+    /// Leading whitespace of the text
+    indent: usize,
+    lines: Vec<Line<'static>>,
+    /// Spans of the line being built
+    spans: Vec<Span<'static>>,
+    /// Styles of the open inline elements, innermost last
+    styles: Vec<Style>,
+    /// Next number of each open list; None for bullet lists
+    lists: Vec<Option<u64>>,
+    /// Marker widths of the open list items, which their other lines hang under
+    items: Vec<usize>,
+    /// Marker of a list item whose first line isn't drawn yet
+    marker: Option<String>,
+    quotes: usize,
+    /// Destination and text so far of the link being drawn
+    link: Option<(String, String)>,
+    in_code_block: bool,
+}
+
+impl Renderer<'_> {
+    fn style(&self) -> Style {
+        self.styles.last().copied().unwrap_or_default()
+    }
+
+    fn push_style(&mut self, style: Style) {
+        self.styles.push(self.style().patch(style));
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some((_, link_text)) = self.link.as_mut() {
+            link_text.push_str(text);
+        }
+        self.spans.push(Span::styled(text.to_string(), self.style()));
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) if self.in_code_block => {
+                for line in text.lines() {
+                    self.spans.push(Span::styled(line.to_string(), Style::default().fg(self.theme.code)));
+                    self.flush();
+                }
+            }
+            Event::Text(text) | Event::InlineHtml(text) => self.text(&text),
+            Event::Html(html) => self.text(html.trim_end_matches('\n')),
+            Event::Code(code) => {
+                let style = self.style().fg(self.theme.code);
+                self.spans.push(Span::styled(code.to_string(), style));
+            }
+            Event::SoftBreak | Event::HardBreak => self.flush(),
+            Event::Rule => {
+                self.flush();
+                // As wide as the history, or a fixed width when that isn't known
+                let rule = "─".repeat(if self.width > 0 { self.width } else { 40 });
+                self.lines.push(Line::from(Span::styled(rule, Style::default().fg(self.theme.muted))));
+            }
+            Event::TaskListMarker(done) => {
+                let marker = if done { "[x] " } else { "[ ] " };
+                self.spans.push(Span::styled(marker, Style::default().fg(self.theme.heading)));
+            }
+            Event::FootnoteReference(label) => self.text(&format!("[^{label}]")),
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        let theme = self.theme;
+        match tag {
+            Tag::Heading { level, .. } => {
+                let color = match level {
+                    HeadingLevel::H1 => theme.accent,
+                    HeadingLevel::H2 => theme.heading,
+                    _ => theme.link,
+                };
+                self.push_style(Style::default().fg(color).add_modifier(Modifier::BOLD));
+            }
+            Tag::BlockQuote(_) => self.quotes += 1,
+            Tag::CodeBlock(_) => self.in_code_block = true,
+            Tag::List(start) => self.lists.push(start),
+            Tag::Item => {
+                self.flush();
+                let marker = if let Some(Some(number)) = self.lists.last_mut() {
+                    let marker = format!("{number}. ");
+                    *number += 1;
+                    marker
+                } else {
+                    // Nested levels take turns between bullet shapes
+                    let level = self.indent / 2 + self.lists.len().saturating_sub(1);
+                    format!("{} ", ["•", "◦", "▪"][level % 3])
+                };
+                self.items.push(marker.width());
+                self.marker = Some(marker);
+            }
+            Tag::Emphasis => self.push_style(Style::default().add_modifier(Modifier::ITALIC)),
+            Tag::Strong => self.push_style(Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+            Tag::Strikethrough => self.push_style(Style::default().add_modifier(Modifier::CROSSED_OUT)),
+            Tag::Link { dest_url, .. } => {
+                self.link = Some((dest_url.to_string(), String::new()));
+                self.push_style(Style::default().fg(theme.link).add_modifier(Modifier::UNDERLINED));
+            }
+            Tag::Image { .. } => {
+                self.push_style(Style::default().fg(theme.muted).add_modifier(Modifier::ITALIC));
+                self.text("[image: ");
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Heading(_) => {
+                self.styles.pop();
+                self.flush();
+            }
+            TagEnd::Paragraph | TagEnd::HtmlBlock => self.flush(),
+            TagEnd::BlockQuote(_) => {
+                self.flush();
+                self.quotes = self.quotes.saturating_sub(1);
+            }
+            TagEnd::CodeBlock => self.in_code_block = false,
+            TagEnd::List(_) => {
+                self.lists.pop();
+            }
+            TagEnd::Item => {
+                self.flush();
+                self.items.pop();
+            }
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => {
+                self.styles.pop();
+            }
+            TagEnd::Link => {
+                self.styles.pop();
+                // Where the link goes, unless its text already says
+                if let Some((url, _)) = self.link.take().filter(|(url, text)| !url.is_empty() && url != text) {
+                    self.spans.push(Span::styled(format!(" ({url})"), Style::default().fg(self.theme.muted)));
+                }
+            }
+            TagEnd::Image => {
+                self.text("]");
+                self.styles.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// Indent, quote bars and list marker (or the space under it) of a line
+    fn prefix(&self, marker: Option<&str>) -> Vec<Span<'static>> {
+        let hanging: usize = self.items.iter().sum();
+        let mut prefix = Vec::new();
+        if self.indent > 0 {
+            prefix.push(Span::raw(" ".repeat(self.indent)));
+        }
+        if self.quotes > 0 {
+            prefix.push(Span::styled("▎ ".repeat(self.quotes), Style::default().fg(self.theme.muted)));
+        }
+        let marker_width = marker.map_or(0, UnicodeWidthStr::width);
+        if hanging > marker_width {
+            prefix.push(Span::raw(" ".repeat(hanging - marker_width)));
+        }
+        if let Some(marker) = marker {
+            prefix.push(Span::styled(marker.to_string(), Style::default().fg(self.theme.heading)));
+        }
+        prefix
+    }
+
+    /// Finish the line being built under its prefix, wrapping it there when too long
+    fn flush(&mut self) {
+        if self.spans.is_empty() && self.marker.is_none() {
+            return;
+        }
+        let spans = std::mem::take(&mut self.spans);
+        let marker = self.marker.take();
+        let prefix_width = self.indent + 2 * self.quotes + self.items.iter().sum::<usize>();
+        let rows = if prefix_width > 0 && self.width > prefix_width {
+            wrap_spans(spans, self.width - prefix_width)
+        } else {
+            vec![spans]
+        };
+        for (i, row) in rows.into_iter().enumerate() {
+            let mut line = self.prefix(if i == 0 { marker.as_deref() } else { None });
+            line.extend(row);
+            self.lines.push(Line::from(line).style(Style::default().fg(self.theme.assistant)));
+        }
+    }
+
+    fn finish(mut self) -> Vec<Line<'static>> {
+        self.flush();
+        if self.lines.is_empty() {
+            // A blank line still takes up a row
+            self.lines.push(Line::from(" ".repeat(self.indent)));
+        }
+        self.lines
+    }
+}
+
+/// Word-wrap styled spans into rows of at most `width` columns. Rows only break
+/// at spaces; a word longer than a row is left for the paragraph to wrap.
+fn wrap_spans(spans: Vec<Span<'static>>, width: usize) -> Vec<Vec<Span<'static>>> {
+    let mut rows: Vec<Vec<Span<'static>>> = vec![Vec::new()];
+    let mut used = 0;
+    let mut at_space = false;
+    for span in spans {
+        for piece in span.content.split_inclusive(' ') {
+            let visible = piece.trim_end_matches(' ').width();
+            if used > 0 && at_space && used + visible > width {
+                if let Some(last) = rows.last_mut().and_then(|row| row.last_mut()) {
+                    last.content = last.content.trim_end().to_string().into();
+                }
+                rows.push(Vec::new());
+                used = 0;
+            }
+            at_space = piece.ends_with(' ');
+            if used == 0 && piece.trim().is_empty() {
+                continue;
+            }
+            used += piece.width();
+            rows.last_mut().expect("always one row").push(Span::styled(piece.to_string(), span.style));
+        }
+    }
+    rows
 }
 
 /// Check if a line is a markdown table row
@@ -33,156 +290,161 @@ pub fn is_table_separator(line: &str) -> bool {
     trimmed.chars().all(|c| c == '|' || c == '-' || c == ' ' || c == ':')
 }
 
-/// Render a markdown table row - simplified for better readability
-fn render_table_row(line: &str) -> Line<'static> {
+/// Horizontal alignment of a table column, from the `:---:` markers of its separator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+fn split_table_row(line: &str) -> Vec<String> {
     let trimmed = line.trim();
-    // Remove leading and trailing pipes
-    let content = trimmed.trim_start_matches('|').trim_end_matches('|');
-    
-    // Split by pipe and format cells
-    let cells: Vec<&str> = content.split('|').map(str::trim).collect();
-    
-    // For better readability in terminal, just display cells with clear spacing
-    // Instead of trying to align columns (which is hard without knowing column widths),
-    // display as: Cell1  |  Cell2  |  Cell3
-    let formatted = cells.join(" | ");
-    
-    Line::from(Span::styled(
-        format!("  {formatted}"),
-        Style::default().fg(Color::Cyan),
-    ))
+    let content = trimmed.strip_prefix('|').unwrap_or(trimmed);
+    let content = content.strip_suffix('|').unwrap_or(content);
+    content.split('|').map(|cell| cell.trim().to_string()).collect()
 }
 
-/// Render a single line of markdown with basic styling
-#[allow(clippy::too_many_lines)]
-fn render_markdown_line(line: &str) -> Line<'static> {
-    // Check for table rows first
-    if is_table_separator(line) {
-        // Skip separator lines - they're just visual noise in terminals
-        return Line::from("");
-    }
-    
-    if is_table_row(line) {
-        return render_table_row(line);
+fn column_align(marker: &str) -> Align {
+    match (marker.starts_with(':'), marker.ends_with(':') && marker.len() > 1) {
+        (true, true) => Align::Center,
+        (false, true) => Align::Right,
+        _ => Align::Left,
     }
-    
-    let mut spans = Vec::new();
-    let mut current_text = String::new();
-    let mut chars = line.chars().peekable();
-    
-    while let Some(ch) = chars.next() {
-        match ch {
-            // Bold: **text**
-            '*' if chars.peek() == Some(&'*') => {
-                if !current_text.is_empty() {
-                    spans.push(Span::raw(current_text.clone()));
-                    current_text.clear();
-                }
-                chars.next(); // consume second *
-                
-                // Find closing **
-                let mut bold_text = String::new();
-                let mut found_close = false;
-                while let Some(ch) = chars.next() {
-                    if ch == '*' && chars.peek() == Some(&'*') {
-                        chars.next(); // consume second *
-                        found_close = true;
-                        break;
-                    }
-                    bold_text.push(ch);
-                }
-                
-                if found_close {
-                    spans.push(Span::styled(
-                        bold_text,
-                        Style::default()
-                            .fg(Color::Yellow)
-                            .add_modifier(Modifier::BOLD),
-                    ));
-                } else {
-                    // No closing **, treat as literal
-                    current_text.push_str("**");
-                    current_text.push_str(&bold_text);
-                }
-            }
-            // Inline code: `code`
-            '`' => {
-                if !current_text.is_empty() {
-                    spans.push(Span::raw(current_text.clone()));
-                    current_text.clear();
-                }
-                
-                // Find closing `
-                let mut code_text = String::new();
-                let mut found_close = false;
-                for ch in chars.by_ref() {
-                    if ch == '`' {
-                        found_close = true;
-                        break;
-                    }
-                    code_text.push(ch);
-                }
-                
-                if found_close {
-                    spans.push(Span::styled(
-                        code_text,
-                        Style::default().fg(Color::Magenta),
-                    ));
-                } else {
-                    // No closing `, treat as literal
-                    current_text.push('`');
-                    current_text.push_str(&code_text);
-                }
-            }
-            // Headers: # ## ###
-            '#' if current_text.is_empty() => {
-                let mut level = 1;
-                while chars.peek() == Some(&'#') {
-                    level += 1;
-                    chars.next();
-                }
-                
-                // Skip space after #
-                if chars.peek() == Some(&' ') {
-                    chars.next();
-                }
-                
-                // Rest of line is header
-                let header_text: String = chars.collect();
-                let color = match level {
-                    1 => Color::Yellow,
-                    2 => Color::Cyan,
-                    _ => Color::Blue,
-                };
-                
-                return Line::from(Span::styled(
-                    header_text.trim().to_string(),
-                    Style::default().fg(color).add_modifier(Modifier::BOLD),
-                ));
+}
+
+/// Word-wrap a cell to `width` columns, splitting words that don't fit
+fn wrap_cell(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = vec![String::new()];
+    let mut used = 0;
+    for word in text.split_whitespace() {
+        if used > 0 && used + 1 + word.width() <= width {
+            let current = lines.last_mut().expect("always one line");
+            current.push(' ');
+            current.push_str(word);
+            used += 1 + word.width();
+            continue;
+        }
+        if used > 0 {
+            lines.push(String::new());
+            used = 0;
+        }
+        // Graphemes stay whole; a wide one that doesn't fit starts the next line
+        for grapheme in word.graphemes(true) {
+            if used > 0 && used + grapheme.width() > width {
+                lines.push(String::new());
+                used = 0;
             }
-            // List items: - item or * item
-            '-' | '*' if current_text.is_empty() && chars.peek() == Some(&' ') => {
-                chars.next(); // consume space
-                let rest: String = chars.collect();
-                spans.push(Span::styled("• ", Style::default().fg(Color::Cyan)));
-                spans.push(Span::raw(rest.trim().to_string()));
+            lines.last_mut().expect("always one line").push_str(grapheme);
+            used += grapheme.width();
+        }
+    }
+    lines
+}
+
+fn pad_cell(text: &str, width: usize, align: Align) -> String {
+    let pad = width.saturating_sub(text.width());
+    let (left, right) = match align {
+        Align::Left => (0, pad),
+        Align::Right => (pad, 0),
+        Align::Center => (pad / 2, pad - pad / 2),
+    };
+    format!("{}{text}{}", " ".repeat(left), " ".repeat(right))
+}
+
+/// Lay out the rows of a markdown table with box-drawing borders, aligned as the
+/// separator row asks and fitted to `width` columns (0 for no limit) by wrapping
+/// the widest cells. Each line comes with the markdown it shows; borders have none,
+/// except the rule under the header, which stands for the separator row.
+pub fn render_markdown_table(rows: &[&str], width: usize, theme: &Theme) -> Vec<(Line<'static>, Option<String>)> {
+    let separator = rows.get(1).copied().filter(|row| is_table_separator(row));
+    let aligns: Vec<Align> = separator
+        .map(|row| split_table_row(row).iter().map(|marker| column_align(marker)).collect())
+        .unwrap_or_default();
+    let body: Vec<(&str, Vec<String>)> = rows
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !(*i == 1 && separator.is_some()))
+        .map(|(_, row)| (*row, split_table_row(row)))
+        .collect();
+
+    let columns = body.iter().map(|(_, cells)| cells.len()).max().unwrap_or(0);
+    let mut widths = vec![1; columns];
+    for (_, cells) in &body {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.width());
+        }
+    }
+    // "  │ a │ b │": two columns of indent, then three per column and one more
+    if width > 0 {
+        let budget = width.saturating_sub(3 + 3 * columns);
+        while widths.iter().sum::<usize>() > budget {
+            let Some(widest) = widths.iter_mut().max() else { break };
+            if *widest <= 3 {
                 break;
             }
-            _ => {
-                current_text.push(ch);
-            }
+            *widest -= 1;
         }
     }
-    
-    if !current_text.is_empty() {
-        spans.push(Span::raw(current_text));
+
+    let border_style = Style::default().fg(theme.muted);
+    let rule = |left: &str, middle: &str, right: &str| {
+        let bars: Vec<String> = widths.iter().map(|width| "─".repeat(width + 2)).collect();
+        Line::from(Span::styled(format!("  {left}{}{right}", bars.join(middle)), border_style))
+    };
+
+    let mut lines = vec![(rule("┌", "┬", "┐"), None)];
+    for (i, (row, cells)) in body.iter().enumerate() {
+        let header = i == 0 && separator.is_some();
+        let style = if header {
+            Style::default().fg(theme.heading).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(theme.assistant)
+        };
+        let wrapped: Vec<Vec<String>> = widths
+            .iter()
+            .enumerate()
+            .map(|(column, width)| wrap_cell(cells.get(column).map_or("", String::as_str), *width))
+            .collect();
+        let height = wrapped.iter().map(Vec::len).max().unwrap_or(1);
+        for visual in 0..height {
+            let mut spans = vec![Span::styled("  │", border_style)];
+            for (column, width) in widths.iter().enumerate() {
+                let text = wrapped[column].get(visual).map_or("", String::as_str);
+                let align = aligns.get(column).copied().unwrap_or(Align::Left);
+                spans.push(Span::styled(format!(" {} ", pad_cell(text, *width, align)), style));
+                spans.push(Span::styled("│", border_style));
+            }
+            let source = (visual == 0).then(|| row.trim().to_string());
+            lines.push((Line::from(spans), source));
+        }
+        if header {
+            lines.push((rule("├", "┼", "┤"), separator.map(|row| row.trim().to_string())));
+        }
     }
-    
-    if spans.is_empty() {
-        Line::from("")
-    } else {
-        Line::from(spans)
+    lines.push((rule("└", "┴", "┘"), None));
+    lines
+}
+
+/// Web links in a line of text, bare or in markdown `[text](url)` form, in order
+/// and without repeats. Trailing punctuation isn't part of a link.
+pub fn extract_links(text: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = ["https://", "http://"].iter().filter_map(|scheme| rest.find(scheme)).min() {
+        let candidate = &rest[start..];
+        let end = candidate
+            .find(|ch: char| ch.is_whitespace() || matches!(ch, ')' | '(' | '<' | '>' | '[' | ']' | '"' | '\'' | '`'))
+            .unwrap_or(candidate.len());
+        let link = candidate[..end].trim_end_matches(['.', ',', ';', ':', '!', '?', '*', '_']);
+        let host = link.trim_start_matches("https://").trim_start_matches("http://");
+        if !host.is_empty() && !links.iter().any(|known| known == link) {
+            links.push(link.to_string());
+        }
+        rest = &candidate[end.max(1)..];
     }
+    links
 }
 
 /// Detect if a line is a code block fence
@@ -190,6 +452,41 @@ pub fn is_code_fence(line: &str) -> bool {
     line.trim().starts_with("```")
 }
 
+/// The line of a streaming answer that is still arriving, as it should be shown so it
+/// doesn't change shape when the rest comes: None holds back what may be a fence,
+/// thinking tag, math delimiter or table row, and in prose an unclosed code span, bold
+/// or strikethrough is closed
+pub fn settle_partial(line: &str, prose: bool) -> Option<String> {
+    const DELIMITERS: [&str; 5] = ["<thinking>", "</thinking>", "$$", "\\[", "\\]"];
+    let trimmed = line.trim();
+    if trimmed.is_empty()
+        || trimmed.starts_with("```")
+        || "```".starts_with(trimmed)
+        || DELIMITERS.iter().any(|delimiter| delimiter.starts_with(trimmed))
+        || (prose && trimmed.starts_with('|'))
+    {
+        return None;
+    }
+    if !prose {
+        return Some(line.to_string());
+    }
+    // A marker cut in half ("**bold*") would otherwise show as a stray star
+    let mut settled = line.trim_end_matches(['*', '~']).to_string();
+    if settled.trim().is_empty() {
+        return None;
+    }
+    if settled.matches('`').count() % 2 == 1 {
+        settled.push('`');
+    }
+    let outside_code: String = settled.split('`').step_by(2).collect();
+    for marker in ["**", "~~"] {
+        if outside_code.matches(marker).count() % 2 == 1 {
+            settled.push_str(marker);
+        }
+    }
+    Some(settled)
+}
+
 /// Extract language from code fence
 pub fn extract_code_language(line: &str) -> Option<String> {
     line.trim()
@@ -199,38 +496,338 @@ pub fn extract_code_language(line: &str) -> Option<String> {
         .map(ToString::to_string)
 }
 
+/// Reformat the body of a code block according to its render mode.
+/// Returns `None` when the mode keeps lines as written, or when the content
+/// cannot be parsed (e.g. malformed JSON), so callers fall back to raw lines.
+pub fn transform_code_block(
+    mode: CodeRenderMode,
+    language: Option<&str>,
+    code: &str,
+) -> Option<Vec<String>> {
+    match mode {
+        CodeRenderMode::Code | CodeRenderMode::Raw | CodeRenderMode::Diagram => None,
+        CodeRenderMode::Json => {
+            let value: serde_json::Value = serde_json::from_str(code).ok()?;
+            let pretty = serde_json::to_string_pretty(&value).ok()?;
+            Some(pretty.lines().map(ToString::to_string).collect())
+        }
+        CodeRenderMode::Table => {
+            let delimiter = if language.is_some_and(|lang| lang.eq_ignore_ascii_case("tsv")) {
+                '\t'
+            } else {
+                detect_delimiter(code).unwrap_or(',')
+            };
+            render_delimited_table(code, delimiter)
+        }
+    }
+}
+
+/// Detect CSV/TSV-like text: at least two rows that all split into the same
+/// number (2+) of fields. Returns the delimiter.
+pub fn detect_delimiter(text: &str) -> Option<char> {
+    let rows: Vec<&str> = text.lines().filter(|line| !line.trim().is_empty()).collect();
+    if rows.len() < 2 {
+        return None;
+    }
+
+    ['\t', ','].into_iter().find(|&delimiter| {
+        let columns = split_delimited(rows[0], delimiter).len();
+        columns >= 2
+            && rows
+                .iter()
+                .all(|row| split_delimited(row, delimiter).len() == columns)
+    })
+}
+
+/// Split one line of delimited text into fields, honoring double-quoted fields
+fn split_delimited(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ch if ch == delimiter && !in_quotes => {
+                fields.push(field.trim().to_string());
+                field.clear();
+            }
+            ch => field.push(ch),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+/// Numbers as commonly written in data: `-1,234.5`, `12%`, `$3.50`
+fn is_number(cell: &str) -> bool {
+    let cleaned: String = cell
+        .trim_start_matches(['$', '€', '£'])
+        .trim_end_matches('%')
+        .chars()
+        .filter(|&ch| ch != ',' && ch != '_')
+        .collect();
+    cleaned.parse::<f64>().is_ok()
+}
+
+/// Lay out delimited rows as aligned columns, with a rule under the header row
+pub fn render_delimited_table(code: &str, delimiter: char) -> Option<Vec<String>> {
+    let rows: Vec<Vec<String>> = code
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| split_delimited(line, delimiter))
+        .collect();
+
+    // A single column is just text; nothing to align
+    if rows.iter().all(|row| row.len() < 2) {
+        return None;
+    }
+
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![0; columns];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.width());
+        }
+    }
+
+    // Right-align columns whose values (below the header) are all numbers
+    let numeric: Vec<bool> = (0..columns)
+        .map(|i| {
+            let values: Vec<&String> = rows
+                .iter()
+                .skip(1)
+                .filter_map(|row| row.get(i))
+                .filter(|cell| !cell.is_empty())
+                .collect();
+            !values.is_empty() && values.iter().all(|cell| is_number(cell))
+        })
+        .collect();
+
+    let format_row = |row: &[String]| {
+        widths
+            .iter()
+            .enumerate()
+            .map(|(i, width)| {
+                let cell = row.get(i).map_or("", String::as_str);
+                pad_cell(cell, *width, if numeric[i] { Align::Right } else { Align::Left })
+            })
+            .collect::<Vec<_>>()
+            .join(" │ ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines = Vec::with_capacity(rows.len() + 1);
+    for (i, row) in rows.iter().enumerate() {
+        lines.push(format_row(row));
+        if i == 0 {
+            lines.push(
+                widths
+                    .iter()
+                    .map(|width| "─".repeat(*width))
+                    .collect::<Vec<_>>()
+                    .join("─┼─"),
+            );
+        }
+    }
+    Some(lines)
+}
+
+/// A fenced code block extracted from a response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlock {
+    pub language: Option<String>,
+    pub code: String,
+    /// The last line of prose before the block, e.g. "Create `src/main.rs`:"
+    pub intro: Option<String>,
+}
+
+/// Extract fenced code blocks in the order the chat history renders (and numbers) them.
+/// Fences inside `<thinking>` sections are ignored, and an unterminated block
+/// (e.g. while streaming) is returned as-is.
+pub fn extract_code_blocks(content: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut current: Option<CodeBlock> = None;
+    let mut in_thinking = false;
+    let mut last_text: Option<&str> = None;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.contains("<thinking>") {
+            in_thinking = true;
+        }
+        if in_thinking {
+            if trimmed.contains("</thinking>") {
+                in_thinking = false;
+            }
+            continue;
+        }
+
+        if is_code_fence(line) {
+            match current.take() {
+                Some(block) => blocks.push(block),
+                None => {
+                    current = Some(CodeBlock {
+                        language: extract_code_language(line),
+                        code: String::new(),
+                        intro: last_text.take().map(ToString::to_string),
+                    });
+                }
+            }
+        } else if let Some(block) = current.as_mut() {
+            if !block.code.is_empty() {
+                block.code.push('\n');
+            }
+            block.code.push_str(line);
+        } else if !trimmed.is_empty() {
+            last_text = Some(trimmed);
+        }
+    }
+
+    blocks.extend(current);
+    blocks
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_render_plain_text() {
-        let lines = render_markdown_to_lines("Hello world");
+        let lines = render_markdown_to_lines("Hello world", &Theme::default(), 0);
         assert_eq!(lines.len(), 1);
     }
 
     #[test]
     fn test_render_bold_text() {
-        let lines = render_markdown_to_lines("This is **bold** text");
+        let lines = render_markdown_to_lines("This is **bold** text", &Theme::default(), 0);
         assert_eq!(lines.len(), 1);
     }
 
+    #[test]
+    fn test_render_emphasis() {
+        let line = &render_markdown_to_lines(
+            "*it* _also_ ~~gone~~ ***both*** snake_case_name 2 * 3 * 4",
+            &Theme::default(),
+            0,
+        )[0];
+        assert_eq!(line.to_string(), "it also gone both snake_case_name 2 * 3 * 4");
+        let style_of = |text: &str| line.spans.iter().find(|span| span.content == text).unwrap().style;
+        assert!(style_of("it").add_modifier.contains(Modifier::ITALIC));
+        assert!(style_of("also").add_modifier.contains(Modifier::ITALIC));
+        assert!(style_of("gone").add_modifier.contains(Modifier::CROSSED_OUT));
+        assert!(style_of("both").add_modifier.contains(Modifier::BOLD | Modifier::ITALIC));
+        // Unclosed markers stay as written
+        assert_eq!(render_markdown_to_lines("**open *a", &Theme::default(), 0)[0].to_string(), "**open *a");
+    }
+
+    #[test]
+    fn test_render_inline_styles_links_and_quotes() {
+        let theme = Theme::default();
+        let heading = &render_markdown_to_lines("## Using `cargo` *fast*", &theme, 0)[0];
+        assert_eq!(heading.to_string(), "Using cargo fast");
+        assert_eq!(heading.spans[1].style.fg, Some(theme.code));
+        let fast = heading.spans.iter().find(|span| span.content == "fast").unwrap();
+        assert!(fast.style.add_modifier.contains(Modifier::BOLD | Modifier::ITALIC));
+
+        let link = &render_markdown_to_lines("See [docs](https://docs.rs) or <https://a.dev>", &theme, 0)[0];
+        assert_eq!(link.to_string(), "See docs (https://docs.rs) or https://a.dev");
+        assert_eq!(link.spans[1].style.fg, Some(theme.link));
+
+        let nested = &render_markdown_to_lines("**bold *and italic***", &theme, 0)[0];
+        assert!(nested.spans[1].style.add_modifier.contains(Modifier::BOLD | Modifier::ITALIC));
+
+        assert_eq!(render_markdown_to_lines("> quoted \\*text\\*", &theme, 0)[0].to_string(), "▎ quoted *text*");
+        assert_eq!(render_markdown_to_lines("- [x] done", &theme, 0)[0].to_string(), "• [x] done");
+    }
+
     #[test]
     fn test_render_inline_code() {
-        let lines = render_markdown_to_lines("Use `println!` macro");
+        let lines = render_markdown_to_lines("Use `println!` macro", &Theme::default(), 0);
         assert_eq!(lines.len(), 1);
     }
 
     #[test]
     fn test_render_header() {
-        let lines = render_markdown_to_lines("## Header");
+        let lines = render_markdown_to_lines("## Header", &Theme::default(), 0);
         assert_eq!(lines.len(), 1);
     }
 
+    #[test]
+    fn test_render_uses_theme_colors() {
+        let theme = Theme {
+            assistant: ratatui::style::Color::White,
+            heading: ratatui::style::Color::Rgb(1, 2, 3),
+            ..Theme::default()
+        };
+        let lines = render_markdown_to_lines("## Header\nplain `code`", &theme, 0);
+        assert_eq!(lines[0].spans[0].style.fg, Some(theme.heading));
+        assert_eq!(lines[1].style.fg, Some(theme.assistant));
+        assert_eq!(lines[1].spans[1].style.fg, Some(theme.code));
+    }
+
     #[test]
     fn test_render_list() {
-        let lines = render_markdown_to_lines("- List item");
+        let lines = render_markdown_to_lines("- List item", &Theme::default(), 0);
         assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].to_string(), "• List item");
+    }
+
+    #[test]
+    fn test_render_horizontal_rule() {
+        for rule in ["---", "***", "___", "- - -", "  *****"] {
+            assert_eq!(render_markdown_to_lines(rule, &Theme::default(), 12)[0].to_string(), "─".repeat(12));
+        }
+        for text in ["--", "-- x", "-*-", "**bold**"] {
+            assert_ne!(render_markdown_to_lines(text, &Theme::default(), 12)[0].to_string(), "─".repeat(12));
+        }
+    }
+
+    #[test]
+    fn test_render_ordered_and_nested_lists() {
+        // The history renders a line at a time
+        let markdown = "1. First **step**\n2) Second\n  - nested\n    - deeper\n10. Tenth";
+        let rendered: Vec<String> = markdown
+            .lines()
+            .flat_map(|line| render_markdown_to_lines(line, &Theme::default(), 0))
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(rendered, ["1. First step", "2. Second", "  ◦ nested", "    ▪ deeper", "10. Tenth"]);
+        // Nesting within one text
+        let rendered: Vec<String> = render_markdown_to_lines("- a\n  - b\n    1. c", &Theme::default(), 0)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(rendered, ["• a", "  ◦ b", "    1. c"]);
+        // Not lists
+        assert_eq!(render_markdown_to_lines("2024 was a year", &Theme::default(), 0)[0].to_string(), "2024 was a year");
+        assert_eq!(render_markdown_to_lines("-not a bullet", &Theme::default(), 0)[0].to_string(), "-not a bullet");
+    }
+
+    #[test]
+    fn test_list_items_wrap_with_hanging_indent() {
+        let rendered: Vec<String> =
+            render_markdown_to_lines("  1. one two three four five", &Theme::default(), 16)
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+        assert_eq!(rendered, ["  1. one two", "     three four", "     five"]);
+    }
+
+    #[test]
+    fn test_extract_links() {
+        assert_eq!(
+            extract_links("See [the docs](https://docs.rs/ratatui) and http://example.com/a?b=1."),
+            ["https://docs.rs/ratatui", "http://example.com/a?b=1"]
+        );
+        assert_eq!(extract_links("<https://a.dev>, https://a.dev again"), ["https://a.dev"]);
+        assert!(extract_links("no links, just https:// and http").is_empty());
     }
 
     #[test]
@@ -263,4 +860,125 @@ mod tests {
         assert!(is_table_separator("|:---|---:|"));
         assert!(!is_table_separator("| Col1 | Col2 |"));
     }
+
+    #[test]
+    fn test_render_markdown_table() {
+        let rows = ["| Name | Qty | Note |", "|:----|:---:|---:|", "| tea | 3 | hot |", "| coffee | 12 |"];
+        let rendered: Vec<String> = render_markdown_table(&rows, 0, &Theme::default())
+            .iter()
+            .map(|(line, _)| line.to_string())
+            .collect();
+        assert_eq!(
+            rendered,
+            [
+                "  ┌────────┬─────┬──────┐",
+                "  │ Name   │ Qty │ Note │",
+                "  ├────────┼─────┼──────┤",
+                "  │ tea    │  3  │  hot │",
+                "  │ coffee │ 12  │      │",
+                "  └────────┴─────┴──────┘",
+            ]
+        );
+        // The header rule stands for the separator row when copying
+        let sources: Vec<Option<String>> = render_markdown_table(&rows, 0, &Theme::default())
+            .into_iter()
+            .map(|(_, source)| source)
+            .collect();
+        assert_eq!(sources[2].as_deref(), Some("|:----|:---:|---:|"));
+        assert_eq!(sources[0], None);
+    }
+
+    #[test]
+    fn test_render_markdown_table_wraps_to_width() {
+        let rows = ["| Term | Meaning |", "|---|---|", "| tea | a drink made by steeping leaves |"];
+        let rendered: Vec<String> = render_markdown_table(&rows, 30, &Theme::default())
+            .iter()
+            .map(|(line, _)| line.to_string())
+            .collect();
+        assert!(rendered.iter().all(|line| line.width() <= 30), "{rendered:#?}");
+        assert_eq!(rendered[3], "  │ tea  │ a drink made by   │");
+        assert_eq!(rendered[4], "  │      │ steeping leaves   │");
+    }
+
+    #[test]
+    fn test_transform_json() {
+        let lines = transform_code_block(CodeRenderMode::Json, Some("json"), r#"{"a":[1,2]}"#).unwrap();
+        assert_eq!(lines, vec!["{", "  \"a\": [", "    1,", "    2", "  ]", "}"]);
+        // Partial or invalid JSON falls back to the raw text
+        assert!(transform_code_block(CodeRenderMode::Json, Some("json"), "{\"a\":").is_none());
+        assert!(transform_code_block(CodeRenderMode::Raw, None, "x").is_none());
+    }
+
+    #[test]
+    fn test_transform_csv_table() {
+        let csv = "name,qty\n\"Smith, J\",12\nbo,3";
+        let lines = transform_code_block(CodeRenderMode::Table, Some("csv"), csv).unwrap();
+        assert_eq!(lines, vec![
+            "name     │ qty",
+            "─────────┼────",
+            "Smith, J │  12",
+            "bo       │   3",
+        ]);
+
+        let tsv = "a\tb\nx\t2";
+        let lines = transform_code_block(CodeRenderMode::Table, Some("tsv"), tsv).unwrap();
+        assert_eq!(lines[0], "a │ b");
+        assert!(transform_code_block(CodeRenderMode::Table, Some("csv"), "just text").is_none());
+    }
+
+    #[test]
+    fn test_detect_delimiter() {
+        assert_eq!(detect_delimiter("a,b\n1,2\n"), Some(','));
+        assert_eq!(detect_delimiter("a\tb, c\n1\t2"), Some('\t'));
+        assert_eq!(detect_delimiter("\"x, y\",z\n1,2"), Some(','));
+        // Ragged rows or a single row are prose, not data
+        assert_eq!(detect_delimiter("Hello, world\nHow are you, friend, today"), None);
+        assert_eq!(detect_delimiter("a,b"), None);
+    }
+
+    #[test]
+    fn test_settle_partial() {
+        assert_eq!(settle_partial("So **very", true).as_deref(), Some("So **very**"));
+        assert_eq!(settle_partial("So **very*", true).as_deref(), Some("So **very**"));
+        assert_eq!(settle_partial("run `cargo te", true).as_deref(), Some("run `cargo te`"));
+        assert_eq!(settle_partial("`a ** b` and ~~gone", true).as_deref(), Some("`a ** b` and ~~gone~~"));
+        // Held back until the line is whole
+        assert_eq!(settle_partial("``", true), None);
+        assert_eq!(settle_partial("```py", false), None);
+        assert_eq!(settle_partial("<think", true), None);
+        assert_eq!(settle_partial("| a | b", true), None);
+        // Code is shown as it is
+        assert_eq!(settle_partial("let s = \"**", false).as_deref(), Some("let s = \"**"));
+    }
+
+    #[test]
+    fn test_tables_align_wide_characters() {
+        let lines = render_delimited_table("city,pop\n東京,14\nOslo,1", ',').unwrap();
+        assert_eq!(lines[2], "東京 │  14");
+        assert_eq!(lines[3], "Oslo │   1");
+        assert_eq!(wrap_cell("日本語です", 5), vec!["日本", "語で", "す"]);
+    }
+
+    #[test]
+    fn test_numeric_columns_right_aligned() {
+        let lines = render_delimited_table("item,price\ntea,$3.50\ncoffee,12", ',').unwrap();
+        assert_eq!(lines[2], "tea    │ $3.50");
+        assert_eq!(lines[3], "coffee │    12");
+    }
+
+    #[test]
+    fn test_extract_code_blocks() {
+        let content = "<thinking>\n```python\nignored()\n```\n</thinking>\nHere:\n```rust\nfn main() {}\n\nlet x = 1;\n```\nAnd:\n```\nplain\n```\n```sh\nstill streaming";
+        let blocks = extract_code_blocks(content);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].language.as_deref(), Some("rust"));
+        assert_eq!(blocks[0].code, "fn main() {}\n\nlet x = 1;");
+        assert_eq!(blocks[1].language, None);
+        assert_eq!(blocks[1].code, "plain");
+        assert_eq!(blocks[2].code, "still streaming");
+        assert_eq!(blocks[0].intro.as_deref(), Some("Here:"));
+        assert_eq!(blocks[1].intro.as_deref(), Some("And:"));
+        // Prose is consumed by the block it introduces
+        assert_eq!(blocks[2].intro, None);
+    }
 }
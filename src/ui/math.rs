@@ -0,0 +1,420 @@
+// LaTeX math in responses, shown as Unicode approximations
+
+use ratatui::text::Line;
+
+/// Marks where math was taken out of a line, around its index
+const OPEN: char = '\u{E000}';
+const CLOSE: char = '\u{E001}';
+
+/// A line that starts a display block: `$$` or `\[` on its own
+pub fn opens_block(line: &str) -> bool {
+    matches!(line.trim(), "$$" | "\\[")
+}
+
+/// A line that ends a display block
+pub fn closes_block(line: &str) -> bool {
+    matches!(line.trim(), "$$" | "\\]")
+}
+
+/// Take the math (`\( \)`, `\[ \]`, `$$ $$` and `$ $`) out of a line of markdown,
+/// leaving placeholders that markdown rendering passes through untouched. Code
+/// spans are left alone, and `$` only counts when it hugs its text, so prices
+/// like $5 and $10 aren't math.
+pub fn extract(line: &str) -> (String, Vec<String>) {
+    let mut text = String::with_capacity(line.len());
+    let mut math = Vec::new();
+    let mut rest = line;
+    while let Some(ch) = rest.chars().next() {
+        if ch == '`' {
+            // Copy the code span through to its closing run of backticks
+            let run = rest.len() - rest.trim_start_matches('`').len();
+            let end = rest[run..].find(&rest[..run]).map_or(rest.len(), |i| run + i + run);
+            text.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        if let Some(length) = math_at(rest) {
+            text.push(OPEN);
+            text.push_str(&math.len().to_string());
+            text.push(CLOSE);
+            math.push(rest[..length].to_string());
+            rest = &rest[length..];
+            continue;
+        }
+        text.push(ch);
+        rest = &rest[ch.len_utf8()..];
+    }
+    (text, math)
+}
+
+/// Length of the math starting at the beginning of `text`, delimiters included
+fn math_at(text: &str) -> Option<usize> {
+    for (open, close) in [("\\(", "\\)"), ("\\[", "\\]"), ("$$", "$$")] {
+        if let Some(after) = text.strip_prefix(open) {
+            let end = after.find(close).filter(|&end| !after[..end].trim().is_empty())?;
+            return Some(open.len() + end + close.len());
+        }
+    }
+    // `$` before a digit is a price, not math
+    let after = text.strip_prefix('$')?;
+    if after.starts_with(|ch: char| ch.is_whitespace() || ch.is_ascii_digit()) {
+        return None;
+    }
+    let end = after.find('$')?;
+    let inner = &after[..end];
+    let next = after[end + 1..].chars().next();
+    if inner.is_empty()
+        || inner.contains('`')
+        || inner.ends_with(char::is_whitespace)
+        || next.is_some_and(|ch| ch.is_ascii_digit())
+    {
+        return None;
+    }
+    Some(1 + end + 1)
+}
+
+/// Put the math taken out by `extract` back into rendered lines, as Unicode when
+/// `pretty`, else as written
+pub fn restore(lines: &mut [Line<'static>], math: &[String], pretty: bool) {
+    if math.is_empty() {
+        return;
+    }
+    for span in lines.iter_mut().flat_map(|line| line.spans.iter_mut()) {
+        if !span.content.contains(OPEN) {
+            continue;
+        }
+        let mut content = String::new();
+        let mut rest = span.content.as_ref();
+        while let Some(start) = rest.find(OPEN) {
+            content.push_str(&rest[..start]);
+            let after = &rest[start + OPEN.len_utf8()..];
+            let end = after.find(CLOSE).unwrap_or(after.len());
+            if let Some(source) = after[..end].parse::<usize>().ok().and_then(|i| math.get(i)) {
+                if pretty {
+                    content.push_str(&to_unicode(strip_delimiters(source)));
+                } else {
+                    content.push_str(source);
+                }
+            }
+            rest = after.get(end + CLOSE.len_utf8()..).unwrap_or("");
+        }
+        content.push_str(rest);
+        span.content = content.into();
+    }
+}
+
+fn strip_delimiters(math: &str) -> &str {
+    for (open, close) in [("\\(", "\\)"), ("\\[", "\\]"), ("$$", "$$"), ("$", "$")] {
+        if let Some(inner) = math.strip_prefix(open).and_then(|rest| rest.strip_suffix(close)) {
+            return inner.trim();
+        }
+    }
+    math
+}
+
+/// A Unicode approximation of LaTeX math: Greek letters and symbols, superscripts
+/// and subscripts where Unicode has them, `a/b` fractions and `√` roots.
+/// Commands it doesn't know are kept as written.
+pub fn to_unicode(latex: &str) -> String {
+    let mut out = String::new();
+    let mut rest = latex;
+    while let Some(ch) = rest.chars().next() {
+        rest = &rest[ch.len_utf8()..];
+        match ch {
+            '\\' => {
+                let name_length = rest
+                    .find(|ch: char| !ch.is_ascii_alphabetic())
+                    .unwrap_or(rest.len())
+                    .max(usize::from(!rest.is_empty()));
+                let name = &rest[..rest.char_indices().nth(name_length).map_or(rest.len(), |(i, _)| i)];
+                rest = &rest[name.len()..];
+                command(name, &mut rest, &mut out);
+            }
+            '^' | '_' => {
+                let argument = to_unicode(take_argument(&mut rest));
+                script(&argument, ch == '^', &mut out);
+            }
+            '{' | '}' => {}
+            '&' | '~' => out.push(' '),
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+/// The next argument of a command: a `{group}`, a `\command` or one character
+fn take_argument<'a>(rest: &mut &'a str) -> &'a str {
+    *rest = rest.trim_start();
+    let text = *rest;
+    let length = if text.starts_with('{') {
+        let mut depth = 0;
+        text.char_indices()
+            .find_map(|(i, ch)| {
+                match ch {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                (depth == 0).then_some(i + 1)
+            })
+            .unwrap_or(text.len())
+    } else if let Some(command) = text.strip_prefix('\\') {
+        // A named command, or a single escaped character (which may be multi-byte)
+        match command.find(|ch: char| !ch.is_ascii_alphabetic()).unwrap_or(command.len()) {
+            0 => 1 + command.chars().next().map_or(0, char::len_utf8),
+            name => 1 + name,
+        }
+    } else {
+        text.chars().next().map_or(0, char::len_utf8)
+    };
+    *rest = &text[length..];
+    let argument = &text[..length];
+    argument.strip_prefix('{').and_then(|inner| inner.strip_suffix('}')).unwrap_or(argument)
+}
+
+/// Parentheses around a converted argument that isn't a single term
+fn grouped(text: &str) -> String {
+    if text.chars().count() > 1 && text.contains([' ', '+', '-', '−', '/', '·', '×', '=']) {
+        format!("({text})")
+    } else {
+        text.to_string()
+    }
+}
+
+fn script(text: &str, superscript: bool, out: &mut String) {
+    if superscript && !text.is_empty() && text.chars().all(|ch| ch == '′') {
+        out.push_str(text);
+        return;
+    }
+    let mapped: Option<String> = text
+        .chars()
+        .map(|ch| if superscript { superscript_of(ch) } else { subscript_of(ch) })
+        .collect();
+    match mapped {
+        Some(mapped) if !mapped.is_empty() => out.push_str(&mapped),
+        _ => {
+            out.push(if superscript { '^' } else { '_' });
+            if text.chars().count() > 1 {
+                out.push('(');
+                out.push_str(text);
+                out.push(')');
+            } else {
+                out.push_str(text);
+            }
+        }
+    }
+}
+
+fn command(name: &str, rest: &mut &str, out: &mut String) {
+    match name {
+        "frac" | "dfrac" | "tfrac" => {
+            let numerator = to_unicode(take_argument(rest));
+            let denominator = to_unicode(take_argument(rest));
+            out.push_str(&grouped(&numerator));
+            out.push('/');
+            out.push_str(&grouped(&denominator));
+        }
+        "sqrt" => {
+            if let Some(index) = rest.strip_prefix('[').and_then(|after| after.split_once(']')) {
+                script(&to_unicode(index.0), true, out);
+                *rest = index.1;
+            }
+            let radicand = to_unicode(take_argument(rest));
+            out.push('√');
+            if radicand.chars().count() > 1 {
+                out.push('(');
+                out.push_str(&radicand);
+                out.push(')');
+            } else {
+                out.push_str(&radicand);
+            }
+        }
+        "mathbb" => {
+            let argument = take_argument(rest);
+            for ch in argument.chars() {
+                out.push(match ch {
+                    'R' => 'ℝ',
+                    'N' => 'ℕ',
+                    'Z' => 'ℤ',
+                    'Q' => 'ℚ',
+                    'C' => 'ℂ',
+                    ch => ch,
+                });
+            }
+        }
+        "text" | "mathrm" | "mathbf" | "mathit" | "mathsf" | "textbf" | "textit" | "operatorname" | "boldsymbol" => {
+            out.push_str(&to_unicode(take_argument(rest)));
+        }
+        "left" | "right" | "big" | "Big" | "bigg" | "Bigg" | "displaystyle" | "limits" | "!" => {}
+        "," | ":" | ";" | " " | "\\" => out.push(' '),
+        "quad" | "qquad" => out.push_str("  "),
+        "{" | "}" | "%" | "$" | "&" | "#" | "_" | "|" => out.push_str(name),
+        _ => {
+            if let Some((_, symbol)) = SYMBOLS.iter().find(|(command, _)| *command == name) {
+                out.push_str(symbol);
+            } else {
+                out.push('\\');
+                out.push_str(name);
+            }
+        }
+    }
+}
+
+/// LaTeX commands that stand for one symbol (or a function name)
+const SYMBOLS: &[(&str, &str)] = &[
+    ("alpha", "α"), ("beta", "β"), ("gamma", "γ"), ("delta", "δ"), ("epsilon", "ϵ"),
+    ("varepsilon", "ε"), ("zeta", "ζ"), ("eta", "η"), ("theta", "θ"), ("vartheta", "ϑ"),
+    ("iota", "ι"), ("kappa", "κ"), ("lambda", "λ"), ("mu", "μ"), ("nu", "ν"), ("xi", "ξ"),
+    ("pi", "π"), ("rho", "ρ"), ("sigma", "σ"), ("tau", "τ"), ("upsilon", "υ"), ("phi", "ϕ"),
+    ("varphi", "φ"), ("chi", "χ"), ("psi", "ψ"), ("omega", "ω"), ("Gamma", "Γ"),
+    ("Delta", "Δ"), ("Theta", "Θ"), ("Lambda", "Λ"), ("Xi", "Ξ"), ("Pi", "Π"),
+    ("Sigma", "Σ"), ("Upsilon", "Υ"), ("Phi", "Φ"), ("Psi", "Ψ"), ("Omega", "Ω"),
+    ("times", "×"), ("cdot", "·"), ("div", "÷"), ("pm", "±"), ("mp", "∓"), ("leq", "≤"),
+    ("le", "≤"), ("geq", "≥"), ("ge", "≥"), ("neq", "≠"), ("ne", "≠"), ("approx", "≈"),
+    ("sim", "∼"), ("equiv", "≡"), ("propto", "∝"), ("infty", "∞"), ("sum", "∑"),
+    ("prod", "∏"), ("int", "∫"), ("oint", "∮"), ("partial", "∂"), ("nabla", "∇"),
+    ("to", "→"), ("rightarrow", "→"), ("leftarrow", "←"), ("gets", "←"),
+    ("Rightarrow", "⇒"), ("implies", "⇒"), ("Leftarrow", "⇐"), ("leftrightarrow", "↔"),
+    ("Leftrightarrow", "⇔"), ("iff", "⇔"), ("mapsto", "↦"), ("in", "∈"), ("notin", "∉"),
+    ("subset", "⊂"), ("subseteq", "⊆"), ("supset", "⊃"), ("cup", "∪"), ("cap", "∩"),
+    ("emptyset", "∅"), ("varnothing", "∅"), ("forall", "∀"), ("exists", "∃"), ("neg", "¬"),
+    ("lnot", "¬"), ("land", "∧"), ("wedge", "∧"), ("lor", "∨"), ("vee", "∨"),
+    ("ldots", "…"), ("dots", "…"), ("cdots", "⋯"), ("prime", "′"), ("circ", "∘"),
+    ("degree", "°"), ("angle", "∠"), ("perp", "⊥"), ("parallel", "∥"), ("hbar", "ℏ"),
+    ("ell", "ℓ"), ("langle", "⟨"), ("rangle", "⟩"), ("lfloor", "⌊"), ("rfloor", "⌋"),
+    ("lceil", "⌈"), ("rceil", "⌉"), ("sin", "sin"), ("cos", "cos"), ("tan", "tan"),
+    ("log", "log"), ("ln", "ln"), ("exp", "exp"), ("lim", "lim"), ("max", "max"),
+    ("min", "min"), ("det", "det"),
+];
+
+const fn superscript_of(ch: char) -> Option<char> {
+    Some(match ch {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '-' | '−' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        'T' => 'ᵀ',
+        _ => return None,
+    })
+}
+
+const fn subscript_of(ch: char) -> Option<char> {
+    Some(match ch {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        '+' => '₊',
+        '-' | '−' => '₋',
+        '=' => '₌',
+        '(' => '₍',
+        ')' => '₎',
+        'a' => 'ₐ',
+        'e' => 'ₑ',
+        'h' => 'ₕ',
+        'i' => 'ᵢ',
+        'j' => 'ⱼ',
+        'k' => 'ₖ',
+        'l' => 'ₗ',
+        'm' => 'ₘ',
+        'n' => 'ₙ',
+        'o' => 'ₒ',
+        'p' => 'ₚ',
+        'r' => 'ᵣ',
+        's' => 'ₛ',
+        't' => 'ₜ',
+        'u' => 'ᵤ',
+        'v' => 'ᵥ',
+        'x' => 'ₓ',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::markdown::render_markdown_to_lines;
+    use crate::ui::theme::Theme;
+
+    #[test]
+    fn test_to_unicode() {
+        assert_eq!(to_unicode("x^2 + y_{i}"), "x² + yᵢ");
+        assert_eq!(to_unicode(r"\alpha \to \infty"), "α → ∞");
+        assert_eq!(to_unicode(r"\frac{a+b}{2}"), "(a+b)/2");
+        assert_eq!(to_unicode(r"\sqrt{x^2+1} \leq \mathbb{R}^n"), "√(x²+1) ≤ ℝⁿ");
+        assert_eq!(to_unicode(r"e^{i\pi} = -1"), "e^(iπ) = -1");
+        assert_eq!(to_unicode(r"\sum_{k=1}^{n} k \cdot \text{cost}"), "∑ₖ₌₁ⁿ k · cost");
+        // Unknown commands stay as written
+        assert_eq!(to_unicode(r"\unknown + 1"), r"\unknown + 1");
+    }
+
+    #[test]
+    fn test_escaped_multibyte_argument() {
+        let mut rest = "\\é + 1";
+        assert_eq!(take_argument(&mut rest), "\\é");
+        assert_eq!(rest, " + 1");
+        // Model output like this must not panic mid-character
+        for latex in [r"x^\é", r"a_\α", r"\frac\é\α", r"\sqrt\é"] {
+            to_unicode(latex);
+        }
+    }
+
+    #[test]
+    fn test_extract_and_restore() {
+        let line = r"Area \( \pi r^2 \), and $E = mc^2$ for $5 or $10, not `$x$`";
+        let (text, math) = extract(line);
+        assert_eq!(math, [r"\( \pi r^2 \)", "$E = mc^2$"]);
+        assert!(text.contains("`$x$`"));
+
+        let theme = Theme::default();
+        let mut lines = render_markdown_to_lines(&text, &theme, 0);
+        restore(&mut lines, &math, true);
+        assert_eq!(lines[0].to_string(), "Area π r², and E = mc² for $5 or $10, not $x$");
+        // Markdown doesn't eat the backslashes of the source
+        let mut lines = render_markdown_to_lines(&text, &theme, 0);
+        restore(&mut lines, &math, false);
+        assert_eq!(lines[0].to_string(), r"Area \( \pi r^2 \), and $E = mc^2$ for $5 or $10, not $x$");
+    }
+}
@@ -1,4 +1,7 @@
+pub mod highlight;
 pub mod markdown;
+pub mod math;
+pub mod theme;
 pub mod widgets;
 
 use crate::app::{App, AppMode};
@@ -8,21 +11,18 @@ use ratatui::{
 };
 
 pub fn render(frame: &mut Frame, app: &mut App) {
+    let theme = app.previewed_theme().map_or_else(
+        || theme::Theme::from_config(&app.config.theme),
+        |preview| theme::Theme::from_config(&preview),
+    );
+    app.popup_area = None;
+
     // Calculate required input height
     // Width available for text is total width - 2 (for borders)
     let available_width = frame.area().width.saturating_sub(2) as usize;
     
-    // Calculate how many lines the input text will take
-    // We start with 1 line minimum
-    let input_lines = if app.input_buffer.is_empty() {
-        1
-    } else {
-        // Approximate wrapping: (chars + width - 1) / width
-        // Note: This is a simple approximation. Ratatui's Wrap might differ slightly with words,
-        // but this is usually close enough for auto-resizing.
-        let chars_count = app.input_buffer.chars().count();
-        chars_count.div_ceil(available_width)
-    };
+    // Calculate how many lines the input text will take (wrapped the same way it is drawn)
+    let input_lines = widgets::wrap_input(&app.input_buffer, app.cursor(), available_width).0.len();
     
     // Clamp lines: Min 1, Max 50% of screen height (approx)
     let max_lines = (frame.area().height as usize / 2).saturating_sub(2); // -2 for borders
@@ -43,25 +43,86 @@ pub fn render(frame: &mut Frame, app: &mut App) {
         ])
         .split(frame.area());
 
-    widgets::render_chat_history(frame, app, chunks[0]);
+    widgets::render_chat_history(frame, app, &theme, chunks[0]);
     // chunks[1] is the gap, left empty
-    widgets::render_status_bar(frame, app, chunks[2]);
-    widgets::render_input_field(frame, app, chunks[3]);
-    widgets::render_bottom_bar(frame, app, chunks[4]);
+    widgets::render_status_bar(frame, app, &theme, chunks[2]);
+    widgets::render_input_field(frame, app, &theme, chunks[3]);
+    app.input_area = chunks[3];
+    widgets::render_bottom_bar(frame, app, &theme, chunks[4]);
 
     // Render help window on top if active
     if app.show_help {
-        widgets::render_help_window(frame, frame.area());
+        widgets::render_help_window(frame, app, &theme, frame.area());
     }
 
     // Render info window on top if active
     if app.show_info {
-        widgets::render_info_window(frame, app, frame.area());
+        widgets::render_info_window(frame, app, &theme, frame.area());
     }
 
     // Render model selector if active
     if app.mode == AppMode::ModelSelector {
-        widgets::render_model_selector(frame, app, frame.area());
+        widgets::render_model_selector(frame, app, &theme, frame.area());
+    }
+
+    if app.mode == AppMode::ConversationList {
+        widgets::render_conversation_list(frame, app, &theme, frame.area());
+    }
+
+    if app.mode == AppMode::PersonaSelector {
+        widgets::render_persona_selector(frame, app, &theme, frame.area());
+    }
+
+    if app.mode == AppMode::ThemeSelector {
+        widgets::render_theme_selector(frame, app, &theme, frame.area());
+    }
+
+    if app.mode == AppMode::TemplateSelector {
+        widgets::render_template_selector(frame, app, &theme, frame.area());
+    }
+
+    if app.mode == AppMode::TemplateForm {
+        widgets::render_template_form(frame, app, &theme, frame.area());
+    }
+
+    if app.mode == AppMode::Collections {
+        widgets::render_collections(frame, app, &theme, frame.area());
+    }
+
+    if app.mode == AppMode::Search {
+        widgets::render_search(frame, app, &theme, frame.area());
+    }
+
+    if app.mode == AppMode::Memory {
+        widgets::render_memory(frame, app, &theme, frame.area());
+    }
+
+    if app.mode == AppMode::Bookmarks {
+        widgets::render_bookmarks(frame, app, &theme, frame.area());
+    }
+
+    if app.mode == AppMode::ReadLater {
+        widgets::render_read_later(frame, app, &theme, frame.area());
+    }
+
+    if app.mode == AppMode::Settings {
+        widgets::render_settings(frame, app, &theme, frame.area());
+    }
+
+    if app.mode == AppMode::Experiment {
+        widgets::render_experiment(frame, app, &theme, frame.area());
+    }
+
+    if let Some(model) = &app.pull_prompt {
+        widgets::render_pull_prompt(frame, model, &theme, frame.area());
+    }
+
+    if app.traffic.is_recording() {
+        widgets::render_debug_overlay(frame, app, &theme, frame.area());
+    }
+
+    if !app.config_problems.is_empty() {
+        widgets::render_config_problems(frame, app, &theme, frame.area());
     }
 }
 
@@ -0,0 +1,121 @@
+// Theme color handling
+
+use ratatui::style::Color;
+
+use crate::models::ThemeConfig;
+
+/// Colors of the interface, resolved from `[theme]` in config.toml
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    /// The user's messages and the input being typed
+    pub user: Color,
+    /// Plain text of the model's responses
+    pub assistant: Color,
+    /// Borders of the chat history and input field
+    pub border: Color,
+    pub accent: Color,
+    pub selection: Color,
+    pub text: Color,
+    pub muted: Color,
+    pub heading: Color,
+    pub thinking: Color,
+    pub code: Color,
+    pub link: Color,
+    pub error: Color,
+    pub warning: Color,
+    pub success: Color,
+    pub highlight: Color,
+}
+
+impl Theme {
+    /// Colors that don't parse fall back to the default theme's
+    pub fn from_config(config: &ThemeConfig) -> Self {
+        let defaults = ThemeConfig::default();
+        let color = |value: &str, default: &str| {
+            parse_color(value)
+                .or_else(|| parse_color(default))
+                .unwrap_or(Color::Reset)
+        };
+        Self {
+            user: color(&config.user_message_color, &defaults.user_message_color),
+            assistant: color(&config.assistant_message_color, &defaults.assistant_message_color),
+            border: color(&config.border_color, &defaults.border_color),
+            accent: color(&config.accent_color, &defaults.accent_color),
+            selection: color(&config.selection_color, &defaults.selection_color),
+            text: color(&config.text_color, &defaults.text_color),
+            muted: color(&config.muted_color, &defaults.muted_color),
+            heading: color(&config.heading_color, &defaults.heading_color),
+            thinking: color(&config.thinking_color, &defaults.thinking_color),
+            code: color(&config.code_color, &defaults.code_color),
+            link: color(&config.link_color, &defaults.link_color),
+            error: color(&config.error_color, &defaults.error_color),
+            warning: color(&config.warning_color, &defaults.warning_color),
+            success: color(&config.success_color, &defaults.success_color),
+            highlight: color(&config.highlight_color, &defaults.highlight_color),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::from_config(&ThemeConfig::default())
+    }
+}
+
+/// Parse a color name (`blue`, `light-red`, `dark-gray`), hex (`#1e90ff`) or
+/// 256-color index (`208`) as used in config.toml
+pub fn parse_color(value: &str) -> Option<Color> {
+    value.trim().parse::<Color>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("blue"), Some(Color::Blue));
+        assert_eq!(parse_color(" Cyan "), Some(Color::Cyan));
+        assert_eq!(parse_color("#ff0000"), Some(Color::Rgb(255, 0, 0)));
+        assert_eq!(parse_color("208"), Some(Color::Indexed(208)));
+        assert_eq!(parse_color("not-a-color"), None);
+    }
+
+    #[test]
+    fn test_theme_from_config() {
+        let theme = Theme::default();
+        assert_eq!(theme.user, Color::Blue);
+        assert_eq!(theme.muted, Color::DarkGray);
+        assert_eq!(theme.highlight, Color::LightRed);
+
+        let config: ThemeConfig = toml::from_str(
+            r##"
+border_color = "#1e90ff"
+accent_color = "not-a-color"
+"##,
+        )
+        .unwrap();
+        let theme = Theme::from_config(&config);
+        assert_eq!(theme.border, Color::Rgb(0x1e, 0x90, 0xff));
+        // Invalid and missing keys keep the defaults
+        assert_eq!(theme.accent, Color::Yellow);
+        assert_eq!(theme.assistant, Color::Green);
+    }
+
+    #[test]
+    fn test_presets_resolve() {
+        for name in crate::models::THEME_PRESETS {
+            let config = ThemeConfig::preset(name).unwrap();
+            for value in [&config.user_message_color, &config.text_color, &config.highlight_color] {
+                assert!(parse_color(value).is_some(), "{name}: {value}");
+            }
+        }
+        assert_eq!(Theme::from_config(&ThemeConfig::preset("dark").unwrap()), Theme::default());
+
+        // Explicit colors win over the preset's
+        let config: ThemeConfig = toml::from_str("preset = \"gruvbox\"\nborder_color = \"red\"").unwrap();
+        let theme = Theme::from_config(&config);
+        assert_eq!(theme.border, Color::Red);
+        assert_eq!(theme.accent, Color::Rgb(0xfa, 0xbd, 0x2f));
+    }
+}
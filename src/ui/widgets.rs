@@ -1,14 +1,24 @@
 use ratatui::{
-    layout::Rect,
+    layout::{Constraint, Layout, Position, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, Paragraph, Wrap, Clear, List, ListItem},
     Frame,
 };
 
-use crate::app::{App, AppMode};
+use crate::app::{App, AppMode, HistoryLayout};
+use crate::models::{CodeRenderMode, THEME_PRESETS};
+use crate::settings::SettingsField;
 
-pub fn render_model_selector(frame: &mut Frame, app: &mut App, area: Rect) {
+use super::theme::Theme;
+use crate::keymap::Action;
+use crate::traffic::Direction;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+pub fn render_model_selector(frame: &mut Frame, app: &mut App, theme: &Theme, area: Rect) {
     if app.mode != AppMode::ModelSelector {
         return;
     }
@@ -31,15 +41,19 @@ pub fn render_model_selector(frame: &mut Frame, app: &mut App, area: Rect) {
     let items: Vec<ListItem> = app.available_models
         .iter()
         .map(|m| {
-            let content = if m == &app.current_model {
+            let mut content = if m == &app.current_model {
                 Line::from(vec![
-                    Span::styled(format!("* {m}"), Style::default().fg(Color::Green).add_modifier(Modifier::BOLD))
+                    Span::styled(format!("* {m}"), Style::default().fg(theme.selection).add_modifier(Modifier::BOLD))
                 ])
             } else {
                 Line::from(vec![
-                   Span::styled(format!("  {m}"), Style::default().fg(Color::White))
+                   Span::styled(format!("  {m}"), Style::default().fg(theme.text))
                 ])
             };
+            let aliases = app.config.aliases_of(m);
+            if !aliases.is_empty() {
+                content.push_span(Span::styled(format!(" ({})", aliases.join(", ")), Style::default().fg(theme.muted)));
+            }
             ListItem::new(content)
         })
         .collect();
@@ -48,499 +62,2430 @@ pub fn render_model_selector(frame: &mut Frame, app: &mut App, area: Rect) {
         .block(Block::default()
             .borders(Borders::ALL)
             .title(" Select Model (Enter to confirm, Esc to cancel) ")
-            .border_style(Style::default().fg(Color::Yellow))
+            .border_style(Style::default().fg(theme.accent))
         )
         .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
 
-    frame.render_stateful_widget(list, popup_area, &mut app.model_list_state);
+    frame.render_stateful_widget(list, popup_area, &mut app.lists.model);
+    app.popup_area = Some(popup_area);
 }
 
-pub fn render_help_window(frame: &mut Frame, area: Rect) {
-    let help_text = vec![
-        Line::from(Span::styled(
-            "YumChat - Keyboard Shortcuts",
-            Style::default()
-                .fg(Color::Cyan)
-                .add_modifier(Modifier::BOLD),
-        )),
-        Line::from(""),
-        Line::from(Span::styled("General:", Style::default().add_modifier(Modifier::BOLD))),
-        Line::from("  Ctrl+N        - New conversation"),
-        Line::from("  Ctrl+H        - Show/hide this help"),
-        Line::from("  Ctrl+I        - Show/hide model info"),
-        Line::from("  Ctrl+M        - Switch Model"),
-        Line::from("  Ctrl+Q        - Quit application"),
-        Line::from("  Ctrl+C        - Quit application"),
-        Line::from(""),
-        Line::from(Span::styled("Chat:", Style::default().add_modifier(Modifier::BOLD))),
-        Line::from("  Enter         - Send message"),
-        Line::from("  Tab           - Toggle thinking"),
-        Line::from("  Typing        - Auto-targets input"),
-        Line::from(""),
-        Line::from(Span::styled("Navigation:", Style::default().add_modifier(Modifier::BOLD))),
-        Line::from("  Up/Down       - Scroll history"),
-        Line::from("  PgUp/PgDn     - Scroll history"),
-        Line::from("  Home/End      - Jump to start/end"),
-        Line::from(""),
-        Line::from(Span::styled("Coming Soon:", Style::default().add_modifier(Modifier::BOLD))),
-        Line::from("  Ctrl+L        - List conversations"),
-        Line::from("  Ctrl+S        - Settings"),
-        Line::from(""),
-        Line::from(Span::styled(
-            "Press Ctrl+H or Esc to close",
-            Style::default().fg(Color::DarkGray),
-        )),
-    ];
-
-    let help_paragraph = Paragraph::new(help_text)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(" Help ")
-                .border_style(Style::default().fg(Color::Cyan)),
-        )
-        .wrap(Wrap { trim: false });
-
-    // Calculate centered position
-    let popup_width = 60;
-    let popup_height = 25;
-    let x = (area.width.saturating_sub(popup_width)) / 2;
-    let y = (area.height.saturating_sub(popup_height)) / 2;
-
+/// `/experiment` panel: one column per parameter value, outputs side by side
+pub fn render_experiment(frame: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let Some(experiment) = &app.experiment else {
+        return;
+    };
     let popup_area = Rect {
-        x: area.x + x,
-        y: area.y + y,
-        width: popup_width.min(area.width),
-        height: popup_height.min(area.height),
+        x: area.x + 1,
+        y: area.y + 1,
+        width: area.width.saturating_sub(2),
+        height: area.height.saturating_sub(2),
     };
-
     frame.render_widget(Clear, popup_area);
-    frame.render_widget(help_paragraph, popup_area);
-}
 
-#[allow(clippy::too_many_lines)]
-pub fn render_info_window(frame: &mut Frame, app: &App, area: Rect) {
-    let tokens_used = app.total_tokens_used();
-    let context_window = app.context_window_size;
-    let usage_percentage = app.context_usage_percentage();
+    let outer = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(
+            " Experiment: {} ({}){} ",
+            experiment.parameter,
+            experiment.model,
+            if experiment.is_finished() { "" } else { " - running" }
+        ))
+        .border_style(Style::default().fg(theme.accent));
+    let inner = outer.inner(popup_area);
+    frame.render_widget(outer, popup_area);
 
-    // Center popup
-    let popup_width = 50;
-    let popup_height = 18;
-    let x = (area.width.saturating_sub(popup_width)) / 2;
-    let y = (area.height.saturating_sub(popup_height)) / 2;
+    let rows = Layout::default()
+        .direction(ratatui::layout::Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(inner);
+    #[allow(clippy::cast_possible_truncation)]
+    let count = experiment.runs.len() as u32;
+    let columns = Layout::default()
+        .direction(ratatui::layout::Direction::Horizontal)
+        .constraints(experiment.runs.iter().map(|_| Constraint::Ratio(1, count)))
+        .split(rows[0]);
+
+    for (i, (run, column)) in experiment.runs.iter().zip(columns.iter()).enumerate() {
+        let status = match (&run.error, run.done) {
+            (Some(_), _) => "✗",
+            (None, true) => "✓",
+            (None, false) => "…",
+        };
+        let border = if i == experiment.selected { theme.accent } else { theme.muted };
+        let text = run.error.as_ref().map_or_else(|| run.output.trim().to_string(), |e| format!("Error: {e}"));
+        let output = Paragraph::new(text)
+            .wrap(Wrap { trim: false })
+            .scroll((experiment.scroll, 0))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" {}={} {status} ", experiment.parameter, run.value))
+                    .border_style(Style::default().fg(border)),
+            );
+        frame.render_widget(output, *column);
+    }
+
+    let hint = Paragraph::new(" Left/Right: Select | Up/Down: Scroll | Enter: Keep as response | Esc: Close")
+        .style(Style::default().fg(theme.muted));
+    frame.render_widget(hint, rows[1]);
+}
 
+/// Question offering to download a model that is not installed, or pick another
+pub fn render_pull_prompt(frame: &mut Frame, model: &str, theme: &Theme, area: Rect) {
+    let popup_width = 56.min(area.width);
+    let popup_height = 6.min(area.height);
     let popup_area = Rect {
-        x: area.x + x,
-        y: area.y + y,
-        width: popup_width.min(area.width),
-        height: popup_height.min(area.height),
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
     };
+    frame.render_widget(Clear, popup_area);
 
-    let mut info_text = vec![
+    let lines = vec![
+        Line::from(format!("'{model}' is not installed.")),
+        Line::from("Pull it now?"),
+        Line::from(""),
         Line::from(Span::styled(
-            "Session Information",
-            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            "  y/Enter: Pull | m: Pick another | n/Esc: Skip",
+            Style::default().fg(theme.muted),
         )),
-        Line::from(""),
-        Line::from(vec![
-            Span::raw("Model: "),
-            Span::styled(&app.current_model, Style::default().fg(Color::Yellow)),
-        ]),
-        Line::from(vec![
-            Span::raw("Family: "),
-            Span::styled(
-                app.model_details.as_ref().map_or_else(|| "Unknown".to_string(), |d| d.family.clone()), 
-                Style::default().fg(Color::White)
-            ),
-        ]),
-        Line::from(vec![
-            Span::raw("Params: "),
-            Span::styled(
-                app.model_details.as_ref().map_or_else(|| "?".to_string(), |d| d.parameter_size.clone()), 
-                Style::default().fg(Color::White)
-            ),
-        ]),
-        Line::from(vec![
-            Span::raw("Quantization: "),
-            Span::styled(
-                app.model_details.as_ref().map_or_else(|| "?".to_string(), |d| d.quantization_level.clone()), 
-                Style::default().fg(Color::White)
-            ),
-        ]),
-        Line::from(""),
-        Line::from(Span::styled("Capabilities:", Style::default().add_modifier(Modifier::BOLD))),
     ];
+    let prompt = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Missing Model ")
+            .border_style(Style::default().fg(theme.accent)),
+    );
+    frame.render_widget(prompt, popup_area);
+}
 
-    if app.model_capabilities.is_empty() {
-         info_text.push(Line::from(Span::styled("  Unknown", Style::default().fg(Color::DarkGray))));
-    } else {
-        for cap in &app.model_capabilities {
-             let (symbol, color) = match cap.as_str() {
-                 "thinking" => ("🧠", Color::Magenta),
-                 "tools" => ("🛠️", Color::Green),
-                 "vision" => ("👁️", Color::Blue),
-                 "completion" => ("📝", Color::Cyan),
-                 "chat" => ("💬", Color::Yellow),
-                 _ => ("•", Color::White),
-             };
-             info_text.push(Line::from(vec![
-                 Span::raw("  "),
-                 Span::styled(format!("{symbol} {cap}"), Style::default().fg(color))
-             ]));
-        }
-    }
-    
-    info_text.extend(vec![
-        Line::from(""),
-        Line::from(vec![
-            Span::raw("Tokens Used: "),
-            Span::styled(format!("{tokens_used}"), Style::default().fg(Color::Green)),
-        ]),
-        Line::from(vec![
-            Span::raw("Speed: "),
-            Span::styled(format!("{:.1} t/s", app.tokens_per_second), Style::default().fg(Color::Magenta)),
-        ]),
-        Line::from(vec![
-            Span::raw("Context Window: "),
-            Span::styled(format!("{context_window} tokens"), Style::default().fg(Color::Blue)),
-        ]),
-        Line::from(vec![
-            Span::raw("Usage: "),
-            Span::styled(format!("{usage_percentage:.1}%"), Style::default().fg(
-                if usage_percentage > 80.0 { Color::Red }
-                else if usage_percentage > 50.0 { Color::Yellow }
-                else { Color::Green }
-            )),
-        ]),
-        Line::from(""),
-        Line::from(Span::styled(
-            "Press Ctrl+I to close",
-            Style::default().fg(Color::DarkGray),
-        )),
-    ]);
+/// Raw protocol traffic over the lower half of the screen, newest at the bottom
+pub fn render_debug_overlay(frame: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let height = (area.height / 2).max(3).min(area.height);
+    let overlay_area = Rect {
+        x: area.x,
+        y: area.y + area.height - height,
+        width: area.width,
+        height,
+    };
+    frame.render_widget(Clear, overlay_area);
 
-    let info_paragraph = Paragraph::new(info_text)
-        .block(
+    let lines: Vec<Line> = app
+        .traffic
+        .latest(usize::from(height.saturating_sub(2)))
+        .into_iter()
+        .map(|entry| {
+            let color = match entry.direction {
+                Direction::Sent => theme.accent,
+                Direction::Received => theme.code,
+                Direction::Event => theme.muted,
+            };
+            Line::from(vec![
+                Span::styled(entry.at.format("%H:%M:%S%.3f ").to_string(), Style::default().fg(theme.muted)),
+                Span::styled(format!("{} {}", entry.direction.arrow(), entry.text), Style::default().fg(color)),
+            ])
+        })
+        .collect();
+    let title = format!(" Debug: protocol traffic ({} closes) ", app.keymap.label(Action::ToggleDebug));
+    frame.render_widget(
+        Paragraph::new(lines).block(
             Block::default()
                 .borders(Borders::ALL)
-                .title(" Model Info ")
-                .border_style(Style::default().fg(Color::Cyan)),
-        )
-        .wrap(Wrap { trim: false });
+                .title(title)
+                .border_style(Style::default().fg(theme.warning)),
+        ),
+        overlay_area,
+    );
+}
 
+pub fn render_config_problems(frame: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let popup_width = 80.min(area.width);
+    let mut lines = vec![
+        Line::from("These settings couldn't be used, so their defaults apply:"),
+        Line::from(""),
+    ];
+    for problem in &app.config_problems {
+        let location = match (problem.line, problem.key.as_str()) {
+            (Some(line), "") => format!("line {line}"),
+            (Some(line), key) => format!("line {line}, {key}"),
+            (None, "") => "config.toml".to_string(),
+            (None, key) => key.to_string(),
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("  {location}: "), Style::default().fg(theme.warning)),
+            Span::raw(problem.message.clone()),
+        ]));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        "  Fix config.toml and it reloads; any key to continue",
+        Style::default().fg(theme.muted),
+    )));
+    // Long messages wrap onto more rows
+    let inner_width = usize::from(popup_width.saturating_sub(2)).max(1);
+    let rows: usize = lines.iter().map(|line| line.width().max(1).div_ceil(inner_width)).sum();
+    let popup_height = u16::try_from(rows + 2).unwrap_or(u16::MAX).min(area.height);
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
     frame.render_widget(Clear, popup_area);
-    frame.render_widget(info_paragraph, popup_area);
+    frame.render_widget(
+        paragraph.block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Problems in config.toml ")
+                .border_style(Style::default().fg(theme.error)),
+        ),
+        popup_area,
+    );
 }
 
-pub fn render_bottom_bar(frame: &mut Frame, app: &App, area: Rect) {
-    let (text, style) = if app.exit_pending {
-        (
-            "Press Ctrl+C again to exit, Esc to cancel".to_string(),
-            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
-        )
-    } else {
-        let thought_action = if app.show_thinking { "Hide" } else { "Reveal" };
-        (
-            format!("Ctrl+N: New | Ctrl+C: Quit | Ctrl+I: Info | Tab: {thought_action} Thoughts | Ctrl+H: Help"),
-            Style::default().fg(Color::DarkGray),
-        )
+pub fn render_persona_selector(frame: &mut Frame, app: &mut App, theme: &Theme, area: Rect) {
+    let popup_width = 60.min(area.width);
+    let popup_height = 20.min(area.height);
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
     };
+    frame.render_widget(Clear, popup_area);
 
-    let bar = Paragraph::new(text)
-        .alignment(ratatui::layout::Alignment::Center)
-        .style(style);
+    let items: Vec<ListItem> = app
+        .persona_choices()
+        .iter()
+        .map(|choice| {
+            let active = *choice == app.active_persona;
+            let name = choice.as_deref().unwrap_or("(no persona)");
+            let detail = choice
+                .as_ref()
+                .and_then(|name| app.config.personas.get(name))
+                .and_then(|persona| persona.model.as_deref())
+                .map(|model| format!("  [{model}]"))
+                .unwrap_or_default();
+            let style = if active {
+                Style::default().fg(theme.selection).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(theme.text)
+            };
+            let marker = if active { "*" } else { " " };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{marker} {name}"), style),
+                Span::styled(detail, Style::default().fg(theme.muted)),
+            ]))
+        })
+        .collect();
 
-    frame.render_widget(bar, area);
+    let list = List::new(items)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(" Select Persona (Enter to confirm, Esc to cancel) ")
+            .border_style(Style::default().fg(theme.accent))
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    frame.render_stateful_widget(list, popup_area, &mut app.lists.persona);
 }
 
-pub fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
-    let usage_percentage = app.context_usage_percentage();
-    
-    let color = if usage_percentage > 80.0 {
-        Color::Red
-    } else if usage_percentage > 50.0 {
-        Color::Yellow
-    } else {
-        Color::Green
+/// Preset picker; the interface behind it is drawn with the highlighted preset
+pub fn render_theme_selector(frame: &mut Frame, app: &mut App, theme: &Theme, area: Rect) {
+    let popup_width = 50.min(area.width);
+    let popup_height = u16::try_from(THEME_PRESETS.len() + 2).unwrap_or(u16::MAX).min(area.height);
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
     };
+    frame.render_widget(Clear, popup_area);
 
-    let loading_indicator = if app.is_loading {
-        if app.is_thinking {
-            " [Thinking...]"
-        } else {
-            " [Responding...]"
-        }
-    } else {
-        ""
-    };
-    
-    let status_text = format!(
-        "{}{} ({:.1}%)",
-        app.current_model, loading_indicator, usage_percentage
-    );
+    let current = app.config.theme.preset.as_deref();
+    let items: Vec<ListItem> = THEME_PRESETS
+        .iter()
+        .map(|name| {
+            let active = current == Some(*name);
+            let style = if active {
+                Style::default().fg(theme.selection).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(theme.text)
+            };
+            let marker = if active { "*" } else { " " };
+            ListItem::new(Span::styled(format!("{marker} {name}"), style))
+        })
+        .collect();
 
-    let status = Paragraph::new(status_text)
-        .alignment(ratatui::layout::Alignment::Right)
-        .style(Style::default().fg(color).add_modifier(Modifier::BOLD));
+    let list = List::new(items)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(" Theme (Enter to apply, Esc to cancel) ")
+            .border_style(Style::default().fg(theme.accent))
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
 
-    frame.render_widget(status, area);
+    frame.render_stateful_widget(list, popup_area, &mut app.lists.theme);
 }
 
-#[allow(clippy::too_many_lines)]
-pub fn render_chat_history(frame: &mut Frame, app: &mut App, area: Rect) {
-    let mut lines = Vec::new();
-
-    if app.messages.is_empty() {
-        // Render welcome banner at the bottom of the history area
-        let welcome_text = vec![
-            Line::from(Span::styled(
-                "Welcome to YumChat",
-                Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
-            )),
-            Line::from(Span::styled(
-                "Your friendly terminal AI assistant",
-                Style::default().fg(Color::Cyan),
-            )),
-        ];
+/// Search across saved conversations: query line on top, hits with dates below
+pub fn render_search(frame: &mut Frame, app: &mut App, theme: &Theme, area: Rect) {
+    let popup_width = 90.min(area.width);
+    let popup_height = 24.min(area.height);
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+    frame.render_widget(Clear, popup_area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Search conversations (Enter to search/open, Esc to close) ")
+        .border_style(Style::default().fg(theme.accent));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
 
-        let welcome_paragraph = Paragraph::new(welcome_text)
-            .alignment(ratatui::layout::Alignment::Center);
+    let query_area = Rect { height: 1, ..inner };
+    let results_area = Rect {
+        y: inner.y + 2,
+        height: inner.height.saturating_sub(2),
+        ..inner
+    };
+    let query = Paragraph::new(Line::from(vec![
+        Span::styled("Find: ", Style::default().fg(theme.muted)),
+        Span::styled(
+            app.search_query.clone(),
+            Style::default().fg(theme.heading).add_modifier(Modifier::BOLD),
+        ),
+    ]));
+    frame.render_widget(query, query_area);
+    let cursor_x = query_area.x + 6 + u16::try_from(app.search_query.width()).unwrap_or(u16::MAX);
+    frame.set_cursor_position((cursor_x.min(query_area.right().saturating_sub(1)), query_area.y));
 
-        // Position it at the bottom of the history area
-        let welcome_height = 2;
-        let y_pos = area.y + area.height.saturating_sub(welcome_height);
-        
-        let welcome_area = Rect {
-            x: area.x,
-            y: y_pos,
-            width: area.width,
-            height: welcome_height.min(area.height),
+    if app.search_results.is_empty() {
+        let message = if app.searched_query.is_empty() || app.search_query != app.searched_query {
+            "Type a query and press Enter"
+        } else {
+            "No matches"
         };
-
-        frame.render_widget(welcome_paragraph, welcome_area);
+        let empty = Paragraph::new(message).style(Style::default().fg(theme.muted));
+        frame.render_widget(empty, results_area);
         return;
-    } 
-    
-    for message in &app.messages {
-        lines.push(Line::from(""));
-
-        match message.role {
-            crate::models::MessageRole::User => {
-                for line in message.content.lines() {
-                    lines.push(Line::from(vec![
-                        Span::styled("> ", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
-                        Span::styled(line, Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
-                    ]));
-                }
-            }
-            crate::models::MessageRole::Assistant => {
-                // Render content with markdown styling
-                if message.content.is_empty() {
-                // Show a placeholder for empty AI responses (while streaming)
-                lines.push(Line::from(Span::styled("...", Style::default().fg(Color::DarkGray))));
-            } else {
-                let mut in_code_block = false;
-                let mut in_thinking = false;
-                let mut thinking_header_shown = false;
-                
-                for content_line in message.content.lines() {
-                    let trimmed = content_line.trim();
-                    let has_start = trimmed.contains("<thinking>");
-                    let has_end = trimmed.contains("</thinking>");
-                    
-                    if has_start {
-                        in_thinking = true;
-                        thinking_header_shown = false;
-                        if app.show_thinking {
-                             lines.push(Line::from(Span::styled(
-                                "  <thinking>", 
-                                Style::default().fg(Color::DarkGray)
-                            )));
-                        }
-                    }
-                    
-                    if in_thinking {
-                        // Strip tags to get actual content if any
-                        let clean_content = content_line.replace("<thinking>", "").replace("</thinking>", "");
-                        let clean_trimmed = clean_content.trim();
-                        
-                        if !clean_trimmed.is_empty() {
-                            if app.show_thinking {
-                                lines.push(Line::from(Span::styled(
-                                    format!("        {clean_trimmed}"), 
-                                    Style::default().fg(Color::DarkGray),
-                                )));
-                            } else if !thinking_header_shown {
-                                if app.is_loading && app.is_thinking {
-                                    // Animation based on time
-                                    let tick = app.generation_start_time.map_or(0, |start| (start.elapsed().as_millis() / 100) as usize);
-                                    
-                                    let frames = ["⣾", "⣽", "⣻", "⢿", "⡿", "⣟", "⣯", "⣷"];
-                                    let frame = frames[tick % frames.len()];
-                                    let color = match (tick / 8) % 3 {
-                                        0 => Color::Magenta,
-                                        1 => Color::Cyan,
-                                        _ => Color::Blue,
-                                    };
-                                    
-                                    lines.push(Line::from(vec![
-                                        Span::styled("    | AI assistant thoughts (Hidden)   ", Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC)),
-                                        Span::styled(format!("{frame}  "), Style::default().fg(color)),
-                                        Span::styled("Thinking", Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD)),
-                                        Span::styled(format!("  {frame}"), Style::default().fg(color)),
-                                    ]));
-                                } else {
-                                    lines.push(Line::from(Span::styled(
-                                        "    | AI assistant thoughts (Hidden) - Press Tab to show", 
-                                        Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC),
-                                    )));
-                                }
-                                thinking_header_shown = true;
-                            }
-                        }
-                    } else {
-                        // Regular content processing
-                        if trimmed == "[Response stream aborted by user]" {
-                            lines.push(Line::from(Span::styled(
-                                "[Response stream aborted by user]",
-                                Style::default().fg(Color::Yellow).add_modifier(Modifier::ITALIC),
-                            )));
-                            continue;
-                        }
-                        
-                        if super::markdown::is_code_fence(content_line) {
-                            if in_code_block {
-                                // Closing fence
-                                lines.push(Line::from(Span::styled(
-                                    "└──────────────────────────────────────────────",
-                                    Style::default().fg(Color::DarkGray),
-                                )));
-                                in_code_block = false;
-                            } else {
-                                // Opening fence
-                                in_code_block = true;
-                                let code_lang = super::markdown::extract_code_language(content_line);
-                                let lang_display = code_lang.as_deref().unwrap_or("code");
-                                lines.push(Line::from(Span::styled(
-                                    format!("┌─ {lang_display} ───────────────────────────────────────────"),
-                                    Style::default().fg(Color::DarkGray),
-                                )));
-                            }
-                        } else if in_code_block {
-                            // Inside code block - render with simple prefix
-                            lines.push(Line::from(Span::styled(
-                                format!("  {content_line}"),
-                                Style::default().fg(Color::Green),
-                            )));
-                        } else {
-                            // Regular markdown line
-                            if content_line.is_empty() {
-                                lines.push(Line::from(""));
-                            } else {
-                                let rendered_lines = super::markdown::render_markdown_to_lines(content_line);
-                                lines.extend(rendered_lines);
-                            }
-                        }
-                    }
-                    
-                    if has_end {
-                        in_thinking = false;
-                        if app.show_thinking {
-                             lines.push(Line::from(Span::styled(
-                                "  </thinking>", 
-                                Style::default().fg(Color::DarkGray)
-                            )));
-                        }
-                        // Add blank line after thinking block
-                        lines.push(Line::from(""));
-                    }
-                }
-                
-                // Add thinking animation if currently thinking at the end of the message (visible mode)
-                if app.is_loading && app.is_thinking && in_thinking && app.show_thinking {
-                    // Animation based on time
-                    let tick = app.generation_start_time.map_or(0, |start| (start.elapsed().as_millis() / 100) as usize);
-                    
-                    let frames = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
-                    let frame = frames[tick % frames.len()];
-                    
-                    lines.push(Line::from(Span::styled(
-                        format!("        {frame} Thinking..."), 
-                        Style::default().fg(Color::DarkGray),
-                    )));
-                }
-            }
-        }
-    }
-    }
-    
-    // Calculate scroll position - if scroll_offset is very large, 
-    // we want to show the bottom content
-    // We must account for line wrapping to calculate the true visual height
-    // No borders on history anymore, so use full width
-    let available_width = area.width as usize; 
-    let mut total_visual_lines = 0;
-    
-    for line in &lines {
-        let line_width = line.width();
-        if line_width == 0 {
-            total_visual_lines += 1;
-        } else {
-            // Ceiling division: (width + available - 1) / available
-            total_visual_lines += line_width.div_ceil(available_width);
-        }
-    }
-
-    // No borders, so full height visible
-    let visible_height = area.height as usize;
-    let max_scroll = total_visual_lines.saturating_sub(visible_height);
-    let actual_scroll = app.scroll_offset.min(max_scroll);
-    
-    // Sync the actual scroll back to the app state
-    if app.scroll_offset != actual_scroll {
-        app.scroll_offset = actual_scroll;
     }
 
-    let chat_history = Paragraph::new(lines)
-        .wrap(Wrap { trim: false })
-        .scroll((u16::try_from(actual_scroll).unwrap_or(u16::MAX), 0));
+    let items: Vec<ListItem> = app
+        .search_results
+        .iter()
+        .map(|hit| {
+            let summary = hit.conversation.summary.as_deref().unwrap_or("(untitled)");
+            let updated = hit.conversation.updated_at.with_timezone(&chrono::Local).format("%Y-%m-%d");
+            ListItem::new(vec![
+                Line::from(vec![
+                    Span::styled(summary.to_string(), Style::default().fg(theme.text).add_modifier(Modifier::BOLD)),
+                    Span::styled(format!("  {updated}"), Style::default().fg(theme.muted)),
+                ]),
+                Line::from(Span::styled(format!("  {}", hit.snippet), Style::default().fg(theme.text))),
+            ])
+        })
+        .collect();
+    let list = List::new(items).highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, results_area, &mut app.lists.search);
+}
+
+/// Remembered facts, newest last; `d` deletes the highlighted one
+pub fn render_memory(frame: &mut Frame, app: &mut App, theme: &Theme, area: Rect) {
+    let popup_width = 80.min(area.width);
+    let popup_height = 20.min(area.height);
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+    frame.render_widget(Clear, popup_area);
+
+    let items: Vec<ListItem> = app
+        .memory
+        .iter()
+        .map(|entry| {
+            let (source, color) = match entry.source {
+                crate::memory::MemorySource::User => ("you", theme.user),
+                crate::memory::MemorySource::Model => ("model", theme.assistant),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("[{source}] "), Style::default().fg(color)),
+                Span::styled(entry.text.clone(), Style::default().fg(theme.text)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Memory (d to delete, Esc to close) ")
+                .border_style(Style::default().fg(theme.accent)),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, popup_area, &mut app.lists.memory);
+}
+
+/// Bookmarked messages in conversation order; Enter jumps to the highlighted one
+pub fn render_bookmarks(frame: &mut Frame, app: &mut App, theme: &Theme, area: Rect) {
+    let popup_width = 80.min(area.width);
+    let popup_height = 20.min(area.height);
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+    frame.render_widget(Clear, popup_area);
+
+    let items: Vec<ListItem> = app
+        .bookmarks()
+        .into_iter()
+        .map(|i| {
+            let message = &app.messages[i];
+            let (role, color) = match message.role {
+                crate::models::MessageRole::User => ("you", theme.user),
+                crate::models::MessageRole::Assistant => ("assistant", theme.assistant),
+            };
+            let text = crate::clipboard::strip_thinking(&message.content);
+            let first_line = text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("#{} [{role}] ", i + 1), Style::default().fg(color)),
+                Span::styled(first_line.to_string(), Style::default().fg(theme.text)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Bookmarks (Enter to jump, d to remove, Esc to close) ")
+                .border_style(Style::default().fg(theme.accent)),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, popup_area, &mut app.lists.bookmark);
+    app.popup_area = Some(popup_area);
+}
+
+/// Read-later review screen: the selected queued response with where it came from
+pub fn render_read_later(frame: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let Some(item) = app.read_later.get(app.read_later_selected) else {
+        return;
+    };
+    let popup_area = Rect {
+        x: area.x + area.width / 10,
+        y: area.y + area.height / 10,
+        width: area.width - area.width / 5,
+        height: area.height - area.height / 5,
+    };
+    frame.render_widget(Clear, popup_area);
+
+    let added = item.added_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M");
+    let mut lines = vec![
+        Line::from(vec![
+            Span::styled(item.title.clone(), Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+            Span::styled(format!("  queued {added}"), Style::default().fg(theme.muted)),
+        ]),
+    ];
+    lines.extend(item.prompt.lines().take(3).map(|line| {
+        Line::from(Span::styled(
+            format!("> {line}"),
+            Style::default().fg(theme.heading).add_modifier(Modifier::BOLD),
+        ))
+    }));
+    lines.push(Line::from(""));
+    lines.extend(item.content.lines().map(|line| Line::from(line.to_string())));
+
+    let title = format!(" Read later {}/{} ", app.read_later_selected + 1, app.read_later.len());
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(Style::default().fg(theme.border)),
+        )
+        .wrap(Wrap { trim: false })
+        .scroll((app.read_later_scroll, 0));
+    frame.render_widget(paragraph, popup_area);
+}
+
+/// Chip color of a conversation tag
+fn tag_color(tag: &str) -> Color {
+    match tag {
+        "coding" => Color::Blue,
+        "writing" => Color::Magenta,
+        "troubleshooting" => Color::Red,
+        "brainstorm" => Color::Yellow,
+        _ => Color::Gray,
+    }
+}
+
+pub fn render_conversation_list(frame: &mut Frame, app: &mut App, theme: &Theme, area: Rect) {
+    let popup_width = 80.min(area.width);
+    let popup_height = 20.min(area.height);
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+    frame.render_widget(Clear, popup_area);
+
+    let current = app.current_conversation.as_ref().map(|c| c.id);
+    let items: Vec<ListItem> = app
+        .conversation_list
+        .iter()
+        .map(|conversation| {
+            let active = Some(conversation.id) == current;
+            let style = if active {
+                Style::default().fg(theme.selection).add_modifier(Modifier::BOLD)
+            } else if conversation.archived {
+                Style::default().fg(theme.muted)
+            } else {
+                Style::default().fg(theme.text)
+            };
+            let marker = if active { "*" } else { " " };
+            let summary = conversation.summary.as_deref().unwrap_or("(untitled)");
+            let updated = conversation.updated_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M");
+            let draft = if app.conversation_drafts.contains(&conversation.id) {
+                "  ✎ draft"
+            } else {
+                ""
+            };
+            let mut spans = vec![Span::styled(format!("{marker} "), style)];
+            if conversation.pinned {
+                spans.push(Span::styled("📌 ", Style::default().fg(theme.accent)));
+            }
+            if let Some(tag) = &conversation.tag {
+                spans.push(Span::styled(
+                    format!(" {tag} "),
+                    Style::default().fg(Color::Black).bg(tag_color(tag)),
+                ));
+                spans.push(Span::raw(" "));
+            }
+            spans.extend([
+                Span::styled(summary.to_string(), style),
+                Span::styled(format!("  {updated}"), Style::default().fg(theme.muted)),
+                Span::styled(draft, Style::default().fg(theme.accent)),
+            ]);
+            if conversation.archived {
+                spans.push(Span::styled("  archived", Style::default().fg(theme.muted)));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let archived_action = if app.show_archived { "hide" } else { "show" };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(
+            " Conversations (Enter: open · p: pin · a: archive · Tab: {archived_action} archived · Esc) "
+        ))
+        .border_style(Style::default().fg(theme.accent));
+    if items.is_empty() {
+        let empty = Paragraph::new("No saved conversations yet.")
+            .style(Style::default().fg(theme.muted))
+            .block(block);
+        frame.render_widget(empty, popup_area);
+        return;
+    }
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, popup_area, &mut app.lists.conversation);
+    app.popup_area = Some(popup_area);
+}
+
+pub fn render_template_selector(frame: &mut Frame, app: &mut App, theme: &Theme, area: Rect) {
+    let popup_width = 70.min(area.width);
+    let popup_height = 20.min(area.height);
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+    frame.render_widget(Clear, popup_area);
+
+    let items: Vec<ListItem> = app
+        .templates
+        .iter()
+        .map(|(name, template)| {
+            let description = template
+                .description
+                .as_deref()
+                .map(|d| format!("  {d}"))
+                .unwrap_or_default();
+            ListItem::new(Line::from(vec![
+                Span::styled(name.clone(), Style::default().fg(theme.text)),
+                Span::styled(description, Style::default().fg(theme.muted)),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(" Insert Template (Enter to confirm, Esc to cancel) ")
+            .border_style(Style::default().fg(theme.accent))
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    frame.render_stateful_widget(list, popup_area, &mut app.lists.template);
+}
+
+pub fn render_template_form(frame: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let Some(form) = app.template_form.as_ref() else {
+        return;
+    };
+
+    let popup_width = 70.min(area.width);
+    let popup_height = 20.min(area.height);
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" Template: {} (Tab next field, Enter insert, Esc cancel) ", form.name))
+        .border_style(Style::default().fg(theme.accent));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let field_height = u16::try_from(form.fields.len()).unwrap_or(u16::MAX);
+    let [fields_area, preview_area] = Layout::vertical([
+        Constraint::Length(field_height + 1),
+        Constraint::Min(1),
+    ])
+    .areas(inner);
+
+    let field_lines: Vec<Line> = form
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, (name, value))| {
+            let active = i == form.active;
+            let label_style = if active {
+                Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(theme.text)
+            };
+            let cursor = if active { "▏" } else { "" };
+            Line::from(vec![
+                Span::styled(format!("{name}: "), label_style),
+                Span::raw(format!("{value}{cursor}")),
+            ])
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(field_lines), fields_area);
+
+    let preview = Paragraph::new(form.fill())
+        .style(Style::default().fg(theme.muted))
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::TOP).title(" Preview "));
+    frame.render_widget(preview, preview_area);
+}
+
+pub fn render_collections(frame: &mut Frame, app: &mut App, theme: &Theme, area: Rect) {
+    let popup_width = 80.min(area.width);
+    let popup_height = 20.min(area.height);
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Collections (Enter use, r re-index, Esc close) ")
+        .border_style(Style::default().fg(theme.accent));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let [list_area, footer_area] = Layout::vertical([Constraint::Min(1), Constraint::Length(2)]).areas(inner);
+
+    let items: Vec<ListItem> = app
+        .collection_rows()
+        .into_iter()
+        .map(|(i, path)| {
+            let collection = &app.collections.items[i];
+            if let Some(path) = path {
+                return ListItem::new(Line::from(Span::styled(
+                    format!("      {}", collection.paths[path].display()),
+                    Style::default().fg(theme.text),
+                )));
+            }
+            let active = app.active_collection.as_deref() == Some(collection.name.as_str());
+            let style = if active {
+                Style::default().fg(theme.selection).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(theme.text)
+            };
+            let marker = if active { "*" } else { " " };
+            let default_model = &app.config.rag.embedding_model;
+            let detail = match (&collection.embedding_model, collection.indexed_at) {
+                (Some(model), Some(indexed_at)) => format!(
+                    "  {} chunks · {model} · {}",
+                    collection.chunk_count,
+                    indexed_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+                ),
+                _ => format!("  not indexed · {}", collection.target_model(default_model)),
+            };
+            let status = match &app.index_progress {
+                Some((_, done, total)) if app.is_indexing(&collection.name) => {
+                    format!("  indexing {done}/{total}")
+                }
+                _ if collection.needs_migration(default_model) => {
+                    format!("  ⚠ now {} (e: re-embed)", collection.target_model(default_model))
+                }
+                _ => String::new(),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{marker} {}", collection.name), style),
+                Span::styled(detail, Style::default().fg(theme.muted)),
+                Span::styled(status, Style::default().fg(theme.accent)),
+            ]))
+        })
+        .collect();
+
+    if items.is_empty() {
+        let empty = Paragraph::new("No collections yet. Press n to create one.")
+            .style(Style::default().fg(theme.muted));
+        frame.render_widget(empty, list_area);
+    } else {
+        let list = List::new(items).highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut app.lists.collection);
+    }
+
+    let footer = match &app.collection_input {
+        Some((prompt, input)) => {
+            let label = match prompt {
+                crate::app::CollectionPrompt::Name => "New collection name: ",
+                crate::app::CollectionPrompt::Path => "Add file or directory: ",
+                crate::app::CollectionPrompt::Model => "Embedding model (empty for default): ",
+            };
+            Line::from(vec![
+                Span::styled(label, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+                Span::raw(format!("{input}▏")),
+            ])
+        }
+        None => Line::from(Span::styled(
+            "n new · a add path · d delete path/collection · m embedding model · e re-embed",
+            Style::default().fg(theme.muted),
+        )),
+    };
+    let footer = Paragraph::new(footer).block(Block::default().borders(Borders::TOP));
+    frame.render_widget(footer, footer_area);
+}
+
+pub fn render_settings(frame: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let popup_width = 100.min(area.width);
+    // Fields plus blank lines, hint and borders
+    let popup_height = u16::try_from(SettingsField::ALL.len() + 5).unwrap_or(u16::MAX).min(area.height);
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+    frame.render_widget(Clear, popup_area);
+
+    let columns = ratatui::layout::Layout::default()
+        .direction(ratatui::layout::Direction::Horizontal)
+        .constraints([
+            ratatui::layout::Constraint::Percentage(60),
+            ratatui::layout::Constraint::Percentage(40),
+        ])
+        .split(popup_area);
+
+    let preview = app.settings_preview();
+    let mut lines = vec![Line::from("")];
+    for (i, field) in SettingsField::ALL.iter().enumerate() {
+        let selected = i == app.settings_selected;
+        let marker = if selected { "> " } else { "  " };
+        let value = match (&app.settings_input, selected) {
+            (Some(input), true) => format!("{input}_"),
+            _ => field.get(&preview),
+        };
+        let label_style = if selected {
+            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(theme.text)
+        };
+
+        let mut spans = vec![
+            Span::styled(format!("{marker}{:<26}", field.label()), label_style),
+            Span::styled(value, Style::default().fg(theme.heading)),
+        ];
+        if field.is_color() {
+            if let Some(color) = super::theme::parse_color(&field.get(&preview)) {
+                spans.push(Span::styled(" ■", Style::default().fg(color)));
+            }
+        }
+        lines.push(Line::from(spans));
+    }
+    lines.push(Line::from(""));
+    let hint = if app.settings_input.is_some() {
+        "  Enter: Apply | Esc: Cancel edit"
+    } else {
+        "  Up/Down: Select | Enter: Edit | s: Save | Esc: Close"
+    };
+    lines.push(Line::from(Span::styled(hint, Style::default().fg(theme.muted))));
+
+    // Scroll to keep the selected field in view on short terminals
+    let visible = usize::from(columns[0].height.saturating_sub(2));
+    let scroll = (app.settings_selected + 3).saturating_sub(visible);
+    let settings = Paragraph::new(lines)
+        .scroll((u16::try_from(scroll).unwrap_or(u16::MAX), 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Settings ")
+                .border_style(Style::default().fg(theme.accent)),
+        );
+    frame.render_widget(settings, columns[0]);
+
+    render_theme_preview(frame, &Theme::from_config(&preview.theme), columns[1]);
+}
+
+/// Miniature sample transcript drawn with the given theme colors
+fn render_theme_preview(frame: &mut Frame, theme: &Theme, area: Rect) {
+    let user = Style::default().fg(theme.user).add_modifier(Modifier::BOLD);
+    let assistant = Style::default().fg(theme.assistant);
+
+    let sample = vec![
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("> ", user),
+            Span::styled("How do I reverse a string?", user),
+        ]),
+        Line::from(""),
+        Line::from(Span::styled("Collect the chars in reverse:", assistant)),
+        Line::from(""),
+        Line::from(Span::styled("  s.chars().rev().collect()", assistant)),
+    ];
+
+    let preview = Paragraph::new(sample).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Preview ")
+            .border_style(Style::default().fg(theme.border)),
+    );
+    frame.render_widget(preview, area);
+}
+
+pub fn render_help_window(frame: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    // Shortcuts for keymap actions show their current chords
+    let keys = |action: Action| app.keymap.label(action);
+    let bound = |action: Action, description: &str| Line::from(format!("  {:<13} - {description}", keys(action)));
+    let help_text = vec![
+        Line::from(Span::styled(
+            "YumChat - Keyboard Shortcuts",
+            Style::default()
+                .fg(theme.heading)
+                .add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+        Line::from(Span::styled("General:", Style::default().add_modifier(Modifier::BOLD))),
+        bound(Action::NewChat, "New conversation"),
+        bound(Action::ToggleHelp, "Show/hide this help"),
+        bound(Action::ToggleInfo, "Show/hide model info"),
+        bound(Action::ListConversations, "List conversations"),
+        bound(Action::SwitchModel, "Switch Model"),
+        bound(Action::SwitchPersona, "Switch Persona"),
+        bound(Action::InsertTemplate, "Insert Prompt Template"),
+        bound(Action::Settings, "Settings"),
+        bound(Action::Quit, "Quit application"),
+        Line::from("  Ctrl+C        - Quit application"),
+        Line::from(""),
+        Line::from(Span::styled("Chat:", Style::default().add_modifier(Modifier::BOLD))),
+        bound(Action::Send, "Send (queue while responding)"),
+        bound(Action::ToggleThinking, "Toggle thinking"),
+        bound(Action::SelectMessage, "Select message (f fork, v copy, p pin, m mark)"),
+        bound(Action::CopyLastResponse, "Copy last response"),
+        bound(Action::Undo, "Undo last question"),
+        Line::from("  Alt+1..9      - Copy code block [N]"),
+        Line::from("  /copy N       - Copy code block [N]"),
+        Line::from("  /save N       - Save code block [N] to a file"),
+        Line::from("  /codesave dir - Save all code from the last response"),
+        Line::from("  /continue     - Resume a cut-off response"),
+        Line::from("  /experiment   - Compare option values side by side"),
+        Line::from("  /set, /unset  - Options for this conversation only"),
+        Line::from("  /later        - Queue response (/later review)"),
+        Line::from("  /timer 25m    - Focus timer in the status bar"),
+        Line::from("  /math         - Show LaTeX math as Unicode or as written"),
+        Line::from("  /remember     - Remember a fact (/memory to review)"),
+        Line::from("  /bookmarks    - List bookmarked messages and jump to one"),
+        Line::from("  /export html  - Export to a file (markdown/json/html/obsidian)"),
+        Line::from("  /import file  - Import a ChatGPT/JSON export or .md chat"),
+        Line::from("  /attach file  - Attach an image or text file to the next message"),
+        Line::from("  /theme        - Pick a theme preset (/theme gruvbox)"),
+        Line::from("  /model fast   - Switch model by name or alias"),
+        bound(Action::Find, "Find in this conversation (n/N)"),
+        bound(Action::OpenLink, "Open a link on screen in the browser"),
+        bound(Action::Search, "Search all conversations (/search)"),
+        Line::from("  Typing        - Auto-targets input"),
+        Line::from("  Left/Right    - Move cursor (Ctrl: by word)"),
+        bound(Action::DeleteWord, "Delete word"),
+        bound(Action::ClearInput, "Clear input"),
+        bound(Action::Paste, "Paste from clipboard"),
+        Line::from(""),
+        Line::from(Span::styled("Navigation:", Style::default().add_modifier(Modifier::BOLD))),
+        Line::from("  Up/Down       - Recall prompts (empty input) / scroll"),
+        Line::from(format!("  {:<13} - Scroll history", format!("{}/{}", keys(Action::ScrollUp), keys(Action::ScrollDown)))),
+        Line::from(format!("  {:<13} - History top/bottom", format!("{}/{}", keys(Action::ScrollTop), keys(Action::ScrollBottom)))),
+        Line::from(format!("  {:<13} - Previous/next message", format!("{}/{}", keys(Action::PreviousMessage), keys(Action::NextMessage)))),
+        Line::from("  Home/End      - Input start/end (empty input: history)"),
+        Line::from(""),
+        Line::from(Span::styled(
+            format!("Press {} or Esc to close", keys(Action::ToggleHelp)),
+            Style::default().fg(theme.muted),
+        )),
+    ];
+
+    let help_paragraph = Paragraph::new(help_text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Help ")
+                .border_style(Style::default().fg(theme.border)),
+        )
+        .wrap(Wrap { trim: false });
+
+    // Calculate centered position
+    let popup_width = 60;
+    let popup_height = 52;
+    let x = (area.width.saturating_sub(popup_width)) / 2;
+    let y = (area.height.saturating_sub(popup_height)) / 2;
+
+    let popup_area = Rect {
+        x: area.x + x,
+        y: area.y + y,
+        width: popup_width.min(area.width),
+        height: popup_height.min(area.height),
+    };
+
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(help_paragraph, popup_area);
+}
+
+#[allow(clippy::too_many_lines)]
+pub fn render_info_window(frame: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let tokens_used = app.total_tokens_used();
+    let context_window = app.context_window_size;
+    let usage_percentage = app.context_usage_percentage();
+
+    // `/set` overrides of this conversation, listed below the usage figures
+    let mut overrides: Vec<Line> = app
+        .overrides
+        .options
+        .entries()
+        .into_iter()
+        .map(|(name, value)| Line::from(format!("  {name} = {value}")))
+        .collect();
+    if let Some(system_prompt) = &app.overrides.system_prompt {
+        overrides.push(Line::from(format!("  system = {system_prompt}")));
+    }
+
+    // Center popup
+    let popup_width = 50;
+    let popup_height = 19 + if overrides.is_empty() {
+        0
+    } else {
+        u16::try_from(overrides.len() + 2).unwrap_or(u16::MAX)
+    };
+    let x = (area.width.saturating_sub(popup_width)) / 2;
+    let y = (area.height.saturating_sub(popup_height)) / 2;
+
+    let popup_area = Rect {
+        x: area.x + x,
+        y: area.y + y,
+        width: popup_width.min(area.width),
+        height: popup_height.min(area.height),
+    };
+
+    let mut info_text = vec![
+        Line::from(Span::styled(
+            "Session Information",
+            Style::default().fg(theme.heading).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::raw("Model: "),
+            Span::styled(&app.current_model, Style::default().fg(theme.accent)),
+        ]),
+        Line::from(vec![
+            Span::raw("Persona: "),
+            Span::styled(
+                app.active_persona.clone().unwrap_or_else(|| "None".to_string()),
+                Style::default().fg(theme.text)
+            ),
+        ]),
+        Line::from(vec![
+            Span::raw("Family: "),
+            Span::styled(
+                app.model_details.as_ref().map_or_else(|| "Unknown".to_string(), |d| d.family.clone()), 
+                Style::default().fg(theme.text)
+            ),
+        ]),
+        Line::from(vec![
+            Span::raw("Params: "),
+            Span::styled(
+                app.model_details.as_ref().map_or_else(|| "?".to_string(), |d| d.parameter_size.clone()), 
+                Style::default().fg(theme.text)
+            ),
+        ]),
+        Line::from(vec![
+            Span::raw("Quantization: "),
+            Span::styled(
+                app.model_details.as_ref().map_or_else(|| "?".to_string(), |d| d.quantization_level.clone()), 
+                Style::default().fg(theme.text)
+            ),
+        ]),
+        Line::from(""),
+        Line::from(Span::styled("Capabilities:", Style::default().add_modifier(Modifier::BOLD))),
+    ];
+
+    if app.model_capabilities.is_empty() {
+         info_text.push(Line::from(Span::styled("  Unknown", Style::default().fg(theme.muted))));
+    } else {
+        for cap in &app.model_capabilities {
+             let (symbol, color) = match cap.as_str() {
+                 "thinking" => ("🧠", Color::Magenta),
+                 "tools" => ("🛠️", Color::Green),
+                 "vision" => ("👁️", Color::Blue),
+                 "completion" => ("📝", Color::Cyan),
+                 "chat" => ("💬", Color::Yellow),
+                 _ => ("•", Color::White),
+             };
+             info_text.push(Line::from(vec![
+                 Span::raw("  "),
+                 Span::styled(format!("{symbol} {cap}"), Style::default().fg(color))
+             ]));
+        }
+    }
+    
+    info_text.extend(vec![
+        Line::from(""),
+        Line::from(vec![
+            Span::raw("Tokens Used: "),
+            Span::styled(format!("{tokens_used}"), Style::default().fg(theme.success)),
+        ]),
+        Line::from(vec![
+            Span::raw("Speed: "),
+            Span::styled(format!("{:.1} t/s", app.tokens_per_second), Style::default().fg(theme.heading)),
+        ]),
+        Line::from(vec![
+            Span::raw("Context Window: "),
+            Span::styled(format!("{context_window} tokens"), Style::default().fg(theme.heading)),
+        ]),
+        Line::from(vec![
+            Span::raw("Usage: "),
+            Span::styled(format!("{usage_percentage:.1}%"), Style::default().fg(
+                if usage_percentage > 80.0 { theme.error }
+                else if usage_percentage > 50.0 { theme.warning }
+                else { theme.success }
+            )),
+        ]),
+    ]);
+
+    if !overrides.is_empty() {
+        info_text.push(Line::from(""));
+        info_text.push(Line::from(Span::styled(
+            "Conversation Overrides:",
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+        info_text.extend(overrides);
+    }
+
+    info_text.extend(vec![
+        Line::from(""),
+        Line::from(Span::styled(
+            format!("Press {} to close", app.keymap.label(Action::ToggleInfo)),
+            Style::default().fg(theme.muted),
+        )),
+    ]);
+
+    let info_paragraph = Paragraph::new(info_text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Model Info ")
+                .border_style(Style::default().fg(theme.border)),
+        )
+        .wrap(Wrap { trim: false });
+
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(info_paragraph, popup_area);
+}
+
+/// Keys of the popups and screens that take over the keyboard
+const fn selector_hints(mode: &AppMode) -> Option<&'static str> {
+    match mode {
+        AppMode::ModelSelector | AppMode::PersonaSelector | AppMode::ThemeSelector | AppMode::TemplateSelector => {
+            Some("Up/Down: Select | Enter: Choose | Esc: Close")
+        }
+        AppMode::ConversationList => {
+            Some("Up/Down: Select | Enter: Open | p: Pin | a: Archive | Tab: Archived | Esc: Close")
+        }
+        AppMode::Memory => Some("Up/Down: Select | d: Delete | Esc: Close"),
+        AppMode::Bookmarks => Some("Up/Down: Select | Enter: Jump | d: Remove | Esc: Close"),
+        AppMode::Search => Some("Type to search | Up/Down: Select | Enter: Search/Open | Esc: Close"),
+        AppMode::Settings => Some("Up/Down: Select | Enter: Edit | s: Save | Esc: Discard"),
+        AppMode::Experiment => Some("Left/Right: Switch run | j/k: Scroll | Esc: Close"),
+        AppMode::Collections | AppMode::TemplateForm => Some("Esc: Close"),
+        _ => None,
+    }
+}
+
+/// The chords of the `bottom_bar` actions, as currently bound; unbound ones are left
+/// out. While a response streams, Esc (stop) comes first.
+fn keymap_hints(app: &App) -> String {
+    let stop = app.is_loading.then(|| "Esc: Stop".to_string());
+    let bound = app.config.bottom_bar.iter().filter(|action| app.keymap.is_bound(**action));
+    let hints = bound.map(|&action| {
+        let hint = match action {
+            Action::ToggleThinking if app.show_thinking => "Hide Thoughts",
+            Action::ToggleThinking => "Reveal Thoughts",
+            action => action.hint(),
+        };
+        format!("{}: {hint}", app.keymap.label(action))
+    });
+    stop.into_iter().chain(hints).collect::<Vec<_>>().join(" | ")
+}
+
+pub fn render_bottom_bar(frame: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let (text, style) = if app.exit_pending {
+        (
+            "Press Ctrl+C again to exit, Esc to cancel".to_string(),
+            Style::default().fg(theme.error).add_modifier(Modifier::BOLD),
+        )
+    } else if let Some(message) = app.active_notification() {
+        (
+            message.to_string(),
+            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+        )
+    } else if let Some(hint) = app.prompt_hint().filter(|_| app.mode == AppMode::Chat) {
+        (
+            format!("Tip: {hint} (Esc to dismiss)"),
+            Style::default().fg(theme.heading),
+        )
+    } else if let Some(find) = app.find.as_ref().filter(|_| app.mode == AppMode::Find) {
+        if find.editing {
+            (
+                format!("Find: {}▏ | Enter: Search | Esc: Cancel", find.query),
+                Style::default().fg(theme.heading),
+            )
+        } else if find.matches.is_empty() {
+            (
+                format!(
+                    "No matches for '{}' | {}: Edit | Esc: Close",
+                    find.query.trim(),
+                    app.keymap.label(Action::Find)
+                ),
+                Style::default().fg(theme.warning),
+            )
+        } else {
+            (
+                format!(
+                    "n/N: Next/Previous match | {} or /: Edit query | Enter: Stay here | Esc: Close",
+                    app.keymap.label(Action::Find)
+                ),
+                Style::default().fg(theme.muted),
+            )
+        }
+    } else if let Some(select) = app.link_select.as_ref().filter(|_| app.mode == AppMode::LinkSelect) {
+        if select.links.is_empty() {
+            (
+                "No links on screen | PageUp/PageDown: Scroll | Esc: Close".to_string(),
+                Style::default().fg(theme.warning),
+            )
+        } else {
+            (
+                format!("Open link: {}▏ | 1-{}: Link | Enter: Open | Esc: Cancel", select.typed, select.links.len()),
+                Style::default().fg(theme.heading),
+            )
+        }
+    } else if let Some((number, path)) = app.code_block_save.as_ref().filter(|_| app.mode == AppMode::SaveCodeBlock) {
+        (
+            format!("Save code block [{number}] to: {path}▏ | Enter: Save | Esc: Cancel"),
+            Style::default().fg(theme.heading),
+        )
+    } else if app.mode == AppMode::MessageSelect {
+        (
+            "Up/Down: Select message | f/Enter: Fork from here | v: Copy mode | s: Save code | p: Pin | m: Bookmark | r: Read later | 1-9: Open source | Esc: Cancel".to_string(),
+            Style::default().fg(theme.muted),
+        )
+    } else if app.mode == AppMode::ReadLater {
+        (
+            "Left/Right: Previous/Next | j/k: Scroll | d: Done | o: Open in conversation | Esc: Close".to_string(),
+            Style::default().fg(theme.muted),
+        )
+    } else if app.mode == AppMode::CopyMode {
+        let action = if app.copy_anchor.is_some() { "End selection" } else { "Start selection" };
+        (
+            format!("j/k: Move | v: {action} | y: Yank | g/G: Top/Bottom | Esc: Cancel"),
+            Style::default().fg(theme.muted),
+        )
+    } else if let Some(hints) = selector_hints(&app.mode) {
+        (hints.to_string(), Style::default().fg(theme.muted))
+    } else {
+        (keymap_hints(app), Style::default().fg(theme.muted))
+    };
+
+    let bar = Paragraph::new(text)
+        .alignment(ratatui::layout::Alignment::Center)
+        .style(style);
+
+    frame.render_widget(bar, area);
+}
+
+pub fn render_status_bar(frame: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let usage_percentage = app.context_usage_percentage();
+    
+    let color = if usage_percentage > 80.0 {
+        theme.error
+    } else if usage_percentage > 50.0 {
+        theme.warning
+    } else {
+        theme.success
+    };
+
+    let eta = app
+        .generation_eta()
+        .map(|eta| format!(" ~{} left", format_eta(eta)))
+        .unwrap_or_default();
+    let loading_indicator = if app.is_loading {
+        if app.is_thinking {
+            format!(" [Thinking...{eta}]")
+        } else {
+            format!(" [Responding...{eta}]")
+        }
+    } else {
+        String::new()
+    };
+    
+    let persona = app
+        .active_persona
+        .as_deref()
+        .map(|name| format!(" · {name}"))
+        .unwrap_or_default();
+    let indexing = app
+        .index_progress
+        .as_ref()
+        .map(|(name, done, total)| format!(" [Indexing {name} {done}/{total}]"))
+        .unwrap_or_default();
+    let pulling = app
+        .pull_progress
+        .as_ref()
+        .map(|(model, status)| format!(" [Pulling {model} {status}]"))
+        .unwrap_or_default();
+    let resources = app
+        .resource_sample
+        .as_ref()
+        .map(|sample| format!(" [{}]", crate::monitor::format_sample(sample)))
+        .unwrap_or_default();
+    let timer = app
+        .focus_timer
+        .map(|timer| {
+            if timer.announced {
+                " [⏰ done]".to_string()
+            } else {
+                format!(" [⏱ {}]", crate::timer::format_clock(timer.remaining()))
+            }
+        })
+        .unwrap_or_default();
+    let find = app
+        .find_position()
+        .map(|(current, total)| format!(" [Find {current}/{total}]"))
+        .unwrap_or_default();
+    let viewing = if app.read_only { " [Read-only]" } else { "" };
+    let status_text = format!(
+        "{}{}{}{}{}{}{}{}{} ({:.1}%)",
+        app.current_model, persona, viewing, pulling, indexing, loading_indicator, resources, timer, find, usage_percentage
+    );
+
+    let status = Paragraph::new(status_text)
+        .alignment(ratatui::layout::Alignment::Right)
+        .style(Style::default().fg(color).add_modifier(Modifier::BOLD));
+
+    frame.render_widget(status, area);
+}
+
+/// Compact duration for the status bar: "42s", "3m 05s"
+fn format_eta(eta: std::time::Duration) -> String {
+    let secs = eta.as_secs();
+    if secs < 60 {
+        format!("{secs}s")
+    } else {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
+/// A rendered line of chat history together with the raw text it was rendered from.
+/// `source` is `None` for pure decoration (spinners, hidden-thinking notices) so that
+/// copying from the history yields clean text.
+#[derive(Debug, Clone)]
+pub struct HistoryLine {
+    pub line: Line<'static>,
+    pub message: usize,
+    pub source: Option<String>,
+}
+
+impl HistoryLine {
+    fn new(line: impl Into<Line<'static>>, message: usize, source: impl Into<String>) -> Self {
+        Self {
+            line: line.into(),
+            message,
+            source: Some(source.into()),
+        }
+    }
+
+    fn decoration(line: impl Into<Line<'static>>, message: usize) -> Self {
+        Self {
+            line: line.into(),
+            message,
+            source: None,
+        }
+    }
+}
+
+/// Styled lines of the chat history
+#[derive(Debug)]
+struct HistoryBlock {
+    lines: Vec<HistoryLine>,
+    /// Display width of each line, which its wrapped height follows from
+    widths: Vec<usize>,
+}
+
+impl HistoryBlock {
+    fn new(lines: Vec<HistoryLine>) -> Arc<Self> {
+        let widths = lines.iter().map(|entry| entry.line.width()).collect();
+        Arc::new(Self { lines, widths })
+    }
+}
+
+/// What rendering any message depends on besides the message itself
+#[derive(Debug, Clone, PartialEq)]
+struct RenderSettings {
+    width: usize,
+    theme: Theme,
+    show_thinking: bool,
+    pretty_math: bool,
+    code_render: BTreeMap<String, CodeRenderMode>,
+    /// Named in the notice standing in for hidden thinking
+    thinking_key: String,
+}
+
+/// A finished message's lines, reused until the message or the settings change
+#[derive(Debug)]
+struct CachedMessage {
+    message: crate::models::Message,
+    code_block_number: usize,
+    block: Arc<HistoryBlock>,
+    /// Code block number the message leaves off at
+    next_code_block: usize,
+}
+
+/// Rendered messages kept between frames, so a frame only renders what changed
+#[derive(Debug, Default)]
+pub struct HistoryCache {
+    settings: Option<RenderSettings>,
+    messages: HashMap<usize, CachedMessage>,
+}
+
+/// Lines of the chat history; `width` is the columns tables are fitted to (0 for no limit)
+pub fn build_history_lines(app: &App, theme: &Theme, width: usize) -> Vec<HistoryLine> {
+    history_blocks(app, theme, width)
+        .iter()
+        .flat_map(|block| block.lines.iter().cloned())
+        .collect()
+}
+
+/// The chat history in blocks: notices, each message and the queued prompts
+fn history_blocks(app: &App, theme: &Theme, width: usize) -> Vec<Arc<HistoryBlock>> {
+    let mut blocks = Vec::new();
+    // Code blocks are numbered across the whole conversation (see App::code_blocks)
+    let mut code_block_number = 0;
+
+    let summarized = app.active_context_summary().map_or(0, |summary| summary.covers);
+
+    let hidden = app.hidden_messages();
+    if hidden > 0 {
+        code_block_number = app.messages[..hidden]
+            .iter()
+            .filter(|message| message.role == crate::models::MessageRole::Assistant)
+            .map(|message| super::markdown::extract_code_blocks(&message.content).len())
+            .sum();
+        blocks.push(HistoryBlock::new(vec![HistoryLine::decoration(
+            Span::styled(
+                format!("⋯ {hidden} earlier messages not shown (scrollback_limit)"),
+                Style::default().fg(theme.muted).add_modifier(Modifier::ITALIC),
+            ),
+            hidden,
+        )]));
+    }
+
+    let settings = RenderSettings {
+        width,
+        theme: *theme,
+        show_thinking: app.show_thinking,
+        pretty_math: app.pretty_math(),
+        code_render: app.config.code_render.clone(),
+        thinking_key: app.keymap.label(Action::ToggleThinking),
+    };
+    let mut cache = app.history_cache.borrow_mut();
+    if cache.settings.as_ref() != Some(&settings) {
+        cache.settings = Some(settings);
+        cache.messages.clear();
+    }
+    cache.messages.retain(|&index, _| index >= hidden && index < app.messages.len());
+
+    for (index, message) in app.messages.iter().enumerate().skip(hidden) {
+        if index == summarized && index > 0 {
+            // Belongs to the last summarized message so message starts stay put
+            blocks.push(HistoryBlock::new(vec![HistoryLine::decoration(
+                Span::styled(
+                    format!("☰ summarized {summarized} messages"),
+                    Style::default().fg(theme.muted).add_modifier(Modifier::ITALIC),
+                ),
+                index - 1,
+            )]));
+        }
+        let streaming = app.is_loading && index + 1 == app.messages.len();
+        match cache.messages.get(&index) {
+            Some(cached) if !streaming && cached.code_block_number == code_block_number && cached.message == *message => {
+                blocks.push(Arc::clone(&cached.block));
+                code_block_number = cached.next_code_block;
+            }
+            _ => {
+                let (lines, next_code_block) = message_lines(app, theme, width, index, code_block_number);
+                let block = HistoryBlock::new(lines);
+                if !streaming {
+                    cache.messages.insert(index, CachedMessage {
+                        message: message.clone(),
+                        code_block_number,
+                        block: Arc::clone(&block),
+                        next_code_block,
+                    });
+                }
+                blocks.push(block);
+                code_block_number = next_code_block;
+            }
+        }
+    }
+    drop(cache);
+
+    let mut lines = Vec::new();
+
+    // Prompts waiting for the running response to finish; sent automatically afterwards
+    let last = app.messages.len().saturating_sub(1);
+    let queued_style = Style::default().fg(theme.muted);
+    for prompt in &app.queued_prompts {
+        lines.push(HistoryLine::decoration("", last));
+        for line in prompt.lines() {
+            lines.push(HistoryLine::decoration(vec![
+                Span::styled("> ", queued_style),
+                Span::styled(line.to_string(), queued_style),
+            ], last));
+        }
+        lines.push(HistoryLine::decoration(Span::styled(
+            "  ⏳ queued",
+            queued_style.add_modifier(Modifier::ITALIC),
+        ), last));
+    }
+    blocks.push(HistoryBlock::new(lines));
+    blocks
+}
+
+/// Lines of one message and the code block number it leaves off at
+fn message_lines(
+    app: &App,
+    theme: &Theme,
+    width: usize,
+    index: usize,
+    mut code_block_number: usize,
+) -> (Vec<HistoryLine>, usize) {
+    let message = &app.messages[index];
+    let mut lines = Vec::new();
+
+    lines.push(HistoryLine::new("", index, ""));
+    let marks: Vec<&str> = [(message.pinned, "📌 pinned"), (message.bookmarked, "🔖 bookmarked")]
+        .into_iter()
+        .filter_map(|(set, mark)| set.then_some(mark))
+        .collect();
+    if !marks.is_empty() {
+        lines.push(HistoryLine::decoration(
+            Span::styled(marks.join("  "), Style::default().fg(theme.accent)),
+            index,
+        ));
+    }
+
+    match message.role {
+        crate::models::MessageRole::User => {
+            // Pasted CSV/TSV data is shown as an aligned table
+            let table = super::markdown::detect_delimiter(&message.content)
+                .and_then(|delimiter| super::markdown::render_delimited_table(&message.content, delimiter));
+            let user_lines: Vec<String> = table.unwrap_or_else(|| {
+                message.content.lines().map(ToString::to_string).collect()
+            });
+            for line in &user_lines {
+                lines.push(HistoryLine::new(
+                    vec![
+                        Span::styled("> ", Style::default().fg(theme.user).add_modifier(Modifier::BOLD)),
+                        Span::styled(line.clone(), Style::default().fg(theme.user).add_modifier(Modifier::BOLD)),
+                    ],
+                    index,
+                    line.as_str(),
+                ));
+            }
+        }
+        crate::models::MessageRole::Assistant => {
+            // Render content with markdown styling
+            if message.content.is_empty() {
+            // Show a placeholder for empty AI responses (while streaming)
+            lines.push(HistoryLine::decoration(Span::styled("...", Style::default().fg(theme.muted)), index));
+        } else {
+            let (rendered, number) =
+                render_assistant(app, theme, width, index, &message.content, code_block_number);
+            lines.extend(rendered);
+            code_block_number = number;
+        }
+
+            // Retrieved sources, openable with /source N (or N in message selection)
+            if !message.citations.is_empty() {
+                lines.push(HistoryLine::decoration(Span::styled(
+                    "  Sources:",
+                    Style::default().fg(theme.muted).add_modifier(Modifier::BOLD),
+                ), index));
+                for (i, citation) in message.citations.iter().enumerate() {
+                    let label = citation.label();
+                    lines.push(HistoryLine::new(vec![
+                        Span::styled(format!("  [{}] ", i + 1), Style::default().fg(theme.accent)),
+                        Span::styled(label.clone(), Style::default().fg(theme.link).add_modifier(Modifier::UNDERLINED)),
+                    ], index, label));
+                }
+            }
+    }
+}
+
+    (lines, code_block_number)
+}
+
+/// Lines of an assistant message and the code block number it leaves off at. The
+/// message being streamed resumes from where the last frame stopped.
+fn render_assistant(
+    app: &App,
+    theme: &Theme,
+    width: usize,
+    index: usize,
+    content: &str,
+    code_block_number: usize,
+) -> (Vec<HistoryLine>, usize) {
+    let streaming = app.is_loading && index + 1 == app.messages.len();
+    if !streaming {
+        let mut renderer = AssistantRenderer::new(index, width, code_block_number, false);
+        for content_line in content.lines() {
+            renderer.line(app, theme, content_line);
+        }
+        return renderer.finish(app, theme);
+    }
+
+    let key = StreamKey {
+        index,
+        width,
+        code_block_number,
+        show_thinking: app.show_thinking,
+        pretty_math: app.pretty_math(),
+        theme: *theme,
+    };
+    let mut cache = app.stream_render.borrow_mut();
+    let mut renderer = match cache.take() {
+        Some(cached) if cached.key == key && content.starts_with(&cached.rendered) => cached,
+        _ => StreamRender {
+            key,
+            rendered: String::new(),
+            renderer: AssistantRenderer::new(index, width, code_block_number, true),
+        },
+    };
+    // Only whole lines are kept; the one still arriving is rendered on a copy
+    let complete = content.rfind('\n').map_or(0, |i| i + 1);
+    if complete > renderer.rendered.len() {
+        for content_line in content[renderer.rendered.len()..complete].lines() {
+            renderer.renderer.line(app, theme, content_line);
+        }
+        renderer.rendered = content[..complete].to_string();
+    }
+    let mut tail = renderer.renderer.clone();
+    *cache = Some(renderer);
+    drop(cache);
+
+    if let Some(partial) = super::markdown::settle_partial(&content[complete..], tail.in_prose()) {
+        tail.line(app, theme, &partial);
+    }
+    tail.finish(app, theme)
+}
+
+/// What a cached streaming render depends on besides the text
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StreamKey {
+    index: usize,
+    width: usize,
+    code_block_number: usize,
+    show_thinking: bool,
+    pretty_math: bool,
+    theme: Theme,
+}
+
+/// How far rendering of the streaming message got, kept between frames
+#[derive(Debug)]
+pub struct StreamRender {
+    key: StreamKey,
+    /// The whole lines rendered so far
+    rendered: String,
+    renderer: AssistantRenderer,
+}
+
+/// Renders an assistant message line by line, carrying block state across lines
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+struct AssistantRenderer {
+    index: usize,
+    width: usize,
+    // The answer is still arriving, so thinking notices animate
+    streaming: bool,
+    lines: Vec<HistoryLine>,
+    code_block_number: usize,
+    in_code_block: bool,
+    code_language: Option<String>,
+    code_mode: CodeRenderMode,
+    // Body of a block whose mode reformats it as a whole (JSON, tables)
+    code_buffer: Vec<String>,
+    // Rows of a markdown table, laid out together once it ends
+    table_buffer: Vec<String>,
+    // Inside a `$$` or `\[` display math block
+    in_math_block: bool,
+    in_thinking: bool,
+    thinking_header_shown: bool,
+    // Hidden-thinking notices, redrawn when finished since they animate
+    thinking_headers: Vec<usize>,
+}
+
+impl AssistantRenderer {
+    const fn new(index: usize, width: usize, code_block_number: usize, streaming: bool) -> Self {
+        Self {
+            index,
+            width,
+            streaming,
+            lines: Vec::new(),
+            code_block_number,
+            in_code_block: false,
+            code_language: None,
+            code_mode: CodeRenderMode::Code,
+            code_buffer: Vec::new(),
+            table_buffer: Vec::new(),
+            in_math_block: false,
+            in_thinking: false,
+            thinking_header_shown: false,
+            thinking_headers: Vec::new(),
+        }
+    }
+
+    /// Outside code, math and thinking, where inline markdown applies
+    const fn in_prose(&self) -> bool {
+        !self.in_code_block && !self.in_math_block && !self.in_thinking
+    }
+
+    fn line(&mut self, app: &App, theme: &Theme, content_line: &str) {
+        let index = self.index;
+        let trimmed = content_line.trim();
+        let has_start = trimmed.contains("<thinking>");
+        let has_end = trimmed.contains("</thinking>");
+
+        if self.in_prose() && !has_start && super::markdown::is_table_row(content_line) {
+            self.table_buffer.push(content_line.to_string());
+            return;
+        }
+        push_table_lines(&mut self.lines, &mut self.table_buffer, self.width, theme, index);
+
+        if has_start {
+            self.in_thinking = true;
+            self.thinking_header_shown = false;
+            if app.show_thinking {
+                 self.lines.push(HistoryLine::decoration(Span::styled(
+                    "  <thinking>", 
+                    Style::default().fg(theme.muted)
+                ), index));
+            }
+        }
+
+        if self.in_thinking {
+            // Strip tags to get actual content if any
+            let clean_content = content_line.replace("<thinking>", "").replace("</thinking>", "");
+            let clean_trimmed = clean_content.trim();
+
+            if !clean_trimmed.is_empty() {
+                if app.show_thinking {
+                    self.lines.push(HistoryLine::new(Span::styled(
+                        format!("        {clean_trimmed}"), 
+                        Style::default().fg(theme.muted),
+                    ), index, clean_trimmed));
+                } else if !self.thinking_header_shown {
+                    self.thinking_headers.push(self.lines.len());
+                    self.lines.push(thinking_header(app, theme, index, self.streaming));
+                    self.thinking_header_shown = true;
+                }
+            }
+        } else {
+            self.prose_line(app, theme, content_line);
+        }
+
+        if has_end {
+            self.in_thinking = false;
+            if app.show_thinking {
+                 self.lines.push(HistoryLine::decoration(Span::styled(
+                    "  </thinking>", 
+                    Style::default().fg(theme.muted)
+                ), index));
+            }
+            // Add blank line after thinking block
+            self.lines.push(HistoryLine::new("", index, ""));
+        }
+    }
+
+    /// Regular content processing
+    fn prose_line(&mut self, app: &App, theme: &Theme, content_line: &str) {
+        let index = self.index;
+        let trimmed = content_line.trim();
+        if trimmed == crate::app::ABORTED_MARKER || trimmed == crate::app::INTERRUPTED_MARKER {
+            self.lines.push(HistoryLine::new(Span::styled(
+                trimmed.to_string(),
+                Style::default().fg(theme.warning).add_modifier(Modifier::ITALIC),
+            ), index, trimmed));
+            return;
+        }
+
+        if !self.in_code_block && (self.in_math_block || super::math::opens_block(content_line)) {
+            push_math_line(&mut self.lines, content_line, &mut self.in_math_block, app.pretty_math(), theme, index);
+            return;
+        }
+
+        if super::markdown::is_code_fence(content_line) {
+            if self.in_code_block {
+                self.close_code_block(theme, content_line);
+            } else {
+                self.open_code_block(app, theme, content_line);
+            }
+        } else if self.in_code_block {
+            // Inside code block - collected until the fence closes
+            self.code_buffer.push(content_line.to_string());
+        } else if content_line.is_empty() {
+            self.lines.push(HistoryLine::new("", index, ""));
+        } else {
+            // Regular markdown line
+            let (text, math) = super::math::extract(content_line);
+            let mut rendered_lines = super::markdown::render_markdown_to_lines(&text, theme, self.width);
+            super::math::restore(&mut rendered_lines, &math, app.pretty_math());
+            // Rows a list item wraps onto copy as part of its first
+            self.lines.extend(rendered_lines.into_iter().enumerate().map(|(i, line)| match i {
+                0 => HistoryLine::new(line, index, content_line),
+                _ => HistoryLine::decoration(line, index),
+            }));
+        }
+    }
+
+    fn open_code_block(&mut self, app: &App, theme: &Theme, content_line: &str) {
+        self.in_code_block = true;
+        self.code_block_number += 1;
+        let code_block_number = self.code_block_number;
+        self.code_language = super::markdown::extract_code_language(content_line);
+        self.code_mode = app.config.code_render_mode(self.code_language.as_deref());
+        let lang_display = match (self.code_language.as_deref(), self.code_mode) {
+            (Some(lang), CodeRenderMode::Diagram) => {
+                format!("{lang} diagram · /diagram {code_block_number}")
+            }
+            (lang, _) => lang.unwrap_or("code").to_string(),
+        };
+        self.lines.push(HistoryLine::new(vec![
+            Span::styled("┌─ ", Style::default().fg(theme.muted)),
+            Span::styled(
+                format!("[{code_block_number}]"),
+                Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(
+                format!(" {lang_display} ───────────────────────────────────────────"),
+                Style::default().fg(theme.muted),
+            ),
+        ], self.index, content_line));
+    }
+
+    fn close_code_block(&mut self, theme: &Theme, content_line: &str) {
+        let code = self.code_buffer.join("\n");
+        if self.code_language.is_none() && super::markdown::detect_delimiter(&code).is_some() {
+            // Untagged CSV/TSV answer
+            self.code_mode = CodeRenderMode::Table;
+        }
+        let transformed =
+            super::markdown::transform_code_block(self.code_mode, self.code_language.as_deref(), &code);
+        match transformed {
+            Some(formatted) => self.lines.extend(formatted.into_iter().map(|line| {
+                let styled = Span::styled(format!("  {line}"), code_style(self.code_mode, theme));
+                HistoryLine::new(styled, self.index, line)
+            })),
+            None => push_highlighted_lines(
+                &mut self.lines,
+                &self.code_buffer,
+                self.code_language.as_deref(),
+                self.code_mode,
+                theme,
+                self.index,
+            ),
+        }
+        self.code_buffer.clear();
+        self.lines.push(HistoryLine::new(Span::styled(
+            "└──────────────────────────────────────────────",
+            Style::default().fg(theme.muted),
+        ), self.index, content_line));
+        self.in_code_block = false;
+    }
+
+    /// The lines so far, with anything still open shown as it stands
+    fn finish(mut self, app: &App, theme: &Theme) -> (Vec<HistoryLine>, usize) {
+        let index = self.index;
+        push_table_lines(&mut self.lines, &mut self.table_buffer, self.width, theme, index);
+        // Unterminated block (still streaming): show what arrived so far as written
+        push_code_lines(&mut self.lines, &self.code_buffer, self.code_mode, theme, index);
+
+        for &header in &self.thinking_headers {
+            self.lines[header] = thinking_header(app, theme, index, self.streaming);
+        }
+
+        // Add thinking animation if currently thinking at the end of the message (visible mode)
+        if self.streaming && app.is_thinking && self.in_thinking && app.show_thinking {
+            // Animation based on time
+            let tick = app.animation_tick();
+            
+            let frames = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+            let frame = frames[tick % frames.len()];
+            
+            self.lines.push(HistoryLine::decoration(Span::styled(
+                format!("        {frame} Thinking..."), 
+                Style::default().fg(theme.muted),
+            ), index));
+        }
+        (self.lines, self.code_block_number)
+    }
+}
+
+/// Notice standing in for hidden thinking, animated while the model thinks
+fn thinking_header(app: &App, theme: &Theme, index: usize, streaming: bool) -> HistoryLine {
+    if streaming && app.is_thinking {
+        // Animation based on time
+        let tick = app.animation_tick();
+        
+        let frames = ["⣾", "⣽", "⣻", "⢿", "⡿", "⣟", "⣯", "⣷"];
+        let frame = frames[tick % frames.len()];
+        let color = match (tick / 8) % 3 {
+            0 => theme.thinking,
+            1 => theme.heading,
+            _ => theme.link,
+        };
+        
+        HistoryLine::decoration(vec![
+            Span::styled("    | AI assistant thoughts (Hidden)   ", Style::default().fg(theme.muted).add_modifier(Modifier::ITALIC)),
+            Span::styled(format!("{frame}  "), Style::default().fg(color)),
+            Span::styled("Thinking", Style::default().fg(theme.thinking).add_modifier(Modifier::BOLD)),
+            Span::styled(format!("  {frame}"), Style::default().fg(color)),
+        ], index)
+    } else {
+        HistoryLine::decoration(Span::styled(
+            format!("    | AI assistant thoughts (Hidden) - Press {} to show", app.keymap.label(Action::ToggleThinking)),
+            Style::default().fg(theme.muted).add_modifier(Modifier::ITALIC),
+        ), index)
+    }
+}
+
+fn code_style(mode: CodeRenderMode, theme: &Theme) -> Style {
+    match mode {
+        CodeRenderMode::Code | CodeRenderMode::Json => Style::default().fg(theme.code),
+        CodeRenderMode::Table => Style::default().fg(theme.heading),
+        CodeRenderMode::Raw | CodeRenderMode::Diagram => Style::default(),
+    }
+}
+
+/// Code block body lines as written, with the block's indent and style
+fn push_code_lines(lines: &mut Vec<HistoryLine>, code: &[String], mode: CodeRenderMode, theme: &Theme, message: usize) {
+    lines.extend(code.iter().map(|line| {
+        HistoryLine::new(Span::styled(format!("  {line}"), code_style(mode, theme)), message, line.as_str())
+    }));
+}
+
+/// A line of a display math block: converted and indented when `pretty` (without
+/// its delimiters), else as written
+fn push_math_line(
+    lines: &mut Vec<HistoryLine>,
+    content_line: &str,
+    in_block: &mut bool,
+    pretty: bool,
+    theme: &Theme,
+    message: usize,
+) {
+    let delimiter = if *in_block {
+        super::math::closes_block(content_line)
+    } else {
+        super::math::opens_block(content_line)
+    };
+    if delimiter {
+        *in_block = !*in_block;
+    }
+    let style = Style::default().fg(theme.assistant);
+    if !pretty {
+        lines.push(HistoryLine::new(Span::styled(content_line.to_string(), style), message, content_line));
+    } else if !delimiter {
+        let math = super::math::to_unicode(content_line.trim());
+        lines.push(HistoryLine::new(Span::styled(format!("    {math}"), style), message, content_line));
+    }
+}
+
+/// Lay out a buffered markdown table, if there is one, and empty the buffer
+fn push_table_lines(lines: &mut Vec<HistoryLine>, rows: &mut Vec<String>, width: usize, theme: &Theme, message: usize) {
+    if rows.is_empty() {
+        return;
+    }
+    let table: Vec<&str> = rows.iter().map(String::as_str).collect();
+    lines.extend(super::markdown::render_markdown_table(&table, width, theme).into_iter().map(
+        |(line, source)| match source {
+            Some(source) => HistoryLine::new(line, message, source),
+            None => HistoryLine::decoration(line, message),
+        },
+    ));
+    rows.clear();
+}
+
+/// A finished code block, highlighted by its fence language when that's known
+fn push_highlighted_lines(
+    lines: &mut Vec<HistoryLine>,
+    code: &[String],
+    language: Option<&str>,
+    mode: CodeRenderMode,
+    theme: &Theme,
+    message: usize,
+) {
+    let tokens = match (mode, language) {
+        (CodeRenderMode::Code, Some(language)) => {
+            super::highlight::tokenize(language, &code.iter().map(String::as_str).collect::<Vec<_>>())
+        }
+        _ => None,
+    };
+    let Some(tokens) = tokens else {
+        return push_code_lines(lines, code, mode, theme, message);
+    };
+    lines.extend(code.iter().zip(tokens).map(|(line, tokens)| {
+        let mut spans = vec![Span::styled("  ", code_style(mode, theme))];
+        spans.extend(super::highlight::spans(&tokens, theme));
+        HistoryLine::new(spans, message, line.as_str())
+    }));
+}
+
+/// Raw text of history lines `start..=end` (in rendered order), skipping decoration
+pub fn history_text(app: &App, start: usize, end: usize) -> String {
+    build_history_lines(app, &Theme::from_config(&app.config.theme), app.history_layout.width)
+        .into_iter()
+        .skip(start)
+        .take(end.saturating_sub(start) + 1)
+        .filter_map(|line| line.source)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Welcome banner at the bottom of the empty history area
+fn render_welcome(frame: &mut Frame, theme: &Theme, area: Rect) {
+    let welcome_text = vec![
+        Line::from(Span::styled(
+            "Welcome to YumChat",
+            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(Span::styled(
+            "Your friendly terminal AI assistant",
+            Style::default().fg(theme.heading),
+        )),
+    ];
+
+    let welcome_paragraph = Paragraph::new(welcome_text)
+        .alignment(ratatui::layout::Alignment::Center);
+
+    // Position it at the bottom of the history area
+    let welcome_height = 2;
+    let y_pos = area.y + area.height.saturating_sub(welcome_height);
+    
+    let welcome_area = Rect {
+        x: area.x,
+        y: y_pos,
+        width: area.width,
+        height: welcome_height.min(area.height),
+    };
+
+    frame.render_widget(welcome_paragraph, welcome_area);
+}
+
+/// Number the links on the visible history lines for Ctrl+O, recording them
+fn number_links(
+    history: &mut [HistoryLine],
+    line_offsets: &[usize],
+    visible: &std::ops::Range<usize>,
+    select: &mut crate::app::LinkSelectState,
+    theme: &Theme,
+) {
+    select.links.clear();
+    for (entry, row) in history.iter_mut().zip(line_offsets) {
+        let Some(source) = entry.source.as_deref().filter(|_| visible.contains(row)) else {
+            continue;
+        };
+        for link in super::markdown::extract_links(source) {
+            let number = if let Some(i) = select.links.iter().position(|known| *known == link) {
+                i + 1
+            } else {
+                select.links.push(link);
+                select.links.len()
+            };
+            entry.line.spans.push(Span::styled(
+                format!(" [{number}]"),
+                Style::default().fg(theme.accent).add_modifier(Modifier::BOLD | Modifier::REVERSED),
+            ));
+        }
+    }
+}
+
+pub fn render_chat_history(frame: &mut Frame, app: &mut App, theme: &Theme, area: Rect) {
+    if app.messages.is_empty() {
+        render_welcome(frame, theme, area);
+        app.history_layout = HistoryLayout {
+            area,
+            ..HistoryLayout::default()
+        };
+        return;
+    }
+    let (header_area, area) = split_sticky_header(app, area);
+
+    // The selection gutter takes two columns
+    let table_width = if app.mode == AppMode::MessageSelect {
+        area.width.saturating_sub(2)
+    } else {
+        area.width
+    };
+    let blocks = history_blocks(app, theme, table_width as usize);
+    let history: Vec<(&HistoryLine, usize)> = blocks
+        .iter()
+        .flat_map(|block| block.lines.iter().zip(block.widths.iter().copied()))
+        .collect();
+    let selected = app.selected_message.filter(|_| app.mode == AppMode::MessageSelect);
+
+    count_find_matches(app, &history);
+    
+    // Calculate scroll position - if scroll_offset is very large, 
+    // we want to show the bottom content
+    // We must account for line wrapping to calculate the true visual height
+    // No borders on history anymore, so use full width
+    let available_width = area.width as usize; 
+    let mut total_visual_lines = 0;
+    let mut layout = HistoryLayout {
+        width: table_width as usize,
+        area,
+        ..HistoryLayout::default()
+    };
+    
+    for (i, (entry, line_width)) in history.iter().enumerate() {
+        // Messages hidden by `scrollback_limit` start where the history does
+        while layout.message_starts.len() <= entry.message {
+            layout.message_starts.push(i);
+        }
+        layout.line_offsets.push(total_visual_lines);
+
+        let gutter = if selected == Some(entry.message) { 2 } else { 0 };
+        total_visual_lines += wrapped_rows(&entry.line, gutter + line_width, gutter, available_width);
+    }
+
+    // No borders, so full height visible
+    let visible_height = area.height as usize;
+    let max_scroll = total_visual_lines.saturating_sub(visible_height);
+
+    // A resize rewraps everything, so what was at the top is found again by message
+    let previous = &app.history_layout;
+    let resized =
+        previous.area.width != area.width || previous.area.height != area.height || previous.width != layout.width;
+    if let Some(anchor) = previous.anchor.filter(|anchor| resized && anchor.offset == app.scroll_offset) {
+        if let Some(offset) = layout.anchor_offset(anchor) {
+            app.scroll_offset = offset;
+        }
+    }
+
+    // Bring the current find match to the middle of the view
+    if let Some(find) = app.find.as_mut().filter(|find| find.jump) {
+        find.jump = false;
+        if let Some(&row) = find.matches.get(find.current).and_then(|&line| layout.line_offsets.get(line)) {
+            app.scroll_offset = row.saturating_sub(visible_height / 2);
+        }
+    }
+
+    if let Some(index) = app.pending_scroll.take() {
+        if let Some(offset) = layout.message_offset(index) {
+            app.scroll_offset = offset;
+        }
+    }
+
+    // Keep the copy-mode cursor on screen
+    if app.mode == AppMode::CopyMode {
+        if let Some(&cursor_row) = layout.line_offsets.get(app.copy_cursor) {
+            if cursor_row < app.scroll_offset {
+                app.scroll_offset = cursor_row;
+            } else if cursor_row >= app.scroll_offset.saturating_add(visible_height) {
+                app.scroll_offset = cursor_row + 1 - visible_height;
+            }
+        }
+    }
+
+    let actual_scroll = app.scroll_offset.min(max_scroll);
+    
+    // Sync the actual scroll back to the app state
+    if app.scroll_offset != actual_scroll {
+        app.scroll_offset = actual_scroll;
+    }
+    layout.anchor = Some(layout.anchor_at(actual_scroll, actual_scroll >= max_scroll));
+
+    // Only the lines on screen are materialized, so long histories scroll as fast as short ones
+    let first = layout.line_offsets.partition_point(|&row| row <= actual_scroll).saturating_sub(1);
+    let end = layout
+        .line_offsets
+        .partition_point(|&row| row < actual_scroll + visible_height)
+        .max(first);
+    let mut visible: Vec<HistoryLine> = history[first..end].iter().map(|(entry, _)| (*entry).clone()).collect();
+    let skip = actual_scroll - layout.line_offsets.get(first).copied().unwrap_or(0);
+    if let (Some(header_area), Some((entry, _))) = (header_area, history.get(first)) {
+        frame.render_widget(Paragraph::new(sticky_header(app, entry.message, theme)), header_area);
+    }
+    app.history_layout = layout;
+    decorate_visible(app, &mut visible, first, selected, theme);
+
+    if let Some(select) = app.link_select.as_mut() {
+        let rows = actual_scroll..actual_scroll + visible_height;
+        number_links(&mut visible, &app.history_layout.line_offsets[first..end], &rows, select, theme);
+    }
+
+    let lines: Vec<Line> = visible.into_iter().map(|entry| entry.line).collect();
+    let chat_history = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .scroll((u16::try_from(skip).unwrap_or(u16::MAX), 0));
 
     frame.render_widget(chat_history, area);
 }
 
-pub fn render_input_field(frame: &mut Frame, app: &App, area: Rect) {
-    let input_text = if app.input_buffer.is_empty() {
-        "Type your message..."
-    } else {
-        &app.input_buffer
+/// Count find matches, recording where they are for n/N and the status bar
+fn count_find_matches(app: &mut App, history: &[(&HistoryLine, usize)]) {
+    let Some(find) = app.find.as_mut() else {
+        return;
     };
+    find.matches.clear();
+    let query = find.query.trim();
+    if !query.is_empty() {
+        for (i, (entry, _)) in history.iter().enumerate() {
+            find.matches.extend(std::iter::repeat_n(i, count_matches(&entry.line, query)));
+        }
+    }
+    if find.current >= find.matches.len() {
+        find.current = 0;
+    }
+}
 
-    let input_style = if app.input_buffer.is_empty() {
-        // Higher contrast for placeholder
-        Style::default().fg(Color::Gray)
-    } else {
-        // Bright/Bold for input text - Match border color (Cyan)
-        Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+/// With `sticky_header` on, the top row of the history area is kept for the header
+fn split_sticky_header(app: &App, area: Rect) -> (Option<Rect>, Rect) {
+    if !app.config.sticky_header || area.height < 2 {
+        return (None, area);
+    }
+    let [header, rest] = Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(area);
+    (Some(header), rest)
+}
+
+/// Whose message `index` is and when it was sent, e.g. "You — 14:02"; the date is
+/// added for messages from an earlier day
+fn sticky_header(app: &App, index: usize, theme: &Theme) -> Line<'static> {
+    let message = &app.messages[index];
+    let (name, color) = match message.role {
+        crate::models::MessageRole::User => ("You".to_string(), theme.user),
+        crate::models::MessageRole::Assistant => (
+            message.model.as_ref().map_or_else(|| "Assistant".to_string(), |model| format!("Assistant ({model})")),
+            theme.assistant,
+        ),
     };
+    let mut spans = vec![Span::styled(name, Style::default().fg(color).add_modifier(Modifier::BOLD))];
+    if let Some(sent_at) = message.sent_at {
+        let local = sent_at.with_timezone(&chrono::Local);
+        let format = if local.date_naive() == chrono::Local::now().date_naive() { "%H:%M" } else { "%Y-%m-%d %H:%M" };
+        spans.push(Span::styled(format!(" — {}", local.format(format)), Style::default().fg(theme.muted)));
+    }
+    Line::from(spans)
+}
 
-    // Keep border for input to make it distinct
-    let input = Paragraph::new(input_text)
-        .style(input_style)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::Cyan)),
-        )
-        .wrap(Wrap { trim: false });
+/// Rows `line` takes wrapped at `width` columns, after `indent` columns of gutter; a
+/// wide character that doesn't fit at the end of a row moves to the next one
+fn wrapped_rows(line: &Line, line_width: usize, indent: usize, width: usize) -> usize {
+    if line_width <= width {
+        return 1;
+    }
+    let width = width.max(1);
+    let mut rows = 1;
+    let mut column = indent;
+    for grapheme in line.spans.iter().flat_map(|span| span.content.graphemes(true)) {
+        let grapheme_width = grapheme.width();
+        if column > 0 && column + grapheme_width > width {
+            rows += 1;
+            column = 0;
+        }
+        column += grapheme_width;
+    }
+    rows
+}
+
+/// Mark the selected message, find matches and the copy selection on the lines on
+/// screen, the first of which is history line `first`
+fn decorate_visible(app: &App, visible: &mut [HistoryLine], first: usize, selected: Option<usize>, theme: &Theme) {
+    let copy_selection = (app.mode == AppMode::CopyMode).then(|| app.copy_selection());
+    for (i, entry) in visible.iter_mut().enumerate() {
+        let line = first + i;
+        // Mark the selected message with a gutter bar
+        if selected == Some(entry.message) {
+            entry.line.spans.insert(0, Span::styled("▌ ", Style::default().fg(theme.accent)));
+        }
+        if let Some(find) = app.find.as_ref().filter(|find| !find.query.trim().is_empty()) {
+            let before = find.matches.partition_point(|&matched| matched < line);
+            highlight_matches(&mut entry.line, find.query.trim(), find.current.checked_sub(before), theme);
+        }
+        // Highlight the copy-mode cursor / selection
+        if copy_selection.is_some_and(|(start, end)| (start..=end).contains(&line)) {
+            entry.line.style = entry.line.style.add_modifier(Modifier::REVERSED);
+        }
+    }
+}
+
+/// Case-insensitive matches of `query` in `line`, counted as `highlight_matches` does
+fn count_matches(line: &Line, query: &str) -> usize {
+    line.spans
+        .iter()
+        .map(|span| {
+            let mut rest: &str = &span.content;
+            let mut count = 0;
+            while let Some(start) = crate::search::find_ignore_case(rest, query) {
+                count += 1;
+                rest = &rest[start + query.len()..];
+            }
+            count
+        })
+        .sum()
+}
+
+/// Split `line`'s spans around case-insensitive matches of `query` and highlight
+/// them, the `current`-th one (if any) more strongly. Returns the number of matches.
+fn highlight_matches(line: &mut Line<'static>, query: &str, current: Option<usize>, theme: &Theme) -> usize {
+    let match_style = Style::default().fg(Color::Black).bg(theme.accent);
+    let current_style = Style::default().fg(Color::Black).bg(theme.highlight).add_modifier(Modifier::BOLD);
+    let mut count = 0;
+    let mut spans = Vec::with_capacity(line.spans.len());
+    for span in line.spans.drain(..) {
+        let mut rest: &str = &span.content;
+        if crate::search::find_ignore_case(rest, query).is_none() {
+            spans.push(span);
+            continue;
+        }
+        while let Some(start) = crate::search::find_ignore_case(rest, query) {
+            let end = start + query.len();
+            if start > 0 {
+                spans.push(Span::styled(rest[..start].to_string(), span.style));
+            }
+            let style = if current == Some(count) { current_style } else { match_style };
+            spans.push(Span::styled(rest[start..end].to_string(), span.style.patch(style)));
+            count += 1;
+            rest = &rest[end..];
+        }
+        if !rest.is_empty() {
+            spans.push(Span::styled(rest.to_string(), span.style));
+        }
+    }
+    line.spans = spans;
+    count
+}
+
+/// Hard-wrap the input at `width` columns (and at newlines) so the cursor can be
+/// placed exactly. Returns the lines and the cursor's (row, column).
+pub fn wrap_input(text: &str, cursor: usize, width: usize) -> (Vec<String>, (usize, usize)) {
+    let (placed, (mut row, mut column)) = layout_input(text, width);
+    // A cursor after a full line sits at the start of the next one
+    if cursor >= text.len() && column >= width.max(1) {
+        row += 1;
+        column = 0;
+    }
+    let mut lines = vec![String::new(); row + 1];
+    let mut position = (row, column);
+    for &(i, grapheme, row, column) in &placed {
+        if (i..i + grapheme.len()).contains(&cursor) {
+            position = (row, column);
+        }
+        if !is_newline(grapheme) {
+            lines[row].push_str(grapheme);
+        }
+    }
+    (lines, position)
+}
+
+fn is_newline(grapheme: &str) -> bool {
+    matches!(grapheme, "\n" | "\r\n")
+}
+
+/// A grapheme of the input with its byte offset, row and column
+type PlacedGrapheme<'a> = (usize, &'a str, usize, usize);
+
+/// Byte offset, grapheme, row and column of each grapheme of the input hard-wrapped at
+/// `width` columns, and the row and column where the text ends. Graphemes stay whole
+/// and wide ones (CJK, emoji) take two columns; a newline sits at the end of its row.
+fn layout_input(text: &str, width: usize) -> (Vec<PlacedGrapheme<'_>>, (usize, usize)) {
+    let width = width.max(1);
+    let mut placed = Vec::new();
+    let (mut row, mut column) = (0, 0);
+    for (i, grapheme) in text.grapheme_indices(true) {
+        if is_newline(grapheme) {
+            placed.push((i, grapheme, row, column));
+            row += 1;
+            column = 0;
+            continue;
+        }
+        let grapheme_width = grapheme.width();
+        if column > 0 && column + grapheme_width > width {
+            row += 1;
+            column = 0;
+        }
+        placed.push((i, grapheme, row, column));
+        column += grapheme_width;
+    }
+    (placed, (row, column))
+}
+
+/// First input row shown when the field is `height` rows tall, keeping the cursor row visible
+const fn first_input_row(cursor_row: usize, height: usize) -> usize {
+    (cursor_row + 1).saturating_sub(if height == 0 { 1 } else { height })
+}
+
+/// Byte offset in the input under a click at (`row`, `column`) of a field `width` by
+/// `height`: before the grapheme clicked, or at the end of the row when past it
+pub fn input_offset_at(text: &str, cursor: usize, (width, height): (usize, usize), row: usize, column: usize) -> usize {
+    let cursor_row = wrap_input(text, cursor, width).1 .0;
+    let target = first_input_row(cursor_row, height) + row;
+    let (placed, _) = layout_input(text, width);
+    placed
+        .iter()
+        .find(|&&(_, grapheme, at_row, at_column)| {
+            at_row > target
+                || (at_row == target && (is_newline(grapheme) || column < at_column + grapheme.width().max(1)))
+        })
+        .map_or(text.len(), |&(i, ..)| i)
+}
+
+/// The link drawn at `position` in the chat history, if there is one
+pub fn link_at(app: &App, position: Position) -> Option<String> {
+    let layout = &app.history_layout;
+    if !layout.area.contains(position) {
+        return None;
+    }
+    let row = app.scroll_offset + usize::from(position.y - layout.area.y);
+    let line = layout.line_offsets.partition_point(|&offset| offset <= row).checked_sub(1)?;
+    let entry = build_history_lines(app, &Theme::from_config(&app.config.theme), layout.width)
+        .into_iter()
+        .nth(line)?;
+    // Column in the line before it was wrapped
+    let column = (row - layout.line_offsets[line]) * usize::from(layout.area.width)
+        + usize::from(position.x - layout.area.x);
+    let text = entry.line.to_string();
+    super::markdown::extract_links(entry.source.as_deref()?).into_iter().find(|link| {
+        text.match_indices(link.as_str()).any(|(start, _)| {
+            let from = text[..start].width();
+            (from..from + link.width()).contains(&column)
+        })
+    })
+}
+
+pub fn render_input_field(frame: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.border));
+    let inner = block.inner(area);
+
+    if app.input_buffer.is_empty() {
+        // Higher contrast for placeholder
+        let placeholder = Paragraph::new("Type your message...")
+            .style(Style::default().fg(theme.text))
+            .block(block);
+        frame.render_widget(placeholder, area);
+        if app.mode == AppMode::Chat {
+            frame.set_cursor_position((inner.x, inner.y));
+        }
+        return;
+    }
 
+    let (lines, (row, column)) = wrap_input(&app.input_buffer, app.cursor(), inner.width as usize);
+    // Keep the cursor row visible when the input is taller than the field
+    let first_row = first_input_row(row, inner.height as usize);
+
+    // Bright/Bold for input text, in the color of the user's messages
+    let input = Paragraph::new(lines.into_iter().map(Line::from).collect::<Vec<_>>())
+        .style(Style::default().fg(theme.user).add_modifier(Modifier::BOLD))
+        .scroll((u16::try_from(first_row).unwrap_or(u16::MAX), 0))
+        .block(block);
     frame.render_widget(input, area);
+
+    if app.mode == AppMode::Chat {
+        let x = inner.x + u16::try_from(column).unwrap_or(u16::MAX);
+        let y = inner.y + u16::try_from(row - first_row).unwrap_or(u16::MAX);
+        frame.set_cursor_position((x.min(inner.right().saturating_sub(1)), y));
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Everything `draw` puts on a `width` × `height` screen, row after row
+    fn render_to_string(width: u16, height: u16, draw: impl FnOnce(&mut Frame)) -> String {
+        let mut terminal = ratatui::Terminal::new(ratatui::backend::TestBackend::new(width, height)).unwrap();
+        terminal.draw(draw).unwrap();
+        terminal.backend().buffer().content().iter().map(ratatui::buffer::Cell::symbol).collect()
+    }
+
+    #[test]
+    fn test_highlight_matches_splits_spans() {
+        let mut line = Line::from(vec![
+            Span::styled("Rust and rust", Style::default().fg(Color::Green)),
+            Span::raw(" again: RUST"),
+        ]);
+        assert_eq!(highlight_matches(&mut line, "rust", Some(1), &Theme::default()), 3);
+        let texts: Vec<&str> = line.spans.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(texts, vec!["Rust", " and ", "rust", " again: ", "RUST"]);
+        assert_eq!(line.spans[0].style.bg, Some(Color::Yellow));
+        assert_eq!(line.spans[2].style.bg, Some(Color::LightRed));
+        assert_eq!(line.spans[1].style.fg, Some(Color::Green));
+        assert_eq!(highlight_matches(&mut line, "python", None, &Theme::default()), 0);
+    }
+
     #[test]
     fn test_status_bar_color_logic() {
         let mut app = App::new();
@@ -569,4 +2514,402 @@ mod tests {
         let pct = app.context_usage_percentage();
         assert!(pct > 80.0);
     }
+
+    #[test]
+    fn test_format_eta() {
+        assert_eq!(format_eta(std::time::Duration::from_secs(42)), "42s");
+        assert_eq!(format_eta(std::time::Duration::from_secs(185)), "3m 05s");
+    }
+
+    #[test]
+    fn test_bottom_bar_follows_keymap() {
+        let mut app = App::new();
+        assert_eq!(
+            keymap_hints(&app),
+            "Ctrl+N: New | Ctrl+Q: Quit | Ctrl+I: Info | Tab: Reveal Thoughts | Ctrl+H: Help"
+        );
+
+        let keys = toml::from_str("toggle_help = \"f1\"").unwrap();
+        app.keymap = crate::keymap::Keymap::from_config(&keys).unwrap();
+        app.config.bottom_bar = vec![Action::ToggleHelp, Action::ToggleThinking, Action::Find];
+        app.show_thinking = true;
+        app.is_loading = true;
+        assert_eq!(keymap_hints(&app), "Esc: Stop | F1: Help | Tab: Hide Thoughts | Ctrl+F: Find");
+
+        app.mode = AppMode::Memory;
+        assert_eq!(selector_hints(&app.mode), Some("Up/Down: Select | d: Delete | Esc: Close"));
+        assert_eq!(selector_hints(&AppMode::Chat), None);
+
+        // Notices in the history name the rebound chord too
+        let keys = toml::from_str("toggle_thinking = \"alt+t\"").unwrap();
+        app.keymap = crate::keymap::Keymap::from_config(&keys).unwrap();
+        app.is_loading = false;
+        let header = thinking_header(&app, &Theme::default(), 0, false);
+        assert!(header.line.to_string().ends_with("Press Alt+T to show"));
+    }
+
+    #[test]
+    fn test_scrollback_limit_hides_oldest_messages() {
+        use crate::models::{Message, MessageRole};
+        let mut app = App::new();
+        for i in 0..3 {
+            app.messages.push(Message::new(MessageRole::User, format!("question {i}"), 1));
+            app.messages.push(Message::new(MessageRole::Assistant, format!("```rust\nfn f{i}() {{}}\n```"), 1));
+        }
+        app.config.scrollback_limit = 2;
+
+        let lines = build_history_lines(&app, &Theme::default(), 80);
+        let text: Vec<String> = lines.iter().map(|l| l.line.to_string()).collect();
+        assert_eq!(text[0], "⋯ 4 earlier messages not shown (scrollback_limit)");
+        assert!(!text.iter().any(|line| line.contains("question 1")));
+        assert!(text.contains(&"> question 2".to_string()));
+        // Code blocks keep their numbers across the conversation
+        assert!(text.iter().any(|line| line.contains("[3]")));
+        assert!(!text.iter().any(|line| line.contains("[1]")));
+    }
+
+    #[test]
+    fn test_queued_prompts_shown_after_history() {
+        let mut app = App::new();
+        app.messages.push(crate::models::Message::new(crate::models::MessageRole::User, "first".to_string(), 1));
+        app.messages.push(crate::models::Message::new(crate::models::MessageRole::Assistant, String::new(), 0));
+        app.queued_prompts.push_back("second\nquestion".to_string());
+
+        let lines = build_history_lines(&app, &Theme::default(), 80);
+        let text: Vec<String> = lines.iter().map(|l| l.line.to_string()).collect();
+        let n = text.len();
+        assert_eq!(text[n - 3..], ["> second", "> question", "  ⏳ queued"]);
+        // Queued prompts aren't part of the conversation yet, so copying skips them
+        assert!(lines[n - 3..].iter().all(|l| l.source.is_none()));
+    }
+
+    #[test]
+    fn test_history_text_uses_raw_source() {
+        let mut app = App::new();
+        app.messages.push(crate::models::Message::new(
+            crate::models::MessageRole::User,
+            "Show me".to_string(),
+            1,
+        ));
+        app.messages.push(crate::models::Message::new(
+            crate::models::MessageRole::Assistant,
+            "<thinking>\nhidden\n</thinking>\n**Sure**\n```rust\nfn main() {}\n```".to_string(),
+            1,
+        ));
+
+        let lines = build_history_lines(&app, &Theme::default(), 80);
+        let last = lines.len() - 1;
+        let text = history_text(&app, 0, last);
+        // No "> " prompt, code indentation, box borders or hidden-thinking notice
+        assert_eq!(text, "\nShow me\n\n\n**Sure**\n```rust\nfn main() {}\n```");
+        assert!(lines.iter().all(|line| line.message < 2));
+    }
+
+    #[test]
+    fn test_code_blocks_follow_render_overrides() {
+        let mut app = App::new();
+        app.messages.push(crate::models::Message::new(
+            crate::models::MessageRole::Assistant,
+            "```json\n{\"a\":1}\n```\n```mermaid\ngraph TD\n```\n```json\n{\"still".to_string(),
+            1,
+        ));
+
+        let rendered: Vec<String> = build_history_lines(&app, &Theme::default(), 80)
+            .iter()
+            .map(|entry| entry.line.to_string())
+            .collect();
+        assert!(rendered.contains(&"    \"a\": 1".to_string()));
+        assert!(rendered.iter().any(|line| line.contains("mermaid diagram")));
+        assert!(rendered.contains(&"  graph TD".to_string()));
+        // Streaming JSON that does not parse yet is shown as written
+        assert!(rendered.contains(&"  {\"still".to_string()));
+    }
+
+    #[test]
+    fn test_csv_like_content_renders_as_table() {
+        let mut app = App::new();
+        app.messages.push(crate::models::Message::new(
+            crate::models::MessageRole::User,
+            "city,pop\nOslo,709000".to_string(),
+            1,
+        ));
+        app.messages.push(crate::models::Message::new(
+            crate::models::MessageRole::Assistant,
+            "```\nx,y\n1,2\n```".to_string(),
+            1,
+        ));
+
+        let rendered: Vec<String> = build_history_lines(&app, &Theme::default(), 80)
+            .iter()
+            .map(|entry| entry.line.to_string())
+            .collect();
+        assert!(rendered.contains(&"> city │    pop".to_string()));
+        assert!(rendered.contains(&"  x │ y".to_string()));
+    }
+
+    #[test]
+    fn test_streaming_render_matches_full_render() {
+        let content = "Intro with **bold** text\n```rust\nfn main() {}\n```\n| a | b |\n|---|---|\n| 1 | 2 |\nDone\n";
+        let mut app = App::new();
+        app.messages.push(crate::models::Message::new(
+            crate::models::MessageRole::Assistant,
+            String::new(),
+            1,
+        ));
+        let render = |app: &App| -> Vec<String> {
+            build_history_lines(app, &Theme::default(), 80).iter().map(|entry| entry.line.to_string()).collect()
+        };
+
+        // Arriving a few bytes at a time, every whole line renders as it will in the end
+        app.is_loading = true;
+        for end in (1..=content.len()).filter(|&end| content.is_char_boundary(end)) {
+            app.messages[0].content = content[..end].to_string();
+            render(&app);
+        }
+        let streamed = render(&app);
+        app.is_loading = false;
+        assert_eq!(streamed, render(&app));
+
+        // A half-received fence is held back, and half-received bold is already bold
+        app.is_loading = true;
+        app.messages[0].content = "Intro\n``".to_string();
+        assert_eq!(render(&app), vec!["", "Intro"]);
+        app.messages[0].content = "Intro\nSo **very*".to_string();
+        let lines = build_history_lines(&app, &Theme::default(), 80);
+        let bold = lines[2].line.spans.iter().find(|span| span.content == "very").unwrap();
+        assert!(bold.style.add_modifier.contains(Modifier::BOLD));
+        assert_eq!(lines[2].line.to_string(), "So very");
+    }
+
+    #[test]
+    fn test_render_chat_history_scrolled() {
+        let mut app = App::new();
+        for i in 0..200 {
+            app.messages.push(crate::models::Message::new(
+                crate::models::MessageRole::Assistant,
+                format!("answer **{i}**"),
+                1,
+            ));
+        }
+        app.scroll_offset = 101;
+        app.find = Some(crate::app::FindState {
+            query: "ANSWER".to_string(),
+            ..crate::app::FindState::default()
+        });
+
+        let backend = ratatui::backend::TestBackend::new(40, 4);
+        let mut terminal = ratatui::Terminal::new(backend).unwrap();
+        terminal.draw(|f| render_chat_history(f, &mut app, &Theme::default(), f.area())).unwrap();
+        let rows: Vec<String> = terminal
+            .backend()
+            .buffer()
+            .content()
+            .chunks(40)
+            .map(|row| row.iter().map(ratatui::buffer::Cell::symbol).collect::<String>().trim_end().to_string())
+            .collect();
+        // Under the header naming the message at the top
+        assert_eq!(rows, vec!["Assistant", "answer 50", "", "answer 51"]);
+        // Layout and matches still cover the whole history
+        assert_eq!(app.history_layout.line_count(), 400);
+        assert_eq!(app.find.as_ref().unwrap().matches.len(), 200);
+
+        // Unchanged messages are rendered once
+        let before = history_blocks(&app, &Theme::default(), 40);
+        app.messages[1].content = "edited".to_string();
+        let after = history_blocks(&app, &Theme::default(), 40);
+        assert!(Arc::ptr_eq(&before[0], &after[0]));
+        assert!(!Arc::ptr_eq(&before[1], &after[1]));
+    }
+
+    #[test]
+    fn test_wrap_input_wide_characters() {
+        // Two columns each, so three fit in a row of 7
+        let (lines, cursor) = wrap_input("日本語です", 15, 7);
+        assert_eq!(lines, vec!["日本語", "です"]);
+        assert_eq!(cursor, (1, 4));
+        let (lines, cursor) = wrap_input("ab👍🏽c", 2, 3);
+        assert_eq!(lines, vec!["ab", "👍🏽c"]);
+        assert_eq!(cursor, (1, 0));
+
+        let line = Line::from("日本語です");
+        assert_eq!(wrapped_rows(&line, line.width(), 0, 10), 1);
+        assert_eq!(wrapped_rows(&line, line.width(), 0, 7), 2);
+        assert_eq!(wrapped_rows(&line, line.width() + 2, 2, 10), 2);
+    }
+
+    #[test]
+    fn test_resize_keeps_top_message() {
+        let mut app = App::new();
+        for i in 0..30 {
+            app.messages.push(crate::models::Message::new(
+                crate::models::MessageRole::Assistant,
+                format!("answer {i} with a few more words to wrap"),
+                1,
+            ));
+        }
+        // The first row below the sticky header
+        let top_row = |app: &mut App, width: u16| {
+            let backend = ratatui::backend::TestBackend::new(width, 6);
+            let mut terminal = ratatui::Terminal::new(backend).unwrap();
+            terminal.draw(|f| render_chat_history(f, app, &Theme::default(), f.area())).unwrap();
+            let buffer = terminal.backend().buffer().clone();
+            let width = usize::from(width);
+            buffer.content()[width..2 * width].iter().map(ratatui::buffer::Cell::symbol).collect::<String>()
+        };
+
+        top_row(&mut app, 60);
+        app.scroll_offset = 21; // "answer 10", two rows per message
+        assert!(top_row(&mut app, 60).starts_with("answer 10 "));
+        // Narrower, every message wraps and more rows come before it
+        assert!(top_row(&mut app, 20).starts_with("answer 10 "));
+        assert!(top_row(&mut app, 60).starts_with("answer 10 "));
+
+        // At the bottom stays at the bottom
+        app.scroll_to_bottom();
+        top_row(&mut app, 60);
+        top_row(&mut app, 20);
+        assert!(app.history_layout.anchor.unwrap().at_bottom);
+    }
+
+    #[test]
+    fn test_click_positions() {
+        // Second row of "abcdef" wrapped at 3: before 'e', then past the end
+        assert_eq!(input_offset_at("abcdef", 0, (3, 2), 1, 1), 4);
+        assert_eq!(input_offset_at("ab\ncd", 0, (10, 2), 0, 7), 2);
+        assert_eq!(input_offset_at("ab\ncd", 0, (10, 2), 1, 7), 5);
+        // Scrolled to keep the cursor on the last row
+        assert_eq!(input_offset_at("a\nb\nc", 5, (10, 2), 0, 0), 2);
+
+        let mut app = App::new();
+        app.messages.push(crate::models::Message::new(
+            crate::models::MessageRole::Assistant,
+            "See https://example.com for more".to_string(),
+            1,
+        ));
+        let backend = ratatui::backend::TestBackend::new(60, 10);
+        let mut terminal = ratatui::Terminal::new(backend).unwrap();
+        terminal.draw(|f| render_chat_history(f, &mut app, &Theme::default(), f.area())).unwrap();
+        // Below the sticky header and the blank line before the message
+        assert_eq!(link_at(&app, Position::new(10, 2)).as_deref(), Some("https://example.com"));
+        assert_eq!(link_at(&app, Position::new(1, 2)), None);
+        assert_eq!(link_at(&app, Position::new(10, 1)), None);
+        assert_eq!(link_at(&app, Position::new(10, 0)), None);
+    }
+
+    #[test]
+    fn test_sticky_header() {
+        use chrono::TimeZone;
+        let mut app = App::new();
+        let mut question = crate::models::Message::new(crate::models::MessageRole::User, "hi".to_string(), 1);
+        question.sent_at = Some(chrono::Local.with_ymd_and_hms(2026, 3, 1, 14, 2, 0).unwrap().to_utc());
+        app.messages.push(question);
+        app.messages.push(
+            crate::models::Message::new(crate::models::MessageRole::Assistant, "hello".to_string(), 1)
+                .sent_by(Some("qwen3:4b")),
+        );
+        let header = |app: &App, index: usize| sticky_header(app, index, &Theme::default()).to_string();
+        assert_eq!(header(&app, 0), "You — 2026-03-01 14:02");
+        assert!(header(&app, 1).starts_with("Assistant (qwen3:4b) — "));
+        assert_eq!(header(&app, 1).len(), "Assistant (qwen3:4b) — 14:02".len());
+        app.messages[1].model = None;
+        app.messages[1].sent_at = None;
+        assert_eq!(header(&app, 1), "Assistant");
+
+        // Turned off, the history starts on the top row
+        app.config.sticky_header = false;
+        assert_eq!(split_sticky_header(&app, Rect::new(0, 0, 40, 10)), (None, Rect::new(0, 0, 40, 10)));
+        app.config.sticky_header = true;
+        assert_eq!(
+            split_sticky_header(&app, Rect::new(0, 0, 40, 10)),
+            (Some(Rect::new(0, 0, 40, 1)), Rect::new(0, 1, 40, 9))
+        );
+    }
+
+    #[test]
+    fn test_wrap_input_cursor_position() {
+        let (lines, cursor) = wrap_input("abcdef", 6, 3);
+        assert_eq!(lines, vec!["abc", "def", ""]);
+        assert_eq!(cursor, (2, 0));
+
+        let (lines, cursor) = wrap_input("ab\ncdé", 5, 10);
+        assert_eq!(lines, vec!["ab", "cdé"]);
+        assert_eq!(cursor, (1, 2));
+
+        let (_, cursor) = wrap_input("abcdef", 3, 3);
+        assert_eq!(cursor, (1, 0));
+        let (_, cursor) = wrap_input("", 0, 3);
+        assert_eq!(cursor, (0, 0));
+    }
+
+    #[test]
+    fn test_citations_rendered_under_answer() {
+        let mut app = App::new();
+        let mut answer = crate::models::Message::new(
+            crate::models::MessageRole::Assistant,
+            "Per [1]".to_string(),
+            1,
+        );
+        answer.citations.push(crate::models::Citation {
+            path: std::path::PathBuf::from("src/app.rs"),
+            start_line: 41,
+            end_line: 80,
+        });
+        app.messages.push(answer);
+
+        let rendered: Vec<String> = build_history_lines(&app, &Theme::default(), 80)
+            .iter()
+            .map(|entry| entry.line.to_string())
+            .collect();
+        assert!(rendered.contains(&"  Sources:".to_string()));
+        assert!(rendered.contains(&"  [1] src/app.rs:41-80".to_string()));
+    }
+
+    #[test]
+    fn test_render_settings_with_preview() {
+        let mut app = App::new();
+        app.open_settings();
+        app.start_editing_setting();
+
+        let content = render_to_string(100, 20, |f| render_settings(f, &app, &Theme::default(), f.area()));
+        assert!(content.contains("Settings"));
+        assert!(content.contains("Preview"));
+        assert!(content.contains("http://localhost:11434_"));
+    }
+
+    #[test]
+    fn test_render_debug_overlay() {
+        let app = App::new();
+        app.toggle_debug();
+        app.traffic.record(Direction::Received, || r#"{"message":{"content":"Hel"},"done":false}"#.to_string());
+        app.traffic.record(Direction::Event, || crate::events::AppEvent::AiResponseChunk("Hel".to_string()).describe());
+
+        let content = render_to_string(100, 20, |f| render_debug_overlay(f, &app, &Theme::default(), f.area()));
+        assert!(content.contains("Debug: protocol traffic (F12 closes)"));
+        assert!(content.contains(r#"← {"message":{"content":"Hel"},"done":false}"#));
+        assert!(content.contains(r#"• AiResponseChunk("Hel")"#));
+    }
+
+    #[test]
+    fn test_render_config_problems() {
+        let mut app = App::new();
+        app.config_problems = crate::config::parse_config("[theme]\nborder_color = \"blurple\"").unwrap().1;
+
+        let content = render_to_string(100, 20, |f| render_config_problems(f, &app, &Theme::default(), f.area()));
+        assert!(content.contains("Problems in config.toml"));
+        assert!(content.contains("line 2, theme.border_color: Unknown color 'blurple'"));
+    }
+
+    #[test]
+    fn test_render_template_form() {
+        let mut app = App::new();
+        app.templates = crate::templates::default_templates();
+        app.open_template_selector();
+        app.choose_template();
+        app.template_form.as_mut().unwrap().push_char('+');
+
+        let content = render_to_string(100, 24, |f| render_template_form(f, &app, &Theme::default(), f.area()));
+        assert!(content.contains("commit-message"));
+        assert!(content.contains("diff: +"));
+        assert!(content.contains("Write a concise git commit message"));
+    }
 }